@@ -0,0 +1,69 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Compares the JSON transport of Script::call() with the direct V8 conversion of Script::call_direct().
+// Run with `cargo bench`; each case reports the average time per call.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use js_sandbox::{AnyError, Script};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Record {
+	id: u32,
+	name: String,
+	score: f64,
+	tags: Vec<String>,
+}
+
+fn records(count: u32) -> Vec<Record> {
+	(0..count)
+		.map(|id| Record {
+			id,
+			name: format!("record #{}", id),
+			score: id as f64 * 0.5,
+			tags: vec!["alpha".to_string(), "beta".to_string()],
+		})
+		.collect()
+}
+
+fn measure(iterations: u32, mut f: impl FnMut() -> Result<(), AnyError>) -> Result<Duration, AnyError> {
+	// Warm up the JIT before measuring
+	for _ in 0..iterations / 10 + 1 {
+		f()?;
+	}
+
+	let start = Instant::now();
+	for _ in 0..iterations {
+		f()?;
+	}
+	Ok(start.elapsed() / iterations)
+}
+
+fn main() -> Result<(), AnyError> {
+	let mut script = Script::from_string("function identity(records) { return records; }")?;
+
+	for &count in &[1, 100, 10_000] {
+		let input = records(count);
+		let iterations = 100_000 / count + 10;
+
+		let json = measure(iterations, || {
+			let _: Vec<Record> = script.call("identity", &input, None)?;
+			Ok(())
+		})?;
+		let direct = measure(iterations, || {
+			let _: Vec<Record> = script.call_direct("identity", &input, None)?;
+			Ok(())
+		})?;
+
+		println!(
+			"{:>6} records:  call {:>10.1?}  call_direct {:>10.1?}  ({:.1}x)",
+			count,
+			json,
+			direct,
+			json.as_secs_f64() / direct.as_secs_f64()
+		);
+	}
+	Ok(())
+}
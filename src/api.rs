@@ -0,0 +1,51 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use crate::{AnyError, JsValue};
+
+/// Method of a [`HostApi`](trait.HostApi.html), invoked with the API object and the JS argument.
+pub type HostMethod<T> = fn(&T, JsValue) -> Result<JsValue, AnyError>;
+
+/// A Rust type whose methods are exposed to JavaScript as a group of host functions.
+///
+/// Registered at once with [`Script::register_ops()`](struct.Script.html#method.register_ops), instead of one
+/// [`add_fn()`](struct.Script.html#method.add_fn) per function. Each method must have the signature of a
+/// [`HostMethod`](type.HostMethod.html):
+///
+/// ```rust
+/// use js_sandbox::{AnyError, HostApi, HostMethod, JsValue, Script};
+///
+/// struct Inventory {
+/// 	items: Vec<String>,
+/// }
+///
+/// impl Inventory {
+/// 	fn count(&self, _arg: JsValue) -> Result<JsValue, AnyError> {
+/// 		Ok(self.items.len().into())
+/// 	}
+///
+/// 	fn contains(&self, arg: JsValue) -> Result<JsValue, AnyError> {
+/// 		Ok(self.items.iter().any(|item| Some(item.as_str()) == arg.as_str()).into())
+/// 	}
+/// }
+///
+/// impl HostApi for Inventory {
+/// 	fn methods() -> Vec<(&'static str, HostMethod<Self>)> {
+/// 		vec![("count", Inventory::count), ("contains", Inventory::contains)]
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let mut script = Script::from_string("function check(item) { return [inventory.count(), inventory.contains(item)]; }")?;
+/// 	script.register_ops("inventory", Inventory { items: vec!["map".to_string()] })?;
+///
+/// 	let result: (usize, bool) = script.call("check", &"map", None)?;
+/// 	assert_eq!(result, (1, true));
+/// 	Ok(())
+/// }
+/// ```
+pub trait HostApi: 'static {
+	/// Lists the methods to expose, by the name under which JavaScript invokes them.
+	fn methods() -> Vec<(&'static str, HostMethod<Self>)>
+	where
+		Self: Sized;
+}
@@ -0,0 +1,348 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Conversion of Rust arguments to JSON, with diagnostics that name the offending value.
+// Wraps serde_json's value serializer, keeping track of the path to the value being serialized. Non-finite floats, which
+// serde_json would silently turn into `null`, are rejected.
+
+use std::cell::RefCell;
+
+use deno_core::error::generic_error;
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::bytes;
+use crate::{AnyError, JsValue};
+
+/// Converts `args` to JSON, failing with an error that mentions `fn_name` and the path to the value that cannot be converted.
+pub(crate) fn to_json<P: Serialize + ?Sized>(fn_name: &str, args: &P) -> Result<JsValue, AnyError> {
+	bytes::clear_outgoing();
+	let ctx = Context::default();
+
+	Checked { value: args, ctx: &ctx }.serialize(serde_json::value::Serializer).map_err(|e| {
+		let path = ctx.error_path.borrow_mut().take().unwrap_or_else(|| ctx.format_path());
+		generic_error(format!("cannot pass arguments to JS function '{}': {} (at `{}`)", fn_name, e, path))
+	})
+}
+
+enum Segment {
+	Index(usize),
+	Field(&'static str),
+	Key(String),
+}
+
+#[derive(Default)]
+struct Context {
+	path: RefCell<Vec<Segment>>,
+	// Path of the innermost value that failed, recorded while the error propagates
+	error_path: RefCell<Option<String>>,
+}
+
+impl Context {
+	fn nested<T>(&self, segment: Segment, f: impl FnOnce() -> T) -> T {
+		self.path.borrow_mut().push(segment);
+		let result = f();
+		self.path.borrow_mut().pop();
+
+		result
+	}
+
+	fn format_path(&self) -> String {
+		let mut formatted = String::from("args");
+		for segment in self.path.borrow().iter() {
+			match segment {
+				Segment::Index(index) => formatted += &format!("[{}]", index),
+				Segment::Field(field) => formatted += &format!(".{}", field),
+				Segment::Key(key) => formatted += &format!("[{}]", serde_json::to_string(key).unwrap_or_default()),
+			}
+		}
+
+		formatted
+	}
+}
+
+/// Value to be serialized, at the current path of the context.
+struct Checked<'a, 'c, T: ?Sized> {
+	value: &'a T,
+	ctx: &'c Context,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Checked<'_, '_, T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let result = self.value.serialize(CheckedSerializer { inner: serializer, ctx: self.ctx });
+
+		if result.is_err() {
+			let mut error_path = self.ctx.error_path.borrow_mut();
+			if error_path.is_none() {
+				*error_path = Some(self.ctx.format_path());
+			}
+		}
+
+		result
+	}
+}
+
+fn float_error<E: ser::Error>(value: f64) -> E {
+	let name = if value.is_nan() {
+		"NaN"
+	} else if value > 0.0 {
+		"Infinity"
+	} else {
+		"-Infinity"
+	};
+
+	E::custom(format!("{} is not a valid JSON number; consider `Option<f64>` or a string representation", name))
+}
+
+struct CheckedSerializer<'c, S> {
+	inner: S,
+	ctx: &'c Context,
+}
+
+impl<'c, S: Serializer> CheckedSerializer<'c, S> {
+	fn checked<'a, T: ?Sized>(&self, value: &'a T) -> Checked<'a, 'c, T> {
+		Checked { value, ctx: self.ctx }
+	}
+}
+
+macro_rules! forward {
+	($($method:ident($ty:ty)),* $(,)?) => {
+		$(
+			fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+				self.inner.$method(v)
+			}
+		)*
+	};
+}
+
+impl<'c, S: Serializer> Serializer for CheckedSerializer<'c, S> {
+	type Ok = S::Ok;
+	type Error = S::Error;
+	type SerializeSeq = Compound<'c, S::SerializeSeq>;
+	type SerializeTuple = Compound<'c, S::SerializeTuple>;
+	type SerializeTupleStruct = Compound<'c, S::SerializeTupleStruct>;
+	type SerializeTupleVariant = Compound<'c, S::SerializeTupleVariant>;
+	type SerializeMap = Compound<'c, S::SerializeMap>;
+	type SerializeStruct = Compound<'c, S::SerializeStruct>;
+	type SerializeStructVariant = Compound<'c, S::SerializeStructVariant>;
+
+	forward! {
+		serialize_bool(bool),
+		serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64), serialize_i128(i128),
+		serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64), serialize_u128(u128),
+		serialize_char(char),
+		serialize_str(&str),
+		serialize_unit_struct(&'static str),
+	}
+
+	// Binary data is transferred next to the JSON, see bytes.rs
+	fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+		bytes::serialize_staged(v, self.inner)
+	}
+
+	fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+		if !v.is_finite() {
+			return Err(float_error(v.into()));
+		}
+		self.inner.serialize_f32(v)
+	}
+
+	fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+		if !v.is_finite() {
+			return Err(float_error(v));
+		}
+		self.inner.serialize_f64(v)
+	}
+
+	fn serialize_none(self) -> Result<S::Ok, S::Error> {
+		self.inner.serialize_none()
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+		let value = self.checked(value);
+		self.inner.serialize_some(&value)
+	}
+
+	fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+		self.inner.serialize_unit()
+	}
+
+	fn serialize_unit_variant(self, name: &'static str, index: u32, variant: &'static str) -> Result<S::Ok, S::Error> {
+		self.inner.serialize_unit_variant(name, index, variant)
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+		let value = self.checked(value);
+		self.inner.serialize_newtype_struct(name, &value)
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(
+		self,
+		name: &'static str,
+		index: u32,
+		variant: &'static str,
+		value: &T,
+	) -> Result<S::Ok, S::Error> {
+		let ctx = self.ctx;
+		let value = self.checked(value);
+		ctx.nested(Segment::Field(variant), || self.inner.serialize_newtype_variant(name, index, variant, &value))
+	}
+
+	fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_seq(len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_tuple(len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_tuple_struct(name, len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		name: &'static str,
+		index: u32,
+		variant: &'static str,
+		len: usize,
+	) -> Result<Self::SerializeTupleVariant, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_tuple_variant(name, index, variant, len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_map(len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_struct(name, len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn serialize_struct_variant(
+		self,
+		name: &'static str,
+		index: u32,
+		variant: &'static str,
+		len: usize,
+	) -> Result<Self::SerializeStructVariant, S::Error> {
+		let ctx = self.ctx;
+		self.inner.serialize_struct_variant(name, index, variant, len).map(|inner| Compound::new(inner, ctx))
+	}
+
+	fn is_human_readable(&self) -> bool {
+		self.inner.is_human_readable()
+	}
+}
+
+/// Sequence, map or struct being serialized; tracks the path of each element.
+struct Compound<'c, C> {
+	inner: C,
+	ctx: &'c Context,
+	index: usize,
+	key: Option<String>,
+}
+
+impl<'c, C> Compound<'c, C> {
+	fn new(inner: C, ctx: &'c Context) -> Self {
+		Compound { inner, ctx, index: 0, key: None }
+	}
+
+	fn next_index(&mut self) -> Segment {
+		self.index += 1;
+		Segment::Index(self.index - 1)
+	}
+}
+
+macro_rules! impl_indexed {
+	($($tr:ident::$method:ident),* $(,)?) => {
+		$(
+			impl<C: ser::$tr> ser::$tr for Compound<'_, C> {
+				type Ok = C::Ok;
+				type Error = C::Error;
+
+				fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+					let ctx = self.ctx;
+					let segment = self.next_index();
+					ctx.nested(segment, || self.inner.$method(&Checked { value, ctx }))
+				}
+
+				fn end(self) -> Result<C::Ok, C::Error> {
+					self.inner.end()
+				}
+			}
+		)*
+	};
+}
+
+impl_indexed! {
+	SerializeSeq::serialize_element,
+	SerializeTuple::serialize_element,
+	SerializeTupleStruct::serialize_field,
+	SerializeTupleVariant::serialize_field,
+}
+
+macro_rules! impl_named {
+	($($tr:ident),* $(,)?) => {
+		$(
+			impl<C: ser::$tr> ser::$tr for Compound<'_, C> {
+				type Ok = C::Ok;
+				type Error = C::Error;
+
+				fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+					let ctx = self.ctx;
+					ctx.nested(Segment::Field(key), || self.inner.serialize_field(key, &Checked { value, ctx }))
+				}
+
+				fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+					self.inner.skip_field(key)
+				}
+
+				fn end(self) -> Result<C::Ok, C::Error> {
+					self.inner.end()
+				}
+			}
+		)*
+	};
+}
+
+impl_named! {
+	SerializeStruct,
+	SerializeStructVariant,
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<'_, C> {
+	type Ok = C::Ok;
+	type Error = C::Error;
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+		let ctx = self.ctx;
+		let label = key_label(key);
+		self.key = Some(label.clone());
+
+		ctx.nested(Segment::Key(label), || self.inner.serialize_key(&Checked { value: key, ctx }))
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+		let ctx = self.ctx;
+		let label = self.key.take().unwrap_or_default();
+
+		ctx.nested(Segment::Key(label), || self.inner.serialize_value(&Checked { value, ctx }))
+	}
+
+	fn end(self) -> Result<C::Ok, C::Error> {
+		self.inner.end()
+	}
+}
+
+/// Human-readable representation of a map key, for use in paths.
+fn key_label<T: Serialize + ?Sized>(key: &T) -> String {
+	match serde_json::to_value(key) {
+		Ok(JsValue::String(key)) => key,
+		Ok(key) => key.to_string(),
+		Err(_) => String::from("?"),
+	}
+}
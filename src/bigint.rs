@@ -0,0 +1,69 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+/// Key of the JSON object which represents a `BigInt` on its way from Rust to JS.
+const BIGINT_KEY: &str = "__rust_bigint";
+
+/// An integer which is represented as a `BigInt` on the JavaScript side.
+///
+/// Plain Rust integers become JS numbers, which cannot exactly represent magnitudes beyond 2<sup>53</sup>. Wrapping a value in
+/// `BigInt` makes it arrive as a JS `BigInt` instead.
+///
+/// In the other direction, every JS `BigInt` is converted to its decimal string representation. This string can be deserialized
+/// into a `BigInt<i128>` or `BigInt<u128>` for exact arithmetic in Rust, or into a `String` if the value may exceed 128 bits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BigInt<T = i128>(pub T);
+
+impl<T: fmt::Display> Serialize for BigInt<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(1))?;
+		map.serialize_entry(BIGINT_KEY, &self.0.to_string())?;
+		map.end()
+	}
+}
+
+impl<'de, T: FromStr> Deserialize<'de> for BigInt<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		deserializer.deserialize_any(BigIntVisitor(PhantomData))
+	}
+}
+
+struct BigIntVisitor<T>(PhantomData<T>);
+
+impl<T> BigIntVisitor<T> {
+	fn parse<E: de::Error>(v: &str) -> Result<BigInt<T>, E>
+	where
+		T: FromStr,
+	{
+		v.parse()
+			.map(BigInt)
+			.map_err(|_| E::custom(format!("integer '{}' is out of range for {}", v, std::any::type_name::<T>())))
+	}
+}
+
+impl<'de, T: FromStr> Visitor<'de> for BigIntVisitor<T> {
+	type Value = BigInt<T>;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("a BigInt in decimal string representation, or an integer")
+	}
+
+	fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+		Self::parse(&v.to_string())
+	}
+
+	fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+		Self::parse(&v.to_string())
+	}
+
+	fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+		Self::parse(v)
+	}
+}
@@ -0,0 +1,948 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use deno_core::error::generic_error;
+
+use crate::compat;
+use crate::console::ConsoleLimit;
+use crate::engine;
+use crate::gas;
+use crate::heap;
+use crate::module::SandboxLoader;
+use crate::ops::{FastFn, HostFn};
+use crate::{AnyError, Capability, CompatEnv, CompiledScript, FetchOptions, JsError, JsValue, SandboxPolicy, Script, VirtualFs, WasmOptions};
+
+/// Configures and creates a [`Script`](struct.Script.html).
+///
+/// Obtained through [`Script::builder()`](struct.Script.html#method.builder). All settings are optional; a builder without any
+/// settings creates the same script as [`Script::from_string()`](struct.Script.html#method.from_string).
+///
+/// ```rust
+/// use js_sandbox::{Script, AnyError};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let mut script = Script::builder()
+/// 		.add_fn("double", |arg| Ok((2 * arg.as_i64().unwrap_or(0)).into()))
+/// 		.op_limit("double", 10)
+/// 		.build("function quadruple(a) { return host.double(host.double(a)); }")?;
+///
+/// 	let result: i32 = script.call("quadruple", &5, None)?;
+/// 	assert_eq!(result, 20);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct ScriptBuilder {
+	host_fns: Vec<(String, Box<HostFn>)>,
+	fast_fns: Vec<(String, Box<FastFn>)>,
+	op_limits: HashMap<String, u64>,
+	source_name: Option<String>,
+	line_offset: u32,
+	compat_env: CompatEnv,
+	node_compat: bool,
+	deno_compat: bool,
+	lexical_globals: Vec<String>,
+	cpu_profile: bool,
+	console_limit: Option<(u64, Duration)>,
+	pure_sandbox: bool,
+	freeze_builtins: bool,
+	protect_host: bool,
+	allowed_calls: Option<HashSet<String>>,
+	source_transforms: Vec<Box<SourceTransform>>,
+	gas_limit: Option<u64>,
+	json_reviver: Option<String>,
+	json_replacer: Option<String>,
+	max_source_bytes: Option<u64>,
+	ops_namespace: Option<String>,
+	time_steps: Option<(i64, u64)>,
+	deterministic: Option<(u64, i64)>,
+	timers: bool,
+	fetch: Option<FetchOptions>,
+	filesystem: Option<VirtualFs>,
+	web_globals: bool,
+	wasm: Option<WasmOptions>,
+	wasm_modules: HashMap<String, Vec<u8>>,
+	message_channel: bool,
+	policy: Option<SandboxPolicy>,
+	stack_trace_limit: Option<u32>,
+	source_map: Option<String>,
+	sticky_globals: Vec<(String, JsValue)>,
+	init_timeout_ms: Option<u64>,
+	initial_heap_size: Option<usize>,
+	max_heap_size: Option<usize>,
+	v8_flags: Option<Vec<String>>,
+	strict_mode: bool,
+	locale: Option<String>,
+	timezone: Option<String>,
+	typescript: bool,
+	module_root: Option<PathBuf>,
+	allowed_imports: Option<Vec<PathBuf>>,
+}
+
+type SourceTransform = dyn Fn(&str) -> Result<String, AnyError>;
+
+/// Top-level code of the script being built.
+enum Entry<'a> {
+	Code {
+		js_code: &'a str,
+		js_filename: &'a str,
+		code_cache: Option<&'a [u8]>,
+	},
+	Module(&'a Path),
+}
+
+impl ScriptBuilder {
+	const PROFILE_INTERVAL_US: u64 = 100;
+
+	/// Creates a builder with default settings.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers a Rust function, which JavaScript can invoke as `host.<name>(arg)`.
+	///
+	/// Unlike [`Script::add_fn()`](struct.Script.html#method.add_fn), the function is already available while the script's top-level
+	/// code is executed.
+	pub fn add_fn<F>(mut self, name: &str, f: F) -> Self
+	where
+		F: Fn(JsValue) -> Result<JsValue, AnyError> + 'static,
+	{
+		self.host_fns.push((name.to_string(), Box::new(f)));
+		self
+	}
+
+	/// Registers a numeric Rust function, which JavaScript can invoke as `host.<name>(x)` with minimal overhead.
+	///
+	/// See [`Script::add_fast_fn()`](struct.Script.html#method.add_fast_fn). Like with [`add_fn()`](#method.add_fn), the
+	/// function is already available while the script's top-level code is executed.
+	pub fn add_fast_fn<F>(mut self, name: &str, f: F) -> Self
+	where
+		F: Fn(f64) -> f64 + 'static,
+	{
+		self.fast_fns.push((name.to_string(), Box::new(f)));
+		self
+	}
+
+	/// Restricts which functions the host may invoke, e.g. `allow_calls(&["onTick", "onEvent"])`.
+	///
+	/// Calls to any other name, through [`Script::call()`](struct.Script.html#method.call) and its variants, fail with
+	/// [`JsError::NotAllowed`](enum.JsError.html#variant.NotAllowed) without executing anything, even if the script defines
+	/// such a function. Names must match exactly. This keeps the host from accidentally invoking internal functions of an
+	/// untrusted script. Functions obtained as [handles](struct.JsFunctionHandle.html) from an allowed call can be invoked.
+	/// Multiple invocations add to the allowed set. Without this setting, every function can be called.
+	pub fn allow_calls(mut self, fn_names: &[&str]) -> Self {
+		self.allowed_calls
+			.get_or_insert_with(HashSet::new)
+			.extend(fn_names.iter().map(|name| name.to_string()));
+		self
+	}
+
+	/// Sets the name of the global object holding the host functions, e.g. `ops_namespace("engine")` for `engine.spawn(arg)`.
+	///
+	/// Applies to all functions registered via [`add_fn()`](#method.add_fn) and [`add_fast_fn()`](#method.add_fast_fn), on the
+	/// builder as well as on the script later. Such functions are still referred to by their plain name, e.g. in
+	/// [`op_limit()`](#method.op_limit). [`protect_host_globals()`](#method.protect_host_globals) then protects this global
+	/// instead of `host`. Without this setting, the namespace is `host`. Building fails if `name` is not a valid identifier.
+	pub fn ops_namespace(mut self, name: &str) -> Self {
+		self.ops_namespace = Some(name.to_string());
+		self
+	}
+
+	/// Prevents the script from shadowing or overwriting the `host` global and the functions registered on it.
+	///
+	/// Without this, a script declaring `var host = {}` silently replaces the host functions, and later calls fail in obscure
+	/// ways. With it, assigning to `host` or one of its functions throws a `TypeError`, also in sloppy mode, and redeclaring
+	/// `host` with `let`, `const`, `class` or `function` is rejected as well; at the top level, this makes building the script
+	/// fail. Functions registered later via [`Script::add_fn()`](struct.Script.html#method.add_fn) are protected too.
+	/// New properties can still be added to `host`.
+	pub fn protect_host_globals(mut self) -> Self {
+		self.protect_host = true;
+		self
+	}
+
+	/// Limits how often the host function `name` may be invoked during a single call.
+	///
+	/// Once a script exceeds the limit, its execution is aborted and the call fails with
+	/// [`JsError::OpLimitExceeded`](enum.JsError.html#variant.OpLimitExceeded), which names the function.
+	/// The count starts from zero for every call into the script.
+	pub fn op_limit(mut self, name: &str, max_calls: u64) -> Self {
+		self.op_limits.insert(name.to_string(), max_calls);
+		self
+	}
+
+	/// Defines a minimal `process` global, for code written for Node.js.
+	///
+	/// Only `process.env`, `process.platform` and an empty `process.argv` are available, backed by the values in `env`. **This is
+	/// not Node**: everything else (`require`, `Buffer`, `process.exit()`, ...) is absent, and no real environment of the host
+	/// is exposed. When combined with [`deno_compat()`](#method.deno_compat), the `env` passed last is used for both.
+	pub fn node_compat(mut self, env: CompatEnv) -> Self {
+		self.compat_env = env;
+		self.node_compat = true;
+		self
+	}
+
+	/// Adds minimal `Deno.env` and `Deno.build` members, for code written for Deno.
+	///
+	/// Only `Deno.env.get()`, `Deno.env.toObject()` and `Deno.build.os` are available, backed by the values in `env`. **This is
+	/// not Deno**: no other runtime APIs are present, and no real environment of the host is exposed. When combined with
+	/// [`node_compat()`](#method.node_compat), the `env` passed last is used for both.
+	pub fn deno_compat(mut self, env: CompatEnv) -> Self {
+		self.compat_env = env;
+		self.deno_compat = true;
+		self
+	}
+
+	/// Makes top-level `let`, `const` and `class` bindings accessible as properties of `globalThis`.
+	///
+	/// Such bindings are visible to subsequent calls anyway, but unlike `var` declarations, they are not properties of the global
+	/// object. After the top-level code has run, each binding in `names` is exposed through a `globalThis` accessor that reads
+	/// (and for `let`, writes) the binding itself. Since JavaScript offers no way to enumerate lexical bindings, they must be
+	/// named explicitly. Building the script fails if one of them is not declared, or not a valid identifier.
+	pub fn expose_lexical_globals(mut self, names: &[&str]) -> Self {
+		self.lexical_globals.extend(names.iter().map(|name| name.to_string()));
+		self
+	}
+
+	/// Restricts the script to pure computation, for running maximally untrusted code such as user-supplied formulas.
+	///
+	/// All globals which reach the host or behave nondeterministically are removed before the script's code runs: `console`,
+	/// `Date`, `Intl`, `Math.random()`, `SharedArrayBuffer`, `Atomics`, `WebAssembly`, the internal `Deno` object, as well as
+	/// timers, `fetch()`, `crypto`, `performance` and `addEventListener()` where present. Using one of them throws a `ReferenceError` or `TypeError`,
+	/// which the script can catch like any other exception. What remains are the ECMAScript primitives (numbers, strings, arrays,
+	/// `JSON`, `Math` etc.) and host functions registered explicitly via [`add_fn()`](#method.add_fn).
+	///
+	/// The preset takes precedence over [`node_compat()`](#method.node_compat) and [`deno_compat()`](#method.deno_compat).
+	/// Although the script cannot create dates itself, [`Date`](struct.Date.html) values passed by the host still arrive as JS
+	/// `Date` objects.
+	pub fn pure_sandbox(mut self) -> Self {
+		self.pure_sandbox = true;
+		self
+	}
+
+	/// Provides `fetch()` and `Headers`, for scripts which need to call HTTP APIs.
+	///
+	/// Requires the `http` feature of js-sandbox; without it, building fails. Every request is made by the host, and only if
+	/// `options` allow it: the URL must use HTTPS (unless `allow_http` is set) and point to one of the allowed domains, and the
+	/// request and response bodies must not exceed their size limits. Otherwise, the promise returned by `fetch()` is rejected.
+	/// Redirects are not followed, but returned to the script, since they could lead to any domain.
+	///
+	/// Requests block the script's thread until they complete or their timeout elapses. Their timeout is shortened to what is
+	/// left of the call's timeout, and once that has elapsed, `fetch()` fails right away. As `fetch()` returns a promise, use
+	/// [`Script::call_async()`](struct.Script.html#method.call_async) to await it. The implementation covers the commonly used part of the Fetch API: requests with method, headers and a string
+	/// or binary body, and responses with status, headers, `text()`, `json()` and `arrayBuffer()`.
+	pub fn fetch(mut self, options: FetchOptions) -> Self {
+		self.fetch = Some(options);
+		self
+	}
+
+	/// Provides the `fs` global, through which the script can read and write the files of `fs`.
+	///
+	/// The script only ever sees the [virtual filesystem](struct.VirtualFs.html) chosen by the host, never the host's real files.
+	/// All operations are synchronous and take paths relative to its root: `fs.readFile(path)` returns a `Uint8Array`,
+	/// `fs.readTextFile(path)` a string decoded as UTF-8; `fs.writeFile(path, data)` accepts a string or binary data, and
+	/// `fs.writeTextFile(path, text)` a string; `fs.exists(path)`, `fs.remove(path)` and `fs.readDir(path)` complete the set.
+	/// Failing operations, e.g. for missing files or writes to a read-only filesystem, throw an `Error`.
+	pub fn filesystem(mut self, fs: VirtualFs) -> Self {
+		self.filesystem = Some(fs);
+		self
+	}
+
+	/// Provides the web platform globals which many libraries expect: `TextEncoder`, `TextDecoder`, `URL`, `URLSearchParams`,
+	/// `atob()`, `btoa()` and `structuredClone()`.
+	///
+	/// They are implemented in JavaScript and never reach the host, so they are unaffected by a [sandbox policy](#method.policy)
+	/// and the [pure sandbox](#method.pure_sandbox). Text encoding is limited to UTF-8, and `TextDecoder` does not support
+	/// streaming. `structuredClone()` copies primitives, plain objects, arrays, `Date`, `RegExp`, `Map`, `Set`, errors,
+	/// `ArrayBuffer` and its views, preserving cycles; other values such as functions throw an error named `DataCloneError`.
+	pub fn web_globals(mut self) -> Self {
+		self.web_globals = true;
+		self
+	}
+
+	/// Limits the WebAssembly modules which the script compiles itself, e.g. with `WebAssembly.instantiate(bytes, imports)`.
+	///
+	/// Without this setting, the `WebAssembly` API is available without restrictions. With it, module binaries exceeding the
+	/// size limit of `options` are rejected before they are compiled, and so are modules which define a linear memory without
+	/// a maximum size, or with one above the memory limit. `new WebAssembly.Memory()` underlies the same memory limit. Since
+	/// memories cannot grow beyond their declared maximum, this bounds the memory of every instance, which is not part of the
+	/// V8 heap limited by [`max_heap_size()`](#method.max_heap_size). The streaming variants `compileStreaming()` and
+	/// `instantiateStreaming()` are removed. Rejected modules throw an `Error`, or reject the returned promise.
+	///
+	/// The [pure sandbox](#method.pure_sandbox) removes `WebAssembly` altogether.
+	pub fn wasm(mut self, options: WasmOptions) -> Self {
+		self.wasm = Some(options);
+		self
+	}
+
+	/// Registers a WebAssembly module, which JavaScript can obtain as `host.wasmModule(name)`.
+	///
+	/// `bytes` is the module's binary, shipped by the host alongside the script's JS glue code. The first call to
+	/// `host.wasmModule(name)` compiles it to a `WebAssembly.Module`, which is returned by later calls as well; the script then
+	/// instantiates it with its own imports, e.g. `new WebAssembly.Instance(host.wasmModule("physics"), imports)`. As the host
+	/// chooses these modules, the limits set by [`wasm()`](#method.wasm) do not apply to them. Registering a module again under
+	/// the same name replaces it. `wasmModule()` belongs to the [host namespace](#method.ops_namespace), and counts as a host
+	/// function for a [sandbox policy](#method.policy).
+	pub fn add_wasm_module(mut self, name: &str, bytes: impl Into<Vec<u8>>) -> Self {
+		self.wasm_modules.insert(name.to_string(), bytes.into());
+		self
+	}
+
+	/// Opens a message channel between script and host, for event-driven plugins in which neither side blocks on the other.
+	///
+	/// The script sends messages with `host.postMessage(value)`, which the host collects with
+	/// [`Script::poll_messages()`](struct.Script.html#method.poll_messages) or receives through a
+	/// [`Script::message_handler()`](struct.Script.html#method.message_handler). The host sends messages with
+	/// [`Script::post_message()`](struct.Script.html#method.post_message), which the script receives by assigning a function to
+	/// the global `onmessage`, like in a web worker: it is invoked with an event whose `data` is the message. Messages are
+	/// converted like arguments and results of calls. `postMessage()` belongs to the [host namespace](#method.ops_namespace), and
+	/// counts as a host function for a [sandbox policy](#method.policy).
+	pub fn message_channel(mut self) -> Self {
+		self.message_channel = true;
+		self
+	}
+
+	/// Restricts the capabilities of the script to those granted by `policy`; see [`SandboxPolicy`](struct.SandboxPolicy.html).
+	///
+	/// The policy is applied after the other setup, such as the [compatibility shims](#method.node_compat), and takes precedence
+	/// over it: for example, [`timers()`](#method.timers) has no effect unless the policy grants
+	/// [`Capability::Timers`](enum.Capability.html#variant.Timers), which in turn enables timers on its own. Building the script
+	/// fails if host functions were registered, but the policy does not grant [`Capability::HostFns`](enum.Capability.html#variant.HostFns).
+	/// Setting a policy again replaces the previous one.
+	pub fn policy(mut self, policy: SandboxPolicy) -> Self {
+		self.policy = Some(policy);
+		self
+	}
+
+	/// Freezes the built-in prototypes and constructors before the script's code runs, as a guard against prototype pollution.
+	///
+	/// Untrusted code could otherwise add properties to `Object.prototype` or replace methods such as `JSON.stringify`, and
+	/// thereby change the data that other code -- including the host, reading call results -- sees. With this setting,
+	/// `Object`, `Array`, `Function`, the other built-in constructors and their prototypes, as well as `JSON`, `Math` and
+	/// `Reflect`, are frozen. Modifying them throws a `TypeError` in strict mode, which the script can catch; in sloppy mode,
+	/// adding a property is silently ignored instead, as for any frozen object.
+	///
+	/// Scripts extending built-in prototypes, e.g. polyfills defining `Array.prototype.last`, no longer work. Objects can still
+	/// override inherited methods: assigning `obj.toString`, `this.name` in an `Error` subclass and other properties that
+	/// are commonly overridden defines them on the object, while assigning them on the prototype itself throws. Settings such
+	/// as `Error.stackTraceLimit` are frozen as well; use [`stack_trace_limit()`](#method.stack_trace_limit) instead.
+	/// Combines with [`pure_sandbox()`](#method.pure_sandbox) for running maximally untrusted code.
+	pub fn freeze_builtins(mut self) -> Self {
+		self.freeze_builtins = true;
+		self
+	}
+
+	/// Limits how many `console` messages the script may emit within a period of time.
+	///
+	/// At most `max_lines` messages are output within each period `per`; further messages until the end of the period are
+	/// dropped, without affecting the script otherwise. Use [`Script::console_dropped()`](struct.Script.html#method.console_dropped)
+	/// to find out whether and how many messages have been suppressed. This protects the host's logging from floods.
+	pub fn console_rate_limit(mut self, max_lines: u64, per: Duration) -> Self {
+		self.console_limit = Some((max_lines, per));
+		self
+	}
+
+	/// Records a CPU profile of the script, using V8's sampling profiler.
+	///
+	/// Profiling starts before the top-level code is executed. Use [`Script::take_cpu_profile()`](struct.Script.html#method.take_cpu_profile)
+	/// to obtain the samples. Sampling has a noticeable overhead, so this should not be enabled in production.
+	pub fn cpu_profile(mut self) -> Self {
+		self.cpu_profile = true;
+		self
+	}
+
+	/// Sets the file name and line offset under which the script's code is reported in stack traces and error messages.
+	///
+	/// `line_offset` is the number of lines preceding the code in the original file, i.e. the code's first line is reported as
+	/// line `line_offset + 1`. This is useful when the code is a fragment of a larger file, or has been extracted from a
+	/// template, and errors should point to the position the author sees in their editor. The name takes precedence over the
+	/// file name passed to [`build_file()`](#method.build_file); by default, it is "sandboxed.js" or that file name respectively.
+	/// V8 limits the offset to `i32::MAX`; building fails for larger ones.
+	pub fn source_name_and_offset(mut self, name: &str, line_offset: u32) -> Self {
+		self.source_name = Some(name.to_string());
+		self.line_offset = line_offset;
+		self
+	}
+
+	/// Sets how many frames stack traces include, instead of V8's default of 10.
+	///
+	/// Applies to `error.stack` inside the script (by setting `Error.stackTraceLimit`) as well as to errors surfaced to Rust, whose
+	/// messages and frames then reach deeper into nested calls. Raising the limit helps diagnosing errors in complex scripts, at a
+	/// small cost whenever an error is created.
+	pub fn stack_trace_limit(mut self, frames: u32) -> Self {
+		self.stack_trace_limit = Some(frames);
+		self
+	}
+
+	/// Translates positions in errors back to the original sources, using the source map `map_json` of the script's code.
+	///
+	/// Meant for code that was bundled or transpiled, e.g. by esbuild or webpack: the locations, stack frames and stack traces of
+	/// errors then refer to the original files and lines, as listed in the map, instead of the generated code. Only positions
+	/// within the script's own code are translated. `map_json` is a source map of version 3, as written by common tools; building
+	/// fails if it cannot be decoded.
+	///
+	/// Without this setting, a source map embedded in the code as a data URL -- a last line like
+	/// `//# sourceMappingURL=data:application/json;base64,...`, which bundlers emit for inline source maps -- is used instead.
+	/// The same applies to [`Script::add_source()`](struct.Script.html#method.add_source). ES modules are not translated.
+	pub fn source_map(mut self, map_json: &str) -> Self {
+		self.source_map = Some(map_json.to_string());
+		self
+	}
+
+	/// Rewrites the script's source code before it is compiled, e.g. to instrument loops or wrap functions for tracing.
+	///
+	/// `transform` receives the code passed to [`build()`](#method.build) or loaded by [`build_file()`](#method.build_file), and
+	/// returns the code to be executed instead. If it fails, building the script fails with its error, before any code runs.
+	/// Multiple transforms are applied in the order they were added. Positions in error messages and the
+	/// [source index](struct.Script.html#method.source_index) refer to the transformed code. js-sandbox's own setup code, such
+	/// as the [compatibility shims](#method.node_compat), is not passed to the transform.
+	pub fn source_transform<F>(mut self, transform: F) -> Self
+	where
+		F: Fn(&str) -> Result<String, AnyError> + 'static,
+	{
+		self.source_transforms.push(Box::new(transform));
+		self
+	}
+
+	/// Replaces the clock by a deterministic sequence, which advances by `step_ms` on every read.
+	///
+	/// The first read of the current time -- `Date.now()`, `new Date()` or `Date()` -- returns `start_ms` milliseconds since the
+	/// Unix epoch, the next one `start_ms + step_ms`, and so on. This makes time-based logic such as rate limits or expiry fully
+	/// reproducible in tests. A `step_ms` of 0 freezes the time. Dates constructed from explicit values, as well as
+	/// [`Date`](struct.Date.html) arguments passed from Rust, are not affected. Without this setting, the real time is used.
+	/// Has no effect in a [pure sandbox](#method.pure_sandbox), which removes `Date` altogether.
+	pub fn time_steps(mut self, start_ms: i64, step_ms: u64) -> Self {
+		self.time_steps = Some((start_ms, step_ms));
+		self
+	}
+
+	/// Makes the script deterministic, for lockstep simulations and replays: the same calls yield the same results on every run.
+	///
+	/// `Math.random()` returns a pseudo-random sequence determined by `seed`. The current time -- `Date.now()`, `new Date()`,
+	/// `Date()` and `performance.now()` -- is a virtual clock, which starts at `start_ms` milliseconds since the Unix epoch
+	/// (`performance.now()` at 0) and stands still until the host calls
+	/// [`Script::advance_time()`](struct.Script.html#method.advance_time). The clock is shared with [timers](#method.timers),
+	/// if enabled. Takes precedence over [`time_steps()`](#method.time_steps). Dates constructed from explicit values, as well
+	/// as [`Date`](struct.Date.html) arguments passed from Rust, are not affected.
+	///
+	/// Other sources of nondeterminism, such as the iteration order of `WeakMap`s or the timing of garbage collection, are not
+	/// observable in JavaScript and need no treatment. Host functions must be deterministic themselves.
+	pub fn deterministic(mut self, seed: u64, start_ms: i64) -> Self {
+		self.deterministic = Some((seed, start_ms));
+		self
+	}
+
+	/// Provides `setTimeout()`, `setInterval()`, `clearTimeout()` and `clearInterval()`, driven by a clock under the host's control.
+	///
+	/// Bare scripts have no timers, so code relying on them throws a `ReferenceError`. With this setting, timers can be scheduled
+	/// as usual, but time only passes for them when the host calls [`Script::advance_time()`](struct.Script.html#method.advance_time),
+	/// which runs the callbacks that become due. This keeps scripts fully deterministic: a test can step through a debounce or
+	/// retry logic in simulated time, without waiting. The timer clock starts at 0 and is independent of `Date`; combine with
+	/// [`time_steps()`](#method.time_steps) to control the latter as well. Has no effect in a [pure sandbox](#method.pure_sandbox),
+	/// which removes timers.
+	pub fn timers(mut self) -> Self {
+		self.timers = true;
+		self
+	}
+
+	/// Limits the amount of work the script may do per call, independent of wall-clock time.
+	///
+	/// The script's code is instrumented so that every loop iteration and every function invocation consumes one unit of gas.
+	/// Each call, as well as the top-level code, starts with `limit` units; once they are used up, execution is aborted and the
+	/// call fails with [`JsError::GasExhausted`](enum.JsError.html#variant.GasExhausted). Unlike a timeout, the outcome is
+	/// deterministic: the same call with the same arguments always succeeds or fails in the same way, regardless of machine load.
+	///
+	/// Since code compiled at runtime would escape metering, `eval()` and the `Function` constructors throw `EvalError`.
+	/// Instrumentation works on tokens, not a full parse: the script must not use the identifier `__rust`, `with` statements or
+	/// escape sequences in identifiers, otherwise building fails. Work done inside built-in functions (e.g. sorting a huge
+	/// array) and in host functions is not metered, so combine this with a timeout when that matters. The instrumentation is
+	/// applied after [`source_transform()`](#method.source_transform), and columns in error messages refer to the instrumented code.
+	pub fn gas_limit(mut self, limit: u64) -> Self {
+		self.gas_limit = Some(limit);
+		self
+	}
+
+	/// Customizes how JSON received from Rust is turned into JS values.
+	///
+	/// `js_function` is a JS expression evaluating to a function, which is used as the reviver of `JSON.parse()` for every value
+	/// passed into JS: [call](struct.Script.html#method.call) arguments as well as results of host functions. It runs after the
+	/// crate's own conversions, so it receives [`BigInt`](struct.BigInt.html) and [`Date`](struct.Date.html) values already
+	/// converted. This allows to represent types that JSON lacks, e.g. turning a `{ "__map__": [[k, v], ...] }` marker into a `Map`.
+	/// The expression is evaluated once, before the script's code runs.
+	pub fn json_reviver(mut self, js_function: &str) -> Self {
+		self.json_reviver = Some(js_function.to_string());
+		self
+	}
+
+	/// Customizes how JS values sent to Rust are turned into JSON.
+	///
+	/// The counterpart to [`json_reviver()`](#method.json_reviver): `js_function` evaluates to a function used as the replacer of
+	/// `JSON.stringify()` for every value leaving JS, i.e. call results and host function arguments. It runs before the crate's
+	/// own conversion of `BigInt`s. As with plain `JSON.stringify()`, objects having a `toJSON()` method, such as `Date`, are
+	/// converted before the replacer sees them.
+	pub fn json_replacer(mut self, js_function: &str) -> Self {
+		self.json_replacer = Some(js_function.to_string());
+		self
+	}
+
+	/// Rejects source code larger than `limit` bytes, before it is compiled.
+	///
+	/// Building then fails with [`JsError::SourceTooLarge`](enum.JsError.html#variant.SourceTooLarge), stating the actual and the
+	/// allowed size. [`build_file()`](#method.build_file) checks the file's size before reading it. This is a cheap defense
+	/// against oversized uploads, which would otherwise cost compile time and memory. The limit applies to the code as given,
+	/// not to the result of a [source transform](#method.source_transform).
+	pub fn max_source_bytes(mut self, limit: u64) -> Self {
+		self.max_source_bytes = Some(limit);
+		self
+	}
+
+	/// Limits how long the script's top-level code may run while the script is created.
+	///
+	/// Top-level code runs before any function is called, so the timeouts of calls do not guard it. If it exceeds `timeout_ms`
+	/// milliseconds, execution is aborted and creating the script fails with [`JsError::InitTimeout`](enum.JsError.html#variant.InitTimeout).
+	/// [`Script::reset()`](struct.Script.html#method.reset) re-runs the top-level code without this limit. Without this setting,
+	/// top-level code may run indefinitely.
+	pub fn init_timeout(mut self, timeout_ms: u64) -> Self {
+		self.init_timeout_ms = Some(timeout_ms);
+		self
+	}
+
+	/// Starts the script's V8 heap with a size of `bytes`, instead of V8's small default.
+	///
+	/// V8 grows the heap on demand, collecting garbage each time it runs full. A script that quickly builds up a large working
+	/// set thus triggers many collections and resizes early on; starting with a heap of about that size avoids them. This
+	/// only affects performance, not what the script may allocate.
+	///
+	/// The initial size must not exceed the maximum heap size, which is set by [`max_heap_size()`](#method.max_heap_size) or
+	/// else V8's default limit for the machine (see
+	/// [`HeapStats::heap_size_limit`](struct.HeapStats.html#structfield.heap_size_limit)); otherwise building fails. The
+	/// script gets an isolate of its own, so a runtime prepared by [`enable_isolate_reuse()`](fn.enable_isolate_reuse.html)
+	/// is not used. The first script built with this setting also determines V8's default limit, by creating a runtime once.
+	pub fn initial_heap_size(mut self, bytes: usize) -> Self {
+		self.initial_heap_size = Some(bytes);
+		self
+	}
+
+	/// Limits the script's V8 heap to `bytes`, so that a script cannot exhaust the memory of the host process.
+	///
+	/// Once the heap approaches the limit and garbage collection does not free enough memory, execution is aborted and the
+	/// running call fails with [`JsError::HeapLimitExceeded`](enum.JsError.html#variant.HeapLimitExceeded); without this
+	/// setting, V8 would abort the whole process instead. To let the script unwind, the limit is temporarily raised, so the
+	/// heap may briefly grow beyond it. The original limit is restored with the next call, and applies in full once garbage
+	/// collection shrinks the heap again. Memory outside the heap, such as `ArrayBuffer` contents, is not covered.
+	///
+	/// The script stays usable after the error, but its globals may be in an inconsistent state; consider a
+	/// [reset](struct.Script.html#method.reset). V8 needs a few MB for itself, so very small limits make building fail. Like
+	/// with [`initial_heap_size()`](#method.initial_heap_size), the script gets an isolate of its own.
+	pub fn max_heap_size(mut self, bytes: usize) -> Self {
+		self.max_heap_size = Some(bytes);
+		self
+	}
+
+	/// Sets command-line flags of the V8 engine before the script is created, for example `--jitless` for tighter sandboxing.
+	///
+	/// Behaves like [`set_v8_flags()`](fn.set_v8_flags.html), including its caveat: **V8 flags are process-global and can only
+	/// be set once**, before the first script of the process is created. Building fails otherwise, unless exactly these flags
+	/// are already set -- so all builders of a process may carry the same flags, and the first build applies them. This keeps
+	/// the flags next to the other settings of the scripts which depend on them.
+	pub fn v8_flags(mut self, flags: &[&str]) -> Self {
+		self.v8_flags = Some(flags.iter().map(|flag| flag.to_string()).collect());
+		self
+	}
+
+	/// Runs the script's code in strict mode, as if it started with a `"use strict";` directive.
+	///
+	/// Strict mode turns silent mistakes into errors, e.g. assigning to undeclared variables or to read-only properties, and
+	/// applies to the top-level code and every function it defines. The directive is inserted as a line of its own, which
+	/// reported positions account for. Since the code thus differs from the one compiled in advance, a script built with
+	/// [`build_compiled()`](#method.build_compiled) compiles it anew. ES modules always run in strict mode; for them, this
+	/// setting has no effect.
+	pub fn strict_mode(mut self) -> Self {
+		self.strict_mode = true;
+		self
+	}
+
+	/// Sets the default locale of locale-aware formatting, e.g. `"de-CH"`, instead of the host's locale.
+	///
+	/// Applies to the `Intl` constructors, `toLocaleString()` and similar methods of dates, numbers, strings and arrays, as
+	/// well as `localeCompare()`, whenever the script does not pass a locale itself. This makes output independent of the
+	/// machine the script runs on. Building fails if `locale` is not a valid BCP 47 language tag.
+	pub fn locale(mut self, locale: &str) -> Self {
+		self.locale = Some(locale.to_string());
+		self
+	}
+
+	/// Sets the default time zone of locale-aware date formatting, e.g. `"UTC"` or `"Europe/Zurich"`, instead of the host's.
+	///
+	/// Applies to `Intl.DateTimeFormat` and the `toLocaleString()`, `toLocaleDateString()` and `toLocaleTimeString()` methods
+	/// of `Date`, whenever the script does not pass a `timeZone` option itself. `Intl.DateTimeFormat().resolvedOptions().timeZone`
+	/// reports it as well. Other `Date` methods, such as `getHours()` and `toString()`, keep using the host's time zone,
+	/// since V8 takes it from the process. Building fails if `timezone` is not an IANA time zone name known to V8.
+	pub fn timezone(mut self, timezone: &str) -> Self {
+		self.timezone = Some(timezone.to_string());
+		self
+	}
+
+	/// Defines the global `name` as a constant, before the script's top-level code runs.
+	///
+	/// Behaves like [`Script::set_sticky_global()`](struct.Script.html#method.set_sticky_global): the value is deeply frozen and
+	/// defined anew on every [reset](struct.Script.html#method.reset), again before the top-level code. Multiple constants
+	/// are defined in the order of invocation; a repeated name replaces the value.
+	pub fn sticky_global(mut self, name: &str, value: JsValue) -> Self {
+		match self.sticky_globals.iter_mut().find(|(existing, _)| existing == name) {
+			Some((_, existing)) => *existing = value,
+			None => self.sticky_globals.push((name.to_string(), value)),
+		}
+		self
+	}
+
+	/// Treats the script's source code as TypeScript, which is transpiled to JavaScript before it is compiled.
+	///
+	/// Requires the `typescript` feature of js-sandbox, which uses [swc](https://swc.rs) like Deno does; without it, building
+	/// fails. Types are stripped without type checking. Files loaded by [`build_file()`](#method.build_file) and
+	/// [`build_module()`](#method.build_module) with the extension `.ts` are transpiled anyway.
+	///
+	/// The transpiled code keeps each statement on the line it has in the TypeScript source, so line numbers in stack traces
+	/// and error messages can be looked up there; columns may differ. Transpilation happens before any
+	/// [source transform](#method.source_transform), which thus receives JavaScript.
+	pub fn typescript(mut self) -> Self {
+		self.typescript = true;
+		self
+	}
+
+	/// Sets the directory to which imports of ES modules are restricted; see [`build_module()`](#method.build_module).
+	///
+	/// Without this setting, it is the directory of the entry module.
+	pub fn module_root(mut self, dir: impl AsRef<Path>) -> Self {
+		self.module_root = Some(dir.as_ref().to_path_buf());
+		self
+	}
+
+	/// Restricts which files ES modules may import, e.g. `allow_imports(&["lib/math.js", "lib/text.js"])`.
+	///
+	/// Paths are relative to the [module root](#method.module_root). Importing any other file fails, and so does building the
+	/// script. Multiple invocations add to the allowed files. Without this setting, all files below the module root can be imported.
+	pub fn allow_imports(mut self, paths: &[&str]) -> Self {
+		self.allowed_imports
+			.get_or_insert_with(Vec::new)
+			.extend(paths.iter().map(PathBuf::from));
+		self
+	}
+
+	/// Creates a script by loading an ES module from a .js file, together with the modules it imports.
+	///
+	/// Static and dynamic `import`s are resolved relative to the importing module, and must be relative paths such as
+	/// `"./util.js"`; package names and URLs are not supported. Only files below the [module root](#method.module_root) and
+	/// permitted by [`allow_imports()`](#method.allow_imports) can be imported. Top-level `await` is supported.
+	///
+	/// After evaluation, each export of the entry module is defined as a global of the same name, e.g. `export function run()`
+	/// as `run`, so it can be invoked with [`Script::call()`](struct.Script.html#method.call); a default export is named `default`.
+	/// Module code runs in strict mode, and its own declarations are not visible to calls otherwise. [`Script::reset()`](struct.Script.html#method.reset)
+	/// does not evaluate modules again. [`max_source_bytes()`](#method.max_source_bytes) applies to every module file,
+	/// [`init_timeout()`](#method.init_timeout) to the evaluation of all of them. Building fails if a
+	/// [source transform](#method.source_transform) or [gas limit](#method.gas_limit) is set, as they do not apply to modules.
+	pub fn build_module(self, file: impl AsRef<Path>) -> Result<Script, AnyError> {
+		if !self.source_transforms.is_empty() || self.gas_limit.is_some() {
+			return Err(generic_error("source transforms and gas limits are not supported for ES modules"));
+		}
+		self.build_entry(Entry::Module(file.as_ref()))
+	}
+
+	/// Creates a script from the given JavaScript source code.
+	///
+	/// Fails in case of syntax or initialization error with the code.
+	pub fn build(self, js_code: &str) -> Result<Script, AnyError> {
+		self.build_named(js_code, Script::DEFAULT_FILENAME)
+	}
+
+	/// Creates a script by loading it from a .js file.
+	///
+	/// Fails if the file cannot be opened or in case of syntax or initialization error with the code.
+	pub fn build_file(mut self, file: impl AsRef<Path>) -> Result<Script, AnyError> {
+		let filename = file
+			.as_ref()
+			.file_name()
+			.and_then(|s| s.to_str())
+			.unwrap_or(Script::DEFAULT_FILENAME)
+			.to_owned();
+
+		if self.max_source_bytes.is_some() {
+			self.check_source_size(std::fs::metadata(&file)?.len())?;
+		}
+		if file.as_ref().extension().map_or(false, |extension| extension == "ts") {
+			self.typescript = true;
+		}
+
+		let modified = std::fs::metadata(&file).and_then(|metadata| metadata.modified()).ok();
+		let mut script = match std::fs::read_to_string(&file) {
+			Ok(js_code) => self.build_named(&js_code, &filename)?,
+			Err(e) => return Err(AnyError::from(e)),
+		};
+
+		script.set_source_file(file.as_ref().to_path_buf(), modified);
+		Ok(script)
+	}
+
+	/// Creates a script by reading its source code from `reader` until its end.
+	///
+	/// `source_name` is the file name under which the code appears in stack traces. The limit set by
+	/// [`max_source_bytes()`](#method.max_source_bytes) is checked while reading, so an oversized stream is not buffered entirely; the size reported in the error is then one byte
+	/// over the limit, as the rest of the stream is never read.
+	///
+	/// Fails if reading fails or the source is not valid UTF-8, or in case of syntax or initialization error with the code.
+	pub fn build_reader(self, mut reader: impl Read, source_name: &str) -> Result<Script, AnyError> {
+		let mut bytes = Vec::new();
+		let read = match self.max_source_bytes {
+			Some(limit) => reader.take(limit.saturating_add(1)).read_to_end(&mut bytes),
+			None => reader.read_to_end(&mut bytes),
+		};
+
+		if let Err(e) = read {
+			return Err(AnyError::from(e).context(format!("failed to read source of '{}'", source_name)));
+		}
+		self.check_source_size(bytes.len() as u64)?;
+
+		match String::from_utf8(bytes) {
+			Ok(js_code) => self.build_named(&js_code, source_name),
+			Err(e) => Err(AnyError::from(e).context(format!("source of '{}' is not valid UTF-8", source_name))),
+		}
+	}
+
+	fn check_source_size(&self, size: u64) -> Result<(), JsError> {
+		match self.max_source_bytes {
+			Some(limit) if size > limit => Err(JsError::SourceTooLarge { size, limit }),
+			_ => Ok(()),
+		}
+	}
+
+	/// Creates a script from source code compiled in advance, which saves compiling it again.
+	///
+	/// See [`CompiledScript`](struct.CompiledScript.html). If a [source transform](#method.source_transform), a
+	/// [gas limit](#method.gas_limit) or [strict mode](#method.strict_mode) changes the code, the compiled form does not match
+	/// and the code is compiled normally.
+	pub fn build_compiled(self, compiled: &CompiledScript) -> Result<Script, AnyError> {
+		let code_cache = Some(compiled.code_cache()).filter(|code_cache| !code_cache.is_empty());
+		self.build_cached(compiled.js_code(), compiled.js_filename(), code_cache)
+	}
+
+	fn build_named(self, js_code: &str, js_filename: &str) -> Result<Script, AnyError> {
+		self.build_cached(js_code, js_filename, None)
+	}
+
+	fn build_cached(self, js_code: &str, js_filename: &str, code_cache: Option<&[u8]>) -> Result<Script, AnyError> {
+		self.check_source_size(js_code.len() as u64)?;
+
+		let mut transformed_code = None;
+		if self.typescript {
+			transformed_code = Some(transpile_typescript(js_filename, js_code, false)?);
+		}
+		for transform in &self.source_transforms {
+			transformed_code = Some(transform(transformed_code.as_deref().unwrap_or(js_code))?);
+		}
+		if self.gas_limit.is_some() {
+			transformed_code = Some(gas::instrument(transformed_code.as_deref().unwrap_or(js_code))?);
+		}
+		let js_code = transformed_code.as_deref().unwrap_or(js_code);
+
+		// Transformed code does not match the compiled form
+		let code_cache = code_cache.filter(|_| transformed_code.is_none());
+		self.build_entry(Entry::Code { js_code, js_filename, code_cache })
+	}
+
+	fn build_entry(self, entry: Entry) -> Result<Script, AnyError> {
+		if let Some(flags) = &self.v8_flags {
+			engine::require_v8_flags(flags)?;
+		}
+
+		let policy = self.policy.as_ref();
+		let allows = |capability| policy.map_or(true, |policy| policy.allows(capability));
+		if !allows(Capability::HostFns) && (!self.host_fns.is_empty() || !self.fast_fns.is_empty() || !self.wasm_modules.is_empty() || self.message_channel) {
+			return Err(generic_error("host functions are not allowed by the sandbox policy"));
+		}
+		let timers = match &self.policy {
+			Some(policy) => policy.allows(Capability::Timers),
+			None => self.timers,
+		};
+		let fetch_allowed = allows(Capability::Fetch);
+		let fetch = self.fetch.filter(|_| fetch_allowed);
+		let filesystem_allowed = allows(Capability::FileSystem);
+		let filesystem = self.filesystem.filter(|_| filesystem_allowed);
+
+		let module_loader = match entry {
+			Entry::Module(path) => {
+				let allowed = self.allowed_imports.as_deref();
+				let loader = SandboxLoader::new(path, self.module_root.as_deref(), allowed, self.max_source_bytes)?;
+				let specifier = loader.entry_specifier(path)?;
+				Some((Rc::new(loader), specifier))
+			}
+			Entry::Code { .. } => None,
+		};
+
+		let mut script = match (self.initial_heap_size, self.max_heap_size, &module_loader) {
+			(None, None, None) => Script::new_runtime()?,
+			(initial, max, loader) => {
+				let create_params = match (initial, max) {
+					(None, None) => None,
+					(initial, max) => Some(heap::heap_params(initial, max)?),
+				};
+				let loader = loader.as_ref().map(|(loader, _)| loader.clone() as Rc<dyn deno_core::ModuleLoader>);
+				Script::create_runtime_with(create_params, loader)?
+			}
+		};
+		if let Some(limit) = self.max_heap_size {
+			script.enforce_heap_limit(limit);
+		}
+		script.set_op_limits(self.op_limits);
+		if let Some(allowed_calls) = self.allowed_calls {
+			script.set_allowed_calls(allowed_calls);
+		}
+		if let Some((max_lines, per)) = self.console_limit {
+			script.set_console_limit(ConsoleLimit::new(max_lines, per));
+		}
+		if let Some(frames) = self.stack_trace_limit {
+			script.set_stack_trace_limit(frames)?;
+		}
+
+		let namespace = self.ops_namespace.as_deref().unwrap_or(Script::HOST_NAMESPACE);
+		script.set_host_namespace(namespace)?;
+		for (name, f) in self.host_fns {
+			script.add_boxed_fn(namespace, &name, f)?;
+		}
+		for (name, f) in self.fast_fns {
+			script.add_boxed_fast_fn(&name, f)?;
+		}
+		if self.protect_host {
+			script.protect_host_globals()?;
+		}
+
+		if self.cpu_profile {
+			script.start_cpu_profile(Self::PROFILE_INTERVAL_US)?;
+		}
+
+		if self.node_compat || self.deno_compat {
+			let compat_code = compat::compat_code(&self.compat_env, self.node_compat, self.deno_compat)?;
+			script.execute_internal(&compat_code)?;
+			if self.node_compat {
+				script.add_feature("polyfill", "node")?;
+			}
+			if self.deno_compat {
+				script.add_feature("polyfill", "deno")?;
+			}
+		}
+
+		// Every read of the current time advances the clock by a fixed step
+		if let Some((start_ms, step_ms)) = self.time_steps {
+			let now = format!("(() => {{ let reads = 0; return () => {} + {} * reads++; }})()", start_ms, step_ms);
+			let js_code = format!("(\n{}\n)(globalThis, {});", include_str!("time.js"), now);
+			script.execute_internal(&js_code)?;
+		}
+
+		// Time only passes in Script::advance_time(), which moves the timer clock
+		if let Some((seed, start_ms)) = self.deterministic {
+			let time_js = format!("(\n{}\n)(globalThis, () => {} + clock());", include_str!("time.js"), start_ms);
+			let seed = format!("[{}, {}]", seed >> 32, seed & 0xffff_ffff);
+			let random_js = format!("(\n{}\n)(globalThis, {}, clock);", include_str!("deterministic.js"), seed);
+			script.execute_internal(&format!("{{ const clock = __rust.useTimerClock();\n{}\n{} }}", time_js, random_js))?;
+		}
+
+		if timers {
+			script.execute_internal("__rust.installTimers();")?;
+			script.add_feature("polyfill", "timers")?;
+		}
+
+		if let Some(options) = fetch {
+			script.enable_fetch(options)?;
+		}
+
+		if let Some(fs) = filesystem {
+			script.enable_filesystem(fs)?;
+		}
+
+		if self.web_globals {
+			script.enable_web_globals()?;
+		}
+
+		if self.locale.is_some() || self.timezone.is_some() {
+			script.set_locale_defaults(self.locale.as_deref(), self.timezone.as_deref())?;
+		}
+
+		if self.wasm.is_some() || !self.wasm_modules.is_empty() {
+			script.enable_wasm(self.wasm, self.wasm_modules)?;
+		}
+
+		if self.message_channel {
+			script.enable_messages()?;
+		}
+
+		if let Some(policy) = &self.policy {
+			script.execute_internal(&policy.js_code()?)?;
+			if !policy.allows(Capability::HostFns) {
+				script.deny_host_fns();
+			}
+		}
+
+		if self.pure_sandbox {
+			script.execute_internal(include_str!("pure.js"))?;
+		}
+
+		if self.json_reviver.is_some() || self.json_replacer.is_some() {
+			script.set_json_hooks(self.json_reviver.as_deref(), self.json_replacer.as_deref())?;
+		}
+
+		if let Some(limit) = self.gas_limit {
+			script.enable_gas(limit)?;
+		}
+
+		// After all other setup code, which may still modify built-ins
+		if self.freeze_builtins {
+			script.execute_internal(include_str!("frozen.js"))?;
+		}
+
+		for (name, value) in &self.sticky_globals {
+			script.set_sticky_global(name, value)?;
+		}
+
+		match (entry, module_loader) {
+			(Entry::Code { js_code, js_filename, code_cache }, _) => {
+				let js_filename = self.source_name.as_deref().unwrap_or(js_filename);
+				match &self.source_map {
+					Some(map_json) => script.add_source_map(js_filename, map_json, self.line_offset)?,
+					None => script.add_inline_source_map(js_filename, js_code, self.line_offset),
+				}
+				let requested_offset = self.line_offset;
+				let line_offset = i32::try_from(requested_offset)
+					.map_err(|_| generic_error(format!("line offset {} exceeds the maximum of {}", requested_offset, i32::MAX)))?;
+				if self.strict_mode {
+					// The directive takes a line of its own, which is subtracted from the offset again
+					let strict_code = format!("\"use strict\";\n{}", js_code);
+					script.execute_with_timeout(js_filename, &strict_code, line_offset - 1, None, self.init_timeout_ms, JsError::InitTimeout)?;
+				} else {
+					script.execute_with_timeout(js_filename, js_code, line_offset, code_cache, self.init_timeout_ms, JsError::InitTimeout)?;
+				}
+			}
+			(Entry::Module(_), Some((_, specifier))) => script.execute_module(&specifier, self.init_timeout_ms)?,
+			(Entry::Module(_), None) => unreachable!("Modules are built with a module loader"),
+		}
+
+		for name in self.lexical_globals {
+			script.expose_lexical_global(&name)?;
+		}
+
+		Ok(script)
+	}
+}
+
+#[cfg(feature = "typescript")]
+pub(crate) fn transpile_typescript(ts_filename: &str, ts_code: &str, is_module: bool) -> Result<String, AnyError> {
+	crate::typescript::transpile(ts_filename, ts_code, is_module)
+}
+
+#[cfg(not(feature = "typescript"))]
+pub(crate) fn transpile_typescript(ts_filename: &str, _ts_code: &str, _is_module: bool) -> Result<String, AnyError> {
+	Err(generic_error(format!("cannot load '{}': TypeScript requires the `typescript` feature of js-sandbox", ts_filename)))
+}
@@ -0,0 +1,239 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Binary data crosses the boundary next to the JSON, as raw buffers passed to ops.
+// Bytes serializes as plain serde bytes. When converting arguments, args::to_json() stages such bytes on the current thread
+// and writes a marker with the buffer's index into the JSON instead; the call moves the staged buffers into the op state,
+// from where JS copies them into fresh Uint8Arrays. In the other direction,
+// JS hands each binary value to an op and replaces it by a marker; the received buffers are moved to the current thread
+// before the result is deserialized. Arguments converted on one thread for a call on another are detached together with their
+// staged buffers, which are staged anew when the call converts them on its own thread.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use deno_core::error::generic_error;
+use deno_core::{OpState, ZeroCopyBuf};
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::{AnyError, JsValue};
+
+/// Key of the JSON object which represents binary data in either direction.
+const BYTES_KEY: &str = "__rust_bytes";
+
+thread_local! {
+	/// Buffers staged by args::to_json() since the last call on this thread, not yet handed to a script.
+	static OUTGOING: RefCell<Vec<Vec<u8>>> = RefCell::new(Vec::new());
+
+	/// Buffers received from the last call on this thread, taken when deserialized.
+	static INCOMING: RefCell<Vec<Option<Vec<u8>>>> = RefCell::new(Vec::new());
+}
+
+/// Binary data which is represented as a `Uint8Array` on the JavaScript side.
+///
+/// Plain `Vec<u8>` becomes a JS array of numbers, which is slow and large for blobs such as images. Wrapping it in `Bytes` makes
+/// it arrive as a `Uint8Array` instead. The buffer is transferred as a whole, without encoding it as text.
+///
+/// In the other direction, every `ArrayBuffer`, typed array and `DataView` which is part of a call's result is transferred the
+/// same way, as its raw bytes. Receive it as `Bytes`; other types see an opaque marker object in its place. A `Bytes` can also
+/// be deserialized from an array of numbers.
+///
+/// `Bytes` is supported in the arguments and results of calls, when serialized directly as (part of) the argument. Elsewhere,
+/// it serializes like any serde byte array; a `JsValue` converted from a `Bytes` beforehand holds an array of numbers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for Bytes {
+	fn from(bytes: Vec<u8>) -> Self {
+		Bytes(bytes)
+	}
+}
+
+impl From<Bytes> for Vec<u8> {
+	fn from(bytes: Bytes) -> Self {
+		bytes.0
+	}
+}
+
+impl Serialize for Bytes {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_bytes(&self.0)
+	}
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		deserializer.deserialize_any(BytesVisitor)
+	}
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+	type Value = Bytes;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("binary data, or an array of bytes")
+	}
+
+	fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+		Ok(Bytes(v.to_vec()))
+	}
+
+	fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+		Ok(Bytes(v))
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+		while let Some(byte) = seq.next_element::<u8>()? {
+			bytes.push(byte);
+		}
+		Ok(Bytes(bytes))
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+		let index = match map.next_key::<String>()? {
+			Some(key) if key == BYTES_KEY => map.next_value::<usize>()?,
+			_ => return Err(de::Error::custom("expected binary data")),
+		};
+
+		let bytes = INCOMING.with(|incoming| incoming.borrow_mut().get_mut(index).and_then(Option::take));
+		bytes.map(Bytes).ok_or_else(|| de::Error::custom("binary data is no longer available"))
+	}
+}
+
+/// Buffers to be copied into the script during a call, stored in the op state.
+pub(crate) struct Outgoing(Vec<Option<Vec<u8>>>);
+
+/// Buffers received from the script during a call, stored in the op state.
+#[derive(Default)]
+pub(crate) struct Incoming(Vec<Option<Vec<u8>>>);
+
+/// Stages `bytes` for the next call, and serializes the marker which refers to them in its arguments.
+pub(crate) fn serialize_staged<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+	let index = OUTGOING.with(|outgoing| {
+		let mut outgoing = outgoing.borrow_mut();
+		outgoing.push(bytes.to_vec());
+		outgoing.len() - 1
+	});
+
+	let mut map = serializer.serialize_map(Some(1))?;
+	map.serialize_entry(BYTES_KEY, &(index, bytes.len()))?;
+	map.end()
+}
+
+/// Arguments converted by args::to_json(), detached from the current thread together with the buffers staged for them.
+///
+/// Serializing it, as part of a call on another thread, stages the buffers on that thread and yields the same markers.
+pub(crate) struct Detached {
+	value: JsValue,
+	buffers: Vec<Vec<u8>>,
+}
+
+/// Detaches `value`, which was just returned by args::to_json() on this thread, along with the buffers it refers to.
+pub(crate) fn detach(value: JsValue) -> Detached {
+	let buffers = OUTGOING.with(|outgoing| outgoing.borrow_mut().drain(..).collect());
+	Detached { value, buffers }
+}
+
+impl Serialize for Detached {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		Restaged { value: &self.value, buffers: &self.buffers }.serialize(serializer)
+	}
+}
+
+/// Part of a detached value, with markers replaced by the bytes they refer to.
+struct Restaged<'a> {
+	value: &'a JsValue,
+	buffers: &'a [Vec<u8>],
+}
+
+impl Serialize for Restaged<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let nested = |value| Restaged { value, buffers: self.buffers };
+
+		match self.value {
+			JsValue::Object(object) => {
+				if let Some(bytes) = marked_buffer(object, self.buffers) {
+					return serializer.serialize_bytes(bytes);
+				}
+
+				let mut map = serializer.serialize_map(Some(object.len()))?;
+				for (key, value) in object {
+					map.serialize_entry(key, &nested(value))?;
+				}
+				map.end()
+			}
+			JsValue::Array(array) => {
+				let mut seq = serializer.serialize_seq(Some(array.len()))?;
+				for value in array {
+					seq.serialize_element(&nested(value))?;
+				}
+				seq.end()
+			}
+			value => value.serialize(serializer),
+		}
+	}
+}
+
+/// Returns the buffer which `object` refers to, if it is a marker written by serialize_staged().
+fn marked_buffer<'a>(object: &serde_json::Map<String, JsValue>, buffers: &'a [Vec<u8>]) -> Option<&'a Vec<u8>> {
+	if object.len() != 1 {
+		return None;
+	}
+
+	let index = object.get(BYTES_KEY)?.get(0)?.as_u64()?;
+	buffers.get(index as usize)
+}
+
+/// Discards buffers staged by earlier conversions, which no call picked up.
+pub(crate) fn clear_outgoing() {
+	OUTGOING.with(|outgoing| outgoing.borrow_mut().clear());
+}
+
+/// Moves the buffers staged on this thread into `state`, for the call about to start.
+pub(crate) fn begin_call(state: &mut OpState) {
+	let outgoing = OUTGOING.with(|outgoing| outgoing.borrow_mut().drain(..).map(Some).collect());
+	state.put(Outgoing(outgoing));
+	state.put(Incoming::default());
+}
+
+/// Moves the buffers received during the finished call to this thread, for deserializing the result.
+pub(crate) fn finish_call(state: &mut OpState) {
+	let incoming = state.try_take::<Incoming>().unwrap_or_default();
+	INCOMING.with(|buffers| *buffers.borrow_mut() = incoming.0);
+}
+
+/// Copies an outgoing buffer into the `Uint8Array` allocated for it by JS.
+pub(crate) fn op_bytes_in(state: &mut OpState, index: usize, buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let bytes = state
+		.try_borrow_mut::<Outgoing>()
+		.and_then(|outgoing| outgoing.0.get_mut(index))
+		.and_then(Option::take)
+		.ok_or_else(|| generic_error("binary data is no longer available"))?;
+	if bytes.is_empty() {
+		return Ok(JsValue::Null);
+	}
+
+	let mut buf = buf.ok_or_else(|| generic_error("no buffer to receive binary data"))?;
+	if buf.len() != bytes.len() {
+		return Err(generic_error("buffer size does not match binary data"));
+	}
+	buf.copy_from_slice(&bytes);
+
+	Ok(JsValue::Null)
+}
+
+/// Receives a binary value from JS, and returns its index for the marker.
+pub(crate) fn op_bytes_out(state: &mut OpState, _args: (), buf: Option<ZeroCopyBuf>) -> Result<usize, AnyError> {
+	let bytes = buf.map_or_else(Vec::new, |buf| buf.to_vec());
+
+	if state.try_borrow::<Incoming>().is_none() {
+		state.put(Incoming::default());
+	}
+	let incoming = state.borrow_mut::<Incoming>();
+	incoming.0.push(Some(bytes));
+	Ok(incoming.0.len() - 1)
+}
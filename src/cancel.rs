@@ -0,0 +1,119 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::{Arc, Mutex};
+
+use crate::termination::Terminator;
+use crate::JsError;
+
+/// Allows to cancel script execution from any thread.
+///
+/// Attach a token to one or more scripts with [`Script::set_cancellation_token()`](struct.Script.html#method.set_cancellation_token).
+/// Calling [`cancel()`](#method.cancel) then terminates whatever those scripts are executing, and makes the affected calls
+/// fail with [`JsError::Cancelled`](enum.JsError.html#variant.Cancelled). Like in most token-based ecosystems, cancellation
+/// is permanent: calls started afterwards fail immediately, until another token is attached.
+///
+/// Tokens are cheap to clone; all clones refer to the same cancellation state. To integrate with other cancellation
+/// mechanisms (e.g. `tokio_util::sync::CancellationToken`), call `cancel()` once the other token is cancelled.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+	state: Arc<Mutex<TokenState>>,
+}
+
+#[derive(Default)]
+struct TokenState {
+	cancelled: bool,
+	next_id: u64,
+	running: Vec<(u64, Terminator)>,
+}
+
+impl CancellationToken {
+	/// Creates a token which is not cancelled.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Cancels the token, terminating all calls currently running in scripts it is attached to.
+	pub fn cancel(&self) {
+		let mut state = self.state.lock().unwrap();
+		state.cancelled = true;
+
+		for (_, terminator) in state.running.iter() {
+			terminator.terminate(JsError::Cancelled);
+		}
+	}
+
+	/// Whether [`cancel()`](#method.cancel) has been called on this token or one of its clones.
+	pub fn is_cancelled(&self) -> bool {
+		self.state.lock().unwrap().cancelled
+	}
+
+	/// Registers a call that is about to start, and returns its id; fails if the token is already cancelled.
+	fn register(&self, terminator: &Terminator) -> Result<u64, JsError> {
+		let mut state = self.state.lock().unwrap();
+		if state.cancelled {
+			return Err(JsError::Cancelled);
+		}
+
+		let id = state.next_id;
+		state.next_id += 1;
+		state.running.push((id, terminator.clone()));
+
+		Ok(id)
+	}
+}
+
+/// Aborts the call currently running in a script, from any thread.
+///
+/// Obtained with [`Script::termination_handle()`](struct.Script.html#method.termination_handle), e.g. to stop a long-running
+/// call on shutdown or Ctrl-C, or when a user gives up waiting. [`terminate()`](#method.terminate) makes the running call fail
+/// with [`JsError::Cancelled`](enum.JsError.html#variant.Cancelled). Unlike a [`CancellationToken`](struct.CancellationToken.html),
+/// this affects only the call running at that moment: if none is running, nothing happens, and later calls run normally.
+///
+/// Handles are cheap to clone, and can be sent to other threads.
+#[derive(Clone, Default)]
+pub struct TerminationHandle {
+	running: Arc<Mutex<Option<Terminator>>>,
+}
+
+impl TerminationHandle {
+	/// Terminates the call currently running in the script, and returns whether there was one.
+	pub fn terminate(&self) -> bool {
+		match &*self.running.lock().unwrap() {
+			Some(terminator) => {
+				terminator.terminate(JsError::Cancelled);
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// Call that can be aborted through the script's termination handle, and possibly a token, until this is dropped.
+pub(crate) struct Registration {
+	token: Option<(CancellationToken, u64)>,
+	handle: TerminationHandle,
+}
+
+impl Registration {
+	/// Registers a call that is about to start; fails if `token` is already cancelled.
+	pub fn new(terminator: &Terminator, handle: &TerminationHandle, token: Option<&CancellationToken>) -> Result<Self, JsError> {
+		let token = match token {
+			Some(token) => Some((token.clone(), token.register(terminator)?)),
+			None => None,
+		};
+
+		*handle.running.lock().unwrap() = Some(terminator.clone());
+		Ok(Registration { token, handle: handle.clone() })
+	}
+}
+
+impl Drop for Registration {
+	fn drop(&mut self) {
+		self.handle.running.lock().unwrap().take();
+
+		if let Some((token, id)) = &self.token {
+			let mut state = token.state.lock().unwrap();
+			state.running.retain(|(running, _)| running != id);
+		}
+	}
+}
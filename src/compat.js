@@ -0,0 +1,30 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Minimal stand-ins for the `process` (Node) and `Deno` globals, to ease porting existing code.
+// Only the members below are provided, backed by values from the host. This is NOT a real Node or Deno environment.
+
+((globalThis, config) => {
+	const env = Object.freeze(Object.assign(Object.create(null), config.env));
+
+	if (config.node) {
+		Object.defineProperty(globalThis, "process", {
+			value: Object.freeze({
+				env,
+				platform: config.platform,
+				argv: Object.freeze([]),
+			}),
+			writable: true,
+			configurable: true,
+		});
+	}
+
+	if (config.deno) {
+		Object.assign(globalThis.Deno, {
+			env: Object.freeze({
+				get: key => env[key],
+				toObject: () => ({ ...env }),
+			}),
+			build: Object.freeze({ os: config.platform }),
+		});
+	}
+})
@@ -0,0 +1,34 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Values exposed by the partial `process` and `Deno` shims.
+///
+/// See [`ScriptBuilder::node_compat()`](struct.ScriptBuilder.html#method.node_compat) and
+/// [`ScriptBuilder::deno_compat()`](struct.ScriptBuilder.html#method.deno_compat). By default, all values are empty: no
+/// environment variables are visible, and the platform is `""`. The host's real environment is never exposed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompatEnv {
+	/// Environment variables, visible as `process.env` or through `Deno.env`.
+	pub env: HashMap<String, String>,
+	/// Platform name, visible as `process.platform` or `Deno.build.os`.
+	pub platform: String,
+}
+
+#[derive(Serialize)]
+struct CompatConfig<'a> {
+	node: bool,
+	deno: bool,
+	env: &'a HashMap<String, String>,
+	platform: &'a str,
+}
+
+/// Returns JS code which installs the requested shims.
+pub(crate) fn compat_code(env: &CompatEnv, node: bool, deno: bool) -> Result<String, serde_json::Error> {
+	let config = CompatConfig { node, deno, env: &env.env, platform: &env.platform };
+	let config = serde_json::to_string(&config)?;
+
+	Ok(format!("{}(globalThis, {});", include_str!("compat.js").trim_end(), config))
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::Arc;
+
+use crate::{AnyError, Script, ScriptBuilder};
+
+/// JavaScript source code compiled once, from which scripts can be created repeatedly.
+///
+/// Creating a [`Script`](struct.Script.html) from a string parses and compiles the whole source. When many scripts are
+/// created from the same source, e.g. a fresh instance of a stateless plugin per request, `CompiledScript` does this work only
+/// once: [`compile()`](#method.compile) keeps V8's code cache of the source, which [`instantiate()`](#method.instantiate)
+/// passes to each new isolate. All functions are compiled eagerly for the cache, so they do not need compiling on their first
+/// call either.
+///
+/// Only compiling is skipped: each instance still gets an isolate of its own and runs the top-level code, since it has state
+/// of its own. For skipping that as well, reuse one script and [`reset()`](struct.Script.html#method.reset) it. V8 only accepts
+/// the cache from the same V8 version and flags; otherwise, instances silently compile the source from scratch, with identical
+/// behavior.
+///
+/// The compiled form holds no isolate, only bytes: it is `Send` and `Sync`, and cloning is cheap, so it can be shared among threads.
+/// To reuse it across process restarts, store the [`code_cache()`](#method.code_cache) next to the source, and restore it with
+/// [`from_code_cache()`](#method.from_code_cache).
+///
+/// ```rust
+/// use js_sandbox::{AnyError, CompiledScript};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let js_code = "function triple(x) { return 3 * x; }";
+/// 	let code_cache = CompiledScript::compile(js_code)?.code_cache().to_vec();
+///
+/// 	// Later, e.g. after reading the cache from disk
+/// 	let compiled = CompiledScript::from_code_cache(js_code, code_cache);
+/// 	let result: i32 = compiled.instantiate()?.call("triple", &7, None)?;
+/// 	assert_eq!(result, 21);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompiledScript {
+	inner: Arc<Compiled>,
+}
+
+#[derive(Debug)]
+struct Compiled {
+	js_code: String,
+	js_filename: String,
+	code_cache: Vec<u8>,
+}
+
+impl CompiledScript {
+	/// Compiles the given JavaScript source code, without running it.
+	///
+	/// Fails in case of syntax error. Errors that only occur when the top-level code is run are reported by
+	/// [`instantiate()`](#method.instantiate) instead.
+	pub fn compile(js_code: &str) -> Result<Self, AnyError> {
+		let js_filename = Script::DEFAULT_FILENAME;
+		let mut script = Script::new_runtime()?;
+		let code_cache = script.compile_with_cache(js_filename, js_code, true)?;
+
+		Ok(CompiledScript {
+			inner: Arc::new(Compiled {
+				js_code: js_code.to_string(),
+				js_filename: js_filename.to_string(),
+				code_cache,
+			}),
+		})
+	}
+
+	/// Restores the compiled form of `js_code` from a cache previously obtained by [`code_cache()`](#method.code_cache).
+	///
+	/// The cache is not checked here. V8 validates it when a script is instantiated, and rejects it if it belongs to a different
+	/// source, V8 version or set of flags, or if it is corrupted; instances then compile the source from scratch, with identical
+	/// behavior. A stale cache thus means compiling again, but never changes what the script does.
+	pub fn from_code_cache(js_code: &str, code_cache: Vec<u8>) -> Self {
+		CompiledScript {
+			inner: Arc::new(Compiled {
+				js_code: js_code.to_string(),
+				js_filename: Script::DEFAULT_FILENAME.to_string(),
+				code_cache,
+			}),
+		}
+	}
+
+	/// Creates a script in a fresh isolate, and runs its top-level code.
+	///
+	/// Equivalent to [`Script::from_string()`](struct.Script.html#method.from_string) with the compiled source, but without
+	/// compiling it again. Use [`ScriptBuilder::build_compiled()`](struct.ScriptBuilder.html#method.build_compiled) to configure
+	/// the script.
+	pub fn instantiate(&self) -> Result<Script, AnyError> {
+		ScriptBuilder::new().build_compiled(self)
+	}
+
+	/// Size of V8's code cache in bytes; 0 if V8 could not produce one.
+	pub fn cache_size(&self) -> usize {
+		self.inner.code_cache.len()
+	}
+
+	pub(crate) fn js_code(&self) -> &str {
+		&self.inner.js_code
+	}
+
+	pub(crate) fn js_filename(&self) -> &str {
+		&self.inner.js_filename
+	}
+
+	/// V8's code cache, which can be stored to skip compiling in another process; see [`from_code_cache()`](#method.from_code_cache).
+	///
+	/// Empty if V8 could not produce a cache.
+	pub fn code_cache(&self) -> &[u8] {
+		&self.inner.code_cache
+	}
+}
@@ -0,0 +1,130 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime};
+
+use deno_core::{OpState, ZeroCopyBuf};
+use serde::Deserialize;
+
+use crate::json;
+use crate::{AnyError, JsValue};
+
+/// Severity of a message logged from JavaScript, corresponding to the `console` method that was used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+	/// `console.debug()`
+	Debug,
+	/// `console.info()`
+	Info,
+	/// `console.log()`
+	Log,
+	/// `console.warn()`
+	Warn,
+	/// `console.error()`
+	Error,
+}
+
+/// A single message logged from JavaScript through one of the `console` methods.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord {
+	/// Which `console` method was used.
+	pub level: LogLevel,
+	/// The logged arguments, converted to strings and separated by spaces.
+	///
+	/// `console.dir()` formats its argument as indented JSON, and `console.table()` renders rows of its argument as a text
+	/// table. Inside a `console.group()`, each line is indented by two spaces per level.
+	pub message: String,
+	/// The logged arguments themselves, converted to JSON like the arguments of [`Script::call()`](struct.Script.html#method.call), for
+	/// formatting messages in the handler. Arguments without a JSON representation, such as functions, are `null`.
+	pub args: Vec<JsValue>,
+	/// Point in time at which the message was logged.
+	pub timestamp: SystemTime,
+}
+
+/// Console output captured during a call, as returned by [`Script::call_with_output()`](struct.Script.html#method.call_with_output).
+///
+/// Messages are split like the output of a process: each stream preserves the order in which its messages were logged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Output {
+	/// Messages from `console.log()`, `console.info()` and `console.debug()`.
+	pub stdout: Vec<String>,
+	/// Messages from `console.warn()` and `console.error()`.
+	pub stderr: Vec<String>,
+}
+
+/// Destination of console output, stored in the op state of each script.
+pub(crate) enum ConsoleSink {
+	Stdout,
+	Channel(Sender<LogRecord>),
+	Handler(Box<dyn FnMut(LogRecord)>),
+	Capture(Output),
+}
+
+/// Rate limit for console output, stored in the op state of each script.
+///
+/// Allows at most `max_lines` messages within each window of length `per`; further messages in the same window are dropped.
+pub(crate) struct ConsoleLimit {
+	max_lines: u64,
+	per: Duration,
+	window_start: Instant,
+	lines_in_window: u64,
+	pub dropped: u64,
+}
+
+impl ConsoleLimit {
+	pub fn new(max_lines: u64, per: Duration) -> Self {
+		ConsoleLimit { max_lines, per, window_start: Instant::now(), lines_in_window: 0, dropped: 0 }
+	}
+
+	/// Counts one message, and returns whether it may be output.
+	fn admit(&mut self) -> bool {
+		let now = Instant::now();
+		if now.duration_since(self.window_start) >= self.per {
+			self.window_start = now;
+			self.lines_in_window = 0;
+		}
+
+		if self.lines_in_window < self.max_lines {
+			self.lines_in_window += 1;
+			true
+		} else {
+			self.dropped += 1;
+			false
+		}
+	}
+}
+
+pub(crate) fn op_console(
+	state: &mut OpState,
+	args: (LogLevel, String, String),
+	_buf: Option<ZeroCopyBuf>,
+) -> Result<JsValue, AnyError> {
+	if let Some(limit) = state.try_borrow_mut::<ConsoleLimit>() {
+		if !limit.admit() {
+			return Ok(JsValue::Null);
+		}
+	}
+
+	let (level, message, raw_args) = args;
+	let args = serde_json::from_value(json::from_js(&raw_args)?)?;
+	let record = LogRecord { level, message, args, timestamp: SystemTime::now() };
+
+	match state.borrow_mut::<ConsoleSink>() {
+		ConsoleSink::Stdout => match record.level {
+			LogLevel::Warn | LogLevel::Error => eprintln!("{}", record.message),
+			_ => println!("{}", record.message),
+		},
+		ConsoleSink::Channel(sender) => {
+			// Receiver may have been dropped; output is then discarded
+			let _ = sender.send(record);
+		}
+		ConsoleSink::Handler(handler) => handler(record),
+		ConsoleSink::Capture(output) => match record.level {
+			LogLevel::Warn | LogLevel::Error => output.stderr.push(record.message),
+			_ => output.stdout.push(record.message),
+		},
+	}
+
+	Ok(JsValue::Null)
+}
@@ -0,0 +1,37 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Key of the JSON object which represents a `Date` on its way from Rust to JS.
+const DATE_KEY: &str = "__rust_date";
+
+/// A point in time which is represented as a `Date` on the JavaScript side.
+///
+/// JavaScript converts every `Date` to an ISO-8601 string in UTC, such as `"2021-03-14T15:09:26.535Z"`, on its way to Rust.
+/// Any type that deserializes from such a string can therefore receive it, for example `String` or `chrono::DateTime<Utc>`.
+/// Wrapping it in `Date` is optional in this direction. An invalid JS date (whose time value is `NaN`) arrives as `null`, which
+/// can be received by `Option<Date<T>>`.
+///
+/// In the other direction, wrapping a value in `Date` makes it arrive as a JS `Date` instead of a plain string or number.
+/// `T` must serialize to either a date string that JavaScript's `Date` constructor understands (ISO-8601 is recommended, as
+/// produced by `chrono`), or a number of milliseconds since the Unix epoch. Strings without timezone offset are interpreted by
+/// JavaScript as local time for date-time forms, and UTC for date-only forms; include a `Z` or explicit offset to avoid ambiguity.
+///
+/// js-sandbox itself does not depend on any date crate, so this works with whichever one your project uses.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date<T = String>(pub T);
+
+impl<T: Serialize> Serialize for Date<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(1))?;
+		map.serialize_entry(DATE_KEY, &self.0)?;
+		map.end()
+	}
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Date<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		T::deserialize(deserializer).map(Date)
+	}
+}
@@ -0,0 +1,39 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Deterministic mode, see ScriptBuilder::deterministic(); Date is replaced by time.js.
+// Evaluates to a function installing a seeded Math.random(), and performance.now() reading the virtual clock.
+
+(globalThis, seed, clock) => {
+	// sfc32 generator, with the seed's high and low 32 bits as state
+	let a = 0x9e3779b9;
+	let b = seed[0] >>> 0;
+	let c = seed[1] >>> 0;
+	let d = 1;
+
+	function next() {
+		const t = (((a + b) | 0) + d) | 0;
+		d = (d + 1) | 0;
+		a = b ^ (b >>> 9);
+		b = (c + (c << 3)) | 0;
+		c = (c << 21) | (c >>> 11);
+		c = (c + t) | 0;
+		return (t >>> 0) / 4294967296;
+	}
+
+	// Mixes the seed into the whole state, so that similar seeds yield unrelated sequences
+	for (let i = 0; i < 16; i++) {
+		next();
+	}
+
+	Object.defineProperty(Math, "random", {
+		value: function random() {
+			return next();
+		},
+		writable: true,
+		configurable: true,
+	});
+
+	const performance = globalThis.performance ?? {};
+	Object.defineProperty(performance, "now", { value: () => clock(), writable: true, configurable: true });
+	Object.defineProperty(globalThis, "performance", { value: performance, writable: true, configurable: true });
+}
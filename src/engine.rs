@@ -0,0 +1,179 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::cell::{Cell, RefCell};
+use std::sync::Mutex;
+
+use deno_core::error::generic_error;
+
+use crate::{AnyError, Script};
+
+/// Process-global state of the V8 engine.
+struct EngineState {
+	/// Whether a JS runtime has been created
+	started: bool,
+	/// Flags passed to V8, once set
+	flags: Option<Vec<String>>,
+}
+
+static ENGINE_STATE: Mutex<EngineState> = Mutex::new(EngineState { started: false, flags: None });
+
+/// Sets command-line flags of the V8 engine, for example `--jitless` or `--max-old-space-size=64`.
+///
+/// **V8 flags are process-global and can only be set once.** This function must be called before the first [`Script`] is created
+/// anywhere in the process, and at most once; otherwise it returns an error without changing anything. The flags then apply to all
+/// scripts created afterwards, on any thread. [`ScriptBuilder::v8_flags()`] offers the same per builder.
+///
+/// Flags are passed on to V8 unchecked. Fails if V8 does not recognize some of them; the recognized ones are applied nonetheless.
+/// Note that some flags may destabilize the engine or conflict with the setup js-sandbox relies on -- use at your own risk.
+///
+/// [`Script`]: struct.Script.html
+/// [`ScriptBuilder::v8_flags()`]: struct.ScriptBuilder.html#method.v8_flags
+pub fn set_v8_flags(flags: &[&str]) -> Result<(), AnyError> {
+	let mut state = ENGINE_STATE.lock().unwrap();
+	if state.flags.is_some() {
+		return Err(generic_error("V8 flags can only be set once per process"));
+	}
+	if state.started {
+		return Err(generic_error("V8 flags must be set before the first script is created"));
+	}
+
+	let flags: Vec<String> = flags.iter().copied().map(String::from).collect();
+	apply_v8_flags(&mut state, flags)
+}
+
+/// Sets the flags of ScriptBuilder::v8_flags(), which succeeds as well if exactly these flags are already set.
+pub(crate) fn require_v8_flags(flags: &[String]) -> Result<(), AnyError> {
+	let mut state = ENGINE_STATE.lock().unwrap();
+	match &state.flags {
+		Some(applied) if applied.as_slice() == flags => Ok(()),
+		Some(applied) => Err(generic_error(format!(
+			"V8 flags can only be set once per process, and are already set to `{}`",
+			applied.join(" ")
+		))),
+		None if state.started => Err(generic_error("V8 flags must be set before the first script is created")),
+		None => apply_v8_flags(&mut state, flags.to_vec()),
+	}
+}
+
+fn apply_v8_flags(state: &mut EngineState, flags: Vec<String>) -> Result<(), AnyError> {
+	// V8 expects a command line, whose first element is the program name
+	let args = std::iter::once("js-sandbox".to_string()).chain(flags.iter().cloned()).collect();
+	state.flags = Some(flags);
+
+	let unrecognized = deno_core::v8_set_flags(args);
+	if unrecognized.len() > 1 {
+		return Err(generic_error(format!("unrecognized V8 flags: {}", unrecognized[1..].join(" "))));
+	}
+
+	Ok(())
+}
+
+/// Called before each JS runtime is created; from then on, flags can no longer be set.
+pub(crate) fn on_runtime_created() {
+	ENGINE_STATE.lock().unwrap().started = true;
+}
+
+/// Versions of the JavaScript engine components that js-sandbox runs on; see [`engine_version()`](fn.engine_version.html).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct EngineVersion {
+	/// Version of the linked V8 library, as reported by V8 itself, e.g. `"9.1.269.5"`.
+	pub v8: String,
+	/// Version series of `deno_core`, e.g. `"0.84"`.
+	pub deno_core: String,
+}
+
+/// Returns the versions of V8 and `deno_core` that js-sandbox runs on.
+///
+/// Useful as part of cache keys, in bug reports, or to gate behavior that differs between engine versions. The V8 version is
+/// queried from the linked library at runtime. `deno_core` does not report its version; the returned value is the version
+/// series it is pinned to by js-sandbox, within which only patch releases can differ.
+pub fn engine_version() -> EngineVersion {
+	EngineVersion {
+		v8: deno_core::v8_version().to_string(),
+		deno_core: DENO_CORE_VERSION.to_string(),
+	}
+}
+
+/// Must match the `deno_core` dependency in Cargo.toml; the `engine_version` test checks it against Cargo.toml and Cargo.lock.
+const DENO_CORE_VERSION: &str = "0.84";
+
+thread_local! {
+	static REUSE_ENABLED: Cell<bool> = const { Cell::new(false) };
+	static SPARE_SCRIPT: RefCell<Option<Script>> = const { RefCell::new(None) };
+	static LIVE_SCRIPTS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Keeps a warmed-up JS runtime ready on the current thread, which the next [`Script`] created on this thread takes over.
+///
+/// Creating the V8 isolate and initializing it is the most expensive part of creating a script. With this enabled, that work
+/// is done ahead of time: right now, and whenever the last script alive on this thread is dropped. In the pattern this is
+/// meant for -- single-threaded servers creating short-lived scripts one after another -- creating a script thus only costs
+/// the execution of its own code, while dropping it pays for preparing the next runtime.
+///
+/// V8 requires the isolates of a thread to be dropped in the reverse order of their creation. A runtime is therefore only
+/// prepared while no other script is alive on this thread, so that it is older than any script which outlives it:
+/// - Scripts created while another one is alive create their isolate as usual, as do scripts with their own isolate
+///   parameters, such as [`ScriptBuilder::initial_heap_size()`](struct.ScriptBuilder.html#method.initial_heap_size).
+/// - Enabling this fails while a script is alive on this thread. Enabling it more than once has no further effect.
+///
+/// **Isolation is not weakened:** each script still receives an isolate that no other script has ever used. Handing over
+/// a used isolate with all globals reset is not possible, since top-level `let`/`const` bindings and `var` declarations cannot
+/// be removed from a JS realm once created, and they would break the next script. The prepared runtime only consumes memory
+/// while it waits; it is discarded when the thread exits.
+///
+/// [`Script`]: struct.Script.html
+pub fn enable_isolate_reuse() -> Result<(), AnyError> {
+	if REUSE_ENABLED.with(Cell::get) {
+		return Ok(());
+	}
+	if LIVE_SCRIPTS.with(Cell::get) > 0 {
+		return Err(generic_error("isolate reuse can only be enabled while no script is alive on this thread"));
+	}
+
+	REUSE_ENABLED.with(|enabled| enabled.set(true));
+	prepare_spare_script()
+}
+
+/// Takes the prepared runtime of this thread, if there is one.
+pub(crate) fn take_spare_script() -> Option<Script> {
+	SPARE_SCRIPT.try_with(|spare| spare.borrow_mut().take()).ok().flatten()
+}
+
+/// Counts a script as alive on its thread, prepared ones included.
+///
+/// Must be the last field of Script, so that it is dropped after the isolate: only then may the next runtime be prepared.
+pub(crate) struct LiveScript(());
+
+impl LiveScript {
+	pub(crate) fn new() -> Self {
+		let _ = LIVE_SCRIPTS.try_with(|live| live.set(live.get() + 1));
+		LiveScript(())
+	}
+}
+
+impl Drop for LiveScript {
+	fn drop(&mut self) {
+		let remaining = LIVE_SCRIPTS.try_with(|live| {
+			live.set(live.get().saturating_sub(1));
+			live.get()
+		});
+
+		if matches!(remaining, Ok(0)) {
+			// Errors are reported once the next script creates its own runtime instead
+			let _ = prepare_spare_script();
+		}
+	}
+}
+
+fn prepare_spare_script() -> Result<(), AnyError> {
+	let needed = REUSE_ENABLED.try_with(Cell::get).unwrap_or(false)
+		&& SPARE_SCRIPT.try_with(|spare| spare.borrow().is_none()).unwrap_or(false);
+
+	if needed {
+		let script = Script::create_runtime()?;
+		// Thread may be shutting down, in which case the script is just dropped
+		let _ = SPARE_SCRIPT.try_with(|spare| *spare.borrow_mut() = Some(script));
+	}
+	Ok(())
+}
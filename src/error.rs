@@ -0,0 +1,239 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::fmt;
+
+use crate::{AnyError, JsValue, SourcePosition};
+
+/// Error conditions detected by js-sandbox itself, as opposed to exceptions thrown by JavaScript code.
+///
+/// The functions in this crate return [`AnyError`](type.AnyError.html); use `downcast_ref::<JsError>()` to check for these cases.
+/// To log any error uniformly, convert it with [`from_any()`](#method.from_any): `Display` then yields a single line with the
+/// message and, for exceptions, the position where they were thrown, while `Debug` prefixes the variant name and adds the stack
+/// trace or thrown value, e.g. `Timeout: function did not complete within its timeout`.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JsError {
+	/// Execution was aborted, because a host function was invoked more often during a single call than allowed.
+	OpLimitExceeded {
+		/// Name of the host function.
+		op: String,
+		/// Maximum number of invocations per call.
+		limit: u64,
+	},
+
+	/// Execution was aborted on request of the host, before the call completed.
+	Cancelled,
+
+	/// Execution was aborted, because the call did not complete within its timeout, e.g. the one passed to [`Script::call()`](struct.Script.html#method.call).
+	Timeout,
+
+	/// A script was not created, because its top-level code did not complete within the limit set by
+	/// [`ScriptBuilder::init_timeout()`](struct.ScriptBuilder.html#method.init_timeout).
+	InitTimeout,
+
+	/// Execution of an async call was aborted, because the JS function did not return within the budget for its synchronous part.
+	SyncTimeout,
+
+	/// Execution of an async call was aborted, because the returned promise did not settle within the call's total budget.
+	TotalTimeout,
+
+	/// Pending async work of a script did not finish within the timeout passed to [`Script::drain_async()`](struct.Script.html#method.drain_async).
+	DrainTimeout {
+		/// Number of async ops which were still pending; dynamic imports and other work of the event loop are not counted.
+		pending: usize,
+	},
+
+	/// A function was not invoked, because it is not among the entry points allowed for the script.
+	NotAllowed {
+		/// Name of the function, as passed to the call.
+		function: String,
+	},
+
+	/// A script was not created, because its source code exceeds the limit set by [`ScriptBuilder::max_source_bytes()`](struct.ScriptBuilder.html#method.max_source_bytes).
+	SourceTooLarge {
+		/// Size of the source code in bytes.
+		size: u64,
+		/// Maximum allowed size in bytes.
+		limit: u64,
+	},
+
+	/// The called JS function threw a value that is not an `Error`, e.g. `throw "failed"` or `throw { code: "E_LIMIT" }`.
+	Thrown {
+		/// The thrown value as text: strings as-is, objects as JSON, other values converted with JS `String()`.
+		message: String,
+		/// The thrown value converted to JSON; `null` if it has no JSON representation.
+		value: JsValue,
+	},
+
+	/// The called JS function returned a value which cannot be converted to the Rust type requested by the host.
+	Conversion {
+		/// Name of the function, as passed to the call.
+		function: String,
+		/// Name of the requested Rust type, as reported by `std::any::type_name()`.
+		expected: String,
+		/// The returned value converted to JSON; `null` for `undefined`.
+		value: JsValue,
+		/// Description of the failure, including the reason given by serde.
+		message: String,
+	},
+
+	/// Execution was aborted, because the script ran out of gas during a call; see [`ScriptBuilder::gas_limit()`](struct.ScriptBuilder.html#method.gas_limit).
+	GasExhausted,
+
+	/// Execution was aborted, because the script's V8 heap reached the limit set by
+	/// [`ScriptBuilder::max_heap_size()`](struct.ScriptBuilder.html#method.max_heap_size).
+	HeapLimitExceeded {
+		/// Maximum heap size in bytes.
+		limit: usize,
+	},
+
+	/// The JS code threw an `Error`, or failed to compile; obtained from other errors with [`JsError::from_any()`](#method.from_any).
+	Exception {
+		/// The error message, e.g. `Uncaught TypeError: x is not a function`.
+		message: String,
+		/// Name of the error class, e.g. `TypeError`; `None` if the message does not start with one.
+		name: Option<String>,
+		/// Where the error was thrown, i.e. the innermost stack frame with a known position.
+		location: Option<SourcePosition>,
+		/// The stack trace as reported by V8, starting with the message.
+		stack: Option<String>,
+	},
+
+	/// Work sent to another thread was not completed, because that thread failed while running it: a submission to a
+	/// [`ScriptPool`](struct.ScriptPool.html), or a closure passed to [`ScriptThread::run()`](struct.ScriptThread.html#method.run).
+	WorkerFailed,
+}
+
+impl fmt::Display for JsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			JsError::OpLimitExceeded { op, limit } => {
+				write!(f, "host function '{}' exceeded its limit of {} invocations per call", op, limit)
+			}
+			JsError::Cancelled => write!(f, "execution was cancelled by the host"),
+			JsError::Timeout => write!(f, "function did not complete within its timeout"),
+			JsError::InitTimeout => write!(f, "top-level code did not complete within the initialization timeout"),
+			JsError::SyncTimeout => write!(f, "function did not return within its synchronous time budget"),
+			JsError::TotalTimeout => write!(f, "promise did not settle within the call's total time budget"),
+			JsError::DrainTimeout { pending } => {
+				write!(f, "pending async work did not finish within the drain timeout ({} async ops still pending)", pending)
+			}
+			JsError::NotAllowed { function } => write!(f, "calling JS function '{}' is not allowed", function),
+			JsError::SourceTooLarge { size, limit } => {
+				write!(f, "source code has {} bytes, which exceeds the limit of {} bytes", size, limit)
+			}
+			JsError::Thrown { message, .. } => write!(f, "Uncaught {}", message),
+			JsError::Conversion { message, .. } => write!(f, "{}", message),
+			JsError::GasExhausted => write!(f, "script exhausted its gas limit"),
+			JsError::HeapLimitExceeded { limit } => write!(f, "script exceeded its heap limit of {} bytes", limit),
+			JsError::WorkerFailed => write!(f, "worker thread failed before completing its work"),
+			JsError::Exception { message, location, .. } => {
+				write!(f, "{}", message)?;
+				if let Some(location) = location {
+					write!(f, " (at {}:{}:{})", location.file, location.line, location.column)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl fmt::Debug for JsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {}", self.variant_name(), self)?;
+		match self {
+			// The first line of V8's stack repeats the message
+			JsError::Exception { stack: Some(stack), .. } => {
+				for frame in stack.lines().map(str::trim).filter(|line| line.starts_with("at ")) {
+					write!(f, "\n    {}", frame)?;
+				}
+				Ok(())
+			}
+			JsError::Thrown { value, .. } => {
+				let value = serde_json::to_string_pretty(value).map_err(|_| fmt::Error)?;
+				write!(f, "\n    thrown value: {}", value.replace('\n', "\n    "))
+			}
+			JsError::Conversion { value, .. } => {
+				let value = serde_json::to_string_pretty(value).map_err(|_| fmt::Error)?;
+				write!(f, "\n    returned value: {}", value.replace('\n', "\n    "))
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+impl JsError {
+	/// Converts an error returned by this crate into a `JsError`, for uniform formatting.
+	///
+	/// Errors that already are a `JsError` are returned as a copy. Exceptions raised by JavaScript, such as a thrown `Error` or a
+	/// syntax error, become [`JsError::Exception`](#variant.Exception) with their position and stack trace. Returns `None` for
+	/// other errors, e.g. I/O or serialization failures, whose own formatting is already a single line.
+	pub fn from_any(err: &AnyError) -> Option<JsError> {
+		if let Some(error) = err.downcast_ref::<JsError>() {
+			return Some(error.clone());
+		}
+		let error = err.downcast_ref::<deno_core::error::JsError>()?;
+
+		// The stack frames point to the throw site; the message location may be that of a rethrow instead
+		let frame_location = error.frames.iter().find_map(|frame| {
+			Some(SourcePosition {
+				file: frame.file_name.clone()?,
+				line: frame.line_number? as u32,
+				column: frame.column_number? as u32,
+			})
+		});
+		let message_location = || {
+			Some(SourcePosition {
+				file: error.script_resource_name.clone()?,
+				line: error.line_number? as u32,
+				column: error.start_column? as u32 + 1,
+			})
+		};
+
+		Some(JsError::Exception {
+			message: error.message.clone(),
+			name: exception_name(&error.message),
+			location: frame_location.or_else(message_location),
+			stack: error.stack.clone(),
+		})
+	}
+
+	fn variant_name(&self) -> &'static str {
+		match self {
+			JsError::OpLimitExceeded { .. } => "OpLimitExceeded",
+			JsError::Cancelled => "Cancelled",
+			JsError::Timeout => "Timeout",
+			JsError::InitTimeout => "InitTimeout",
+			JsError::SyncTimeout => "SyncTimeout",
+			JsError::TotalTimeout => "TotalTimeout",
+			JsError::DrainTimeout { .. } => "DrainTimeout",
+			JsError::NotAllowed { .. } => "NotAllowed",
+			JsError::SourceTooLarge { .. } => "SourceTooLarge",
+			JsError::Thrown { .. } => "Thrown",
+			JsError::Conversion { .. } => "Conversion",
+			JsError::GasExhausted => "GasExhausted",
+			JsError::HeapLimitExceeded { .. } => "HeapLimitExceeded",
+			JsError::Exception { .. } => "Exception",
+			JsError::WorkerFailed => "WorkerFailed",
+		}
+	}
+}
+
+/// Extracts the error class from a message like `Uncaught (in promise) TypeError: x is not a function`.
+pub(crate) fn exception_name(message: &str) -> Option<String> {
+	let message = message.strip_prefix("Uncaught ").unwrap_or(message);
+	let message = message.strip_prefix("(in promise) ").unwrap_or(message);
+	let name = match message.find(':') {
+		Some(end) => &message[..end],
+		None => message,
+	};
+
+	let is_identifier = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+	if is_identifier {
+		Some(name.to_string())
+	} else {
+		None
+	}
+}
+
+impl std::error::Error for JsError {}
@@ -0,0 +1,125 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// fetch() and Headers for scripts, see ScriptBuilder::fetch().
+// Requests are made by the host, which checks them against its allow-list and size limits. Only the commonly used subset of
+// the Fetch API is provided: bodies are strings or binary data, and are read as a whole; there are no Request objects,
+// streams or abort signals.
+
+((globalThis, core) => {
+	const normalize = name => String(name).toLowerCase();
+
+	class Headers {
+		#entries = new Map();
+
+		constructor(init = {}) {
+			const entries = init instanceof Headers || Array.isArray(init) ? init : Object.entries(init);
+			for (const [name, value] of entries) {
+				this.append(name, value);
+			}
+		}
+
+		append(name, value) {
+			const existing = this.#entries.get(normalize(name));
+			this.#entries.set(normalize(name), existing === undefined ? String(value) : `${existing}, ${value}`);
+		}
+		set(name, value) {
+			this.#entries.set(normalize(name), String(value));
+		}
+		get(name) {
+			return this.#entries.get(normalize(name)) ?? null;
+		}
+		has(name) {
+			return this.#entries.has(normalize(name));
+		}
+		delete(name) {
+			this.#entries.delete(normalize(name));
+		}
+		forEach(callback, thisArg) {
+			for (const [name, value] of this.#entries) {
+				callback.call(thisArg, value, name, this);
+			}
+		}
+		entries() {
+			return this.#entries.entries();
+		}
+		keys() {
+			return this.#entries.keys();
+		}
+		values() {
+			return this.#entries.values();
+		}
+		[Symbol.iterator]() {
+			return this.entries();
+		}
+	}
+
+	class Response {
+		#body;
+		#bodyUsed = false;
+
+		constructor(meta, body) {
+			this.url = meta.url;
+			this.status = meta.status;
+			this.statusText = meta.statusText;
+			this.ok = meta.status >= 200 && meta.status < 300;
+			this.headers = new Headers(meta.headers);
+			this.#body = body;
+		}
+
+		get bodyUsed() {
+			return this.#bodyUsed;
+		}
+
+		#consume() {
+			if (this.#bodyUsed) {
+				throw new TypeError("response body has already been read");
+			}
+			this.#bodyUsed = true;
+			return this.#body;
+		}
+
+		async arrayBuffer() {
+			return this.#consume().buffer;
+		}
+		async text() {
+			return core.decode(this.#consume());
+		}
+		async json() {
+			return JSON.parse(await this.text());
+		}
+	}
+
+	function requestBody(body) {
+		if (body === undefined || body === null) {
+			return undefined;
+		}
+		if (typeof body === "string") {
+			return core.encode(body);
+		}
+		if (body instanceof ArrayBuffer) {
+			return new Uint8Array(body);
+		}
+		if (ArrayBuffer.isView(body)) {
+			return new Uint8Array(body.buffer, body.byteOffset, body.byteLength);
+		}
+		throw new TypeError("fetch() only supports strings and binary data as request body");
+	}
+
+	// Async, so that failures reject the returned promise like in browsers
+	async function fetch(input, init = {}) {
+		const url = String(input);
+		const method = String(init.method ?? "GET").toUpperCase();
+		const headers = [...new Headers(init.headers ?? {})];
+		const body = requestBody(init.body);
+
+		core.ops();
+		const meta = core.opSync("__rust_fetch", { url, method, headers }, body);
+		const bytes = new Uint8Array(meta.bodyLength);
+		core.opSync("__rust_fetch_body", null, bytes);
+		return new Response(meta, bytes);
+	}
+
+	for (const [name, value] of Object.entries({ fetch, Headers })) {
+		Object.defineProperty(globalThis, name, { value, writable: true, configurable: true });
+	}
+})
@@ -0,0 +1,185 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// fetch() for scripts, backed by ureq. The requests themselves require the `http` feature.
+// An op makes each request synchronously, after checking it against the host's FetchOptions; fetch.js wraps the outcome in a
+// promise. The response body is staged in the op state, from where a second op copies it into a Uint8Array.
+
+use std::time::Duration;
+
+use deno_core::error::generic_error;
+use deno_core::JsRuntime;
+
+use crate::AnyError;
+
+/// Restricts the HTTP requests which a script can make with `fetch()`; see
+/// [`ScriptBuilder::fetch()`](struct.ScriptBuilder.html#method.fetch).
+///
+/// The default options deny every request, since no domain is allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchOptions {
+	/// Domains which may be requested. An entry matches the domain itself and all its subdomains: `"example.com"` allows
+	/// `https://example.com/` and `https://api.example.com/`, but not `https://example.com.evil.net/`.
+	pub allowed_domains: Vec<String>,
+	/// Whether plain `http:` URLs may be requested besides `https:`; `false` by default.
+	pub allow_http: bool,
+	/// Maximum size of a request body in bytes; larger requests are rejected before they are sent. 1 MiB by default.
+	pub max_request_bytes: usize,
+	/// Maximum size of a response body in bytes; larger responses are rejected. 10 MiB by default.
+	pub max_response_bytes: usize,
+	/// Time limit of each request, from connecting until the response body is read. 30 seconds by default.
+	pub timeout: Duration,
+}
+
+impl Default for FetchOptions {
+	fn default() -> Self {
+		FetchOptions {
+			allowed_domains: Vec::new(),
+			allow_http: false,
+			max_request_bytes: 1 << 20,
+			max_response_bytes: 10 << 20,
+			timeout: Duration::from_secs(30),
+		}
+	}
+}
+
+/// Registers the ops behind `fetch()`, and installs it in the global scope.
+#[cfg(feature = "http")]
+pub(crate) fn install(runtime: &mut JsRuntime, options: FetchOptions) -> Result<(), AnyError> {
+	runtime.register_op("__rust_fetch", deno_core::op_sync(http::op_fetch));
+	runtime.register_op("__rust_fetch_body", deno_core::op_sync(http::op_fetch_body));
+	runtime.op_state().borrow_mut().put(options);
+
+	let js_code = format!("{}(globalThis, Deno.core);", include_str!("fetch.js").trim_end());
+	runtime.execute("js-sandbox:fetch.js", &js_code)
+}
+
+#[cfg(not(feature = "http"))]
+pub(crate) fn install(_runtime: &mut JsRuntime, _options: FetchOptions) -> Result<(), AnyError> {
+	Err(generic_error("fetch() requires the `http` feature of js-sandbox"))
+}
+
+#[cfg(feature = "http")]
+mod http {
+	use std::io::Read;
+
+	use deno_core::url::Url;
+	use deno_core::{OpState, ZeroCopyBuf};
+	use serde::{Deserialize, Serialize};
+
+	use super::*;
+	use crate::termination::Terminator;
+	use crate::JsValue;
+
+	#[derive(Deserialize)]
+	pub struct FetchRequest {
+		url: String,
+		method: String,
+		headers: Vec<(String, String)>,
+	}
+
+	#[derive(Serialize)]
+	#[serde(rename_all = "camelCase")]
+	pub struct FetchResponse {
+		url: String,
+		status: u16,
+		status_text: String,
+		headers: Vec<(String, String)>,
+		body_length: usize,
+	}
+
+	/// Body of the last response, until JS takes it.
+	struct ResponseBody(Vec<u8>);
+
+	/// Whether `host` is one of the allowed domains, or a subdomain thereof.
+	fn allows_host(options: &FetchOptions, host: &str) -> bool {
+		let host = host.to_ascii_lowercase();
+		options.allowed_domains.iter().any(|domain| {
+			let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+			!domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain)))
+		})
+	}
+
+	pub fn op_fetch(state: &mut OpState, request: FetchRequest, buf: Option<ZeroCopyBuf>) -> Result<FetchResponse, AnyError> {
+		let options = state.borrow::<FetchOptions>().clone();
+
+		let url = Url::parse(&request.url).map_err(|e| generic_error(format!("invalid URL '{}': {}", request.url, e)))?;
+		match url.scheme() {
+			"https" => {}
+			"http" if options.allow_http => {}
+			scheme => return Err(generic_error(format!("fetching '{}:' URLs is not allowed", scheme))),
+		}
+		let host = url.host_str().unwrap_or_default();
+		if !allows_host(&options, host) {
+			return Err(generic_error(format!("fetching '{}' is not allowed: domain '{}' is not on the allow-list", url, host)));
+		}
+
+		let body = buf.map_or_else(Vec::new, |buf| buf.to_vec());
+		if body.len() > options.max_request_bytes {
+			return Err(generic_error(format!(
+				"request body of {} bytes exceeds the limit of {} bytes",
+				body.len(),
+				options.max_request_bytes
+			)));
+		}
+
+		// The request cannot be interrupted by the watchdog, so it must not outlast the call's timeout either
+		let timeout = match state.try_borrow::<Terminator>().and_then(Terminator::remaining) {
+			Some(remaining) if remaining.is_zero() => {
+				return Err(generic_error(format!("fetching '{}' failed: the call's timeout has elapsed", url)))
+			}
+			Some(remaining) => options.timeout.min(remaining),
+			None => options.timeout,
+		};
+
+		// Redirects are handed to the script instead of followed, since they could lead to any domain
+		let agent = ureq::AgentBuilder::new().timeout(timeout).redirects(0).build();
+		let request = request
+			.headers
+			.iter()
+			.fold(agent.request(&request.method, url.as_str()), |request, (name, value)| request.set(name, value));
+
+		// Error statuses are regular responses for fetch()
+		let response = match request.send_bytes(&body) {
+			Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+			Err(e) => return Err(generic_error(format!("fetching '{}' failed: {}", url, e))),
+		};
+
+		let headers = response
+			.headers_names()
+			.into_iter()
+			.filter_map(|name| response.header(&name).map(|value| (name.clone(), value.to_string())))
+			.collect();
+		let url = response.get_url().to_string();
+		let status = response.status();
+		let status_text = response.status_text().to_string();
+
+		let limit = options.max_response_bytes;
+		let mut body = Vec::new();
+		response.into_reader().take(limit as u64 + 1).read_to_end(&mut body)?;
+		if body.len() > limit {
+			return Err(generic_error(format!("response of '{}' exceeds the limit of {} bytes", url, limit)));
+		}
+
+		let body_length = body.len();
+		state.put(ResponseBody(body));
+		Ok(FetchResponse { url, status, status_text, headers, body_length })
+	}
+
+	/// Copies the body of the last response into the `Uint8Array` allocated for it by JS.
+	pub fn op_fetch_body(state: &mut OpState, _args: (), buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+		let ResponseBody(body) = state
+			.try_take::<ResponseBody>()
+			.ok_or_else(|| generic_error("response body is no longer available"))?;
+		if body.is_empty() {
+			return Ok(JsValue::Null);
+		}
+
+		let mut buf = buf.ok_or_else(|| generic_error("no buffer to receive response body"))?;
+		if buf.len() != body.len() {
+			return Err(generic_error("buffer size does not match response body"));
+		}
+		buf.copy_from_slice(&body);
+
+		Ok(JsValue::Null)
+	}
+}
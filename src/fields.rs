@@ -0,0 +1,46 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use serde::de::value::Error;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::AnyError;
+
+/// Returns the serialized field names of the struct `R`, as seen by serde (i.e. after `#[serde(rename)]`).
+///
+/// Works by letting `R` deserialize itself from a deserializer that only records which fields are requested.
+pub(crate) fn struct_fields<R: DeserializeOwned>() -> Result<&'static [&'static str], AnyError> {
+	let mut fields = None;
+	let _ = R::deserialize(FieldTracer { fields: &mut fields });
+
+	fields.ok_or_else(|| {
+		deno_core::error::generic_error(format!("type `{}` is not a struct with named fields", std::any::type_name::<R>()))
+	})
+}
+
+struct FieldTracer<'a> {
+	fields: &'a mut Option<&'static [&'static str]>,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for FieldTracer<'a> {
+	type Error = Error;
+
+	fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+		Err(de::Error::custom("not a struct"))
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		fields: &'static [&'static str],
+		_visitor: V,
+	) -> Result<V::Value, Error> {
+		*self.fields = Some(fields);
+		Err(de::Error::custom("fields recorded"))
+	}
+
+	forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+		newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+	}
+}
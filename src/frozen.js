@@ -0,0 +1,81 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Hardening against prototype pollution, see ScriptBuilder::freeze_builtins().
+// Freezes the built-in constructors, their prototypes and namespace objects like JSON, before the user's code runs.
+// Afterwards, no script can change how built-in objects behave -- neither for other code, nor for values crossing the boundary.
+
+((globalThis) => {
+	const constructors = [
+		"Object", "Function", "Array", "String", "Number", "Boolean", "Symbol", "BigInt", "RegExp", "Date", "Promise",
+		"Error", "EvalError", "RangeError", "ReferenceError", "SyntaxError", "TypeError", "URIError", "AggregateError",
+		"Map", "Set", "WeakMap", "WeakSet", "WeakRef", "FinalizationRegistry", "Proxy",
+		"ArrayBuffer", "SharedArrayBuffer", "DataView", "Int8Array", "Uint8Array", "Uint8ClampedArray", "Int16Array",
+		"Uint16Array", "Int32Array", "Uint32Array", "Float32Array", "Float64Array", "BigInt64Array", "BigUint64Array",
+	];
+	const namespaces = ["JSON", "Math", "Reflect", "Atomics"];
+
+	// Prototypes which are only reachable through instances
+	const arrayIterator = Object.getPrototypeOf([][Symbol.iterator]());
+	const hiddenPrototypes = [
+		Object.getPrototypeOf(Int8Array),
+		Object.getPrototypeOf(Int8Array.prototype),
+		arrayIterator,
+		Object.getPrototypeOf(arrayIterator),
+		Object.getPrototypeOf(new Map()[Symbol.iterator]()),
+		Object.getPrototypeOf(new Set()[Symbol.iterator]()),
+		Object.getPrototypeOf(""[Symbol.iterator]()),
+		Object.getPrototypeOf(/./[Symbol.matchAll]("")),
+		Object.getPrototypeOf(function* () {}),
+		Object.getPrototypeOf(function* () {}.prototype),
+		Object.getPrototypeOf(async function () {}),
+		Object.getPrototypeOf(async function* () {}),
+		Object.getPrototypeOf(async function* () {}.prototype),
+	];
+
+	// Assigning a property which is inherited as read-only fails, so freezing a prototype would keep objects from overriding
+	// its properties. For those that code commonly overrides, e.g. `this.name` in Error subclasses, an accessor defines the
+	// property on the object instead. Assigning to the prototype itself throws, also in sloppy mode.
+	const overridable = ["constructor", "name", "message", "toString", "toLocaleString", "valueOf", "toJSON"];
+
+	function allowOverride(object, name) {
+		const descriptor = Object.getOwnPropertyDescriptor(object, name);
+		if (descriptor === undefined || !("value" in descriptor) || !descriptor.configurable) {
+			return;
+		}
+
+		const value = descriptor.value;
+		Object.defineProperty(object, name, {
+			get: () => value,
+			set(newValue) {
+				if (this === object) {
+					throw new TypeError(`cannot modify built-in property '${name}'`);
+				}
+				Object.defineProperty(this, name, { value: newValue, writable: true, enumerable: true, configurable: true });
+			},
+			enumerable: descriptor.enumerable,
+			configurable: false,
+		});
+	}
+
+	function harden(object) {
+		if (object === null || (typeof object !== "object" && typeof object !== "function")) {
+			return;
+		}
+		for (const name of overridable) {
+			allowOverride(object, name);
+		}
+		Object.freeze(object);
+	}
+
+	for (const name of constructors) {
+		const constructor = globalThis[name];
+		if (typeof constructor === "function") {
+			harden(constructor.prototype);
+			harden(constructor);
+		}
+	}
+	for (const name of namespaces) {
+		harden(globalThis[name]);
+	}
+	hiddenPrototypes.forEach(harden);
+})(globalThis);
@@ -0,0 +1,48 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// The `fs` global, see ScriptBuilder::filesystem().
+// Every operation is performed by the host on its virtual filesystem; paths never refer to the host's real files.
+
+((globalThis, core) => {
+	function fsOp(command, path, buf) {
+		core.ops();
+		return core.opSync("__rust_fs", [command, String(path)], buf);
+	}
+
+	function toBytes(data) {
+		if (typeof data === "string") {
+			return core.encode(data);
+		}
+		if (data instanceof ArrayBuffer) {
+			return new Uint8Array(data);
+		}
+		if (ArrayBuffer.isView(data)) {
+			return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+		}
+		throw new TypeError("expected a string or binary data as file contents");
+	}
+
+	function readFile(path) {
+		const bytes = new Uint8Array(fsOp("read", path));
+		core.opSync("__rust_fs_take", null, bytes);
+		return bytes;
+	}
+
+	const fs = Object.freeze({
+		readFile,
+		readTextFile: path => core.decode(readFile(path)),
+		writeFile(path, data) {
+			fsOp("write", path, toBytes(data));
+		},
+		writeTextFile(path, text) {
+			fsOp("write", path, core.encode(String(text)));
+		},
+		exists: path => fsOp("exists", path),
+		remove(path) {
+			fsOp("remove", path);
+		},
+		readDir: (path = "") => fsOp("list", path),
+	});
+
+	Object.defineProperty(globalThis, "fs", { value: fs, writable: true, configurable: true });
+})
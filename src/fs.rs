@@ -0,0 +1,317 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Virtual filesystem for scripts, exposed as the `fs` global by fs.js.
+// Paths are always relative to the root of the virtual filesystem, which the host chooses; `..` cannot leave it. File contents
+// cross the boundary as raw buffers: written data is passed to the op, read data is staged in the op state, from where a
+// second op copies it into a Uint8Array.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use deno_core::error::generic_error;
+use deno_core::{JsRuntime, OpState, ZeroCopyBuf};
+
+use crate::{AnyError, JsValue};
+
+/// Files which a script can access through the `fs` global; see
+/// [`ScriptBuilder::filesystem()`](struct.ScriptBuilder.html#method.filesystem).
+///
+/// The files are either kept in memory, or stored below a directory chosen by the host. Either way, scripts address them by
+/// paths relative to the root of this filesystem, such as `"data/levels.json"` (a leading `/` is allowed as well). Paths that
+/// would leave the root through `..`, or through symbolic links in case of a directory, are rejected. No other file of the
+/// host is reachable.
+///
+/// Cloning is cheap, and clones share the same files: the host can keep one to inspect what the script wrote. The methods below
+/// are meant for the host, and are not restricted by [`read_only()`](#method.read_only).
+///
+/// ```rust
+/// use js_sandbox::{AnyError, Script, VirtualFs};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let fs = VirtualFs::in_memory();
+/// 	fs.write("config.json", br#"{ "speed": 3 }"#)?;
+///
+/// 	let mut script = Script::builder().filesystem(fs.clone()).build("
+/// 		function save() {
+/// 			const config = JSON.parse(fs.readTextFile('config.json'));
+/// 			fs.writeTextFile('saves/speed.txt', String(config.speed * 2));
+/// 		}")?;
+///
+/// 	script.call_void("save", &(), None)?;
+/// 	assert_eq!(fs.read("saves/speed.txt")?, b"6");
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct VirtualFs {
+	inner: Rc<RefCell<FsData>>,
+}
+
+#[derive(Debug)]
+struct FsData {
+	backend: Backend,
+	read_only: bool,
+}
+
+#[derive(Debug)]
+enum Backend {
+	/// Files by normalized path; directories exist implicitly
+	Memory(BTreeMap<String, Vec<u8>>),
+	/// Canonical root directory
+	Directory(PathBuf),
+}
+
+/// Contents of the file read last, until JS takes it.
+struct FileContents(Vec<u8>);
+
+impl VirtualFs {
+	/// Creates an empty filesystem, which keeps its files in memory.
+	pub fn in_memory() -> Self {
+		Self::with_backend(Backend::Memory(BTreeMap::new()))
+	}
+
+	/// Creates a filesystem backed by the directory `root` and everything below it.
+	///
+	/// Fails if `root` is not an accessible directory.
+	pub fn directory(root: impl AsRef<Path>) -> Result<Self, AnyError> {
+		let root = root.as_ref();
+		let canonical = root
+			.canonicalize()
+			.map_err(|e| AnyError::from(e).context(format!("directory '{}' is not accessible", root.display())))?;
+		if !canonical.is_dir() {
+			return Err(generic_error(format!("'{}' is not a directory", root.display())));
+		}
+		Ok(Self::with_backend(Backend::Directory(canonical)))
+	}
+
+	fn with_backend(backend: Backend) -> Self {
+		VirtualFs { inner: Rc::new(RefCell::new(FsData { backend, read_only: false })) }
+	}
+
+	/// Prevents scripts from writing or removing files; reading remains possible.
+	pub fn read_only(self) -> Self {
+		self.inner.borrow_mut().read_only = true;
+		self
+	}
+
+	/// Returns the contents of the file at `path`.
+	pub fn read(&self, path: &str) -> Result<Vec<u8>, AnyError> {
+		let relative = normalize(path)?;
+		match &self.inner.borrow().backend {
+			Backend::Memory(files) => files.get(&relative).cloned().ok_or_else(|| not_found(path)),
+			Backend::Directory(root) => {
+				let file = resolve(root, &relative)?;
+				fs::read(&file).map_err(|e| AnyError::from(e).context(format!("cannot read '{}'", path)))
+			}
+		}
+	}
+
+	/// Creates or overwrites the file at `path`, including missing parent directories.
+	pub fn write(&self, path: &str, contents: &[u8]) -> Result<(), AnyError> {
+		let relative = normalize(path)?;
+		if relative.is_empty() {
+			return Err(generic_error("cannot write to the root directory"));
+		}
+
+		match &mut self.inner.borrow_mut().backend {
+			Backend::Memory(files) => {
+				if has_children(files, &relative) {
+					return Err(generic_error(format!("cannot write '{}': it is a directory", path)));
+				}
+				files.insert(relative, contents.to_vec());
+				Ok(())
+			}
+			Backend::Directory(root) => {
+				let file = root.join(&relative);
+				check_inside(root, &file, path)?;
+				if let Some(parent) = file.parent() {
+					fs::create_dir_all(parent)?;
+				}
+				fs::write(&file, contents).map_err(|e| AnyError::from(e).context(format!("cannot write '{}'", path)))
+			}
+		}
+	}
+
+	/// Whether a file or directory exists at `path`.
+	pub fn exists(&self, path: &str) -> bool {
+		let relative = match normalize(path) {
+			Ok(relative) => relative,
+			Err(_) => return false,
+		};
+		match &self.inner.borrow().backend {
+			Backend::Memory(files) => relative.is_empty() || files.contains_key(&relative) || has_children(files, &relative),
+			Backend::Directory(root) => resolve(root, &relative).is_ok(),
+		}
+	}
+
+	/// Removes the file at `path`, or the directory including all its contents.
+	pub fn remove(&self, path: &str) -> Result<(), AnyError> {
+		let relative = normalize(path)?;
+		if relative.is_empty() {
+			return Err(generic_error("cannot remove the root directory"));
+		}
+
+		match &mut self.inner.borrow_mut().backend {
+			Backend::Memory(files) => {
+				let prefix = format!("{}/", relative);
+				let count = files.len();
+				files.retain(|file, _| *file != relative && !file.starts_with(&prefix));
+				if files.len() == count {
+					return Err(not_found(path));
+				}
+				Ok(())
+			}
+			Backend::Directory(root) => {
+				let file = resolve(root, &relative)?;
+				let result = if file.is_dir() { fs::remove_dir_all(&file) } else { fs::remove_file(&file) };
+				result.map_err(|e| AnyError::from(e).context(format!("cannot remove '{}'", path)))
+			}
+		}
+	}
+
+	/// Lists the names of the files and directories directly within the directory at `path`, in alphabetical order.
+	pub fn list(&self, path: &str) -> Result<Vec<String>, AnyError> {
+		let relative = normalize(path)?;
+		match &self.inner.borrow().backend {
+			Backend::Memory(files) => {
+				let prefix = if relative.is_empty() { String::new() } else { format!("{}/", relative) };
+				let mut names: Vec<String> = files
+					.keys()
+					.filter_map(|file| file.strip_prefix(&prefix))
+					.map(|rest| rest.split('/').next().unwrap_or(rest).to_string())
+					.collect();
+				names.sort();
+				names.dedup();
+				if names.is_empty() && !relative.is_empty() {
+					return Err(not_found(path));
+				}
+				Ok(names)
+			}
+			Backend::Directory(root) => {
+				let dir = resolve(root, &relative)?;
+				let mut names = Vec::new();
+				for entry in fs::read_dir(&dir).map_err(|e| AnyError::from(e).context(format!("cannot list '{}'", path)))? {
+					names.push(entry?.file_name().to_string_lossy().into_owned());
+				}
+				names.sort();
+				Ok(names)
+			}
+		}
+	}
+
+	fn check_writable(&self) -> Result<(), AnyError> {
+		if self.inner.borrow().read_only {
+			return Err(generic_error("the filesystem is read-only"));
+		}
+		Ok(())
+	}
+}
+
+/// Turns `path` into a `/`-separated path relative to the root; the root itself is `""`.
+fn normalize(path: &str) -> Result<String, AnyError> {
+	let mut components = Vec::new();
+	for component in path.split(|c| c == '/' || c == '\\') {
+		match component {
+			"" | "." => {}
+			".." => {
+				if components.pop().is_none() {
+					return Err(outside(path));
+				}
+			}
+			component => components.push(component),
+		}
+	}
+	Ok(components.join("/"))
+}
+
+/// Returns the path of an existing file below `root`, after following symbolic links.
+fn resolve(root: &Path, relative: &str) -> Result<PathBuf, AnyError> {
+	let path = root.join(relative).canonicalize().map_err(|_| not_found(relative))?;
+	if !path.starts_with(root) {
+		return Err(outside(relative));
+	}
+	Ok(path)
+}
+
+/// Checks that writing `file` stays below `root`, before any directory is created.
+fn check_inside(root: &Path, file: &Path, path: &str) -> Result<(), AnyError> {
+	// Symbolic links are followed when writing, so the closest existing ancestor (or the file itself) decides where it ends up
+	let existing = file.ancestors().find(|ancestor| ancestor.symlink_metadata().is_ok()).unwrap_or(root);
+	let canonical = existing.canonicalize().map_err(|_| outside(path))?;
+	if !canonical.starts_with(root) {
+		return Err(outside(path));
+	}
+	Ok(())
+}
+
+fn has_children(files: &BTreeMap<String, Vec<u8>>, relative: &str) -> bool {
+	let prefix = format!("{}/", relative);
+	files.keys().any(|file| file.starts_with(&prefix))
+}
+
+fn outside(path: &str) -> AnyError {
+	generic_error(format!("path '{}' is outside of the filesystem", path))
+}
+
+fn not_found(path: &str) -> AnyError {
+	generic_error(format!("'{}' does not exist", path))
+}
+
+/// Registers the ops behind the `fs` global, and installs it.
+pub(crate) fn install(runtime: &mut JsRuntime, fs: VirtualFs) -> Result<(), AnyError> {
+	runtime.register_op("__rust_fs", deno_core::op_sync(op_fs));
+	runtime.register_op("__rust_fs_take", deno_core::op_sync(op_fs_take));
+	runtime.op_state().borrow_mut().put(fs);
+
+	let js_code = format!("{}(globalThis, Deno.core);", include_str!("fs.js").trim_end());
+	runtime.execute("js-sandbox:fs.js", &js_code)
+}
+
+fn op_fs(state: &mut OpState, args: (String, String), buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let (command, path) = args;
+	let fs = state.borrow::<VirtualFs>().clone();
+
+	match command.as_str() {
+		"read" => {
+			let contents = fs.read(&path)?;
+			let length = contents.len();
+			state.put(FileContents(contents));
+			Ok(length.into())
+		}
+		"write" => {
+			fs.check_writable()?;
+			let contents = buf.map_or_else(Vec::new, |buf| buf.to_vec());
+			fs.write(&path, &contents)?;
+			Ok(JsValue::Null)
+		}
+		"remove" => {
+			fs.check_writable()?;
+			fs.remove(&path)?;
+			Ok(JsValue::Null)
+		}
+		"exists" => Ok(fs.exists(&path).into()),
+		"list" => Ok(fs.list(&path)?.into()),
+		_ => Err(generic_error(format!("unknown filesystem operation '{}'", command))),
+	}
+}
+
+/// Copies the contents of the file read last into the `Uint8Array` allocated for it by JS.
+fn op_fs_take(state: &mut OpState, _args: (), buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let FileContents(contents) = state
+		.try_take::<FileContents>()
+		.ok_or_else(|| generic_error("file contents are no longer available"))?;
+	if contents.is_empty() {
+		return Ok(JsValue::Null);
+	}
+
+	let mut buf = buf.ok_or_else(|| generic_error("no buffer to receive file contents"))?;
+	if buf.len() != contents.len() {
+		return Err(generic_error("buffer size does not match file contents"));
+	}
+	buf.copy_from_slice(&contents);
+
+	Ok(JsValue::Null)
+}
@@ -0,0 +1,43 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Executed for scripts with ScriptBuilder::gas_limit(), before the user's code.
+// Code compiled at runtime would not be instrumented, so eval() and the function constructors are disabled.
+// Evaluates to the function that sets the gas of a call, which only Rust holds on to.
+
+((globalThis) => {
+	function unavailable(name, prototype) {
+		const blocked = function () {
+			throw new EvalError(`${name}() is not available in a script with gas limit`);
+		};
+		// Keeps `instanceof` working
+		blocked.prototype = prototype;
+		return blocked;
+	}
+
+	function block(object, name, value) {
+		Object.defineProperty(object, name, {
+			value,
+			writable: false,
+			configurable: false,
+		});
+	}
+
+	const constructors = [
+		Function,
+		async function () {}.constructor,
+		function* () {}.constructor,
+		async function* () {}.constructor,
+	];
+
+	for (const constructor of constructors) {
+		const blocked = unavailable(constructor.name, constructor.prototype);
+		block(constructor.prototype, "constructor", blocked);
+		if (constructor === Function) {
+			block(globalThis, "Function", blocked);
+		}
+	}
+
+	block(globalThis, "eval", unavailable("eval", undefined));
+
+	return __rust.takeGasControl();
+})(globalThis);
@@ -0,0 +1,525 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::convert::TryFrom;
+
+use deno_core::error::generic_error;
+use deno_core::{v8, JsRuntime, OpState, ZeroCopyBuf};
+
+use crate::termination::Terminator;
+use crate::{AnyError, JsError, JsValue};
+
+/// Expression consuming one unit of gas; always `true` while gas is left. Defined in prelude.js.
+const GAS_CHECK: &str = "__rust.gas()";
+
+/// Identifier which instrumented code relies on, and which user code must therefore not shadow.
+const RESERVED_IDENT: &str = "__rust";
+
+/// Keywords after which `(...) {` does not start a function body.
+const CONTROL_KEYWORDS: &[&str] = &["if", "for", "while", "switch", "catch", "with"];
+
+/// Keywords after which a `/` starts a regular expression rather than a division.
+const EXPRESSION_KEYWORDS: &[&str] = &[
+	"return", "typeof", "instanceof", "in", "of", "new", "delete", "void", "throw", "case", "do", "else", "yield", "await",
+];
+
+/// Gas limit of a script, together with the JS function that refills the gas before each call.
+pub(crate) struct GasMeter {
+	limit: u64,
+	refill: v8::Global<v8::Function>,
+}
+
+impl GasMeter {
+	/// Runs gas.js in the runtime, and fills the gas for the top-level code.
+	pub fn install(runtime: &mut JsRuntime, limit: u64) -> Result<Self, AnyError> {
+		let context = runtime.global_context();
+		let scope = &mut v8::HandleScope::with_context(runtime.v8_isolate(), context);
+		let tc_scope = &mut v8::TryCatch::new(scope);
+
+		let source = v8::String::new(tc_scope, include_str!("gas.js")).ok_or_else(|| generic_error("cannot allocate gas.js"))?;
+		let value = v8::Script::compile(tc_scope, source, None).and_then(|script| script.run(tc_scope));
+		let refill = match value {
+			Some(value) => v8::Local::<v8::Function>::try_from(value).map_err(AnyError::from)?,
+			None => {
+				return Err(match tc_scope.exception() {
+					Some(exception) => deno_core::error::JsError::from_v8_exception(tc_scope, exception).into(),
+					None => generic_error("execution of gas.js was aborted"),
+				});
+			}
+		};
+
+		let meter = GasMeter { limit, refill: v8::Global::new(tc_scope, refill) };
+		meter.set_gas(tc_scope)?;
+		Ok(meter)
+	}
+
+	/// Changes the limit applied by refill(), and returns the previous one.
+	pub fn set_limit(&mut self, limit: u64) -> u64 {
+		std::mem::replace(&mut self.limit, limit)
+	}
+
+	/// Restores the full gas limit, at the start of a call.
+	pub fn refill(&self, runtime: &mut JsRuntime) -> Result<(), AnyError> {
+		let context = runtime.global_context();
+		let scope = &mut v8::HandleScope::with_context(runtime.v8_isolate(), context);
+		self.set_gas(scope)
+	}
+
+	fn set_gas(&self, scope: &mut v8::HandleScope) -> Result<(), AnyError> {
+		let refill = v8::Local::new(scope, &self.refill);
+		let receiver = v8::undefined(scope).into();
+		// Precise up to 2^53, far beyond what a call could consume
+		let limit = v8::Number::new(scope, self.limit as f64).into();
+
+		match refill.call(scope, receiver, &[limit]) {
+			Some(_) => Ok(()),
+			None => Err(generic_error("failed to refill gas")),
+		}
+	}
+}
+
+/// Called from JS once the gas of the current call is used up; aborts execution.
+pub(crate) fn op_gas_exhausted(state: &mut OpState, _args: (), _buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	state.borrow::<Terminator>().terminate(JsError::GasExhausted);
+	Err(JsError::GasExhausted.into())
+}
+
+/// Inserts a gas check into every loop iteration and function body of `code`.
+///
+/// Works on tokens rather than a syntax tree: strings, comments, template literals and regular expressions are skipped, and
+/// brackets are matched to recognize loop headers and function bodies. Loop conditions of `while`, `do-while` and `for(;;)`
+/// are extended by a check, `for-in`/`for-of` loops and functions get a check as the first statement of their body, and
+/// concise arrow bodies `x => expr` become `x => (check, expr)`. Braceless `for-in`/`for-of` bodies `stmt` become
+/// `if (check) stmt`. Line breaks are preserved, so line numbers stay the same.
+pub(crate) fn instrument(code: &str) -> Result<String, AnyError> {
+	let mut scanner = Scanner {
+		code: code.as_bytes(),
+		pos: 0,
+		groups: Vec::new(),
+		arrows: Vec::new(),
+		prev: Prev::None,
+		class_pending: false,
+		insertions: Vec::new(),
+	};
+	scanner.scan()?;
+
+	let mut insertions = scanner.insertions;
+	insertions.sort_by_key(|&(pos, _)| pos);
+
+	let mut result = String::with_capacity(code.len() + insertions.len() * GAS_CHECK.len());
+	let mut copied = 0;
+	for (pos, text) in insertions {
+		result.push_str(&code[copied..pos]);
+		result.push_str(text);
+		copied = pos;
+	}
+	result.push_str(&code[copied..]);
+	Ok(result)
+}
+
+/// Opening bracket whose closing counterpart has not been reached yet.
+enum Group {
+	Paren(ParenInfo),
+	Bracket,
+	Brace,
+	/// `${` inside a template literal
+	TemplateExpr,
+}
+
+struct ParenInfo {
+	/// Identifier preceding the `(`, e.g. `while` or a function name
+	keyword: Option<String>,
+	/// Byte position after the `(`
+	start: usize,
+	/// Positions of `;` directly inside the parentheses
+	semicolons: Vec<usize>,
+}
+
+/// Previous significant token, as far as relevant for the decisions of the scanner.
+enum Prev {
+	None,
+	Ident(String),
+	Value,
+	Punct(u8),
+	Arrow,
+	/// `)`, with whether a following `{` starts a block that needs a gas check, and whether it ends the header of a control
+	/// statement such as `if (...)`, after which a `/` starts a regular expression
+	CloseParen { checked_block: bool, control: bool },
+	/// `class` keyword, whose body must not be instrumented
+	Class,
+}
+
+/// Arrow function with concise body, whose end has not been reached yet.
+struct ConciseArrow {
+	/// Byte position of the body
+	start: usize,
+	/// Number of open groups at the start of the body
+	depth: usize,
+	/// Number of `?` of conditional expressions in the body, whose `:` is still ahead
+	ternaries: u32,
+}
+
+struct Scanner<'a> {
+	code: &'a [u8],
+	pos: usize,
+	groups: Vec<Group>,
+	arrows: Vec<ConciseArrow>,
+	prev: Prev,
+	/// Whether the `class` keyword has been seen, but not its body
+	class_pending: bool,
+	insertions: Vec<(usize, &'static str)>,
+}
+
+impl<'a> Scanner<'a> {
+	fn scan(&mut self) -> Result<(), AnyError> {
+		while let Some(&c) = self.code.get(self.pos) {
+			match c {
+				b'\n' => {
+					if self.arrow_ends_at_line_break() {
+						self.end_arrows();
+					}
+					self.pos += 1;
+				}
+				b' ' | b'\t' | b'\r' => self.pos += 1,
+				b'/' if self.peek(1) == Some(b'/') => self.skip_until(b"\n"),
+				b'/' if self.peek(1) == Some(b'*') => {
+					self.pos += 2;
+					self.skip_until(b"*/");
+					self.pos += 2;
+				}
+				b'/' if self.regex_allowed() => {
+					self.skip_regex();
+					self.prev = Prev::Value;
+				}
+				b'\'' | b'"' => {
+					self.skip_string(c);
+					self.prev = Prev::Value;
+				}
+				b'`' => {
+					self.pos += 1;
+					self.scan_template();
+				}
+				b'(' => {
+					let keyword = match &self.prev {
+						Prev::Ident(word) => Some(word.clone()),
+						_ => None,
+					};
+					self.pos += 1;
+					self.groups.push(Group::Paren(ParenInfo { keyword, start: self.pos, semicolons: Vec::new() }));
+					self.prev = Prev::Punct(c);
+				}
+				b')' => {
+					self.end_arrows();
+					let (checked_block, control) = match self.groups.pop() {
+						Some(Group::Paren(paren)) => {
+							let control = matches!(paren.keyword.as_deref(), Some(keyword) if CONTROL_KEYWORDS.contains(&keyword));
+							(self.close_paren(paren), control)
+						}
+						_ => (false, false),
+					};
+					self.pos += 1;
+					self.prev = Prev::CloseParen { checked_block, control };
+				}
+				b'{' => {
+					self.pos += 1;
+					let checked = match self.prev {
+						Prev::Arrow => true,
+						Prev::CloseParen { checked_block, .. } => checked_block,
+						_ => false,
+					};
+					if checked && !self.class_pending {
+						self.insertions.push((self.pos, "__rust.gas();"));
+					}
+					self.class_pending = false;
+					self.groups.push(Group::Brace);
+					self.prev = Prev::Punct(c);
+				}
+				b'}' => {
+					self.end_arrows();
+					self.pos += 1;
+					if let Some(Group::TemplateExpr) = self.groups.pop() {
+						self.scan_template();
+					} else {
+						self.prev = Prev::Punct(c);
+					}
+				}
+				b'[' => {
+					self.pos += 1;
+					self.groups.push(Group::Bracket);
+					self.prev = Prev::Punct(c);
+				}
+				b']' => {
+					self.end_arrows();
+					self.pos += 1;
+					self.groups.pop();
+					self.prev = Prev::Value;
+				}
+				b';' | b',' => {
+					self.end_arrows();
+					if let (b';', Some(Group::Paren(paren))) = (c, self.groups.last_mut()) {
+						paren.semicolons.push(self.pos);
+					}
+					self.pos += 1;
+					self.prev = Prev::Punct(c);
+				}
+				b'?' if !matches!(self.peek(1), Some(b'?') | Some(b'.')) => {
+					let depth = self.groups.len();
+					if let Some(arrow) = self.arrows.last_mut().filter(|arrow| arrow.depth == depth) {
+						arrow.ternaries += 1;
+					}
+					self.pos += 1;
+					self.prev = Prev::Punct(c);
+				}
+				b':' => {
+					let depth = self.groups.len();
+					match self.arrows.last_mut() {
+						Some(arrow) if arrow.depth == depth && arrow.ternaries > 0 => arrow.ternaries -= 1,
+						_ => self.end_arrows(),
+					}
+					self.pos += 1;
+					self.prev = Prev::Punct(c);
+				}
+				b'\\' => {
+					// Escapes in identifiers could spell the reserved identifier
+					return Err(generic_error("scripts with a gas limit must not use escape sequences in identifiers"));
+				}
+				b'=' if self.peek(1) == Some(b'>') => {
+					self.pos += 2;
+					self.prev = Prev::Arrow;
+
+					match self.significant_from(self.pos) {
+						Some(body) if self.code[body] != b'{' => {
+							self.insertions.push((body, "(__rust.gas(), "));
+							self.arrows.push(ConciseArrow { start: body, depth: self.groups.len(), ternaries: 0 });
+						}
+						_ => {}
+					}
+				}
+				c if is_ident_part(c) => self.scan_word()?,
+				_ => {
+					self.pos += 1;
+					self.prev = Prev::Punct(c);
+				}
+			}
+		}
+
+		while !self.arrows.is_empty() {
+			self.insertions.push((self.pos, ")"));
+			self.arrows.pop();
+		}
+		Ok(())
+	}
+
+	fn scan_word(&mut self) -> Result<(), AnyError> {
+		let start = self.pos;
+		while matches!(self.code.get(self.pos), Some(&c) if is_ident_part(c)) {
+			self.pos += 1;
+		}
+
+		if self.code[start].is_ascii_digit() {
+			self.prev = Prev::Value;
+			return Ok(());
+		}
+
+		let word = &self.code[start..self.pos];
+		let is_property = self.is_property(start);
+		let next = self.significant_from(self.pos).map(|next| self.code[next]);
+
+		if is_property {
+			self.prev = Prev::Value;
+		} else if word == RESERVED_IDENT.as_bytes() {
+			return Err(generic_error(format!("scripts with a gas limit must not use the identifier '{}'", RESERVED_IDENT)));
+		} else if word == b"with" && next == Some(b'(') {
+			return Err(generic_error("scripts with a gas limit must not use 'with' statements"));
+		} else if word == b"class" && next != Some(b':') {
+			self.class_pending = true;
+			self.prev = Prev::Class;
+		} else {
+			self.prev = Prev::Ident(String::from_utf8_lossy(word).into_owned());
+		}
+		Ok(())
+	}
+
+	/// Closes the concise arrow bodies which end at the current position, because the enclosing group ends or an operator
+	/// with lower precedence follows.
+	fn end_arrows(&mut self) {
+		let depth = self.groups.len();
+		while let Some(arrow) = self.arrows.last() {
+			if arrow.depth != depth {
+				break;
+			}
+			self.insertions.push((self.pos, ")"));
+			self.arrows.pop();
+		}
+	}
+
+	/// Whether the line break at the current position ends a concise arrow body by automatic semicolon insertion.
+	fn arrow_ends_at_line_break(&self) -> bool {
+		match self.arrows.last() {
+			Some(arrow) if arrow.depth == self.groups.len() && arrow.start < self.pos => {}
+			_ => return false,
+		}
+
+		// After an operator, the expression continues on the next line
+		let ends_expression = match &self.prev {
+			Prev::Value | Prev::CloseParen { .. } | Prev::Punct(b'}') => true,
+			Prev::Ident(word) => !EXPRESSION_KEYWORDS.contains(&word.as_str()),
+			Prev::Punct(c @ b'+') | Prev::Punct(c @ b'-') => {
+				// Postfix increment or decrement
+				let last = self.code[..self.pos].iter().rposition(|b| !b.is_ascii_whitespace());
+				matches!(last, Some(last) if last > 0 && self.code[last - 1] == *c)
+			}
+			_ => false,
+		};
+		if !ends_expression {
+			return false;
+		}
+
+		let next = match self.significant_from(self.pos) {
+			Some(next) => &self.code[next..],
+			None => return true,
+		};
+
+		// Operators continue the expression, except for prefix increment/decrement on the next line
+		if next.starts_with(b"++") || next.starts_with(b"--") {
+			return true;
+		}
+		if b".?+-*/%&|^<>=,)]};:([`".contains(&next[0]) {
+			return false;
+		}
+
+		let word_len = next.iter().take_while(|&&c| is_ident_part(c)).count();
+		!matches!(&next[..word_len], b"in" | b"instanceof")
+	}
+
+	/// Instruments a loop header, and returns whether a block following the parentheses needs a gas check.
+	fn close_paren(&mut self, paren: ParenInfo) -> bool {
+		let keyword = paren.keyword.as_deref();
+		match keyword {
+			Some("while") => {
+				self.insertions.push((paren.start, "__rust.gas() && ("));
+				self.insertions.push((self.pos, ")"));
+				false
+			}
+			Some("for") if paren.semicolons.len() == 2 => {
+				let (first, second) = (paren.semicolons[0] + 1, paren.semicolons[1]);
+				if self.code[first..second].iter().all(u8::is_ascii_whitespace) {
+					self.insertions.push((first, GAS_CHECK));
+				} else {
+					self.insertions.push((first, "__rust.gas() && ("));
+					self.insertions.push((second, ")"));
+				}
+				false
+			}
+			// for-in and for-of loops are checked in their body, which is guarded by a check if it is a single statement
+			Some("for") => match self.significant_from(self.pos + 1) {
+				Some(body) if self.code[body] != b'{' => {
+					self.insertions.push((body, "if (__rust.gas()) "));
+					false
+				}
+				_ => true,
+			},
+			Some(keyword) => !CONTROL_KEYWORDS.contains(&keyword),
+			None => true,
+		}
+	}
+
+	/// Skips the remainder of a template literal, up to its end or the next `${`.
+	fn scan_template(&mut self) {
+		while let Some(&c) = self.code.get(self.pos) {
+			match c {
+				b'\\' => self.pos += 2,
+				b'`' => {
+					self.pos += 1;
+					self.prev = Prev::Value;
+					return;
+				}
+				b'$' if self.peek(1) == Some(b'{') => {
+					self.pos += 2;
+					self.groups.push(Group::TemplateExpr);
+					self.prev = Prev::Punct(b'{');
+					return;
+				}
+				_ => self.pos += 1,
+			}
+		}
+	}
+
+	fn skip_string(&mut self, quote: u8) {
+		self.pos += 1;
+		while let Some(&c) = self.code.get(self.pos) {
+			self.pos += if c == b'\\' { 2 } else { 1 };
+			if c == quote || c == b'\n' {
+				return;
+			}
+		}
+	}
+
+	fn skip_regex(&mut self) {
+		self.pos += 1;
+		let mut in_class = false;
+		while let Some(&c) = self.code.get(self.pos) {
+			self.pos += if c == b'\\' { 2 } else { 1 };
+			match c {
+				b'[' => in_class = true,
+				b']' => in_class = false,
+				b'/' if !in_class => break,
+				b'\n' => return,
+				_ => {}
+			}
+		}
+
+		// Flags
+		while matches!(self.code.get(self.pos), Some(&c) if is_ident_part(c)) {
+			self.pos += 1;
+		}
+	}
+
+	fn skip_until(&mut self, end: &[u8]) {
+		while self.pos < self.code.len() && !self.code[self.pos..].starts_with(end) {
+			self.pos += 1;
+		}
+	}
+
+	fn regex_allowed(&self) -> bool {
+		match &self.prev {
+			Prev::None | Prev::Arrow | Prev::Class => true,
+			Prev::Punct(c) => *c != b')' && *c != b']',
+			Prev::Ident(word) => EXPRESSION_KEYWORDS.contains(&word.as_str()),
+			Prev::CloseParen { control, .. } => *control,
+			Prev::Value => false,
+		}
+	}
+
+	/// Whether the identifier starting at `start` is accessed as a property, i.e. follows `.` but not `...`.
+	fn is_property(&self, start: usize) -> bool {
+		let before = self.code[..start].iter().rposition(|c| !c.is_ascii_whitespace());
+		match before {
+			Some(dot) if self.code[dot] == b'.' => dot == 0 || self.code[dot - 1] != b'.',
+			_ => false,
+		}
+	}
+
+	fn peek(&self, offset: usize) -> Option<u8> {
+		self.code.get(self.pos + offset).copied()
+	}
+
+	/// Position of the first character at or after `pos` which is neither whitespace nor part of a comment.
+	fn significant_from(&self, mut pos: usize) -> Option<usize> {
+		loop {
+			let rest = self.code.get(pos..)?;
+			if rest.first()?.is_ascii_whitespace() {
+				pos += 1;
+			} else if rest.starts_with(b"//") {
+				pos += rest.iter().position(|&c| c == b'\n')?;
+			} else if rest.starts_with(b"/*") {
+				pos += 2 + rest[2..].windows(2).position(|w| w == b"*/")? + 2;
+			} else {
+				return Some(pos);
+			}
+		}
+	}
+}
+
+fn is_ident_part(c: u8) -> bool {
+	c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
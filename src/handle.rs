@@ -0,0 +1,64 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Opaque reference to a JavaScript function value, which need not be reachable through a global name.
+///
+/// Obtained with [`Script::call_handle()`](struct.Script.html#method.call_handle), for example from a factory function that
+/// returns a closure. Invoke it with [`Script::invoke()`](struct.Script.html#method.invoke).
+///
+/// A handle keeps the function alive inside its script until [`Script::release()`](struct.Script.html#method.release) is
+/// called. It is only valid for the script that created it; using it with another script fails.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct JsFunctionHandle {
+	pub(crate) script_id: u64,
+	pub(crate) id: u64,
+}
+
+/// Opaque reference to a JavaScript object, such as a class instance, which stays inside its script.
+///
+/// Obtained with [`Script::call_object()`](struct.Script.html#method.call_object), for example from a factory function that
+/// returns an instance. Its methods are invoked with [`Script::call_method()`](struct.Script.html#method.call_method); the object
+/// itself is never converted to JSON, so it keeps its prototype, private state and identity across calls.
+///
+/// A handle keeps the object alive inside its script until [`Script::release_object()`](struct.Script.html#method.release_object)
+/// is called. It is only valid for the script that created it; using it with another script fails.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct JsObjectHandle {
+	pub(crate) script_id: u64,
+	pub(crate) id: u64,
+}
+
+/// Opaque reference to an additional JavaScript context, with a global scope of its own, inside a script's isolate.
+///
+/// Obtained with [`Script::create_context()`](struct.Script.html#method.create_context). Its functions are invoked with
+/// [`Script::call_in_context()`](struct.Script.html#method.call_in_context).
+///
+/// A handle keeps the context alive until [`Script::release_context()`](struct.Script.html#method.release_context) is called.
+/// It is only valid for the script that created it; using it with another script fails.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct JsContextHandle {
+	pub(crate) script_id: u64,
+	pub(crate) id: u64,
+}
+
+/// A global JavaScript function resolved once, for repeated calls with argument type `A` and result type `R`.
+///
+/// Obtained with [`Script::bind()`](struct.Script.html#method.bind), and invoked with
+/// [`Script::call_bound()`](struct.Script.html#method.call_bound). Calls skip the name lookup and code generation of
+/// [`Script::call()`](struct.Script.html#method.call), which pays off in tight loops with thousands of calls.
+///
+/// The function stays bound even if the global is reassigned, until [`Script::unbind()`](struct.Script.html#method.unbind) is
+/// called. It is only valid for the script that created it; using it with another script fails.
+pub struct JsFunction<A, R> {
+	pub(crate) script_id: u64,
+	pub(crate) id: u64,
+	pub(crate) signature: PhantomData<fn(&A) -> R>,
+}
+
+impl<A, R> fmt::Debug for JsFunction<A, R> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("JsFunction").field("script_id", &self.script_id).field("id", &self.id).finish()
+	}
+}
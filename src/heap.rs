@@ -0,0 +1,74 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::Mutex;
+
+use deno_core::error::generic_error;
+use deno_core::v8;
+
+use crate::{AnyError, Script};
+
+/// Heap size limit which V8 applies by default, once determined.
+static DEFAULT_HEAP_LIMIT: Mutex<Option<usize>> = Mutex::new(None);
+
+/// Memory statistics of a script's V8 heap.
+///
+/// Obtained through [`Script::heap_stats()`](struct.Script.html#method.heap_stats). All sizes are in bytes, and refer to the
+/// JavaScript heap of this script only, not to the memory of the whole process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeapStats {
+	/// Memory currently occupied by live and not yet collected JS objects.
+	pub used_heap_size: usize,
+	/// Memory reserved by V8 for the heap, including unused parts.
+	pub total_heap_size: usize,
+	/// Maximum size the heap may grow to.
+	pub heap_size_limit: usize,
+	/// Memory allocated outside the heap on behalf of JS objects, e.g. for `ArrayBuffer` contents.
+	pub external_memory: usize,
+	/// Memory allocated by V8 through `malloc`.
+	pub malloced_memory: usize,
+}
+
+impl HeapStats {
+	pub(crate) fn from_isolate(isolate: &mut v8::Isolate) -> Self {
+		let mut stats = v8::HeapStatistics::default();
+		isolate.get_heap_statistics(&mut stats);
+
+		HeapStats {
+			used_heap_size: stats.used_heap_size(),
+			total_heap_size: stats.total_heap_size(),
+			heap_size_limit: stats.heap_size_limit(),
+			external_memory: stats.external_memory(),
+			malloced_memory: stats.malloced_memory(),
+		}
+	}
+}
+
+/// Parameters for an isolate whose heap starts out with `initial` bytes and may grow to `max` bytes; see
+/// ScriptBuilder::initial_heap_size() and ScriptBuilder::max_heap_size().
+pub(crate) fn heap_params(initial: Option<usize>, max: Option<usize>) -> Result<v8::CreateParams, AnyError> {
+	// V8 configures the initial size only together with the maximum, so the default maximum is kept if none is given
+	let max = match max {
+		Some(max) => max,
+		None => default_heap_limit()?,
+	};
+	let initial = initial.unwrap_or(0);
+	if initial > max {
+		let message = format!("initial heap size of {} bytes exceeds the heap size limit of {} bytes", initial, max);
+		return Err(generic_error(message));
+	}
+
+	Ok(v8::Isolate::create_params().heap_limits(initial, max))
+}
+
+/// Heap size limit of an isolate created without heap settings; measured on first use, as it depends on the machine.
+fn default_heap_limit() -> Result<usize, AnyError> {
+	let mut limit = DEFAULT_HEAP_LIMIT.lock().map_err(|_| generic_error("heap limit lock poisoned"))?;
+	if let Some(limit) = *limit {
+		return Ok(limit);
+	}
+
+	let measured = Script::create_runtime()?.heap_stats().heap_size_limit;
+	*limit = Some(measured);
+	Ok(measured)
+}
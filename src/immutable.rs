@@ -0,0 +1,118 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::args;
+use crate::bytes;
+use crate::{AnyError, Script, ScriptThread};
+
+/// Source code shared by all threads calling an immutable script.
+struct SharedCode {
+	id: u64,
+	js_code: String,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+	// Workers serving this thread, keyed by the ID of their shared code.
+	// The weak reference detects workers whose ImmutableScript has been dropped in the meantime.
+	static INSTANCES: RefCell<HashMap<u64, (Weak<SharedCode>, ScriptThread)>> = RefCell::new(HashMap::new());
+}
+
+/// Stateless script which can be called from multiple threads at the same time.
+///
+/// A [`Script`](struct.Script.html) owns a V8 isolate, which can only be used by one thread. `ImmutableScript` instead
+/// holds just the source code, and lazily creates one `Script` per calling thread, which is then reused for all calls on
+/// that thread. Calls never wait for each other, and cloning is cheap, so it can be shared behind an `Arc` or cloned into
+/// worker threads.
+///
+/// Each of these scripts lives on a [worker thread](struct.ScriptThread.html) of its own, to which the calling thread
+/// forwards its calls. V8 requires the isolates of a thread to be dropped in the reverse order of their creation; scripts
+/// created on first use and disposed along with their `ImmutableScript` would violate this as soon as a thread uses two
+/// immutable scripts, or an immutable script next to a `Script`. Since the calling thread holds no isolate itself, it can use
+/// any number of them together with its own scripts. In return, every call passes its arguments and result between threads,
+/// which is why the result type must be `Send`.
+///
+/// This is only correct if the script is stateless, i.e. calls do not depend on effects of previous calls: the user asserts
+/// this by choosing this type. A script that does modify its globals still works, but each thread observes only its own
+/// modifications. Workers serving other threads end when those threads next use any `ImmutableScript`, or exit.
+#[derive(Clone)]
+pub struct ImmutableScript {
+	code: Arc<SharedCode>,
+}
+
+impl ImmutableScript {
+	/// Creates an immutable script from the given JavaScript source code.
+	///
+	/// The code is executed once to validate it, by the worker which then serves calls from the current thread.
+	/// Fails in case of syntax or initialization error with the code.
+	pub fn from_string(js_code: &str) -> Result<Self, AnyError> {
+		let code = Arc::new(SharedCode {
+			id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+			js_code: js_code.to_string(),
+		});
+		let worker = Self::spawn_worker(&code)?;
+
+		INSTANCES.with(|instances| {
+			let mut instances = instances.borrow_mut();
+			instances.retain(|_, (code, _)| code.strong_count() > 0);
+			instances.insert(code.id, (Arc::downgrade(&code), worker));
+		});
+
+		Ok(ImmutableScript { code })
+	}
+
+	/// Invokes a JavaScript function, using the script of this thread's worker.
+	///
+	/// Arguments, result and timeout behave like in [`Script::call()`](struct.Script.html#method.call). The first call on a
+	/// thread spawns its worker, and thus takes additional time for executing the top-level code.
+	pub fn call<P, R>(&self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned + Send + 'static,
+	{
+		// Converted here, since the arguments are borrowed; the worker's call then converts the detached value
+		let args = bytes::detach(args::to_json(fn_name, args)?);
+		let fn_name = fn_name.to_string();
+
+		INSTANCES.with(|instances| {
+			let mut instances = instances.borrow_mut();
+			let key = self.code.id;
+
+			if !instances.contains_key(&key) {
+				instances.retain(|_, (code, _)| code.strong_count() > 0);
+				let worker = Self::spawn_worker(&self.code)?;
+				instances.insert(key, (Arc::downgrade(&self.code), worker));
+			}
+
+			let (_, worker) = &instances[&key];
+			worker.run(move |script| script.call(&fn_name, &args, timeout_ms))?
+		})
+	}
+
+	fn spawn_worker(code: &SharedCode) -> Result<ScriptThread, AnyError> {
+		let js_code = code.js_code.clone();
+		Script::on_thread(&format!("js-sandbox-immutable-{}", code.id), move || Script::from_string(&js_code))
+	}
+}
+
+impl Drop for ImmutableScript {
+	fn drop(&mut self) {
+		// The last clone ends the worker of the current thread right away
+		if Arc::strong_count(&self.code) == 1 {
+			let key = self.code.id;
+			let _ = INSTANCES.try_with(|instances| {
+				if let Ok(mut instances) = instances.try_borrow_mut() {
+					instances.remove(&key);
+				}
+			});
+		}
+	}
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use deno_core::error::generic_error;
+use deno_core::v8::inspector::{
+	ChannelBase, ChannelImpl, StringBuffer, StringView, V8Inspector, V8InspectorClientBase, V8InspectorClientImpl,
+	V8InspectorSession,
+};
+use deno_core::v8::{self, UniquePtr, UniqueRef};
+use deno_core::JsRuntime;
+use serde_json::json;
+
+use crate::{AnyError, JsValue};
+
+const CONTEXT_GROUP_ID: i32 = 1;
+
+/// In-process session of the V8 inspector, to send DevTools protocol commands to the script's isolate.
+///
+/// Must be dropped before the isolate. Fields are dropped in declaration order, which is also required here:
+/// V8 holds pointers to the boxed client and channel until the session and inspector are gone.
+pub(crate) struct Inspector {
+	session: UniqueRef<V8InspectorSession>,
+	_inspector: UniqueRef<V8Inspector>,
+	channel: Box<Channel>,
+	_client: Box<Client>,
+	next_id: i32,
+}
+
+impl Inspector {
+	pub fn new(runtime: &mut JsRuntime) -> Self {
+		let context = runtime.global_context();
+		let mut client = Box::new(Client { base: V8InspectorClientBase::new::<Client>() });
+		let mut channel = Box::new(Channel { base: ChannelBase::new::<Channel>(), responses: Vec::new() });
+
+		let mut inspector = V8Inspector::create(runtime.v8_isolate(), &mut *client);
+		{
+			let scope = &mut v8::HandleScope::with_context(runtime.v8_isolate(), context);
+			let context = scope.get_current_context();
+			inspector.context_created(context, CONTEXT_GROUP_ID, StringView::from(&b"js-sandbox"[..]));
+		}
+
+		let session = inspector.connect(CONTEXT_GROUP_ID, &mut *channel, StringView::from(&b"{}"[..]));
+
+		Inspector { session, _inspector: inspector, channel, _client: client, next_id: 1 }
+	}
+
+	/// Sends a protocol command and returns its result, e.g. `send("Profiler.start", json!({}))`.
+	pub fn send(&mut self, method: &str, params: JsValue) -> Result<JsValue, AnyError> {
+		let id = self.dispatch(method, params);
+
+		// Most commands used by js-sandbox are answered synchronously
+		match self.take_response(id, method) {
+			Some(result) => result,
+			None => Err(generic_error(format!("inspector did not respond to '{}'", method))),
+		}
+	}
+
+	/// Sends a protocol command without waiting for its response; returns the id to pass to `take_response()`.
+	///
+	/// Needed for commands which are answered only once the event loop has made progress, e.g. awaiting a promise.
+	pub fn dispatch(&mut self, method: &str, params: JsValue) -> i32 {
+		let id = self.next_id;
+		self.next_id += 1;
+
+		let message = json!({ "id": id, "method": method, "params": params }).to_string();
+		self.session.dispatch_protocol_message(StringView::from(message.as_bytes()));
+		id
+	}
+
+	/// Returns the result of the command with the given id, or `None` if it has not been answered yet.
+	pub fn take_response(&mut self, id: i32, method: &str) -> Option<Result<JsValue, AnyError>> {
+		let index = self.channel.responses.iter().position(|(response_id, _)| *response_id == id)?;
+		let (_, response) = self.channel.responses.remove(index);
+
+		let mut response: JsValue = match serde_json::from_str(&response) {
+			Ok(response) => response,
+			Err(e) => return Some(Err(e.into())),
+		};
+		if let Some(error) = response.get("error") {
+			return Some(Err(generic_error(format!("inspector command '{}' failed: {}", method, error))));
+		}
+
+		Some(Ok(response["result"].take()))
+	}
+}
+
+struct Client {
+	base: V8InspectorClientBase,
+}
+
+impl V8InspectorClientImpl for Client {
+	fn base(&self) -> &V8InspectorClientBase {
+		&self.base
+	}
+
+	fn base_mut(&mut self) -> &mut V8InspectorClientBase {
+		&mut self.base
+	}
+}
+
+struct Channel {
+	base: ChannelBase,
+	responses: Vec<(i32, String)>,
+}
+
+impl ChannelImpl for Channel {
+	fn base(&self) -> &ChannelBase {
+		&self.base
+	}
+
+	fn base_mut(&mut self) -> &mut ChannelBase {
+		&mut self.base
+	}
+
+	fn send_response(&mut self, call_id: i32, message: UniquePtr<StringBuffer>) {
+		if let Some(message) = message.as_ref() {
+			self.responses.push((call_id, message.string().to_string()));
+		}
+	}
+
+	fn send_notification(&mut self, _message: UniquePtr<StringBuffer>) {}
+
+	fn flush_protocol_notifications(&mut self) {}
+}
@@ -0,0 +1,33 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Parsing of the JSON that JS sends to Rust, see toHost() in prelude.js.
+// JSON.stringify() turns negative zero into 0, so JS sends it as a marker object instead, which is replaced here by -0.0.
+
+use serde_json::Number;
+
+use crate::JsValue;
+
+/// Key of the JSON object which represents negative zero on its way from JS to Rust.
+const NEGATIVE_ZERO_KEY: &str = "__rust_negative_zero";
+
+/// Parses JSON produced by JS, restoring the values that JSON cannot represent.
+pub(crate) fn from_js(json: &str) -> Result<JsValue, serde_json::Error> {
+	let mut value = serde_json::from_str(json)?;
+
+	// Most values contain no marker, and need not be traversed
+	if json.contains(NEGATIVE_ZERO_KEY) {
+		restore_negative_zero(&mut value);
+	}
+	Ok(value)
+}
+
+fn restore_negative_zero(value: &mut JsValue) {
+	match value {
+		JsValue::Object(object) if object.len() == 1 && object.get(NEGATIVE_ZERO_KEY) == Some(&JsValue::Bool(true)) => {
+			*value = Number::from_f64(-0.0).map_or(JsValue::Null, JsValue::Number);
+		}
+		JsValue::Object(object) => object.values_mut().for_each(restore_negative_zero),
+		JsValue::Array(array) => array.iter_mut().for_each(restore_negative_zero),
+		_ => {}
+	}
+}
@@ -94,8 +94,8 @@
 //! 	let mut script = Script::from_string(src)
 //! 		.expect("Initialization succeeds");
 //!
-//! 	let _: () = script.call("append", &"hello", None).unwrap();
-//! 	let _: () = script.call("append", &" world", None).unwrap();
+//! 	script.call_void("append", &"hello", None).unwrap();
+//! 	script.call_void("append", &" world", None).unwrap();
 //! 	let result: String = script.call("get", &(), None).unwrap();
 //!
 //! 	assert_eq!(result, "hello world");
@@ -103,13 +103,40 @@
 //! }
 //! ```
 //!
+//! Top-level declarations with `var` and `function` become properties of `globalThis`. Those with `let`, `const` and `class`
+//! live in the global lexical scope instead: they are equally visible to all subsequent calls, but not accessible as
+//! `globalThis.name`. Use `ScriptBuilder::expose_lexical_globals()` to make them available there as well.
+//!
+//! ## Call Rust from JavaScript
+//!
+//! Scripts can use services of the host application, such as lookups or logging, through Rust functions registered with
+//! `add_fn()`. They are available as methods of the `host` global, and transport their argument and result as JSON:
+//!
+//! ```rust
+//! use js_sandbox::{Script, AnyError};
+//!
+//! fn main() -> Result<(), AnyError> {
+//! 	let src = "function describe(key) { return key + ' = ' + host.get_config(key); }";
+//!
+//! 	let mut script = Script::builder()
+//! 		.add_fn("get_config", |key| Ok(if key == "level" { 3.into() } else { js_sandbox::JsValue::Null }))
+//! 		.build(src)?;
+//!
+//! 	let result: String = script.call("describe", &"level", None)?;
+//! 	assert_eq!(result, "level = 3");
+//! 	Ok(())
+//! }
+//! ```
+//!
+//! An error returned by the Rust function is thrown as a JS `Error`, which the script can catch.
+//!
 //! ## Call a script with timeout
 //!
 //! The JS code may contain long or forever running loops, that block Rust code. It is possible to set
 //! a timeout after which JS script execution is aborted.
 //!
 //! ```rust
-//! use js_sandbox::{Script, AnyError};
+//! use js_sandbox::{Script, AnyError, JsError};
 //!
 //! fn main() -> Result<(), AnyError> {
 //! 	let js_code = "function run_forever() { for(;;){} }";
@@ -117,7 +144,7 @@
 //!
 //! 	let result: Result<String, AnyError> = script.call("run_forever", &(), Some(1000));
 //!
-//! 	debug_assert_eq!(result.unwrap_err().to_string(), "Uncaught Error: execution terminated".to_string());
+//! 	debug_assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Timeout));
 //!
 //! 	Ok(())
 //! }
@@ -127,8 +154,36 @@
 //! [serde_json]: https://docs.serde.rs/serde_json
 
 
+pub use api::{HostApi, HostMethod};
+pub use bigint::BigInt;
+pub use builder::ScriptBuilder;
+pub use bytes::Bytes;
+pub use cancel::{CancellationToken, TerminationHandle};
+pub use compat::CompatEnv;
+pub use compiled::CompiledScript;
+pub use console::{LogLevel, LogRecord, Output};
+pub use date::Date;
+pub use engine::{enable_isolate_reuse, engine_version, set_v8_flags, EngineVersion};
+pub use error::JsError;
+pub use fetch::FetchOptions;
+pub use fs::VirtualFs;
+pub use handle::{JsContextHandle, JsFunction, JsFunctionHandle, JsObjectHandle};
+pub use heap::HeapStats;
+pub use immutable::ImmutableScript;
+pub use outcome::CallOutcome;
+pub use poll::PollDecision;
+pub use policy::{Capability, SandboxPolicy};
+pub use pool::{PoolStats, ScriptPool, Submission, WorkerStats};
+pub use profile::{CpuProfile, ProfileNode};
 pub use script::Script;
+pub use snapshot::Snapshot;
+pub use source::{SourceIndex, SourcePosition};
+pub use stats::ScriptStats;
+pub use stream::{JsIter, JsStream};
+pub use this_arg::ThisArg;
+pub use thread::ScriptThread;
 pub use util::eval_json;
+pub use wasm::WasmOptions;
 
 /// Represents a value passed to or from JavaScript.
 ///
@@ -142,5 +197,47 @@ pub type JsValue = serde_json::Value;
 pub type AnyError = deno_core::error::AnyError;
 
 
+mod api;
+mod args;
+mod bigint;
+mod builder;
+mod bytes;
+mod cancel;
+mod compat;
+mod compiled;
+mod console;
+mod date;
+mod engine;
+mod error;
+mod fetch;
+mod fields;
+mod fs;
+mod gas;
+mod handle;
+mod heap;
+mod immutable;
+mod inspector;
+mod json;
+mod messages;
+mod module;
+mod ops;
+mod outcome;
+mod poll;
+mod policy;
+mod pool;
+mod profile;
+mod schema;
 mod script;
+mod snapshot;
+mod source;
+mod source_map;
+mod stats;
+mod stream;
+mod termination;
+mod this_arg;
+mod thread;
+mod typed;
+#[cfg(feature = "typescript")]
+mod typescript;
 mod util;
+mod wasm;
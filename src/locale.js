@@ -0,0 +1,75 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Default locale and time zone of locale-aware formatting, see ScriptBuilder::locale() and ScriptBuilder::timezone().
+// Each Intl constructor and toLocale*() method is wrapped to fill in the defaults, where the script passes none itself.
+// Invalid defaults throw a RangeError right away, which makes building the script fail.
+
+((globalThis, config) => {
+	const locale = config.locale ?? undefined;
+	const timeZone = config.timeZone ?? undefined;
+	Intl.getCanonicalLocales(locale);
+	new Intl.DateTimeFormat(locale, { timeZone });
+
+	const withLocale = locales => locales ?? locale;
+	const withTimeZone = options => {
+		if (timeZone === undefined || options === null || (options !== undefined && options.timeZone !== undefined)) {
+			return options;
+		}
+		return { ...options, timeZone };
+	};
+
+	function replace(object, name, wrapper) {
+		Object.defineProperty(wrapper, "name", { value: name });
+		Object.defineProperty(object, name, { value: wrapper, writable: true, configurable: true });
+	}
+
+	function wrapConstructor(name, withOptions) {
+		const Original = Intl[name];
+		if (Original === undefined) {
+			return;
+		}
+
+		const wrapper = function (locales, options) {
+			const args = [withLocale(locales), withOptions(options)];
+			return new.target === undefined ? Original(...args) : Reflect.construct(Original, args, new.target);
+		};
+		for (const key of Object.getOwnPropertyNames(Original)) {
+			if (!["length", "name", "prototype", "caller", "arguments"].includes(key)) {
+				Object.defineProperty(wrapper, key, Object.getOwnPropertyDescriptor(Original, key));
+			}
+		}
+		wrapper.prototype = Original.prototype;
+		Object.defineProperty(Original.prototype, "constructor", { value: wrapper, writable: true, configurable: true });
+		replace(Intl, name, wrapper);
+	}
+
+	wrapConstructor("DateTimeFormat", withTimeZone);
+	for (const name of ["Collator", "DisplayNames", "ListFormat", "NumberFormat", "PluralRules", "RelativeTimeFormat", "Segmenter"]) {
+		wrapConstructor(name, options => options);
+	}
+
+	for (const name of ["toLocaleString", "toLocaleDateString", "toLocaleTimeString"]) {
+		const original = Date.prototype[name];
+		replace(Date.prototype, name, function (locales, options) {
+			return original.call(this, withLocale(locales), withTimeZone(options));
+		});
+	}
+
+	for (const prototype of [Number.prototype, BigInt.prototype]) {
+		const original = prototype.toLocaleString;
+		replace(prototype, "toLocaleString", function (locales, options) {
+			return original.call(this, withLocale(locales), options);
+		});
+	}
+
+	const { localeCompare, toLocaleLowerCase, toLocaleUpperCase } = String.prototype;
+	replace(String.prototype, "localeCompare", function (that, locales, options) {
+		return localeCompare.call(this, that, withLocale(locales), options);
+	});
+	replace(String.prototype, "toLocaleLowerCase", function (locales) {
+		return toLocaleLowerCase.call(this, withLocale(locales));
+	});
+	replace(String.prototype, "toLocaleUpperCase", function (locales) {
+		return toLocaleUpperCase.call(this, withLocale(locales));
+	});
+})
@@ -0,0 +1,33 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Message channel between script and host, see ScriptBuilder::message_channel().
+// Messages from JS are posted through an op, and either queued until the host polls them, or passed to a handler right away.
+// Messages from the host are dispatched to the script's `onmessage` handler by prelude.js.
+
+use std::collections::VecDeque;
+
+use deno_core::{JsRuntime, OpState, ZeroCopyBuf};
+
+use crate::json;
+use crate::{AnyError, JsValue};
+
+/// Destination of messages posted by the script, stored in the op state once the channel is enabled.
+pub(crate) enum MessageSink {
+	Queue(VecDeque<JsValue>),
+	Handler(Box<dyn FnMut(JsValue)>),
+}
+
+/// Registers the op behind `host.postMessage()`, with messages queued until polled.
+pub(crate) fn install(runtime: &mut JsRuntime) {
+	runtime.register_op("__rust_post_message", deno_core::op_sync(op_post_message));
+	runtime.op_state().borrow_mut().put(MessageSink::Queue(VecDeque::new()));
+}
+
+fn op_post_message(state: &mut OpState, json: String, _buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let message = json::from_js(&json)?;
+	match state.borrow_mut::<MessageSink>() {
+		MessageSink::Queue(queue) => queue.push_back(message),
+		MessageSink::Handler(handler) => handler(message),
+	}
+	Ok(JsValue::Null)
+}
@@ -0,0 +1,103 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use deno_core::error::generic_error;
+use deno_core::futures::future;
+use deno_core::{ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, OpState};
+
+use crate::builder;
+use crate::{AnyError, JsError};
+
+/// Loads ES modules from disk, restricted to the files below a root directory; see ScriptBuilder::build_module().
+pub(crate) struct SandboxLoader {
+	root: PathBuf,
+	/// Canonical paths of the files that may be imported, besides the entry module; `None` allows all files below the root
+	allowed: Option<Vec<PathBuf>>,
+	max_source_bytes: Option<u64>,
+}
+
+impl SandboxLoader {
+	/// Creates a loader for `root`, which defaults to the directory of the entry module. Paths in `allowed` are relative to the root.
+	pub fn new(entry: &Path, root: Option<&Path>, allowed: Option<&[PathBuf]>, max_source_bytes: Option<u64>) -> Result<Self, AnyError> {
+		let root = match root {
+			Some(root) => root.canonicalize().map_err(|e| AnyError::from(e).context("module root directory is not accessible"))?,
+			None => {
+				let entry = entry.canonicalize()?;
+				entry.parent().unwrap_or(&entry).to_path_buf()
+			}
+		};
+
+		// Files which do not exist cannot be imported anyway, so they are left out
+		let allowed = allowed.map(|paths| paths.iter().filter_map(|path| root.join(path).canonicalize().ok()).collect());
+
+		Ok(SandboxLoader { root, allowed, max_source_bytes })
+	}
+
+	/// Returns the specifier of the entry module, which must lie below the root.
+	pub fn entry_specifier(&self, entry: &Path) -> Result<ModuleSpecifier, AnyError> {
+		let path = entry.canonicalize()?;
+		if !path.starts_with(&self.root) {
+			return Err(generic_error(format!("module '{}' is outside of the module root", entry.display())));
+		}
+		ModuleSpecifier::from_file_path(&path).map_err(|_| generic_error(format!("invalid module path '{}'", entry.display())))
+	}
+
+	/// Reads an imported module, after checking that it may be imported.
+	fn read(&self, specifier: &ModuleSpecifier, is_entry: bool) -> Result<ModuleSource, AnyError> {
+		let path = specifier
+			.to_file_path()
+			.map_err(|_| generic_error(format!("cannot import '{}': only files can be imported", specifier)))?
+			.canonicalize()
+			.map_err(|e| AnyError::from(e).context(format!("cannot import '{}'", specifier)))?;
+
+		let is_allowed = match &self.allowed {
+			Some(allowed) => is_entry || allowed.contains(&path),
+			None => true,
+		};
+		if !path.starts_with(&self.root) || !is_allowed {
+			return Err(generic_error(format!("importing '{}' is not allowed", specifier)));
+		}
+
+		if let Some(limit) = self.max_source_bytes {
+			let size = std::fs::metadata(&path)?.len();
+			if size > limit {
+				return Err(JsError::SourceTooLarge { size, limit }.into());
+			}
+		}
+
+		let mut code = std::fs::read_to_string(&path)?;
+		if path.extension().map_or(false, |extension| extension == "ts") {
+			code = builder::transpile_typescript(specifier.as_str(), &code, true)?;
+		}
+
+		Ok(ModuleSource {
+			code,
+			module_url_specified: specifier.to_string(),
+			module_url_found: specifier.to_string(),
+		})
+	}
+}
+
+impl ModuleLoader for SandboxLoader {
+	fn resolve(&self, _state: Rc<RefCell<OpState>>, specifier: &str, referrer: &str, _is_main: bool) -> Result<ModuleSpecifier, AnyError> {
+		// Bare specifiers such as "lodash" would need a package resolution, which the sandbox does not offer
+		if !specifier.starts_with("./") && !specifier.starts_with("../") && !specifier.starts_with('/') && !specifier.starts_with("file:") {
+			return Err(generic_error(format!("cannot import '{}': only relative paths are supported", specifier)));
+		}
+		Ok(deno_core::resolve_import(specifier, referrer)?)
+	}
+
+	fn load(
+		&self,
+		_state: Rc<RefCell<OpState>>,
+		specifier: &ModuleSpecifier,
+		referrer: Option<ModuleSpecifier>,
+		_is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		Box::pin(future::ready(self.read(specifier, referrer.is_none())))
+	}
+}
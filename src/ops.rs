@@ -0,0 +1,144 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+
+use deno_core::error::generic_error;
+use deno_core::{v8, JsRuntime, OpState};
+
+use crate::termination::Terminator;
+use crate::{AnyError, JsError, JsValue};
+
+/// Rust function that can be called from JavaScript.
+pub(crate) type HostFn = dyn Fn(JsValue) -> Result<JsValue, AnyError> + 'static;
+
+/// Numeric Rust function that can be called from JavaScript, without going through JSON.
+pub(crate) type FastFn = dyn Fn(f64) -> f64 + 'static;
+
+/// Fast function together with its name, referenced by the V8 function object.
+pub(crate) struct FastFnEntry {
+	pub name: String,
+	f: Box<FastFn>,
+}
+
+/// Bookkeeping for host functions, stored in the op state of each script.
+#[derive(Default)]
+pub(crate) struct HostFns {
+	/// Maximum number of invocations per call, by function name
+	pub limits: HashMap<String, u64>,
+
+	/// Number of invocations during the current call, by function name
+	pub calls: HashMap<String, u64>,
+
+	/// Number of invocations over the script's lifetime, by function name; contains every registered function
+	pub totals: HashMap<String, u64>,
+}
+
+impl HostFns {
+	/// Name under which the host function `name` is registered as a deno_core op
+	pub fn op_name(name: &str) -> String {
+		format!("host:{}", name)
+	}
+
+	/// Counts one invocation of `name`, and aborts the script if this exceeds the function's limit.
+	pub fn record_call(state: &mut OpState, name: &str) -> Result<(), AnyError> {
+		let fns = state.borrow_mut::<HostFns>();
+		*fns.totals.entry(name.to_string()).or_insert(0) += 1;
+
+		let count = fns.calls.entry(name.to_string()).or_insert(0);
+		*count += 1;
+
+		match fns.limits.get(name) {
+			Some(&limit) if *count > limit => {
+				let error = JsError::OpLimitExceeded { op: name.to_string(), limit };
+				state.borrow::<Terminator>().terminate(error.clone());
+				Err(error.into())
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Invokes a host function, converting a panic into an error so it cannot unwind into V8.
+pub(crate) fn call_host_fn(name: &str, f: &HostFn, arg: JsValue) -> Result<JsValue, AnyError> {
+	match panic::catch_unwind(AssertUnwindSafe(|| f(arg))) {
+		Ok(result) => result,
+		Err(payload) => Err(generic_error(format!("host function '{}' panicked: {}", name, panic_message(&payload)))),
+	}
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+	if let Some(s) = payload.downcast_ref::<&str>() {
+		s
+	} else if let Some(s) = payload.downcast_ref::<String>() {
+		s
+	} else {
+		"<non-string panic payload>"
+	}
+}
+
+/// Binds `f` as native V8 function `<namespace>.<name>`, bypassing ops and serialization.
+///
+/// The returned entry is referenced by raw pointer from the JS function, so it must outlive the runtime.
+pub(crate) fn install_fast_fn(
+	runtime: &mut JsRuntime,
+	namespace: &str,
+	name: &str,
+	f: Box<FastFn>,
+) -> Result<Box<FastFnEntry>, AnyError> {
+	let entry = Box::new(FastFnEntry { name: name.to_string(), f });
+
+	let context = runtime.global_context();
+	let scope = &mut v8::HandleScope::with_context(runtime.v8_isolate(), context);
+	let global = scope.get_current_context().global(scope);
+
+	let host_key = v8::String::new(scope, namespace).ok_or_else(|| generic_error("host namespace is too long"))?;
+	let host = match global.get(scope, host_key.into()) {
+		Some(host) if host.is_object() => v8::Local::<v8::Object>::try_from(host).unwrap(),
+		_ => {
+			let host = v8::Object::new(scope);
+			global.set(scope, host_key.into(), host.into());
+			host
+		}
+	};
+
+	let name_key = v8::String::new(scope, name).ok_or_else(|| generic_error("host function name is too long"))?;
+	if host.has(scope, name_key.into()) == Some(true) {
+		return Err(generic_error(format!("host function '{}' is already registered", name)));
+	}
+
+	let data = v8::External::new(scope, &*entry as *const FastFnEntry as *mut std::ffi::c_void);
+	let function = v8::Function::builder(fast_fn_callback)
+		.data(data.into())
+		.length(1)
+		.build(scope)
+		.ok_or_else(|| generic_error(format!("cannot create host function '{}'", name)))?;
+	host.set(scope, name_key.into(), function.into());
+
+	Ok(entry)
+}
+
+fn fast_fn_callback(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+	let data = v8::Local::<v8::External>::try_from(args.data().unwrap()).unwrap();
+	// SAFETY: entry is kept alive by the script, see install_fast_fn()
+	let entry = unsafe { &*(data.value() as *const FastFnEntry) };
+
+	// Converts like JS's Number(); may throw, e.g. in valueOf()
+	let arg = match args.get(0).number_value(scope) {
+		Some(arg) => arg,
+		None => return,
+	};
+
+	match panic::catch_unwind(AssertUnwindSafe(|| (entry.f)(arg))) {
+		Ok(result) => rv.set(v8::Number::new(scope, result).into()),
+		Err(payload) => {
+			let message = format!("host function '{}' panicked: {}", entry.name, panic_message(&payload));
+			if let Some(message) = v8::String::new(scope, &message) {
+				let exception = v8::Exception::error(scope, message);
+				scope.throw_exception(exception);
+			}
+		}
+	}
+}
@@ -0,0 +1,32 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use crate::JsError;
+
+/// Whether a call ran to completion, as returned by [`Script::call_outcome()`](struct.Script.html#method.call_outcome).
+///
+/// A terminated call may have been interrupted at any point, so side effects it had on the script's state can be partial.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallOutcome<R> {
+	/// The function returned normally, with the given result.
+	Completed(R),
+	/// Execution was aborted before the function returned, for the given reason.
+	///
+	/// The reason is one of [`JsError::Timeout`](enum.JsError.html#variant.Timeout), [`Cancelled`](enum.JsError.html#variant.Cancelled),
+	/// [`OpLimitExceeded`](enum.JsError.html#variant.OpLimitExceeded) or [`GasExhausted`](enum.JsError.html#variant.GasExhausted).
+	Terminated(JsError),
+}
+
+impl<R> CallOutcome<R> {
+	/// Whether the function returned normally.
+	pub fn is_completed(&self) -> bool {
+		matches!(self, CallOutcome::Completed(_))
+	}
+
+	/// Returns the result of a completed call, or `None` if it was terminated.
+	pub fn completed(self) -> Option<R> {
+		match self {
+			CallOutcome::Completed(result) => Some(result),
+			CallOutcome::Terminated(_) => None,
+		}
+	}
+}
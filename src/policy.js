@@ -0,0 +1,42 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Capabilities of a script, see ScriptBuilder::policy().
+// Removes the globals of every capability the policy denies, as well as the internal Deno object.
+// Only the members of the Deno compatibility shim are kept, since they are backed by values from the host.
+
+((globalThis, config) => {
+	const removedGlobals = [];
+	if (!config.console) {
+		removedGlobals.push("console");
+	}
+	if (!config.timers) {
+		removedGlobals.push("setTimeout", "setInterval", "clearTimeout", "clearInterval");
+	}
+	if (!config.date) {
+		removedGlobals.push("Date");
+	}
+
+	for (const name of removedGlobals) {
+		delete globalThis[name];
+	}
+
+	if (!config.random) {
+		Object.defineProperty(Math, "random", {
+			value: () => {
+				throw new TypeError("Math.random() is not allowed by the sandbox policy");
+			},
+			writable: false,
+			configurable: false,
+		});
+	}
+
+	const deno = globalThis.Deno;
+	delete globalThis.Deno;
+	if (deno?.env !== undefined) {
+		Object.defineProperty(globalThis, "Deno", {
+			value: Object.freeze({ env: deno.env, build: deno.build }),
+			writable: true,
+			configurable: true,
+		});
+	}
+})
@@ -0,0 +1,126 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+/// A capability which a [`SandboxPolicy`](struct.SandboxPolicy.html) can grant to a script.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+	/// The `console` global, whose output reaches the host.
+	Console,
+	/// `setTimeout()`, `setInterval()` and their counterparts, driven by the host; see
+	/// [`ScriptBuilder::timers()`](struct.ScriptBuilder.html#method.timers).
+	Timers,
+	/// Rust functions registered by the host, e.g. through [`ScriptBuilder::add_fn()`](struct.ScriptBuilder.html#method.add_fn).
+	HostFns,
+	/// `fetch()`, within the limits set by [`ScriptBuilder::fetch()`](struct.ScriptBuilder.html#method.fetch), which must be
+	/// configured as well.
+	Fetch,
+	/// The `fs` global, limited to the virtual filesystem set by
+	/// [`ScriptBuilder::filesystem()`](struct.ScriptBuilder.html#method.filesystem), which must be configured as well.
+	FileSystem,
+	/// `Math.random()`.
+	Random,
+	/// The `Date` global, which reads the current time.
+	Date,
+}
+
+impl Capability {
+	/// All capabilities, in the order they are declared.
+	pub const ALL: [Capability; 7] = [
+		Capability::Console,
+		Capability::Timers,
+		Capability::HostFns,
+		Capability::Fetch,
+		Capability::FileSystem,
+		Capability::Random,
+		Capability::Date,
+	];
+}
+
+/// Capabilities granted to a script, as a single place to reason about what untrusted code can reach.
+///
+/// A new policy grants nothing: applied through [`ScriptBuilder::policy()`](struct.ScriptBuilder.html#method.policy), the script
+/// runs without console, timers, host functions, `fetch()`, files, `Math.random()` and `Date`. Each [`allow()`](#method.allow)
+/// opts into one [`Capability`](enum.Capability.html). Globals of denied capabilities are removed before the script's code
+/// runs, so using them throws a `ReferenceError` (or a `TypeError` for `Math.random()`), which the script can catch.
+/// Registering host functions without [`Capability::HostFns`](enum.Capability.html#variant.HostFns) fails, when building the
+/// script as well as afterwards. The internal `Deno` object, through which scripts could reach all ops, is removed in any case.
+///
+/// Scripts built without a policy keep the permissive defaults, in which every available capability is granted.
+///
+/// ```rust
+/// use js_sandbox::{AnyError, Capability, SandboxPolicy, Script};
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let policy = SandboxPolicy::new().allow(Capability::Console);
+/// 	let mut script = Script::builder()
+/// 		.policy(policy)
+/// 		.build("function kinds() { return [typeof console, typeof Date]; }")?;
+///
+/// 	let kinds: Vec<String> = script.call("kinds", &(), None)?;
+/// 	assert_eq!(kinds, ["object", "undefined"]);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SandboxPolicy {
+	allowed: BTreeSet<Capability>,
+}
+
+#[derive(Serialize)]
+struct PolicyConfig {
+	console: bool,
+	timers: bool,
+	random: bool,
+	date: bool,
+}
+
+impl SandboxPolicy {
+	/// Creates a policy which grants no capabilities.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a policy which grants all capabilities, as a starting point for [`deny()`](#method.deny).
+	pub fn permissive() -> Self {
+		SandboxPolicy { allowed: Capability::ALL.iter().copied().collect() }
+	}
+
+	/// Grants `capability`.
+	pub fn allow(mut self, capability: Capability) -> Self {
+		self.allowed.insert(capability);
+		self
+	}
+
+	/// Revokes `capability`.
+	pub fn deny(mut self, capability: Capability) -> Self {
+		self.allowed.remove(&capability);
+		self
+	}
+
+	/// Whether `capability` is granted.
+	pub fn allows(&self, capability: Capability) -> bool {
+		self.allowed.contains(&capability)
+	}
+
+	/// Lists the granted capabilities, in the order they are declared.
+	pub fn allowed(&self) -> impl Iterator<Item = Capability> + '_ {
+		self.allowed.iter().copied()
+	}
+
+	/// Returns JS code which removes the globals of denied capabilities.
+	pub(crate) fn js_code(&self) -> Result<String, serde_json::Error> {
+		let config = PolicyConfig {
+			console: self.allows(Capability::Console),
+			timers: self.allows(Capability::Timers),
+			random: self.allows(Capability::Random),
+			date: self.allows(Capability::Date),
+		};
+		let config = serde_json::to_string(&config)?;
+
+		Ok(format!("{}(globalThis, {});", include_str!("policy.js").trim_end(), config))
+	}
+}
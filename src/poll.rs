@@ -0,0 +1,115 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use deno_core::v8;
+
+use crate::termination::Terminator;
+use crate::JsError;
+
+/// Tells a call started with [`Script::poll_call()`](struct.Script.html#method.poll_call) how to proceed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PollDecision {
+	/// Keep running the call for another time slice.
+	Continue,
+	/// Abort the call; it then fails with [`JsError::Cancelled`](enum.JsError.html#variant.Cancelled).
+	Abort,
+}
+
+pub(crate) type PollFn<'a> = dyn FnMut(Duration) -> PollDecision + 'a;
+
+// Pointer to the poll callback, which lives on the stack of the JS thread.
+// Only ever dereferenced on that thread, inside an interrupt of the isolate.
+struct PollFnPtr(*mut PollFn<'static>);
+
+unsafe impl Send for PollFnPtr {}
+
+struct PollState {
+	poll_fn: Mutex<Option<PollFnPtr>>,
+	start: Instant,
+	terminator: Terminator,
+}
+
+/// Background thread which interrupts JS execution after every time slice, to let the poll callback decide how to proceed.
+///
+/// Like the timeout watchdog, the thread is joined when the ticker is stopped or dropped. Interrupts that are still pending at
+/// that point become no-ops, since the callback is unregistered first.
+pub(crate) struct PollTicker {
+	state: Arc<PollState>,
+	stop: Option<Sender<()>>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl PollTicker {
+	/// Starts ticking. `poll_fn` must stay alive until the ticker is stopped or dropped.
+	pub fn start(handle: v8::IsolateHandle, terminator: Terminator, slice: Duration, poll_fn: &mut PollFn<'_>) -> Self {
+		// SAFETY: erasing the lifetime is sound, because the pointer is removed from the state when the ticker is stopped or
+		// dropped, which the caller guarantees to happen while poll_fn is alive
+		let poll_fn: *mut PollFn<'_> = poll_fn;
+		let poll_fn: *mut PollFn<'static> = unsafe { std::mem::transmute(poll_fn) };
+
+		let state = Arc::new(PollState {
+			poll_fn: Mutex::new(Some(PollFnPtr(poll_fn))),
+			start: Instant::now(),
+			terminator,
+		});
+
+		let (stop, stopped) = mpsc::channel::<()>();
+		let thread_state = state.clone();
+
+		// Loop ends on stop signal, or when the ticker is dropped
+		let thread = thread::spawn(move || {
+			while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(slice) {
+				// Each interrupt owns one reference to the state, released in the callback
+				let data = Box::into_raw(Box::new(thread_state.clone()));
+				if !handle.request_interrupt(Self::on_interrupt, data as *mut c_void) {
+					// Isolate disposed, callback will never run
+					drop(unsafe { Box::from_raw(data) });
+					break;
+				}
+			}
+		});
+
+		PollTicker { state, stop: Some(stop), thread: Some(thread) }
+	}
+
+	pub fn stop(mut self) {
+		self.join();
+	}
+
+	fn join(&mut self) {
+		self.state.poll_fn.lock().unwrap().take();
+		self.stop.take();
+
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+
+	extern "C" fn on_interrupt(_isolate: &mut v8::Isolate, data: *mut c_void) {
+		let state = unsafe { Box::from_raw(data as *mut Arc<PollState>) };
+		let guard = state.poll_fn.lock().unwrap();
+
+		if let Some(PollFnPtr(poll_fn)) = *guard {
+			// SAFETY: registered pointers are valid, see start()
+			let poll_fn = unsafe { &mut *poll_fn };
+
+			// Unwinding into V8 is not possible; a panicking callback aborts the call
+			let decision = panic::catch_unwind(AssertUnwindSafe(|| poll_fn(state.start.elapsed())));
+			if !matches!(decision, Ok(PollDecision::Continue)) {
+				state.terminator.terminate(JsError::Cancelled);
+			}
+		}
+	}
+}
+
+impl Drop for PollTicker {
+	fn drop(&mut self) {
+		self.join();
+	}
+}
@@ -0,0 +1,376 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use deno_core::error::generic_error;
+
+use crate::args;
+use crate::bytes;
+use crate::{AnyError, JsError, Script, Snapshot};
+
+type Job = Box<dyn FnOnce() + Send>;
+type ScriptFactory = Arc<dyn Fn() -> Result<Script, AnyError> + Send + Sync>;
+
+thread_local! {
+	/// Script kept by the worker running on this thread, for ScriptPool::call(); `None` if the pool has no script factory.
+	static RESIDENT: RefCell<Option<Result<Script, AnyError>>> = RefCell::new(None);
+}
+
+/// Set of worker threads, each running submitted scripts in a fresh [`Script`](struct.Script.html).
+///
+/// A `Script` is bound to the thread that created it. The pool instead accepts independent units of work -- source code, a
+/// function name and its arguments -- and runs them on its own threads. Each submission goes to the worker with the fewest
+/// unfinished submissions, so a few expensive scripts do not hold up the cheap ones queued behind them.
+///
+/// A pool created with [`with_script()`](#method.with_script) or [`from_snapshot()`](#method.from_snapshot) additionally keeps
+/// one pre-warmed script per worker, on which [`call()`](#method.call) invokes functions without creating a script each time.
+///
+/// Dropping the pool waits for all submitted work to finish.
+pub struct ScriptPool {
+	workers: Vec<Worker>,
+}
+
+struct Worker {
+	sender: Mutex<Option<mpsc::Sender<Job>>>,
+	thread: Option<JoinHandle<()>>,
+	counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+	queued: AtomicUsize,
+	completed: AtomicU64,
+	busy_nanos: AtomicU64,
+}
+
+/// Load of a [`ScriptPool`](struct.ScriptPool.html), as returned by [`ScriptPool::stats()`](struct.ScriptPool.html#method.stats).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolStats {
+	/// One entry per worker, in the order of creation.
+	pub workers: Vec<WorkerStats>,
+}
+
+/// Load of a single worker in a [`ScriptPool`](struct.ScriptPool.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkerStats {
+	/// Submissions assigned to this worker that have not finished yet, including the one currently running.
+	pub queue_depth: usize,
+	/// Submissions this worker has finished, successfully or not.
+	pub completed: u64,
+	/// Total time this worker spent running submissions.
+	pub busy_time: Duration,
+}
+
+impl ScriptPool {
+	/// Creates a pool with the given number of worker threads.
+	///
+	/// Fails if `workers` is 0 or a thread cannot be spawned.
+	pub fn new(workers: usize) -> Result<Self, AnyError> {
+		Self::spawn(workers, None)
+	}
+
+	/// Creates a pool with the given number of worker threads, each of which keeps a script created by `create`.
+	///
+	/// Every worker runs `create` once on its own thread, when the pool is created, so that the cost of setting up a script is
+	/// not paid per request. Functions are invoked on these scripts with [`call()`](#method.call). If `create` fails on a
+	/// worker, calls assigned to that worker fail with the error message. [`submit()`](#method.submit) works as usual.
+	///
+	/// Fails if `workers` is 0 or a thread cannot be spawned.
+	pub fn with_script(
+		workers: usize,
+		create: impl Fn() -> Result<Script, AnyError> + Send + Sync + 'static,
+	) -> Result<Self, AnyError> {
+		Self::spawn(workers, Some(Arc::new(create)))
+	}
+
+	/// Creates a pool whose workers keep a script restored from `snapshot`; see [`with_script()`](#method.with_script).
+	///
+	/// Restoring a [`Snapshot`](struct.Snapshot.html) skips running the top-level code on each worker.
+	pub fn from_snapshot(workers: usize, snapshot: &Snapshot) -> Result<Self, AnyError> {
+		let snapshot = snapshot.clone();
+		Self::with_script(workers, move || snapshot.instantiate())
+	}
+
+	fn spawn(workers: usize, factory: Option<ScriptFactory>) -> Result<Self, AnyError> {
+		if workers == 0 {
+			return Err(generic_error("ScriptPool needs at least one worker"));
+		}
+
+		// If spawning fails, dropping the pool lets the workers spawned so far exit
+		let mut pool = ScriptPool { workers: Vec::with_capacity(workers) };
+		for index in 0..workers {
+			let (sender, receiver) = mpsc::channel::<Job>();
+			let factory = factory.clone();
+			let thread = thread::Builder::new()
+				.name(format!("js-sandbox-pool-{}", index))
+				.spawn(move || {
+					if let Some(factory) = factory {
+						let script = panic::catch_unwind(AssertUnwindSafe(|| factory()))
+							.unwrap_or_else(|_| Err(JsError::WorkerFailed.into()));
+						RESIDENT.with(|resident| *resident.borrow_mut() = Some(script));
+					}
+
+					for job in receiver {
+						// A panicking job reports a worker error to its submission, but leaves the worker running
+						let _ = panic::catch_unwind(AssertUnwindSafe(job));
+					}
+
+					// Dispose of the script before thread-local storage is torn down
+					RESIDENT.with(|resident| resident.borrow_mut().take());
+				})?;
+
+			pool.workers.push(Worker {
+				sender: Mutex::new(Some(sender)),
+				thread: Some(thread),
+				counters: Arc::new(Counters::default()),
+			});
+		}
+
+		Ok(pool)
+	}
+
+	/// Runs a JavaScript function on the least busy worker.
+	///
+	/// The worker creates a script from `js_code` and invokes `fn_name` on it, with `args` and `timeout_ms` behaving like in
+	/// [`Script::call()`](struct.Script.html#method.call). Every submission gets its own script, so submissions cannot observe each other.
+	///
+	/// The returned [`Submission`](struct.Submission.html) is a future resolving to the function's result, or to the error from
+	/// creating the script or calling it. If the worker fails before completing the submission, the result is
+	/// [`JsError::WorkerFailed`](enum.JsError.html#variant.WorkerFailed).
+	pub fn submit<P, R>(&self, js_code: &str, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Submission<R>
+	where
+		P: Serialize,
+		R: DeserializeOwned + Send + 'static,
+	{
+		let (submission, completer) = Submission::new();
+
+		// Converted here, since the arguments are borrowed; the worker's call then converts the detached value
+		let args = match args::to_json(fn_name, args) {
+			Ok(args) => bytes::detach(args),
+			Err(e) => {
+				completer.complete(Err(e));
+				return submission;
+			}
+		};
+
+		let js_code = js_code.to_string();
+		let fn_name = fn_name.to_string();
+		self.dispatch(completer, move || {
+			Script::from_string(&js_code).and_then(|mut script| script.call(&fn_name, &args, timeout_ms))
+		});
+
+		submission
+	}
+
+	/// Invokes a JavaScript function on the script kept by the least busy worker.
+	///
+	/// Arguments, result and timeout behave like in [`Script::call()`](struct.Script.html#method.call), and the returned
+	/// [`Submission`](struct.Submission.html) like for [`submit()`](#method.submit), e.g. `pool.call("render", &page, None).await`.
+	/// Each worker's script keeps its globals across calls, so functions called this way should not depend on earlier calls
+	/// having run on the same worker. Fails if the pool was created without script, with [`new()`](#method.new).
+	pub fn call<P, R>(&self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Submission<R>
+	where
+		P: Serialize,
+		R: DeserializeOwned + Send + 'static,
+	{
+		let (submission, completer) = Submission::new();
+
+		let args = match args::to_json(fn_name, args) {
+			Ok(args) => bytes::detach(args),
+			Err(e) => {
+				completer.complete(Err(e));
+				return submission;
+			}
+		};
+
+		let fn_name = fn_name.to_string();
+		self.dispatch(completer, move || {
+			RESIDENT.with(|resident| match &mut *resident.borrow_mut() {
+				Some(Ok(script)) => script.call(&fn_name, &args, timeout_ms),
+				Some(Err(e)) => Err(generic_error(format!("script of pool worker could not be created: {}", e))),
+				None => Err(generic_error("ScriptPool::call() requires a pool created with a script")),
+			})
+		});
+
+		submission
+	}
+
+	/// Queues `run` on the least busy worker, which completes the submission with its result.
+	fn dispatch<R>(&self, completer: Completer<R>, run: impl FnOnce() -> Result<R, AnyError> + Send + 'static)
+	where
+		R: Send + 'static,
+	{
+		let worker = self
+			.workers
+			.iter()
+			.min_by_key(|worker| worker.counters.queued.load(Ordering::SeqCst))
+			.expect("Pool has at least one worker");
+
+		let counters = worker.counters.clone();
+		counters.queued.fetch_add(1, Ordering::SeqCst);
+
+		let job: Job = Box::new(move || {
+			let finished = Finished { counters, start: Instant::now() };
+			let result = run();
+
+			// Counters are up to date once the submitter sees the result
+			drop(finished);
+			completer.complete(result);
+		});
+
+		let sender = worker.sender.lock().expect("Pool sender not poisoned");
+		if let Some(sender) = sender.as_ref() {
+			// If the worker is gone, the job is dropped with its completer, which reports the failure
+			let _ = sender.send(job);
+		}
+	}
+
+	/// Returns the current load of each worker.
+	pub fn stats(&self) -> PoolStats {
+		let workers = self
+			.workers
+			.iter()
+			.map(|worker| WorkerStats {
+				queue_depth: worker.counters.queued.load(Ordering::SeqCst),
+				completed: worker.counters.completed.load(Ordering::SeqCst),
+				busy_time: Duration::from_nanos(worker.counters.busy_nanos.load(Ordering::SeqCst)),
+			})
+			.collect();
+
+		PoolStats { workers }
+	}
+}
+
+impl Drop for ScriptPool {
+	fn drop(&mut self) {
+		// Closing the channels lets the workers finish their queues and exit
+		for worker in &self.workers {
+			if let Ok(mut sender) = worker.sender.lock() {
+				sender.take();
+			}
+		}
+
+		for worker in &mut self.workers {
+			if let Some(thread) = worker.thread.take() {
+				let _ = thread.join();
+			}
+		}
+	}
+}
+
+// Updates a worker's counters when a job ends, even by panic.
+struct Finished {
+	counters: Arc<Counters>,
+	start: Instant,
+}
+
+impl Drop for Finished {
+	fn drop(&mut self) {
+		let busy = self.start.elapsed().as_nanos() as u64;
+		self.counters.busy_nanos.fetch_add(busy, Ordering::SeqCst);
+		self.counters.completed.fetch_add(1, Ordering::SeqCst);
+		self.counters.queued.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/// Pending result of [`ScriptPool::submit()`](struct.ScriptPool.html#method.submit).
+///
+/// This is a future, which can be awaited from any executor. Code outside of an async context can use
+/// [`wait()`](#method.wait) instead, which blocks the current thread.
+pub struct Submission<R> {
+	slot: Arc<Slot<R>>,
+}
+
+struct Slot<R> {
+	state: Mutex<SlotState<R>>,
+	ready: Condvar,
+}
+
+struct SlotState<R> {
+	result: Option<Result<R, AnyError>>,
+	waker: Option<Waker>,
+}
+
+// Delivers the result to a submission; dropping it without a result reports a worker failure.
+struct Completer<R> {
+	slot: Option<Arc<Slot<R>>>,
+}
+
+impl<R> Submission<R> {
+	fn new() -> (Self, Completer<R>) {
+		let slot = Arc::new(Slot {
+			state: Mutex::new(SlotState { result: None, waker: None }),
+			ready: Condvar::new(),
+		});
+
+		(Submission { slot: slot.clone() }, Completer { slot: Some(slot) })
+	}
+
+	/// Blocks the current thread until the submission has finished, and returns its result.
+	pub fn wait(self) -> Result<R, AnyError> {
+		let mut state = self.slot.state.lock().expect("Submission state not poisoned");
+		loop {
+			if let Some(result) = state.result.take() {
+				return result;
+			}
+			state = self.slot.ready.wait(state).expect("Submission state not poisoned");
+		}
+	}
+}
+
+impl<R> Future for Submission<R> {
+	type Output = Result<R, AnyError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut state = self.slot.state.lock().expect("Submission state not poisoned");
+		match state.result.take() {
+			Some(result) => Poll::Ready(result),
+			None => {
+				state.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+impl<R> Completer<R> {
+	fn complete(mut self, result: Result<R, AnyError>) {
+		if let Some(slot) = self.slot.take() {
+			Self::deliver(&slot, result);
+		}
+	}
+
+	fn deliver(slot: &Slot<R>, result: Result<R, AnyError>) {
+		let waker = match slot.state.lock() {
+			Ok(mut state) => {
+				state.result = Some(result);
+				state.waker.take()
+			}
+			Err(_) => return,
+		};
+
+		slot.ready.notify_all();
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+}
+
+impl<R> Drop for Completer<R> {
+	fn drop(&mut self) {
+		if let Some(slot) = self.slot.take() {
+			Self::deliver(&slot, Err(JsError::WorkerFailed.into()));
+		}
+	}
+}
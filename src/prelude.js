@@ -0,0 +1,644 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Executed once for every script, before the user's code.
+// Provides the globals which js-sandbox offers in addition to bare deno_core.
+
+((globalThis) => {
+	const core = Deno.core;
+	core.ops();
+
+	// Errors returned from host functions are thrown as these classes
+	core.registerErrorClass("Error", Error);
+	core.registerErrorClass("TypeError", TypeError);
+
+	// Async ops which have not completed yet, reported when Script::drain_async() times out
+	let pendingOps = 0;
+	const { opAsync } = core;
+	core.opAsync = (...args) => {
+		const result = opAsync(...args);
+		if (!(result instanceof Promise)) {
+			return result;
+		}
+		pendingOps++;
+		return result.finally(() => pendingOps--);
+	};
+
+	// Prefix of all console lines, grown by console.group()
+	let groupIndent = "";
+
+	function format(args) {
+		return args.map(arg => String(arg)).join(" ");
+	}
+
+	// Each argument converted separately, so that one without JSON representation does not affect the others
+	function rawArgs(args) {
+		return "[" + args.map(arg => {
+			try {
+				return toHost(arg);
+			} catch {
+				return "null";
+			}
+		}).join(",") + "]";
+	}
+
+	function emit(level, message, args) {
+		const indented = groupIndent === "" ? message : message.split("\n").map(line => groupIndent + line).join("\n");
+		core.opSync("__rust_console", [level, indented, rawArgs(args)]);
+	}
+
+	function logger(level) {
+		return (...args) => {
+			emit(level, format(args), args);
+		};
+	}
+
+	function dump(value) {
+		try {
+			return typeof value === "object" && value !== null ? JSON.stringify(value, replace, 2) ?? String(value) : String(value);
+		} catch {
+			return Object.prototype.toString.call(value);
+		}
+	}
+
+	// Renders rows of an object or array as text, with one column per property like in browsers
+	function table(data) {
+		if (data === null || typeof data !== "object") {
+			return format([data]);
+		}
+
+		const columns = [];
+		let hasValues = false;
+		const rows = Object.entries(data).map(([index, row]) => {
+			const cells = { "(index)": index };
+			if (row !== null && typeof row === "object") {
+				for (const [key, value] of Object.entries(row)) {
+					if (!columns.includes(key)) {
+						columns.push(key);
+					}
+					cells[key] = typeof value === "object" && value !== null ? dump(value).replace(/\s+/g, " ") : String(value);
+				}
+			} else {
+				cells["Values"] = String(row);
+				hasValues = true;
+			}
+			return cells;
+		});
+
+		const header = ["(index)", ...columns, ...(hasValues ? ["Values"] : [])];
+		const widths = header.map(column => Math.max(column.length, ...rows.map(row => (row[column] ?? "").length)));
+		const line = cells => cells.map((cell, i) => cell.padEnd(widths[i])).join(" | ").trimEnd();
+
+		return [
+			line(header),
+			widths.map(width => "-".repeat(width)).join("-+-"),
+			...rows.map(row => line(header.map(column => row[column] ?? ""))),
+		].join("\n");
+	}
+
+	// Values crossing the Rust <-> JS boundary are transported as JSON strings.
+	// BigInts are not supported by JSON; they are sent as decimal strings and received as marker objects.
+	// Dates are sent as ISO strings by JSON.stringify(); they are received as marker objects holding a string or number.
+	// Errors have no enumerable properties of their own; they are sent as objects with name, message and stack.
+	// Binary data is transferred through ops, and represented by marker objects holding the index of the buffer.
+	// Negative zero is stringified as 0; it is sent as a marker object as well.
+	const BIGINT_KEY = "__rust_bigint";
+	const DATE_KEY = "__rust_date";
+	const BYTES_KEY = "__rust_bytes";
+	const NEGATIVE_ZERO_KEY = "__rust_negative_zero";
+	const ErrorClass = Error;
+	const DateClass = Date;
+	const ArrayBufferClass = ArrayBuffer;
+
+	// Optional hooks set by ScriptBuilder::json_reviver() and json_replacer()
+	let userReviver = null;
+	let userReplacer = null;
+	let jsonHooksSet = false;
+
+	function reviveMarkers(value) {
+		if (value !== null && typeof value === "object" && Object.keys(value).length === 1) {
+			if (typeof value[BIGINT_KEY] === "string") {
+				return BigInt(value[BIGINT_KEY]);
+			}
+			if (typeof value[DATE_KEY] === "string" || typeof value[DATE_KEY] === "number") {
+				return new DateClass(value[DATE_KEY]);
+			}
+			if (Array.isArray(value[BYTES_KEY])) {
+				const [index, length] = value[BYTES_KEY];
+				const bytes = new Uint8Array(length);
+				core.ops();
+				core.opSync("__rust_bytes_in", index, bytes);
+				return bytes;
+			}
+		}
+		return value;
+	}
+
+	function revive(key, value) {
+		const revived = reviveMarkers(value);
+		return userReviver === null ? revived : userReviver.call(this, key, revived);
+	}
+
+	function replace(key, value) {
+		const replaced = userReplacer === null ? value : userReplacer.call(this, key, value);
+		if (typeof replaced === "bigint") {
+			return replaced.toString();
+		}
+		if (replaced instanceof ErrorClass) {
+			return { ...replaced, name: replaced.name, message: replaced.message, stack: replaced.stack };
+		}
+		return replaced;
+	}
+
+	function replaceForHost(key, value) {
+		const replaced = replace.call(this, key, value);
+		if (replaced === 0 && 1 / replaced < 0) {
+			return { [NEGATIVE_ZERO_KEY]: true };
+		}
+		if (replaced instanceof ArrayBufferClass || ArrayBufferClass.isView(replaced)) {
+			const view = replaced instanceof ArrayBufferClass
+				? new Uint8Array(replaced)
+				: new Uint8Array(replaced.buffer, replaced.byteOffset, replaced.byteLength);
+			core.ops();
+			return { [BYTES_KEY]: core.opSync("__rust_bytes_out", null, view) };
+		}
+		return replaced;
+	}
+
+	const fromHost = json => JSON.parse(json, revive);
+
+	const toHost = value => JSON.stringify(value, replaceForHost) ?? "null";
+
+	// Functions referenced from Rust by JsFunctionHandle
+	const handles = new Map();
+	let nextHandle = 1;
+
+	// Readers of streams consumed from Rust by JsStream
+	const streams = new Map();
+	let nextStream = 1;
+
+	// Generators and other iterators consumed from Rust by JsIter
+	const iterators = new Map();
+	let nextIterator = 1;
+
+	// Binary chunks have no useful JSON representation; they are sent as arrays of bytes
+	function streamChunk(value) {
+		if (value instanceof ArrayBuffer) {
+			return Array.from(new Uint8Array(value));
+		}
+		if (ArrayBuffer.isView(value)) {
+			return Array.from(new Uint8Array(value.buffer, value.byteOffset, value.byteLength));
+		}
+		return value;
+	}
+
+	// Iterable over the items of a Rust iterator, pulled one by one; see Script::call_with_iter()
+	function inputIterable() {
+		let iterated = false;
+		const iterator = {
+			next() {
+				core.ops();
+				const json = core.opSync("__rust_iter_next");
+				return json === null ? { done: true, value: undefined } : { done: false, value: fromHost(json) };
+			},
+			[Symbol.iterator]() {
+				return this;
+			},
+		};
+		const start = () => {
+			if (iterated) {
+				throw new TypeError("input can only be iterated once");
+			}
+			iterated = true;
+			return iterator;
+		};
+
+		return Object.freeze({
+			[Symbol.iterator]: start,
+			[Symbol.asyncIterator]() {
+				const items = start();
+				return {
+					next: async () => items.next(),
+					[Symbol.asyncIterator]() {
+						return this;
+					},
+				};
+			},
+		});
+	}
+
+	// Gas left for the current call; unlimited unless ScriptBuilder::gas_limit() is set, see gas.js
+	let gas = Infinity;
+	let gasControlTaken = false;
+
+	// Pending timers by ID, once installed by ScriptBuilder::timers(); their clock only moves in Script::advance_time()
+	const timers = new Map();
+	let timersInstalled = false;
+	let timerClockUsed = false;
+	let timerClock = 0;
+	let timerSequence = 0;
+	let nextTimerId = 1;
+
+	function addTimer(callback, delay, args, repeat) {
+		if (typeof callback !== "function") {
+			throw new TypeError(`expected a function as timer callback, got ${typeof callback}`);
+		}
+		delay = Math.max(0, Number(delay) || 0);
+		const id = nextTimerId++;
+		timers.set(id, { callback, args, due: timerClock + delay, sequence: timerSequence++, interval: repeat ? delay : null });
+		return id;
+	}
+
+	// Runs the earliest timer due at `until`, and returns whether there was one; see Script::advance_time()
+	function runTimer(until) {
+		if (!timersInstalled && !timerClockUsed) {
+			throw new TypeError("timers are not enabled for this script");
+		}
+
+		// Timers due at the same time run in the order they were scheduled
+		const isEarlier = (timer, other) => timer.due < other.due || (timer.due === other.due && timer.sequence < other.sequence);
+		let nextId = null;
+		let next = null;
+		for (const [id, timer] of timers) {
+			if (timer.due <= until && (next === null || isEarlier(timer, next))) {
+				nextId = id;
+				next = timer;
+			}
+		}
+		if (next === null) {
+			timerClock = Math.max(timerClock, until);
+			return false;
+		}
+
+		// Rescheduled before running, so that the callback can clear its own interval; at least 1 ms lets the clock progress
+		timerClock = next.due;
+		if (next.interval === null) {
+			timers.delete(nextId);
+		} else {
+			next.due = timerClock + Math.max(next.interval, 1);
+			next.sequence = timerSequence++;
+		}
+		next.callback.apply(globalThis, next.args);
+		return true;
+	}
+
+	// Global holding the host functions, once protected; see ScriptBuilder::protect_host_globals()
+	let protectedNamespace = null;
+
+	// What the host registered for this script, exposed as `host.__features` for feature detection
+	const registeredOps = new Set();
+	const enabledPolyfills = new Set();
+	const features = Object.freeze({
+		get ops() {
+			return [...registeredOps].sort();
+		},
+		get polyfills() {
+			return [...enabledPolyfills].sort();
+		},
+		hasOp: name => registeredOps.has(name),
+		hasPolyfill: name => enabledPolyfills.has(name),
+	});
+
+	// Returns the global holding host functions, creating it on first use
+	function hostObject(namespace) {
+		const host = (globalThis[namespace] ??= {});
+		if (!Object.prototype.hasOwnProperty.call(host, "__features")) {
+			Object.defineProperty(host, "__features", { value: features, enumerable: false, configurable: false });
+		}
+		return host;
+	}
+
+	// Values of the sticky globals, see Script::set_sticky_global(). The globals themselves are non-configurable accessors, so
+	// that the script can neither redefine nor delete them, while the host can still replace their values.
+	const stickyValues = new Map();
+
+	function deepFreeze(value) {
+		if (value !== null && typeof value === "object" && !Object.isFrozen(value)) {
+			Object.freeze(value);
+			Object.values(value).forEach(deepFreeze);
+		}
+		return value;
+	}
+
+	// Hands the result of a call to Rust; see Script::call_impl()
+	function returnValue(value) {
+		core.ops();
+		core.opSync("__rust_return", [toHost(value), typeof value === "undefined"]);
+	}
+
+	// Listeners registered through addEventListener(), by event type; only "error" events are ever dispatched
+	const eventListeners = new Map();
+
+	// Offers an exception escaping a call to the script's error handlers, and returns whether one of them prevented the default
+	function dispatchError(exception) {
+		let message;
+		try {
+			message = exception instanceof ErrorClass ? exception.message : String(exception);
+		} catch {
+			message = Object.prototype.toString.call(exception);
+		}
+
+		const event = {
+			type: "error",
+			error: exception,
+			message,
+			defaultPrevented: false,
+			preventDefault() {
+				this.defaultPrevented = true;
+			},
+		};
+
+		// Like in browsers, exceptions thrown by handlers do not keep the other handlers from running
+		for (const entry of [...(eventListeners.get("error") ?? [])]) {
+			if (entry.once) {
+				removeListener("error", entry.listener);
+			}
+			try {
+				typeof entry.listener === "function" ? entry.listener.call(globalThis, event) : entry.listener.handleEvent(event);
+			} catch {}
+		}
+
+		if (typeof globalThis.onerror === "function") {
+			try {
+				if (globalThis.onerror(message, undefined, undefined, undefined, exception) === true) {
+					event.preventDefault();
+				}
+			} catch {}
+		}
+
+		return event.defaultPrevented;
+	}
+
+	function removeListener(type, listener) {
+		const entries = eventListeners.get(type) ?? [];
+		eventListeners.set(type, entries.filter(entry => entry.listener !== listener));
+	}
+
+	globalThis.addEventListener = (type, listener, options) => {
+		if (listener === null || (typeof listener !== "function" && typeof listener?.handleEvent !== "function")) {
+			return;
+		}
+		const entries = eventListeners.get(type) ?? [];
+		if (!entries.some(entry => entry.listener === listener)) {
+			eventListeners.set(type, [...entries, { listener, once: options?.once === true }]);
+		}
+	};
+	globalThis.removeEventListener = (type, listener) => removeListener(type, listener);
+
+	function defineImmutable(object, name, value, description) {
+		Object.defineProperty(object, name, {
+			get: () => value,
+			set() {
+				throw new TypeError(`cannot overwrite ${description}`);
+			},
+			enumerable: true,
+			configurable: false,
+		});
+	}
+
+	Object.defineProperty(globalThis, "__rust", {
+		value: Object.freeze({
+			fromHost,
+			toHost,
+
+			setJsonHooks(reviver, replacer) {
+				if (jsonHooksSet) {
+					throw new TypeError("JSON hooks have already been set");
+				}
+				for (const hook of [reviver, replacer]) {
+					if (hook !== null && typeof hook !== "function") {
+						throw new TypeError(`expected a function as JSON reviver or replacer, got ${typeof hook}`);
+					}
+				}
+				userReviver = reviver;
+				userReplacer = replacer;
+				jsonHooksSet = true;
+			},
+
+			storeHandle(value) {
+				if (typeof value !== "function") {
+					throw new TypeError(`expected a function to create a handle, got ${typeof value}`);
+				}
+				handles.set(nextHandle, value);
+				return nextHandle++;
+			},
+			handle(id) {
+				const f = handles.get(id);
+				if (f === undefined) {
+					throw new ReferenceError("function handle has been released");
+				}
+				return f;
+			},
+			storeObject(value) {
+				if (value === null || (typeof value !== "object" && typeof value !== "function")) {
+					throw new TypeError(`expected an object to create a handle, got ${value === null ? "null" : typeof value}`);
+				}
+				handles.set(nextHandle, value);
+				return nextHandle++;
+			},
+			callMethod(id, method, args) {
+				const object = handles.get(id);
+				if (object === undefined) {
+					throw new ReferenceError("object handle has been released");
+				}
+				if (typeof object[method] !== "function") {
+					throw new TypeError(`object has no method '${method}'`);
+				}
+				return object[method](...args);
+			},
+			releaseHandle: id => handles.delete(id),
+
+			// Streams consumed by Script::call_stream(); anything with a ReadableStream-like getReader() is accepted
+			storeStream(value) {
+				if (typeof value?.getReader !== "function") {
+					throw new TypeError(`expected a ReadableStream, got ${value === null ? "null" : typeof value}`);
+				}
+				streams.set(nextStream, value.getReader());
+				return nextStream++;
+			},
+			async readStream(id) {
+				const reader = streams.get(id);
+				if (reader === undefined) {
+					throw new ReferenceError("stream has been released");
+				}
+				const { done, value } = await reader.read();
+				if (done) {
+					streams.delete(id);
+					return { done: true };
+				}
+				return { done: false, value: streamChunk(value) };
+			},
+			releaseStream(id) {
+				const reader = streams.get(id);
+				streams.delete(id);
+				try {
+					reader?.cancel?.()?.catch?.(() => {});
+				} catch {}
+			},
+
+			// Iterators consumed by Script::call_iter(); async iterables are preferred over sync ones
+			storeIterator(value) {
+				const method = value?.[Symbol.asyncIterator] ?? value?.[Symbol.iterator];
+				if (typeof method !== "function") {
+					throw new TypeError(`expected an iterable, got ${value === null ? "null" : typeof value}`);
+				}
+				iterators.set(nextIterator, method.call(value));
+				return nextIterator++;
+			},
+			async readIterator(id) {
+				const iterator = iterators.get(id);
+				if (iterator === undefined) {
+					throw new ReferenceError("iterator has been released");
+				}
+				const { done, value } = await iterator.next();
+				if (done) {
+					iterators.delete(id);
+					return { done: true };
+				}
+				return { done: false, value: await value };
+			},
+			releaseIterator(id) {
+				const iterator = iterators.get(id);
+				iterators.delete(id);
+				try {
+					iterator?.return?.()?.catch?.(() => {});
+				} catch {}
+			},
+
+			returnValue,
+			inputIterable,
+
+			// Consumes one unit of gas; inserted into loops and functions by gas::instrument()
+			gas() {
+				if (--gas < 0) {
+					core.ops();
+					core.opSync("__rust_gas_exhausted");
+				}
+				return true;
+			},
+			takeGasControl() {
+				if (gasControlTaken) {
+					throw new TypeError("gas control has already been taken");
+				}
+				gasControlTaken = true;
+				return limit => {
+					gas = limit;
+				};
+			},
+
+			installTimers() {
+				if (timersInstalled) {
+					return;
+				}
+				timersInstalled = true;
+				const clear = id => {
+					timers.delete(id);
+				};
+				Object.assign(globalThis, {
+					setTimeout: (callback, delay, ...args) => addTimer(callback, delay, args, false),
+					setInterval: (callback, delay, ...args) => addTimer(callback, delay, args, true),
+					clearTimeout: clear,
+					clearInterval: clear,
+				});
+			},
+			// Reading the timer clock, for a virtual time which advances with it; see ScriptBuilder::deterministic()
+			useTimerClock() {
+				timerClockUsed = true;
+				return () => timerClock;
+			},
+			timerDeadline: delta => timerClock + delta,
+			runTimer,
+			pendingTimers: () => timers.size,
+			pendingOps: () => pendingOps,
+
+			// Reports an exception that is not an Error to Rust, before it propagates; see Script::finish_call()
+			// Only exceptions escaping the script's entry points are offered to its error handlers, not those of internal helpers
+			thrown(exception, dispatch) {
+				if (dispatch && dispatchError(exception)) {
+					returnValue(undefined);
+					return;
+				}
+				if (!(exception instanceof Error)) {
+					let message, json;
+					try {
+						message = exception !== null && typeof exception === "object" ? toHost(exception) : String(exception);
+						json = toHost(exception);
+					} catch {
+						message = Object.prototype.toString.call(exception);
+						json = "null";
+					}
+					core.ops();
+					core.opSync("__rust_thrown", [message, json]);
+				}
+				throw exception;
+			},
+
+			// Message channel, see ScriptBuilder::message_channel()
+			postMessage(message) {
+				core.ops();
+				core.opSync("__rust_post_message", toHost(message));
+			},
+			dispatchMessage(data) {
+				if (typeof globalThis.onmessage === "function") {
+					globalThis.onmessage({ data });
+				}
+			},
+
+			// Creates the JS side of a host function; see Script::add_fn()
+			hostFn(op) {
+				if (!op.startsWith("host:")) {
+					throw new TypeError(`'${op}' is not a host function`);
+				}
+				core.ops();
+				return arg => fromHost(core.opSync(op, toHost(arg)));
+			},
+			defineHost(namespace, name, f) {
+				const host = hostObject(namespace);
+				if (namespace === protectedNamespace) {
+					defineImmutable(host, name, f, `host function '${namespace}.${name}'`);
+				} else {
+					host[name] = f;
+				}
+			},
+			// Defines a read-only global, which reset() defines anew; see Script::set_sticky_global()
+			defineSticky(name, json) {
+				const value = deepFreeze(fromHost(json));
+				if (!stickyValues.has(name)) {
+					Object.defineProperty(globalThis, name, { get: () => stickyValues.get(name), enumerable: true, configurable: false });
+				}
+				stickyValues.set(name, value);
+			},
+			addFeature(namespace, kind, name) {
+				hostObject(namespace);
+				(kind === "op" ? registeredOps : enabledPolyfills).add(name);
+			},
+			protectHost(namespace) {
+				const host = (globalThis[namespace] ??= {});
+				for (const name of Object.keys(host)) {
+					defineImmutable(host, name, host[name], `host function '${namespace}.${name}'`);
+				}
+				defineImmutable(globalThis, namespace, host, `host-injected global '${namespace}'`);
+				protectedNamespace = namespace;
+			},
+		}),
+	});
+
+	globalThis.console = {
+		debug: logger("debug"),
+		info: logger("info"),
+		log: logger("log"),
+		warn: logger("warn"),
+		error: logger("error"),
+		dir: value => emit("log", dump(value), [value]),
+		table: data => emit("log", table(data), [data]),
+		group(...label) {
+			if (label.length > 0) {
+				emit("log", format(label), label);
+			}
+			groupIndent += "  ";
+		},
+		groupEnd() {
+			groupIndent = groupIndent.slice(2);
+		},
+	};
+	globalThis.console.groupCollapsed = globalThis.console.group;
+})(globalThis);
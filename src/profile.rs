@@ -0,0 +1,157 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{AnyError, JsValue};
+
+/// CPU profile of a script, recorded with V8's sampling profiler.
+///
+/// Obtained through [`Script::take_cpu_profile()`](struct.Script.html#method.take_cpu_profile). The profile is a call tree:
+/// each [`ProfileNode`] represents a function invoked along a specific call path, with the number of samples taken while it
+/// was executing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuProfile {
+	/// All nodes of the call tree. The first one is the root, named `(root)`.
+	pub nodes: Vec<ProfileNode>,
+	/// Sampling interval in microseconds.
+	pub interval_us: u64,
+	/// Duration of the recording in microseconds.
+	pub duration_us: u64,
+}
+
+/// Function in the call tree of a [`CpuProfile`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileNode {
+	/// Unique ID within the profile.
+	pub id: u32,
+	/// Name of the function; empty for anonymous functions. V8 uses names in parentheses for pseudo-functions, such as
+	/// `(program)`, `(idle)` or `(garbage collector)`.
+	pub function_name: String,
+	/// Name of the file containing the function.
+	pub file: String,
+	/// Line of the function's definition (1-based), or 0 if unknown.
+	pub line: u32,
+	/// Column of the function's definition (1-based), or 0 if unknown.
+	pub column: u32,
+	/// Number of samples in which this function itself was executing.
+	pub self_samples: u64,
+	/// Number of samples in which this function or one of its callees was executing.
+	pub total_samples: u64,
+	/// IDs of the functions called from this node.
+	pub children: Vec<u32>,
+}
+
+impl CpuProfile {
+	/// Returns the profile in folded stack format, as consumed by flame graph tools (e.g. `inferno` or `flamegraph.pl`).
+	///
+	/// Each line holds a semicolon-separated call stack starting below the root, followed by the number of samples.
+	pub fn folded_stacks(&self) -> String {
+		let index: HashMap<u32, &ProfileNode> = self.nodes.iter().map(|node| (node.id, node)).collect();
+		let mut lines = Vec::new();
+
+		if let Some(root) = self.nodes.first() {
+			for child in &root.children {
+				Self::fold(&index, *child, String::new(), &mut lines);
+			}
+		}
+
+		lines.join("\n")
+	}
+
+	fn fold(index: &HashMap<u32, &ProfileNode>, id: u32, prefix: String, lines: &mut Vec<String>) {
+		let node = match index.get(&id) {
+			Some(node) => node,
+			None => return,
+		};
+
+		let name = if node.function_name.is_empty() { "(anonymous)" } else { &node.function_name };
+		let stack = if prefix.is_empty() { name.to_string() } else { format!("{};{}", prefix, name) };
+
+		if node.self_samples > 0 {
+			lines.push(format!("{} {}", stack, node.self_samples));
+		}
+		for child in &node.children {
+			Self::fold(index, *child, stack.clone(), lines);
+		}
+	}
+
+	/// Converts the result of the inspector's `Profiler.stop` command.
+	pub(crate) fn from_inspector(result: JsValue, interval_us: u64) -> Result<Self, AnyError> {
+		let raw: RawResult = serde_json::from_value(result)?;
+		let raw = raw.profile;
+
+		let mut nodes: Vec<ProfileNode> = raw
+			.nodes
+			.into_iter()
+			.map(|node| ProfileNode {
+				id: node.id,
+				function_name: node.call_frame.function_name,
+				file: node.call_frame.url,
+				// Protocol positions are 0-based, -1 if unknown
+				line: (node.call_frame.line_number + 1).max(0) as u32,
+				column: (node.call_frame.column_number + 1).max(0) as u32,
+				self_samples: node.hit_count,
+				total_samples: 0,
+				children: node.children,
+			})
+			.collect();
+
+		let positions: HashMap<u32, usize> = nodes.iter().enumerate().map(|(i, node)| (node.id, i)).collect();
+		if !nodes.is_empty() {
+			Self::sum_totals(&mut nodes, &positions, 0);
+		}
+
+		Ok(CpuProfile {
+			nodes,
+			interval_us,
+			duration_us: raw.end_time.saturating_sub(raw.start_time),
+		})
+	}
+
+	fn sum_totals(nodes: &mut Vec<ProfileNode>, positions: &HashMap<u32, usize>, i: usize) -> u64 {
+		let mut total = nodes[i].self_samples;
+		for child in nodes[i].children.clone() {
+			if let Some(&child) = positions.get(&child) {
+				total += Self::sum_totals(nodes, positions, child);
+			}
+		}
+
+		nodes[i].total_samples = total;
+		total
+	}
+}
+
+#[derive(Deserialize)]
+struct RawResult {
+	profile: RawProfile,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawProfile {
+	nodes: Vec<RawNode>,
+	start_time: u64,
+	end_time: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawNode {
+	id: u32,
+	call_frame: RawCallFrame,
+	#[serde(default)]
+	hit_count: u64,
+	#[serde(default)]
+	children: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCallFrame {
+	function_name: String,
+	url: String,
+	line_number: i64,
+	column_number: i64,
+}
@@ -0,0 +1,44 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Strict profile for untrusted code, see ScriptBuilder::pure_sandbox().
+// Removes every global that reaches the host or behaves nondeterministically, leaving pure computation.
+// Accessing a removed global throws ReferenceError; removed methods are replaced by functions throwing TypeError.
+
+((globalThis) => {
+	const removedGlobals = [
+		"Deno",
+		"console",
+		"Date",
+		"Intl",
+		"SharedArrayBuffer",
+		"Atomics",
+		"WebAssembly",
+		"setTimeout",
+		"setInterval",
+		"clearTimeout",
+		"clearInterval",
+		"fetch",
+		"Headers",
+		"fs",
+		"crypto",
+		"performance",
+		"addEventListener",
+		"removeEventListener",
+	];
+
+	for (const name of removedGlobals) {
+		delete globalThis[name];
+	}
+
+	function unavailable(name) {
+		return () => {
+			throw new TypeError(`${name}() is not available in a pure sandbox`);
+		};
+	}
+
+	Object.defineProperty(Math, "random", {
+		value: unavailable("Math.random"),
+		writable: false,
+		configurable: false,
+	});
+})(globalThis);
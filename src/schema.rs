@@ -0,0 +1,150 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Validation of JSON values against a subset of JSON Schema, see Script::call_checked().
+// Supported keywords: type, enum, const, properties, required, additionalProperties (boolean or schema), items (single
+// schema), minItems, maxItems, minimum and maximum. Unknown keywords are ignored, as JSON Schema prescribes.
+
+use crate::JsValue;
+
+/// Mismatch between a value and its schema.
+pub(crate) struct Mismatch {
+	/// Path to the offending value, e.g. `result.items[2].name`
+	pub path: String,
+	/// What was expected at that path
+	pub message: String,
+}
+
+/// Checks `value` against `schema`; `root` is the name of the value in the path of a mismatch.
+pub(crate) fn validate(value: &JsValue, schema: &JsValue, root: &str) -> Result<(), Mismatch> {
+	let mut path = root.to_string();
+	check(value, schema, &mut path)
+}
+
+fn check(value: &JsValue, schema: &JsValue, path: &mut String) -> Result<(), Mismatch> {
+	let fail = |path: &str, message: String| Err(Mismatch { path: path.to_string(), message });
+
+	let schema = match schema {
+		JsValue::Bool(true) => return Ok(()),
+		JsValue::Bool(false) => return fail(path, "no value allowed".to_string()),
+		JsValue::Object(schema) => schema,
+		_ => return fail(path, format!("schema must be an object or boolean, got {}", schema)),
+	};
+
+	if let Some(expected) = schema.get("type") {
+		let matches = match expected {
+			JsValue::String(name) => has_type(value, name),
+			JsValue::Array(names) => names.iter().any(|name| matches!(name.as_str(), Some(name) if has_type(value, name))),
+			_ => false,
+		};
+		if !matches {
+			return fail(path, format!("expected type {}, got {}", expected, type_name(value)));
+		}
+	}
+
+	if let Some(allowed) = schema.get("enum").and_then(JsValue::as_array) {
+		if !allowed.contains(value) {
+			return fail(path, format!("expected one of {}, got {}", JsValue::Array(allowed.clone()), value));
+		}
+	}
+	if let Some(expected) = schema.get("const") {
+		if value != expected {
+			return fail(path, format!("expected {}, got {}", expected, value));
+		}
+	}
+
+	if let Some(number) = value.as_f64() {
+		if let Some(minimum) = schema.get("minimum").and_then(JsValue::as_f64) {
+			if number < minimum {
+				return fail(path, format!("expected at least {}, got {}", minimum, value));
+			}
+		}
+		if let Some(maximum) = schema.get("maximum").and_then(JsValue::as_f64) {
+			if number > maximum {
+				return fail(path, format!("expected at most {}, got {}", maximum, value));
+			}
+		}
+	}
+
+	if let JsValue::Array(elements) = value {
+		if let Some(min) = schema.get("minItems").and_then(JsValue::as_u64) {
+			if (elements.len() as u64) < min {
+				return fail(path, format!("expected at least {} elements, got {}", min, elements.len()));
+			}
+		}
+		if let Some(max) = schema.get("maxItems").and_then(JsValue::as_u64) {
+			if elements.len() as u64 > max {
+				return fail(path, format!("expected at most {} elements, got {}", max, elements.len()));
+			}
+		}
+		if let Some(items) = schema.get("items") {
+			for (index, element) in elements.iter().enumerate() {
+				nested(path, &format!("[{}]", index), |path| check(element, items, path))?;
+			}
+		}
+	}
+
+	if let JsValue::Object(object) = value {
+		if let Some(required) = schema.get("required").and_then(JsValue::as_array) {
+			for key in required.iter().filter_map(JsValue::as_str) {
+				if !object.contains_key(key) {
+					return fail(path, format!("missing required property '{}'", key));
+				}
+			}
+		}
+
+		let properties = schema.get("properties").and_then(JsValue::as_object);
+		for (key, property) in object {
+			let segment = property_segment(key);
+			match properties.and_then(|properties| properties.get(key)) {
+				Some(property_schema) => nested(path, &segment, |path| check(property, property_schema, path))?,
+				None => match schema.get("additionalProperties") {
+					Some(JsValue::Bool(false)) => return fail(path, format!("unexpected property '{}'", key)),
+					Some(additional) => nested(path, &segment, |path| check(property, additional, path))?,
+					None => {}
+				},
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Runs `f` with `segment` appended to the path.
+fn nested<T>(path: &mut String, segment: &str, f: impl FnOnce(&mut String) -> T) -> T {
+	let len = path.len();
+	path.push_str(segment);
+	let result = f(path);
+	path.truncate(len);
+
+	result
+}
+
+fn property_segment(key: &str) -> String {
+	let is_identifier = matches!(key.chars().next(), Some(c) if c.is_alphabetic() || c == '_' || c == '$')
+		&& key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+
+	if is_identifier {
+		format!(".{}", key)
+	} else {
+		format!("[{}]", serde_json::to_string(key).unwrap_or_default())
+	}
+}
+
+fn has_type(value: &JsValue, name: &str) -> bool {
+	match name {
+		"integer" => matches!(value.as_f64(), Some(number) if number.fract() == 0.0),
+		"number" => value.is_number(),
+		_ => type_name(value) == name,
+	}
+}
+
+fn type_name(value: &JsValue) -> &'static str {
+	match value {
+		JsValue::Null => "null",
+		JsValue::Bool(_) => "boolean",
+		JsValue::Number(_) => "number",
+		JsValue::String(_) => "string",
+		JsValue::Array(_) => "array",
+		JsValue::Object(_) => "object",
+	}
+}
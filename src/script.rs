@@ -1,125 +1,2813 @@
 // Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
 
-use std::{thread, time::Duration};
 use std::borrow::Cow;
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::task::Poll;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, SystemTime};
 
-use deno_core::{JsRuntime, OpState, RuntimeOptions, ZeroCopyBuf};
+use deno_core::error::generic_error;
+use deno_core::futures::StreamExt;
+use deno_core::{futures, v8, JsRuntime, ModuleLoader, ModuleSpecifier, OpState, OpTable, RuntimeOptions, ZeroCopyBuf};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::json;
 
-use crate::{AnyError, JsValue};
+use crate::args;
+use crate::bytes;
+use crate::cancel::Registration;
+use crate::console::{self, ConsoleLimit, ConsoleSink};
+use crate::engine;
+use crate::error;
+use crate::fetch;
+use crate::fields;
+use crate::fs;
+use crate::gas::{self, GasMeter};
+use crate::inspector::Inspector;
+use crate::json;
+use crate::messages::{self, MessageSink};
+use crate::ops::{self, FastFn, FastFnEntry, HostFn, HostFns};
+use crate::poll::PollTicker;
+use crate::schema;
+use crate::source::SourceIndex;
+use crate::source_map::{self, SourceMaps};
+use crate::stats::Usage;
+use crate::termination::{Terminator, Watchdog};
+use crate::wasm;
+use crate::{AnyError, CallOutcome, CancellationToken, CpuProfile, FetchOptions, HeapStats, HostApi, JsContextHandle, JsError, JsFunction, JsFunctionHandle, JsIter, JsObjectHandle, JsStream, JsValue, LogRecord, Output, PollDecision, ScriptBuilder, ScriptStats, ScriptThread, Snapshot, SourcePosition, TerminationHandle, ThisArg, VirtualFs, WasmOptions};
+
+/// Source of unique script IDs, to associate handles with the script that created them.
+static NEXT_SCRIPT_ID: AtomicU64 = AtomicU64::new(0);
 
 /// Represents a single JavaScript file that can be executed.
 ///
 /// The code can be loaded from a file or from a string in memory.
 /// A typical usage pattern is to load a file with one or more JS function definitions, and then call those functions from Rust.
+///
+/// Dropping a script disposes its V8 isolate right away. No background threads outlive a call: the timer thread of a
+/// call with timeout is joined before the call returns.
+///
+/// A script must only be used on the thread that created it; the type is neither `Send` nor `Sync`. Debug builds
+/// additionally panic when a script is called or dropped on another thread, which can otherwise only happen through
+/// `unsafe` code. Use [`on_thread()`](#method.on_thread) to create a script on a dedicated thread of its own.
 pub struct Script {
+	// Declared first, so that they are dropped before the isolate
+	inspector: Option<Inspector>,
+	gas: Option<GasMeter>,
+	contexts: HashMap<u64, v8::Global<v8::Context>>,
+	bound_fns: HashMap<u64, (String, v8::Global<v8::Function>)>,
 	runtime: JsRuntime,
 	last_rid: u32,
+	terminator: Terminator,
+	sources: SourceIndex,
+	source_maps: Rc<RefCell<SourceMaps>>,
+	// File name, code and line offset of each top-level source, run again by reset()
+	top_level: Vec<(String, String, i32)>,
+	sticky_globals: Vec<(String, JsValue)>,
+	cancellation: Option<CancellationToken>,
+	termination: TerminationHandle,
+	source_file: Option<(PathBuf, Option<SystemTime>)>,
+	allowed_calls: Option<HashSet<String>>,
+	host_fns_denied: bool,
+	// Set by call_internal() for the duration of a helper call
+	internal_call: bool,
+	host_namespace: String,
+	// Heap limit of ScriptBuilder::max_heap_size(), and whether V8 raised it to let an aborted call unwind
+	heap_limit: Option<(usize, Rc<Cell<bool>>)>,
+	profile_interval_us: Option<u64>,
+	usage: Usage,
+	// Referenced from JS by address, so must be boxed and dropped after the runtime
+	#[allow(clippy::vec_box)]
+	fast_fns: Vec<Box<FastFnEntry>>,
+	next_context: u64,
+	next_bound_fn: u64,
+	id: u64,
+	thread_id: ThreadId,
+	// Declared last, so that the next runtime can be prepared once the isolate is gone, see enable_isolate_reuse()
+	_live: engine::LiveScript,
 }
 
-impl Script {
-	const DEFAULT_FILENAME: &'static str = "sandboxed.js";
+impl Script {
+	pub(crate) const DEFAULT_FILENAME: &'static str = "sandboxed.js";
+	/// Global object holding the host functions registered with add_fn(), unless configured otherwise
+	pub(crate) const HOST_NAMESPACE: &'static str = "host";
+	const PRELUDE_FILENAME: &'static str = "js-sandbox:prelude.js";
+
+	/// Initialize a script with the given JavaScript source code
+	///
+	/// Returns a new object on success, and an error in case of syntax or initialization error with the code.
+	pub fn from_string(js_code: &str) -> Result<Self, AnyError> {
+		ScriptBuilder::new().build(js_code)
+	}
+
+	/// Initialize a script by loading it from a .js file
+	///
+	/// Returns a new object on success. Fails if the file cannot be opened or in case of syntax or initialization error with the code.
+	pub fn from_file(file: impl AsRef<Path>) -> Result<Self, AnyError> {
+		ScriptBuilder::new().build_file(file)
+	}
+
+	/// Initialize a script by reading its source code from a stream, for example a network connection or a decompressor
+	///
+	/// The code is read until the end of the stream, and appears as `source_name` in stack traces. Returns a new object on success.
+	/// Fails if reading fails, with the I/O error as cause, or in case of syntax or initialization error with the code.
+	pub fn from_reader(reader: impl Read, source_name: &str) -> Result<Self, AnyError> {
+		ScriptBuilder::new().build_reader(reader, source_name)
+	}
+
+	/// Initialize a script without any code of its own, to which sources can be added with [`add_source()`](#method.add_source).
+	pub fn new_empty() -> Result<Self, AnyError> {
+		Self::new_runtime()
+	}
+
+	/// Executes another piece of top-level code in the script's global scope, e.g. to assemble a script from several fragments.
+	///
+	/// The code sees all globals defined by earlier sources, and appears as `source_name` in stack traces and error messages, so
+	/// that errors point into the fragment they occur in. Fails in case of syntax or initialization error with the code; globals
+	/// defined before the error remain. Sources added successfully become part of the top-level code re-run by
+	/// [`reset()`](#method.reset), in the order they were added.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::new_empty()?;
+	/// 	script.add_source("prelude.js", "const FACTOR = 3;")?;
+	/// 	script.add_source("plugin.js", "function triple(a) { return FACTOR * a; }")?;
+	///
+	/// 	let result: i32 = script.call("triple", &7, None)?;
+	/// 	assert_eq!(result, 21);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn add_source(&mut self, source_name: &str, js_code: &str) -> Result<(), AnyError> {
+		self.add_inline_source_map(source_name, js_code, 0);
+		let result = self.execute(source_name, js_code, 0, None);
+		if result.is_err() {
+			self.top_level.pop();
+		}
+		result
+	}
+
+	/// Initialize a script by loading an ES module from a .js file, together with the modules it imports
+	///
+	/// Imports are resolved relative to the importing file, and restricted to files in the entry module's directory or below.
+	/// The module's exports become globals, so that they can be invoked with [`call()`](#method.call). See
+	/// [`ScriptBuilder::build_module()`](struct.ScriptBuilder.html#method.build_module) for details and further options.
+	pub fn from_module(file: impl AsRef<Path>) -> Result<Self, AnyError> {
+		ScriptBuilder::new().build_module(file)
+	}
+
+	/// Initialize a script from a heap snapshot, without running its top-level code again
+	///
+	/// See [`Snapshot`](struct.Snapshot.html). Fails if V8 cannot restore the snapshot.
+	pub fn from_snapshot(snapshot: &Snapshot) -> Result<Self, AnyError> {
+		let options = RuntimeOptions {
+			startup_snapshot: Some(deno_core::Snapshot::Boxed(snapshot.data().into())),
+			..RuntimeOptions::default()
+		};
+
+		let mut script = Self::create_runtime_from(options)?;
+		script.add_inline_source_map(snapshot.js_filename(), snapshot.js_code(), 0);
+		script.sources.add(snapshot.js_filename(), snapshot.js_code(), 0);
+		script.top_level.push((snapshot.js_filename().to_string(), snapshot.js_code().to_string(), 0));
+		Ok(script)
+	}
+
+	/// Spawns a thread with the given name, and creates a script on it by running `create`.
+	///
+	/// The script stays on that thread for its entire life; it is accessed through the returned [`ScriptThread`](struct.ScriptThread.html).
+	/// Fails if the thread cannot be spawned or `create` fails, with the error it returned.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let plugin = Script::on_thread("plugin", || Script::from_string("function triple(a) { return 3 * a; }"))?;
+	///
+	/// 	let result: i32 = plugin.run(|script| script.call("triple", &7, None))??;
+	/// 	assert_eq!(result, 21);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn on_thread<F>(name: &str, create: F) -> Result<ScriptThread, AnyError>
+	where
+		F: FnOnce() -> Result<Script, AnyError> + Send + 'static,
+	{
+		ScriptThread::spawn(name, create)
+	}
+
+	/// ID of the thread that created this script, and the only one on which it may be used.
+	pub fn thread_id(&self) -> ThreadId {
+		self.thread_id
+	}
+
+	/// Checks JavaScript source code for syntax errors, without executing it.
+	///
+	/// The code is only compiled, in a fresh runtime that is discarded afterwards: none of its top-level statements run, so it
+	/// cannot cause side effects. This is meant for validating untrusted submissions before they are ever run. In contrast,
+	/// [`from_string()`](#method.from_string) compiles *and* executes the top-level code, and thus also fails for errors that
+	/// only occur at runtime -- including conflicting declarations and undefined variables, which compilation does not detect.
+	pub fn compile_only(js_code: &str) -> Result<(), AnyError> {
+		let mut script = Self::new_runtime()?;
+		script.compile(Self::DEFAULT_FILENAME, js_code)
+	}
+
+	/// Returns a builder, which allows to configure the script before creating it.
+	pub fn builder() -> ScriptBuilder {
+		ScriptBuilder::new()
+	}
+
+	/// Invokes a JavaScript function.
+	///
+	/// Passes a single argument `args` to JS by serializing it to JSON (using serde_json).
+	/// To pass several positional arguments, use [`call_args()`](#method.call_args).
+	/// Optional value for `timeout_ms` forces script to run no more than specified number of milliseconds; exceeding it aborts
+	/// execution with [`JsError::Timeout`](enum.JsError.html#variant.Timeout).
+	///
+	/// A JS function returning `undefined` (or nothing at all) is treated like one returning `null`. This means that `R = ()` and
+	/// `R = Option<T>` accept such functions, yielding `()` and `None` respectively. Any other type results in an error which states
+	/// that the function returned `undefined` or `null`, rather than a bare deserialization failure. Inside returned objects,
+	/// properties holding `undefined` are omitted, so struct fields of type `Option<T>` become `None` for them as well.
+	/// Conversion failures are reported as [`JsError::Conversion`](enum.JsError.html#variant.Conversion), which carries the
+	/// returned value; [`call_lossy()`](#method.call_lossy) hands it out directly.
+	///
+	/// Finite floating-point numbers cross the boundary losslessly in both directions, since both sides use the shortest
+	/// representation that parses back to the same `f64`. This includes subnormal values and negative zero. `NaN` and the
+	/// infinities have no JSON representation, and arrive as `null`.
+	///
+	/// Enums follow serde's representation. For JS-friendly discriminated unions, use an internally tagged enum: with
+	/// `#[serde(tag = "type")]`, the variant `Command::Move { x: 1, y: 2 }` arrives as the flat object `{ type: "Move", x: 1, y: 2 }`
+	/// and a unit variant `Command::Stop` as `{ type: "Stop" }`. The same shape returned from JS deserializes back into the
+	/// variant. Serde rejects tuple variants for this representation; use struct variants or `#[serde(tag = "type", content = "data")]`.
+	///
+	/// A returned `Error` instance, also inside arrays or objects, arrives as an object with the properties `name`, `message`
+	/// and `stack`, plus its own enumerable properties such as a custom `code`. This way, plugins can report errors as data.
+	///
+	/// If the function throws, the call fails. A thrown value that is not an `Error`, such as `throw { code: "E_LIMIT" }`, is
+	/// reported as [`JsError::Thrown`](enum.JsError.html#variant.Thrown), which carries the value itself.
+	///
+	/// Before an exception escapes a call, it is offered to the script's own error handlers, like in browsers: listeners added
+	/// with `addEventListener("error", listener)` receive an event with the properties `error` and `message`, followed by a
+	/// `globalThis.onerror(message, source, lineno, colno, error)` handler. If a listener calls `event.preventDefault()` or
+	/// `onerror` returns `true`, the call does not fail, but returns `undefined` instead. Exceptions thrown by the handlers
+	/// themselves are ignored. Terminations, e.g. by timeout, cannot be intercepted.
+	pub fn call<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let result = self.call_serialized(fn_name, args, timeout_ms)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Invokes a JavaScript function, and returns its result even if it cannot be converted to `R`.
+	///
+	/// Behaves like [`call()`](#method.call), but if the returned value does not match `R`, the error comes with the value
+	/// itself as a [`JsValue`](type.JsValue.html), from which the caller can still extract what it needs, or fall back to a
+	/// default. The error is a [`JsError::Conversion`](enum.JsError.html#variant.Conversion), which carries the value as well.
+	/// If the call fails before returning a value, e.g. because the function throws, the value is `null`.
+	///
+	/// ```rust
+	/// use js_sandbox::{AnyError, Script};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::from_string("function version() { return { major: 2, label: 'beta' }; }")?;
+	///
+	/// 	let version = match script.call_lossy::<_, u32>("version", &(), None) {
+	/// 		Ok(version) => version,
+	/// 		Err((value, _)) => value["major"].as_u64().unwrap_or(0) as u32,
+	/// 	};
+	/// 	assert_eq!(version, 2);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn call_lossy<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, (JsValue, AnyError)>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let result = self.call_serialized(fn_name, args, timeout_ms).map_err(|e| (JsValue::Null, e))?;
+		Self::convert_result(fn_name, result).map_err(|error| {
+			let value = match &error {
+				JsError::Conversion { value, .. } => value.clone(),
+				_ => JsValue::Null,
+			};
+			(value, AnyError::from(error))
+		})
+	}
+
+	/// Invokes a JavaScript function, and reports whether it ran to completion or was terminated.
+	///
+	/// Behaves like [`call()`](#method.call), except that a call aborted by the host -- because `timeout_ms` elapsed, the
+	/// [cancellation token](#method.set_cancellation_token) was cancelled, or a [gas](struct.ScriptBuilder.html#method.gas_limit)
+	/// or [host function](struct.ScriptBuilder.html#method.op_limit) limit was hit -- yields [`CallOutcome::Terminated`](enum.CallOutcome.html)
+	/// with the reason, instead of an error. A timeout is reported as [`JsError::Timeout`](enum.JsError.html#variant.Timeout).
+	/// Exceptions thrown by the function and conversion failures are still returned as errors, since the function did finish.
+	pub fn call_outcome<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<CallOutcome<R>, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+
+		let registration = match self.register_cancellation() {
+			Ok(registration) => registration,
+			Err(reason) => return Ok(CallOutcome::Terminated(reason)),
+		};
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		let result = self.execute_call(fn_name, &json_args, false);
+		if let Some(watchdog) = watchdog {
+			watchdog.stop();
+		}
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Ok(CallOutcome::Terminated(reason));
+		}
+		let result = self.finish_call(result)?;
+		Self::deserialize_result(fn_name, result).map(CallOutcome::Completed)
+	}
+
+	/// Invokes a JavaScript function, converting argument and result directly between Rust and V8 values.
+	///
+	/// Behaves like [`call()`](#method.call), but skips the JSON strings which other calls use for transport: the argument is
+	/// serialized straight into a V8 value, and the result deserialized straight from one, with `serde_v8`. For large payloads
+	/// this saves the copies and the parsing on both sides; `cargo bench` compares both paths. In return, this path offers only
+	/// the plain mapping between serde's data model and JS values: [`BigInt`](struct.BigInt.html), [`Date`](struct.Date.html) and
+	/// [`Bytes`](struct.Bytes.html) are not converted, [JSON hooks](struct.ScriptBuilder.html#method.json_reviver) are not applied,
+	/// `undefined` and thrown values other than `Error` are not reported specially, and returned promises are not awaited.
+	///
+	/// `fn_name` must name a global function. The timeout behaves like in `call()`.
+	pub fn call_direct<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		self.begin_call()?;
+		let context = self.runtime.global_context();
+		let result = self.execute_direct(context, fn_name, args);
+		if let Some(watchdog) = watchdog {
+			watchdog.stop();
+		}
+		self.usage.end();
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Resolves the global function `fn_name` once, and returns a typed handle for invoking it repeatedly.
+	///
+	/// Calls through [`call_bound()`](#method.call_bound) invoke the V8 function directly, without looking up its name or
+	/// generating code for each call. Like in [`call_direct()`](#method.call_direct), arguments and results are converted
+	/// straight between Rust and V8 values. Fails if no such function exists, or if calling it is not
+	/// [allowed](struct.ScriptBuilder.html#method.allow_calls).
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::from_string("function step(x) { return (x * 3 + 1) % 1000; }")?;
+	/// 	let step = script.bind::<u32, u32>("step")?;
+	///
+	/// 	let mut x = 0;
+	/// 	for _ in 0..1000 {
+	/// 		x = script.call_bound(&step, &x, None)?;
+	/// 	}
+	/// 	assert!(x < 1000);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn bind<A, R>(&mut self, fn_name: &str) -> Result<JsFunction<A, R>, AnyError>
+	where
+		A: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		self.debug_assert_thread();
+
+		let function = {
+			let context = self.runtime.global_context();
+			let scope = &mut v8::HandleScope::with_context(self.runtime.v8_isolate(), context);
+			let function = Self::global_function(scope, fn_name)?;
+			v8::Global::new(scope, function)
+		};
+
+		let id = self.next_bound_fn;
+		self.next_bound_fn += 1;
+		self.bound_fns.insert(id, (fn_name.to_string(), function));
+		Ok(JsFunction { script_id: self.id, id, signature: PhantomData })
+	}
+
+	/// Invokes the function bound by [`bind()`](#method.bind).
+	///
+	/// Behaves like [`call_direct()`](#method.call_direct), including the timeout. Fails if the function was bound by another
+	/// script, or has been unbound.
+	pub fn call_bound<A, R>(&mut self, function: &JsFunction<A, R>, args: &A, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		A: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_handle(function.script_id, "function")?;
+		let (fn_name, bound) = self
+			.bound_fns
+			.get(&function.id)
+			.cloned()
+			.ok_or_else(|| generic_error("function has been unbound"))?;
+
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		self.begin_call()?;
+		let result = {
+			let context = self.runtime.global_context();
+			let scope = &mut v8::HandleScope::with_context(self.runtime.v8_isolate(), context);
+			let bound = v8::Local::new(scope, &bound);
+			Self::invoke_direct(scope, bound, &fn_name, args, &self.source_maps)
+		};
+		if let Some(watchdog) = watchdog {
+			watchdog.stop();
+		}
+		self.usage.end();
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Releases the function bound by [`bind()`](#method.bind).
+	///
+	/// Fails if the function was bound by another script.
+	pub fn unbind<A, R>(&mut self, function: JsFunction<A, R>) -> Result<(), AnyError> {
+		self.check_handle(function.script_id, "function")?;
+		self.bound_fns.remove(&function.id);
+		Ok(())
+	}
+
+	fn execute_direct<P, R>(&mut self, context: v8::Global<v8::Context>, fn_name: &str, args: &P) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let scope = &mut v8::HandleScope::with_context(self.runtime.v8_isolate(), context);
+		let function = Self::global_function(scope, fn_name)?;
+		Self::invoke_direct(scope, function, fn_name, args, &self.source_maps)
+	}
+
+	/// Looks up the global function `fn_name` in the scope's context.
+	fn global_function<'s>(scope: &mut v8::HandleScope<'s>, fn_name: &str) -> Result<v8::Local<'s, v8::Function>, AnyError> {
+		let global = scope.get_current_context().global(scope);
+		let name = v8::String::new(scope, fn_name).ok_or_else(|| generic_error("function name is too long"))?;
+		global
+			.get(scope, name.into())
+			.and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+			.ok_or_else(|| generic_error(format!("JS function '{}' is not defined", fn_name)))
+	}
+
+	/// Invokes `function`, converting argument and result with serde_v8; see call_direct().
+	fn invoke_direct<'s, P, R>(
+		scope: &mut v8::HandleScope<'s>,
+		function: v8::Local<'s, v8::Function>,
+		fn_name: &str,
+		args: &P,
+		source_maps: &RefCell<SourceMaps>,
+	) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let arg = serde_v8::to_v8(scope, args)
+			.map_err(|e| generic_error(format!("cannot pass arguments to JS function '{}': {}", fn_name, e)))?;
+
+		let tc_scope = &mut v8::TryCatch::new(scope);
+		let receiver = v8::undefined(tc_scope).into();
+		match function.call(tc_scope, receiver, &[arg]) {
+			Some(result) => serde_v8::from_v8(tc_scope, result)
+				.map_err(|e| generic_error(format!("cannot convert result of JS function '{}': {}", fn_name, e))),
+			None => match tc_scope.exception() {
+				Some(exception) if !tc_scope.has_terminated() => {
+					let error = deno_core::error::JsError::from_v8_exception(tc_scope, exception);
+					Err(source_maps.borrow().apply(error).into())
+				}
+				_ => Err(generic_error("Uncaught Error: execution terminated")),
+			},
+		}
+	}
+
+	/// Invokes a JavaScript function with a gas limit of its own, instead of the script's.
+	///
+	/// Behaves like [`call()`](#method.call), but the call starts with `gas` units, e.g. to give an expensive entry point a larger
+	/// budget than cheap event handlers, or to scale the budget with the size of the input. The outcome is just as deterministic;
+	/// `timeout_ms` can be combined as a safety net for work that gas does not meter. Subsequent calls use the script's limit again.
+	/// Fails if the script was not built with [`ScriptBuilder::gas_limit()`](struct.ScriptBuilder.html#method.gas_limit).
+	pub fn call_with_gas<P, R>(&mut self, fn_name: &str, args: &P, gas: u64, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let previous = match &mut self.gas {
+			Some(meter) => meter.set_limit(gas),
+			None => return Err(generic_error("a gas limit per call requires a script built with ScriptBuilder::gas_limit()")),
+		};
+
+		let result = self.call(fn_name, args, timeout_ms);
+		if let Some(meter) = &mut self.gas {
+			meter.set_limit(previous);
+		}
+		result
+	}
+
+	/// Invokes a JavaScript function, and checks its result against a JSON Schema.
+	///
+	/// Behaves like [`call_value()`](#method.call_value) with a JSON argument, but fails if the returned value does not conform
+	/// to `schema`. The error names the path of the first mismatching value, e.g. `result.items[2].name`, and what was expected
+	/// there. This guards against a plugin returning the wrong shape. A subset of JSON Schema is supported: `type` (including
+	/// `"integer"`), `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`, `minItems`, `maxItems`, `minimum`
+	/// and `maximum`. Other keywords are ignored.
+	pub fn call_checked<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>, schema: &JsValue) -> Result<JsValue, AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let result = self.call_impl(fn_name, &json_args, timeout_ms)?.json_value;
+
+		match schema::validate(&result, schema, "result") {
+			Ok(()) => Ok(result),
+			Err(mismatch) => Err(generic_error(format!(
+				"JS function '{}' returned a value not matching the schema: {} (at `{}`)",
+				fn_name, mismatch.message, mismatch.path
+			))),
+		}
+	}
+
+	/// Invokes a JavaScript function with multiple arguments.
+	///
+	/// Behaves like [`call()`](#method.call), except that `args` must serialize to a JSON array -- typically a tuple, slice or
+	/// `Vec` -- whose elements are passed as separate positional arguments. For example, `call_args("add", &(1, 2), None)` invokes
+	/// `add(1, 2)`. Rest parameters collect them as usual: `function sum(...nums)` receives `nums = [1, 2, 3]` for `&[1, 2, 3]`.
+	/// An empty tuple `&()` serializes to `null` and invokes the function without arguments; other values fail. For argument lists
+	/// of varying length and types, which are only known at runtime, pass a `Vec<JsValue>`.
+	pub fn call_args<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = match args::to_json(fn_name, args)? {
+			JsValue::Null => JsValue::Array(Vec::new()),
+			json_args @ JsValue::Array(_) => json_args,
+			_ => {
+				return Err(generic_error(format!(
+					"cannot pass arguments to JS function '{}': expected a tuple or sequence",
+					fn_name
+				)))
+			}
+		};
+
+		let function = format!("(args => {}(...args))", fn_name);
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Invokes a JavaScript function with globals of the script as arguments, e.g. `call_refs("process", &["input", "config"], None)`
+	/// for `process(input, config)`.
+	///
+	/// The arguments are passed by reference inside JS, so no value is converted to JSON and back; this suits large data that is
+	/// already in the script, such as state produced by earlier calls or host functions. Globals declared with `let` or `const`
+	/// are found as well. Fails before invoking anything if one of the globals does not exist or is `undefined`, naming all
+	/// that are missing. The result and timeout behave like in [`call()`](#method.call).
+	pub fn call_refs<R>(&mut self, fn_name: &str, globals: &[&str], timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+
+		let mut missing = Vec::new();
+		let mut refs = Vec::new();
+		for &name in globals {
+			if self.typeof_global(name)? == "undefined" {
+				missing.push(name);
+			}
+			refs.push(global_ref(name)?);
+		}
+		if !missing.is_empty() {
+			return Err(generic_error(format!(
+				"cannot pass globals to JS function '{}': '{}' not defined",
+				fn_name,
+				missing.join("', '")
+			)));
+		}
+
+		let function = format!("(() => {}({}))", fn_name, refs.join(", "));
+		let result = self.call_impl(&function, &JsValue::Null, timeout_ms)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Invokes a JavaScript function for its side effects.
+	///
+	/// Behaves like [`call()`](#method.call), but discards whatever the function returns. This saves the `let _: () = ...`
+	/// annotation for functions that are only called to modify the script's state.
+	pub fn call_void<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<(), AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		self.call_impl(fn_name, &json_args, timeout_ms).map(|_| ())
+	}
+
+	/// Invokes a JavaScript function, and returns its result as JSON value, whatever its shape.
+	///
+	/// Arguments and timeout behave like in [`call()`](#method.call); equivalent to `call::<P, JsValue>()`, without having to
+	/// spell out the result type. A function returning `undefined` yields `JsValue::Null`.
+	pub fn call_json<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<JsValue, AnyError>
+	where
+		P: Serialize,
+	{
+		self.call(fn_name, args, timeout_ms)
+	}
+
+	/// Invokes a JavaScript function with an argument that is already available as JSON.
+	///
+	/// The dynamic counterpart to [`call()`](#method.call): `arg` is passed on as-is, and the result is returned as a JSON value
+	/// without converting it to a Rust type. This suits pass-through scenarios, such as forwarding a request body to a script.
+	/// A function returning `undefined` yields `JsValue::Null`.
+	pub fn call_value(&mut self, fn_name: &str, arg: &JsValue, timeout_ms: Option<u64>) -> Result<JsValue, AnyError> {
+		self.check_allowed(fn_name)?;
+		self.call_impl(fn_name, arg, timeout_ms).map(|result| result.json_value)
+	}
+
+	/// Invokes a JavaScript function, and returns its result as JSON-encoded bytes.
+	///
+	/// Arguments and timeout behave like in [`call()`](#method.call). The result is encoded exactly as it crosses the boundary,
+	/// including a configured [JSON replacer](struct.ScriptBuilder.html#method.json_replacer), but never parsed on the Rust side.
+	/// This suits proxies which forward the output of a script, e.g. to a socket, as they avoid a typed round trip. A function
+	/// returning `undefined` yields `null`, like with [`call_value()`](#method.call_value).
+	pub fn call_raw_bytes<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<Vec<u8>, AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let result = self.call_impl_raw(fn_name, &json_args, timeout_ms)?;
+		Ok(result.json.into_bytes())
+	}
+
+	/// Invokes a JavaScript function as a method, with `this` bound to the given receiver.
+	///
+	/// `fn_name` is an expression evaluating to a function, like in [`call()`](#method.call), which instead invokes functions
+	/// without receiver. This allows calling e.g. `"Counter.prototype.add"` on a particular instance, or a method which was
+	/// detached from its object. The receiver is either an expression, such as `"counters[0]"`, or a `JsValue`; see
+	/// [`ThisArg`](enum.ThisArg.html). Arguments, result and timeout behave like in [`call()`](#method.call).
+	pub fn call_with_this<'a, P, R>(
+		&mut self,
+		this: impl Into<ThisArg<'a>>,
+		fn_name: &str,
+		args: &P,
+		timeout_ms: Option<u64>,
+	) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+
+		// Receiver on a line of its own, so that a trailing comment cannot swallow the rest
+		let receiver = match this.into() {
+			ThisArg::Expr(expr) => format!("(\n{}\n)", expr),
+			ThisArg::Value(value) => format!("__rust.fromHost({})", serde_json::to_string(&value.to_string())?),
+		};
+		let function = format!("(arg => ({}).call({}, arg))", fn_name, receiver);
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Invokes a JavaScript function with an iterable that lazily pulls its items from a Rust iterator.
+	///
+	/// The function receives a single argument, which supports both `for...of` and `for await...of`. Each step of the loop
+	/// pulls the next item from `input` and converts it like the arguments of [`call()`](#method.call), so only the items JS
+	/// actually consumes are ever produced or serialized. An item that cannot be converted makes that step throw. Result and
+	/// timeout behave like in `call()`.
+	///
+	/// The iterable is single-pass: iterating it a second time throws a `TypeError`. It is also bound to this call only; once
+	/// the call has returned, the iterator is dropped, and pulling from a kept reference throws. The iterator must not borrow
+	/// local data, so e.g. `vec.into_iter()` can be passed, but not `vec.iter()`.
+	pub fn call_with_iter<I, R>(&mut self, fn_name: &str, input: I, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		I: IntoIterator,
+		I::IntoIter: 'static,
+		I::Item: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let owned_name = fn_name.to_string();
+		let items = input
+			.into_iter()
+			.map(move |item| args::to_json(&owned_name, &item).map(|json| json.to_string()));
+
+		self.runtime.op_state().borrow_mut().put(InputIter(Box::new(items)));
+		let function = format!("(() => {}(__rust.inputIterable()))", fn_name);
+		let result = self.call_impl(&function, &JsValue::Null, timeout_ms);
+		self.runtime.op_state().borrow_mut().try_take::<InputIter>();
+
+		Self::deserialize_result(fn_name, result?)
+	}
+
+	/// Invokes a JavaScript function that returns a function, and returns a handle to the latter.
+	///
+	/// Behaves like [`call()`](#method.call), but instead of converting the result to JSON, the returned function is kept
+	/// inside the script. This allows higher-order APIs, such as factories producing handlers, which can later be invoked
+	/// with [`invoke()`](#method.invoke). Fails if the result is not a function.
+	pub fn call_handle<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<JsFunctionHandle, AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let function = format!("(arg => __rust.storeHandle({}(arg)))", fn_name);
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		let id = serde_json::from_value(result.json_value)?;
+		Ok(JsFunctionHandle { script_id: self.id, id })
+	}
+
+	/// Invokes the JavaScript function referred to by `handle`.
+	///
+	/// Arguments, result and timeout behave like in [`call()`](#method.call). Fails if the handle was created by another
+	/// script, or has been released.
+	pub fn invoke<P, R>(&mut self, handle: &JsFunctionHandle, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let function = self.handle_ref(handle)?;
+		let json_args = args::to_json(&function, args)?;
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		Self::deserialize_result(&function, result)
+	}
+
+	/// Releases the function referred to by `handle`, allowing it to be garbage-collected.
+	///
+	/// Fails if the handle was created by another script.
+	pub fn release(&mut self, handle: JsFunctionHandle) -> Result<(), AnyError> {
+		self.handle_ref(&handle)?;
+
+		let function = format!("(() => __rust.releaseHandle({}))", handle.id);
+		self.call_internal(&function, &JsValue::Null).map(|_| ())
+	}
+
+	/// Invokes a JavaScript function that returns an object, and returns a handle to the latter.
+	///
+	/// Behaves like [`call()`](#method.call), but instead of converting the result to JSON, the returned object is kept inside
+	/// the script, e.g. a class instance whose methods are later invoked with [`call_method()`](#method.call_method). This
+	/// enables object-oriented plugin APIs, with state that never crosses the boundary. Fails if the result is not an object.
+	pub fn call_object<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<JsObjectHandle, AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let function = format!("(arg => __rust.storeObject({}(arg)))", fn_name);
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		let id = serde_json::from_value(result.json_value)?;
+		Ok(JsObjectHandle { script_id: self.id, id })
+	}
+
+	/// Invokes the method `method` on the object referred to by `handle`, with `this` bound to the object.
+	///
+	/// Arguments are passed like in [`call_args()`](#method.call_args): a tuple or sequence holds the positional arguments.
+	/// Result and timeout behave like in [`call()`](#method.call). Fails if the handle was created by another script or has
+	/// been released, or if the object has no such method.
+	pub fn call_method<P, R>(&mut self, handle: &JsObjectHandle, method: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_handle(handle.script_id, "object")?;
+		let json_args = match args::to_json(method, args)? {
+			JsValue::Null => JsValue::Array(Vec::new()),
+			json_args @ JsValue::Array(_) => json_args,
+			_ => {
+				return Err(generic_error(format!(
+					"cannot pass arguments to JS method '{}': expected a tuple or sequence",
+					method
+				)))
+			}
+		};
+
+		let function = format!("(args => __rust.callMethod({}, {}, args))", handle.id, serde_json::to_string(method)?);
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		Self::deserialize_result(method, result)
+	}
+
+	/// Releases the object referred to by `handle`, allowing it to be garbage-collected.
+	///
+	/// Fails if the handle was created by another script.
+	pub fn release_object(&mut self, handle: JsObjectHandle) -> Result<(), AnyError> {
+		self.check_handle(handle.script_id, "object")?;
+
+		let function = format!("(() => __rust.releaseHandle({}))", handle.id);
+		self.call_internal(&function, &JsValue::Null).map(|_| ())
+	}
+
+	/// Creates an additional context within this script's isolate, runs `js_code` in it, and returns a handle to it.
+	///
+	/// Each context has a global scope of its own: the script and other contexts cannot see its globals, nor can it see theirs.
+	/// This is far cheaper than a script per plugin, since the isolate, its heap and its limits are shared. Contexts start out
+	/// with the plain ECMAScript globals only; they have no console, host functions, timers or other features of the script.
+	/// Their functions are invoked with [`call_in_context()`](#method.call_in_context).
+	///
+	/// `timeout_ms` limits the top-level code; exceeding it aborts execution, and fails with
+	/// [`JsError::InitTimeout`](enum.JsError.html#variant.InitTimeout).
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::new_empty()?;
+	/// 	let first = script.create_context("var name = 'first'; function greet() { return 'I am ' + name; }", None)?;
+	/// 	let second = script.create_context("var name = 'second'; function greet() { return 'I am ' + name; }", None)?;
+	///
+	/// 	let greeting: String = script.call_in_context(&first, "greet", &(), None)?;
+	/// 	assert_eq!(greeting, "I am first");
+	/// 	let greeting: String = script.call_in_context(&second, "greet", &(), None)?;
+	/// 	assert_eq!(greeting, "I am second");
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn create_context(&mut self, js_code: &str, timeout_ms: Option<u64>) -> Result<JsContextHandle, AnyError> {
+		self.debug_assert_thread();
+		let id = self.next_context;
+		self.next_context += 1;
+
+		let context = {
+			let scope = &mut v8::HandleScope::new(self.runtime.v8_isolate());
+			let context = v8::Context::new(scope);
+			v8::Global::new(scope, context)
+		};
+
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::InitTimeout);
+		let result = Self::run_in_context(self.runtime.v8_isolate(), context.clone(), &format!("context-{}.js", id), js_code);
+		drop(watchdog);
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result?;
+
+		self.contexts.insert(id, context);
+		Ok(JsContextHandle { script_id: self.id, id })
+	}
+
+	/// Invokes the global function `fn_name` of the context referred to by `handle`.
+	///
+	/// Arguments and results are converted like in [`call_direct()`](#method.call_direct), since contexts lack the helpers
+	/// which the JSON transport of [`call()`](#method.call) relies on. The timeout behaves like in `call()`. Fails if the handle
+	/// was created by another script, or has been released.
+	pub fn call_in_context<P, R>(&mut self, handle: &JsContextHandle, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_handle(handle.script_id, "context")?;
+		let context = self
+			.contexts
+			.get(&handle.id)
+			.cloned()
+			.ok_or_else(|| generic_error("context has been released"))?;
+
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		self.begin_call()?;
+		let result = self.execute_direct(context, fn_name, args);
+		if let Some(watchdog) = watchdog {
+			watchdog.stop();
+		}
+		self.usage.end();
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Releases the context referred to by `handle`, allowing it to be garbage-collected along with its globals.
+	///
+	/// Fails if the handle was created by another script.
+	pub fn release_context(&mut self, handle: JsContextHandle) -> Result<(), AnyError> {
+		self.check_handle(handle.script_id, "context")?;
+		self.contexts.remove(&handle.id);
+		Ok(())
+	}
+
+	/// Runs top-level code in `context`, which need not be the script's own; see create_context().
+	fn run_in_context(
+		isolate: &mut v8::Isolate,
+		context: v8::Global<v8::Context>,
+		js_filename: &str,
+		js_code: &str,
+	) -> Result<(), AnyError> {
+		let scope = &mut v8::HandleScope::with_context(isolate, context);
+		let source = Self::script_source(scope, js_filename, js_code, 0, None)?;
+
+		let tc_scope = &mut v8::TryCatch::new(scope);
+		let options = v8::script_compiler::CompileOptions::NoCompileOptions;
+		let script = v8::script_compiler::compile(tc_scope, source, options, v8::script_compiler::NoCacheReason::NoReason);
+
+		match script.and_then(|script| script.run(tc_scope)) {
+			Some(_) => Ok(()),
+			None => match tc_scope.exception() {
+				Some(exception) if !tc_scope.has_terminated() => {
+					Err(deno_core::error::JsError::from_v8_exception(tc_scope, exception).into())
+				}
+				_ => Err(generic_error("Uncaught Error: execution terminated")),
+			},
+		}
+	}
+
+	fn check_allowed(&self, fn_name: &str) -> Result<(), AnyError> {
+		match &self.allowed_calls {
+			Some(allowed) if !allowed.contains(fn_name) => Err(JsError::NotAllowed { function: fn_name.to_string() }.into()),
+			_ => Ok(()),
+		}
+	}
+
+	fn handle_ref(&self, handle: &JsFunctionHandle) -> Result<String, AnyError> {
+		self.check_handle(handle.script_id, "function")?;
+		Ok(format!("__rust.handle({})", handle.id))
+	}
+
+	fn check_handle(&self, script_id: u64, kind: &str) -> Result<(), AnyError> {
+		if script_id != self.id {
+			return Err(generic_error(format!("{} handle belongs to another script", kind)));
+		}
+		Ok(())
+	}
+
+	/// Invokes a JavaScript function which may return a promise, and waits for it to settle.
+	///
+	/// Behaves like [`call()`](#method.call), except that a returned promise is awaited: its fulfillment value is the result,
+	/// while a rejection makes the call fail. Functions returning other values are supported as well.
+	///
+	/// There are two independent time budgets. `sync_timeout_ms` limits the synchronous part of the function, i.e. until it
+	/// returns (for `async` functions: until the first `await`). `total_timeout_ms` limits the whole call, until the promise has
+	/// settled. Exceeding them aborts execution with [`JsError::SyncTimeout`](enum.JsError.html#variant.SyncTimeout) or
+	/// [`JsError::TotalTimeout`](enum.JsError.html#variant.TotalTimeout) respectively. Fails as well if the promise can never
+	/// settle, because there is nothing left to wait for.
+	pub fn call_async<P, R>(
+		&mut self,
+		fn_name: &str,
+		args: &P,
+		sync_timeout_ms: Option<u64>,
+		total_timeout_ms: Option<u64>,
+	) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let registration = self.register_cancellation()?;
+		let total_watchdog = self.start_watchdog(total_timeout_ms, JsError::TotalTimeout);
+
+		let sync_watchdog = self.start_watchdog(sync_timeout_ms, JsError::SyncTimeout);
+		let mut result = self.execute_call(fn_name, &json_args, true);
+		drop(sync_watchdog);
+
+		if result.is_ok() && !self.terminator.is_terminating() {
+			result = futures::executor::block_on(self.runtime.run_event_loop());
+		}
+		drop(total_watchdog);
+		drop(registration);
+
+		if result.is_ok() && self.runtime.op_state().borrow().resource_table.get::<ResultResource>(self.last_rid).is_none() {
+			if let Some(reason) = self.terminator.take_reason() {
+				return Err(reason.into());
+			}
+			return Err(generic_error(format!("promise returned by JS function '{}' never settled", fn_name)));
+		}
+
+		let result = self.finish_call(result)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Invokes a JavaScript function that returns a `ReadableStream`, and returns a stream of its chunks.
+	///
+	/// Arguments behave like in [`call()`](#method.call); the function itself must return the stream synchronously. Chunks
+	/// are pulled one at a time with [`JsStream::next()`](struct.JsStream.html#method.next), each of which drives the event
+	/// loop until the stream's reader has produced the next chunk. Chunks are converted like the results of `call()`, except
+	/// that `Uint8Array`s and other binary chunks become arrays of bytes, so they can be received as `Vec<u8>`.
+	///
+	/// `timeout_ms` limits each pull separately, rather than the whole stream; exceeding it aborts execution with
+	/// [`JsError::TotalTimeout`](enum.JsError.html#variant.TotalTimeout). The bare runtime has no `ReadableStream`
+	/// constructor, so the stream comes from the script or a polyfill; any object whose `getReader()` returns a compatible
+	/// reader is accepted.
+	pub fn call_stream<P, T>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<JsStream<'_, T>, AnyError>
+	where
+		P: Serialize,
+		T: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let function = format!("(arg => __rust.storeStream({}(arg)))", fn_name);
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		let id = serde_json::from_value(result.json_value)?;
+		Ok(JsStream::new(self, id, timeout_ms))
+	}
+
+	/// Invokes a JavaScript function that returns a generator or another iterable, and returns an iterator over its items.
+	///
+	/// Meant for functions producing large datasets: instead of converting one huge result, items are pulled one at a time
+	/// whenever the returned [`JsIter`](struct.JsIter.html) is advanced, so only a single item is held in memory at a time.
+	/// Arguments behave like in [`call()`](#method.call), and each item is converted like its result. Async generators and
+	/// other async iterables are supported as well; each pull then drives the event loop until the next item is available.
+	///
+	/// `timeout_ms` limits each pull separately, rather than the whole iteration; exceeding it aborts execution with
+	/// [`JsError::TotalTimeout`](enum.JsError.html#variant.TotalTimeout).
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::from_string("function* squares(n) { for (let i = 1; i <= n; ++i) yield i * i; }")?;
+	///
+	/// 	let squares = script.call_iter::<_, u32>("squares", &4, None)?.collect::<Result<Vec<_>, _>>()?;
+	/// 	assert_eq!(squares, [1, 4, 9, 16]);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn call_iter<P, T>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<JsIter<'_, T>, AnyError>
+	where
+		P: Serialize,
+		T: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		let function = format!("(arg => __rust.storeIterator({}(arg)))", fn_name);
+
+		let result = self.call_impl(&function, &json_args, timeout_ms)?;
+		let id = serde_json::from_value(result.json_value)?;
+		Ok(JsIter::new(self, id, timeout_ms))
+	}
+
+	/// Invokes a JavaScript function which may return a promise, and returns a future resolving to its settled value.
+	///
+	/// The asynchronous counterpart to [`call_async()`](#method.call_async), for hosts that run an executor themselves: instead
+	/// of blocking until the promise has settled, the returned future drives the script's event loop whenever it is polled.
+	/// Like any future, it does nothing until polled; the function is invoked on the first poll. The future borrows the script
+	/// mutably, so the script cannot be called otherwise in the meantime. Like the script, it must stay on the thread that
+	/// created the script.
+	///
+	/// `timeout_ms` limits the whole call; exceeding it aborts execution with [`JsError::TotalTimeout`](enum.JsError.html#variant.TotalTimeout).
+	/// Fails as well if the promise is rejected or can never settle.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, AnyError};
+	///
+	/// fn main() -> Result<(), AnyError> {
+	/// 	let mut script = Script::from_string("async function double(a) { await null; return 2 * a; }")?;
+	///
+	/// 	let result: i32 = deno_core::futures::executor::block_on(script.call_future("double", &21, None))?;
+	/// 	assert_eq!(result, 42);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub async fn call_future<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+
+		let what = format!("promise returned by JS function '{}'", fn_name);
+		let result = self.execute_until_settled(fn_name, &json_args, timeout_ms, &what).await?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Reads the next chunk of a stream stored by call_stream(), or `None` once it is exhausted.
+	pub(crate) async fn pull_stream(&mut self, id: u64, timeout_ms: Option<u64>) -> Result<Option<JsValue>, AnyError> {
+		#[derive(serde::Deserialize)]
+		struct Pulled {
+			done: bool,
+			#[serde(default)]
+			value: JsValue,
+		}
+
+		let result = self.execute_until_settled("__rust.readStream", &json!(id), timeout_ms, "read from JS stream").await?;
+		let pulled: Pulled = serde_json::from_value(result.json_value)?;
+		Ok(if pulled.done { None } else { Some(pulled.value) })
+	}
+
+	/// Invokes a function which may return a promise, and drives the event loop until it has settled; `what` names the promise
+	/// in the error if it never settles.
+	async fn execute_until_settled(
+		&mut self,
+		function: &str,
+		args: &JsValue,
+		timeout_ms: Option<u64>,
+		what: &str,
+	) -> Result<ReturnValue, AnyError> {
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::TotalTimeout);
+
+		let mut result = self.execute_call(function, args, true);
+		if result.is_ok() && !self.terminator.is_terminating() {
+			result = self.poll_until_idle(watchdog.as_ref()).await;
+		}
+		drop(watchdog);
+		drop(registration);
+
+		if result.is_ok() && self.runtime.op_state().borrow().resource_table.get::<ResultResource>(self.last_rid).is_none() {
+			if let Some(reason) = self.terminator.take_reason() {
+				return Err(reason.into());
+			}
+			return Err(generic_error(format!("{} never settled", what)));
+		}
+
+		self.finish_call(result)
+	}
+
+	/// Returns the next item of an iterator stored by call_iter(), or `None` once it is exhausted.
+	pub(crate) fn pull_iter(&mut self, id: u64, timeout_ms: Option<u64>) -> Result<Option<JsValue>, AnyError> {
+		#[derive(serde::Deserialize)]
+		struct Pulled {
+			done: bool,
+			#[serde(default)]
+			value: JsValue,
+		}
+
+		let args = json!(id);
+		let future = self.execute_until_settled("__rust.readIterator", &args, timeout_ms, "read from JS iterator");
+		let pulled: Pulled = serde_json::from_value(futures::executor::block_on(future)?.json_value)?;
+		Ok(if pulled.done { None } else { Some(pulled.value) })
+	}
+
+	/// Closes and forgets an iterator stored by call_iter().
+	pub(crate) fn release_iter(&mut self, id: u64) -> Result<(), AnyError> {
+		let function = format!("(() => __rust.releaseIterator({}))", id);
+		self.call_internal(&function, &JsValue::Null).map(|_| ())
+	}
+
+	/// Cancels and forgets a stream stored by call_stream().
+	pub(crate) fn release_stream(&mut self, id: u64) -> Result<(), AnyError> {
+		let function = format!("(() => __rust.releaseStream({}))", id);
+		self.call_internal(&function, &JsValue::Null).map(|_| ())
+	}
+
+	/// Runs the event loop until all pending async work of the script has finished, e.g. after a call which started async
+	/// ops without awaiting them.
+	///
+	/// Afterwards, globals reflect the final state, as all promises which can settle have settled. Returns right away if
+	/// nothing is pending. `timeout_ms` limits the time spent waiting, including JS callbacks running in the meantime;
+	/// exceeding it aborts execution with [`JsError::DrainTimeout`](enum.JsError.html#variant.DrainTimeout), which reports
+	/// how many async ops were still pending. Work that is still pending then remains so, and is continued by the next call
+	/// that drives the event loop. Fails as well if a callback throws.
+	pub fn drain_async(&mut self, timeout_ms: Option<u64>) -> Result<(), AnyError> {
+		self.begin_call()?;
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::DrainTimeout { pending: 0 });
+
+		let result = futures::executor::block_on(self.poll_until_idle(watchdog.as_ref()));
+		drop(watchdog);
+		drop(registration);
+		self.usage.end();
+
+		if let Some(reason) = self.terminator.take_reason() {
+			let reason = match reason {
+				JsError::DrainTimeout { .. } => JsError::DrainTimeout { pending: self.pending_ops()? },
+				reason => reason,
+			};
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Returns the number of async ops which the script has started, but which have not completed yet.
+	fn pending_ops(&mut self) -> Result<usize, AnyError> {
+		let count = self.eval_json("__rust.pendingOps()")?;
+		Ok(serde_json::from_value(count)?)
+	}
+
+	/// Drives the event loop until nothing is pending, or until execution is terminated, e.g. by `watchdog`.
+	async fn poll_until_idle(&mut self, watchdog: Option<&Watchdog>) -> Result<(), AnyError> {
+		let runtime = &mut self.runtime;
+		let terminator = &self.terminator;
+		futures::future::poll_fn(|cx| {
+			if let Some(watchdog) = watchdog {
+				watchdog.wake_on_fire(cx.waker());
+			}
+			if terminator.is_terminating() {
+				return Poll::Ready(Ok(()));
+			}
+			runtime.poll_event_loop(cx)
+		})
+		.await
+	}
+
+	/// Advances the clock of the script's timers by `delta`, and runs the callbacks that become due; returns how many ran.
+	///
+	/// Requires [`ScriptBuilder::timers()`](struct.ScriptBuilder.html#method.timers) or
+	/// [`deterministic()`](struct.ScriptBuilder.html#method.deterministic), whose virtual time advances along with the timers.
+	/// Callbacks run one after another, in the order of their due time, and in the order they were scheduled for equal times.
+	/// Timers scheduled by a callback run in the same step if they become due within `delta`, and an interval fires once for
+	/// every period that elapses. Promise reactions triggered by a callback are processed before the next callback runs. A
+	/// `delta` of zero runs the timers that are already due, such as `setTimeout(f, 0)`.
+	///
+	/// `timeout_ms` limits the whole step, including all callbacks; exceeding it aborts execution with
+	/// [`JsError::Timeout`](enum.JsError.html#variant.Timeout). If a callback throws, its exception is returned and the
+	/// remaining callbacks stay due for the next step, unless the script's `error` handlers prevent it.
+	pub fn advance_time(&mut self, delta: Duration, timeout_ms: Option<u64>) -> Result<usize, AnyError> {
+		let delta_ms = JsValue::from(delta.as_secs_f64() * 1000.0);
+		let deadline = self.call_internal("__rust.timerDeadline", &delta_ms)?.json_value;
+
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		let mut count = 0;
+		let result = loop {
+			let result = self.execute_call("__rust.runTimer", &deadline, false);
+			match self.finish_call_raw(result).and_then(Self::parse_result) {
+				Ok(ReturnValue { json_value: JsValue::Bool(false), .. }) => break Ok(count),
+				Ok(_) => {
+					count += 1;
+					self.runtime.v8_isolate().perform_microtask_checkpoint();
+				}
+				Err(e) => break Err(e),
+			}
+		};
+		drop(watchdog);
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Returns the number of timers which are scheduled, but have not run yet; intervals count until they are cleared.
+	///
+	/// Always 0 unless [`ScriptBuilder::timers()`](struct.ScriptBuilder.html#method.timers) is set.
+	pub fn pending_timers(&mut self) -> Result<usize, AnyError> {
+		let count = self.eval_json("__rust.pendingTimers()")?;
+		Ok(serde_json::from_value(count)?)
+	}
+
+	/// Evaluates a JavaScript expression in the script's global scope, and returns its value.
+	///
+	/// The expression sees the script's current state: its globals and functions, including changes made by earlier calls. The
+	/// result is converted like the result of [`call()`](#method.call); use [`JsValue`](type.JsValue.html) as `R` when its shape
+	/// is not known in advance. For example, `script.eval::<JsValue>("state.items.slice(0, 3)", None)` inspects part of the
+	/// state without a dedicated function. Timeout and errors behave like in `call()`, with positions in error messages relative
+	/// to the expression, which is reported as file "eval_json". Statements are not accepted, except within a function
+	/// expression; see [`eval_async()`](#method.eval_async) to run arbitrary code.
+	///
+	/// Evaluation is not subject to [`ScriptBuilder::allow_calls()`](struct.ScriptBuilder.html#method.allow_calls), which
+	/// only restricts invoking functions by name. With a [gas limit](struct.ScriptBuilder.html#method.gas_limit), the
+	/// expression is metered like the script's own code.
+	pub fn eval<R>(&mut self, js_expr: &str, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		let result = self.eval_impl(js_expr, timeout_ms)?;
+		Self::deserialize_result("<eval>", result)
+	}
+
+	/// Evaluates JavaScript code which may use `await` at the top level, and returns its completion value.
+	///
+	/// The completion value is that of the last expression statement, like with `eval()`: for example,
+	/// `const x = await Promise.resolve(5); x` yields `5`. If the completion value is a promise, it is awaited as well.
+	/// The event loop is driven until all top-level awaits have settled. Declarations remain visible to later calls.
+	///
+	/// `timeout_ms` limits the whole evaluation, including the time spent waiting; exceeding it aborts execution with
+	/// [`JsError::TotalTimeout`](enum.JsError.html#variant.TotalTimeout). Fails if the code throws, or if it awaits a promise
+	/// that can never settle. With a [gas limit](struct.ScriptBuilder.html#method.gas_limit), the code is metered like the
+	/// script's own code.
+	pub fn eval_async(&mut self, js_code: &str, timeout_ms: Option<u64>) -> Result<JsValue, AnyError> {
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::TotalTimeout);
+
+		let result = self.evaluate_repl(js_code);
+		drop(watchdog);
+		drop(registration);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Evaluates an expression within the closure scope of a function, for debugging and tooling.
+	///
+	/// `fn_name` is an expression evaluating to a function, e.g. `"counter.increment"`. `js_expr` can then read the variables
+	/// which that function captures from its enclosing scopes, as if it were written inside the function -- including private
+	/// state of modules that is not reachable otherwise. Only captured variables are visible, as V8 does not retain the others;
+	/// the function's own locals and parameters exist only while it runs. The result is converted like in [`call()`](#method.call).
+	///
+	/// This uses the V8 inspector, which is attached to the script on first use. Since calls run to completion synchronously, there
+	/// is no paused frame to evaluate in; the closure scope is what remains of a function between calls.
+	pub fn eval_typed_at<R>(&mut self, fn_name: &str, js_expr: &str) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		const OBJECT_GROUP: &str = "js-sandbox:scope";
+
+		let js_expr = self.metered(js_expr)?;
+		self.begin_call()?;
+
+		let runtime = &mut self.runtime;
+		let inspector = self.inspector.get_or_insert_with(|| Inspector::new(runtime));
+		let value = Self::evaluate_in_closure(inspector, fn_name, &js_expr, OBJECT_GROUP);
+		inspector.send("Runtime.releaseObjectGroup", json!({ "objectGroup": OBJECT_GROUP }))?;
+		self.usage.end();
+
+		Ok(serde_json::from_value(value?)?)
+	}
+
+	fn evaluate_in_closure(inspector: &mut Inspector, fn_name: &str, js_expr: &str, object_group: &str) -> Result<JsValue, AnyError> {
+		let function = inspector.send("Runtime.evaluate", json!({ "expression": fn_name, "objectGroup": object_group }))?;
+		if function.get("exceptionDetails").is_some() {
+			return Self::repl_value(inspector, &function, object_group);
+		}
+		let function_id = match (function["result"]["type"].as_str(), function["result"].get("objectId")) {
+			(Some("function"), Some(id)) => id.clone(),
+			_ => return Err(generic_error(format!("'{}' is not a function", fn_name))),
+		};
+
+		// The scope chain is an internal property of the function, holding one object per scope, innermost first
+		let properties = inspector.send("Runtime.getProperties", json!({ "objectId": function_id, "ownProperties": true }))?;
+		let scopes_id = properties["internalProperties"]
+			.as_array()
+			.and_then(|internal| internal.iter().find(|property| property["name"] == "[[Scopes]]"))
+			.and_then(|scopes| scopes["value"].get("objectId").cloned())
+			.ok_or_else(|| generic_error(format!("scopes of function '{}' are not available", fn_name)))?;
+
+		let scopes = inspector.send("Runtime.getProperties", json!({ "objectId": scopes_id, "ownProperties": true }))?;
+		let mut scope_ids: Vec<(usize, JsValue)> = scopes["result"]
+			.as_array()
+			.map(|entries| {
+				entries
+					.iter()
+					.filter(|entry| entry["value"]["description"] != "Global")
+					.filter_map(|entry| Some((entry["name"].as_str()?.parse().ok()?, entry["value"].get("objectId")?.clone())))
+					.collect()
+			})
+			.unwrap_or_default();
+		scope_ids.sort_by_key(|(index, _)| *index);
+
+		// Scopes are received as arguments, and made visible with nested `with` statements, innermost one last
+		let mut body = format!("return __rust.toHost((\n{}\n));", js_expr);
+		for i in 0..scope_ids.len() {
+			body = format!("with (scope{}) {{ {} }}", i, body);
+		}
+		let parameters: Vec<String> = (0..scope_ids.len()).map(|i| format!("scope{}", i)).collect();
+		let arguments: Vec<JsValue> = scope_ids.into_iter().map(|(_, id)| json!({ "objectId": id })).collect();
+
+		let json = inspector.send(
+			"Runtime.callFunctionOn",
+			json!({
+				"objectId": function_id,
+				"functionDeclaration": format!("function({}) {{ {} }}", parameters.join(", "), body),
+				"arguments": arguments,
+				"returnByValue": true,
+				"objectGroup": object_group,
+			}),
+		)?;
+		if json.get("exceptionDetails").is_some() {
+			return Self::repl_value(inspector, &json, object_group);
+		}
+		Ok(json::from_js(json["result"]["value"].as_str().unwrap_or("null"))?)
+	}
+
+	/// Evaluates code in V8's REPL mode, which allows top-level await; see eval_async().
+	fn evaluate_repl(&mut self, js_code: &str) -> Result<JsValue, AnyError> {
+		const OBJECT_GROUP: &str = "js-sandbox:eval";
+
+		let js_code = self.metered(js_code)?;
+		self.begin_call()?;
+
+		let runtime = &mut self.runtime;
+		let inspector = self.inspector.get_or_insert_with(|| Inspector::new(runtime));
+		let id = inspector.dispatch(
+			"Runtime.evaluate",
+			json!({ "expression": js_code, "replMode": true, "awaitPromise": true, "objectGroup": OBJECT_GROUP }),
+		);
+
+		// The response is sent once the completion value has settled, which may require ops to make progress
+		let response = futures::executor::block_on(futures::future::poll_fn(|cx| {
+			if let Some(response) = inspector.take_response(id, "Runtime.evaluate") {
+				return Poll::Ready(response);
+			}
+
+			let polled = runtime.poll_event_loop(cx);
+			if let Some(response) = inspector.take_response(id, "Runtime.evaluate") {
+				return Poll::Ready(response);
+			}
+
+			match polled {
+				Poll::Ready(Ok(())) => Poll::Ready(Err(generic_error("top-level await in evaluated code never settled"))),
+				Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+				Poll::Pending => Poll::Pending,
+			}
+		}));
+		self.usage.end();
+		let response = response?;
+
+		let value = Self::repl_value(inspector, &response, OBJECT_GROUP);
+		inspector.send("Runtime.releaseObjectGroup", json!({ "objectGroup": OBJECT_GROUP }))?;
+		value
+	}
+
+	/// Converts the result of a Runtime.evaluate command to JSON, applying the same conversions as call(). Exceptions are
+	/// reported like in call(): Errors as JsError::Exception, other thrown values as JsError::Thrown.
+	fn repl_value(inspector: &mut Inspector, response: &JsValue, object_group: &str) -> Result<JsValue, AnyError> {
+		let details = match response.get("exceptionDetails") {
+			Some(details) => details,
+			None => return Self::remote_value(inspector, &response["result"], object_group),
+		};
+
+		let exception = &details["exception"];
+		if exception.is_null() {
+			let text = details["text"].as_str().unwrap_or("exception");
+			return Err(generic_error(format!("Uncaught {}", text)));
+		}
+
+		// The description of an Error is its stack trace, which starts with the message
+		if exception["subtype"] == "error" {
+			let stack = exception["description"].as_str().unwrap_or_default();
+			let message = format!("Uncaught {}", stack.lines().next().unwrap_or_default());
+			let location = match (details["url"].as_str(), details["lineNumber"].as_u64(), details["columnNumber"].as_u64()) {
+				(Some(file), Some(line), Some(column)) if !file.is_empty() => {
+					Some(SourcePosition { file: file.to_string(), line: line as u32 + 1, column: column as u32 + 1 })
+				}
+				_ => None,
+			};
+			let name = error::exception_name(&message);
+			return Err(JsError::Exception { message, name, location, stack: Some(stack.to_string()) }.into());
+		}
+
+		let value = Self::remote_value(inspector, exception, object_group)?;
+		let message = match &value {
+			JsValue::String(text) => text.clone(),
+			_ if exception["type"] == "undefined" => "undefined".to_string(),
+			_ => value.to_string(),
+		};
+		Err(JsError::Thrown { message, value }.into())
+	}
+
+	/// Converts a RemoteObject of the inspector protocol to JSON, like toHost() in the prelude.
+	fn remote_value(inspector: &mut Inspector, object: &JsValue, object_group: &str) -> Result<JsValue, AnyError> {
+		if let Some(object_id) = object.get("objectId") {
+			// Objects are passed through the prelude, so that nested BigInts and Dates are converted as usual
+			let json = inspector.send(
+				"Runtime.callFunctionOn",
+				json!({
+					"objectId": object_id,
+					"functionDeclaration": "function() { return __rust.toHost(this); }",
+					"returnByValue": true,
+					"objectGroup": object_group,
+				}),
+			)?;
+			if json.get("exceptionDetails").is_some() {
+				return Self::repl_value(inspector, &json, object_group);
+			}
+			return Ok(json::from_js(json["result"]["value"].as_str().unwrap_or("null"))?);
+		}
+
+		// Primitives: BigInts are reported as e.g. "5n", and -0, NaN or Infinity have no JSON representation
+		match object.get("unserializableValue").and_then(JsValue::as_str) {
+			Some(value) if value.ends_with('n') => Ok(JsValue::String(value.trim_end_matches('n').to_string())),
+			Some("-0") => Ok(serde_json::Number::from_f64(-0.0).map_or(JsValue::Null, JsValue::Number)),
+			Some(_) => Ok(JsValue::Null),
+			None => Ok(object.get("value").cloned().unwrap_or(JsValue::Null)),
+		}
+	}
+
+	/// Invokes a JavaScript function, periodically letting the host decide whether to wait further.
+	///
+	/// Behaves like [`call()`](#method.call) without timeout, except that every `slice` during which the function is still
+	/// running, `poll` is invoked with the time elapsed since the call started. It returns whether to continue or to abort the
+	/// call; an aborted call fails with [`JsError::Cancelled`](enum.JsError.html#variant.Cancelled), and the script remains
+	/// usable. This allows a scheduler to make wait/kill decisions at runtime, rather than committing to a fixed timeout upfront.
+	///
+	/// `poll` runs on the current thread while JS execution is paused, so it should return quickly.
+	/// It must not panic; if it does, the call is aborted as well.
+	pub fn poll_call<P, R, F>(&mut self, fn_name: &str, args: &P, slice: Duration, mut poll: F) -> Result<R, AnyError>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+		F: FnMut(Duration) -> PollDecision,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+
+		let handle = self.runtime.v8_isolate().thread_safe_handle();
+		let registration = self.register_cancellation()?;
+		let ticker = PollTicker::start(handle, self.terminator.clone(), slice, &mut poll);
+		let result = self.execute_call(fn_name, &json_args, false);
+		ticker.stop();
+		drop(registration);
+
+		let result = self.finish_call(result)?;
+		Self::deserialize_result(fn_name, result)
+	}
+
+	/// Re-runs the script's top-level code, to bring its state back to the freshly initialized form.
+	///
+	/// This is much cheaper than creating a new script from the same source: the isolate, its compiled code and all host
+	/// functions and settings are reused. What is reset is exactly what the top-level code establishes: `var` declarations
+	/// and functions are re-initialized, and properties it assigns (e.g. `globalThis.cache = {}`) are assigned again.
+	///
+	/// Everything else persists, in particular globals that calls have created but the top-level code does not assign, as well
+	/// as modifications of built-in objects. [Function handles](struct.JsFunctionHandle.html) remain valid, referring to the
+	/// functions from before the reset. Top-level `let`, `const` and `class` declarations cannot be re-run, since JavaScript
+	/// forbids redeclaring them; for scripts using them, `reset()` fails. No code of the source declaring them runs again, but
+	/// sources added before it with [`add_source()`](#method.add_source) already have, so their state is reset nonetheless.
+	///
+	/// [Sticky globals](#method.set_sticky_global) are defined anew with their original values before the top-level code runs.
+	pub fn reset(&mut self) -> Result<(), AnyError> {
+		let registration = self.register_cancellation()?;
+		self.begin_call()?;
+
+		let runtime = &mut self.runtime;
+		let mut result = self.sticky_globals.iter().try_for_each(|(name, value)| Self::define_sticky(runtime, name, value));
+		if result.is_ok() {
+			for (js_filename, js_code, line_offset) in &self.top_level {
+				result = Self::run_top_level(runtime, &self.source_maps, js_filename, js_code, *line_offset, None);
+				if result.is_err() {
+					break;
+				}
+			}
+		}
+		drop(registration);
+		self.usage.end();
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Returns the result of the JS `typeof` operator applied to the global `name`.
+	///
+	/// The result is the literal string that JavaScript yields, such as `"function"`, `"object"`, `"number"` or `"undefined"`; the
+	/// latter also for globals that do not exist. Globals declared with `let`, `const` or `class` are found as well. Nothing is
+	/// called, so this is safe to use for introspection. Names that are not valid identifiers are looked up as properties of
+	/// `globalThis`; fails only if the lookup itself throws, e.g. in a getter.
+	pub fn typeof_global(&mut self, name: &str) -> Result<String, AnyError> {
+		let function = format!("(() => typeof {})", global_ref(name)?);
+
+		let result = self.call_internal(&function, &JsValue::Null)?;
+		Ok(serde_json::from_value(result.json_value)?)
+	}
+
+	/// Replaces this script by a new one, created from its source file, and carries over the state of the old one.
+	///
+	/// Intended for iterating on plugins or mods while the host keeps running: the file passed to
+	/// [`ScriptBuilder::build_file()`](struct.ScriptBuilder.html#method.build_file) is read again, and a new script is built from it
+	/// with `builder`, which should be configured like the original one. If the old script defines a global function
+	/// `exportState()`, its result is the old state; otherwise the state is `null`. `migrate` receives the old state and the new
+	/// script, and transfers whatever should survive, e.g. by calling a function of the new script with the converted state.
+	///
+	/// The old script is consumed, and dropped before the new one is built: V8 requires the isolates of a thread to be dropped in
+	/// the reverse order of their creation. If building or migrating fails, the error is returned, and the host has to build
+	/// a script anew. The new script is a fresh isolate: [handles](struct.JsFunctionHandle.html), tokens and channels of the
+	/// old script do not carry over. Fails if the script was not created from a file. Use
+	/// [`source_modified()`](#method.source_modified) to detect when a reload is due.
+	pub fn reload<F>(mut self, builder: ScriptBuilder, migrate: F) -> Result<Script, AnyError>
+	where
+		F: FnOnce(JsValue, &mut Script) -> Result<(), AnyError>,
+	{
+		let file = match &self.source_file {
+			Some((file, _)) => file.clone(),
+			None => return Err(generic_error("only scripts created from a file can be reloaded")),
+		};
+
+		let state = if self.typeof_global("exportState")? == "function" {
+			self.call("exportState", &(), None)?
+		} else {
+			JsValue::Null
+		};
+
+		drop(self);
+		let mut script = builder.build_file(&file)?;
+		migrate(state, &mut script)?;
+		Ok(script)
+	}
+
+	/// Whether the script's source file has been modified since the script was created from it.
+	///
+	/// Compares the file's modification time with the one it had when loaded, so it can be polled cheaply, e.g. once per frame,
+	/// to trigger a [`reload()`](#method.reload). Returns `false` for scripts not created from a file, or if the time cannot be read.
+	pub fn source_modified(&self) -> bool {
+		match &self.source_file {
+			Some((file, Some(loaded))) => {
+				let modified = std::fs::metadata(file).and_then(|metadata| metadata.modified());
+				matches!(modified, Ok(modified) if modified != *loaded)
+			}
+			_ => false,
+		}
+	}
+
+	/// Remembers the file the script was created from, for reload().
+	pub(crate) fn set_source_file(&mut self, file: PathBuf, modified: Option<SystemTime>) {
+		self.source_file = Some((file, modified));
+	}
+
+	/// Reads the globals named like the fields of the struct `R`, and deserializes them into an `R`.
+	///
+	/// Each field is looked up as a global of the same name (after serde's `rename` attributes), including `let` and `const`
+	/// declarations. Globals that do not exist or are `undefined` are left out, so serde's rules for missing fields apply:
+	/// `Option<T>` becomes `None`, `#[serde(default)]` takes effect, and other fields cause an error. This extracts a known
+	/// state shape after running a script, without a JS getter for each value. `R` must be a struct with named fields.
+	pub fn globals_into<R>(&mut self) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		let mut properties = Vec::new();
+		for field in fields::struct_fields::<R>()? {
+			let global = global_ref(field)?;
+			properties.push(format!(
+				"{key}: typeof {global} === \"undefined\" ? undefined : {global}",
+				key = serde_json::to_string(field)?,
+				global = global,
+			));
+		}
+
+		let function = format!("(() => ({{ {} }}))", properties.join(", "));
+		let result = self.call_internal(&function, &JsValue::Null)?;
+		Ok(serde_json::from_value(result.json_value)?)
+	}
+
+	/// Returns whether the global `name` is a function, i.e. can be invoked with [`call()`](#method.call).
+	///
+	/// Equivalent to checking that [`typeof_global()`](#method.typeof_global) returns `"function"`.
+	pub fn is_callable(&mut self, name: &str) -> Result<bool, AnyError> {
+		Ok(self.typeof_global(name)? == "function")
+	}
+
+	/// Returns whether `name` refers to a function, without failing.
+	///
+	/// A quick existence check before invoking an optional hook: `true` only if the global is callable, `false` if it does not
+	/// exist, is not a function, or cannot be inspected (e.g. because a getter throws). A dotted path such as `"plugin.onLoad"`
+	/// is followed property by property, starting at a global, so that functions inside namespace objects can be checked.
+	pub fn has_function(&mut self, name: &str) -> bool {
+		let segments: Vec<&str> = name.split('.').collect();
+		if segments.len() < 2 || !segments.iter().all(|segment| is_identifier(segment)) {
+			return self.is_callable(name).unwrap_or(false);
+		}
+
+		let function = format!(
+			"(() => typeof {root} !== \"undefined\" && typeof {path} === \"function\")",
+			root = segments[0],
+			path = segments.join("?."),
+		);
+		match self.call_internal(&function, &JsValue::Null) {
+			Ok(result) => result.json_value == JsValue::Bool(true),
+			Err(_) => false,
+		}
+	}
+
+	/// Defines the global `name` as a constant, which survives [`reset()`](#method.reset).
+	///
+	/// The value is converted like the arguments of [`call()`](#method.call), and deeply frozen: scripts can read it, but
+	/// neither reassign, redefine or delete the global nor modify the value. Each reset defines the global anew, before the
+	/// top-level code runs, so the top-level code can rely on it just like on the first run. This is meant for configuration
+	/// which stays the same while a plugin processes many inputs. Setting a sticky global again replaces its value, also for
+	/// subsequent resets.
+	///
+	/// Use [`ScriptBuilder::sticky_global()`](struct.ScriptBuilder.html#method.sticky_global) to make the constant available
+	/// to the top-level code on its first run as well. Fails if the script has already declared a global of that name with
+	/// `var` or `function`, since these cannot be redefined.
+	pub fn set_sticky_global(&mut self, name: &str, value: &impl Serialize) -> Result<(), AnyError> {
+		// Typed arrays cannot be frozen, so Bytes are restored from their markers as plain arrays of numbers
+		let value = serde_json::to_value(bytes::detach(args::to_json(name, value)?))?;
+		Self::define_sticky(&mut self.runtime, name, &value)?;
+
+		match self.sticky_globals.iter_mut().find(|(existing, _)| existing == name) {
+			Some((_, existing)) => *existing = value,
+			None => self.sticky_globals.push((name.to_string(), value)),
+		}
+		Ok(())
+	}
+
+	fn define_sticky(runtime: &mut JsRuntime, name: &str, value: &JsValue) -> Result<(), AnyError> {
+		let js_code = format!(
+			"__rust.defineSticky({}, {});",
+			serde_json::to_string(name)?,
+			serde_json::to_string(&value.to_string())?
+		);
+		runtime.execute(Self::PRELUDE_FILENAME, &js_code)
+	}
+
+	/// Assigns `value` to the global `name`, e.g. to seed configuration before or between calls.
+	///
+	/// The value is converted like an argument of [`call()`](#method.call). Globals declared with `let` are assigned as well;
+	/// globals that do not exist yet are created as properties of `globalThis`. Fails if the global is a `const`. Unlike
+	/// [`set_sticky_global()`](#method.set_sticky_global), the value is neither frozen nor restored by [`reset()`](#method.reset),
+	/// so the script can modify it.
+	pub fn set_global(&mut self, name: &str, value: &impl Serialize) -> Result<(), AnyError> {
+		let json_value = args::to_json(name, value)?;
+		// Sloppy mode, so that assigning an undeclared identifier creates the global
+		let function = format!("(value => {{ {} = value; }})", global_ref(name)?);
+
+		self.call_internal(&function, &json_value)
+			.map(|_| ())
+			.map_err(|e| generic_error(format!("cannot set global '{}': {}", name, e)))
+	}
+
+	/// Reads the global `name`, and deserializes it into an `R`.
+	///
+	/// The value is converted like the result of [`call()`](#method.call), so the script's state can be read back without a
+	/// getter function for each variable. Globals declared with `let` or `const` are found as well. A global that does not
+	/// exist reads as `undefined`: use `Option<T>` to receive it as `None`, otherwise this fails. To read several globals at
+	/// once, see [`globals_into()`](#method.globals_into).
+	pub fn get_global<R>(&mut self, name: &str) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		let global = global_ref(name)?;
+		let function = format!("(() => typeof {global} === \"undefined\" ? undefined : {global})", global = global);
+
+		let result = self.call_internal(&function, &JsValue::Null)?;
+		let is_undefined = result.is_undefined;
+		serde_json::from_value(result.json_value).map_err(|e| {
+			if is_undefined {
+				generic_error(format!(
+					"global '{}' is undefined, which cannot be converted to `{}` (use `Option<T>` for globals that may not exist): {}",
+					name,
+					std::any::type_name::<R>(),
+					e
+				))
+			} else {
+				AnyError::from(e)
+			}
+		})
+	}
+
+	/// Compiles a regular expression once, and stores it as the global `name`.
+	///
+	/// Equivalent to `globalThis[name] = new RegExp(pattern, flags)`, but checked up front: `flags` may only contain `g`, `i`,
+	/// `m`, `s`, `u` and `y`, each at most once, and the pattern must be valid. Otherwise, this fails with a Rust error and the
+	/// global is left unchanged, instead of the script throwing whenever it compiles the pattern. Scripts can then reuse the
+	/// object across calls; note that with the `g` or `y` flag, its `lastIndex` persists between uses, as usual in JS.
+	pub fn set_global_regexp(&mut self, name: &str, pattern: &str, flags: &str) -> Result<(), AnyError> {
+		for (i, flag) in flags.char_indices() {
+			if !"gimsuy".contains(flag) {
+				return Err(generic_error(format!("invalid regular expression flag '{}' in '{}'", flag, flags)));
+			}
+			if flags[..i].contains(flag) {
+				return Err(generic_error(format!("duplicate regular expression flag '{}' in '{}'", flag, flags)));
+			}
+		}
+
+		let function = format!(
+			"(() => {{ globalThis[{name}] = new RegExp({pattern}, {flags}); }})",
+			name = serde_json::to_string(name)?,
+			pattern = serde_json::to_string(pattern)?,
+			flags = serde_json::to_string(flags)?,
+		);
+		self.call_internal(&function, &JsValue::Null)
+			.map(|_| ())
+			.map_err(|e| generic_error(format!("invalid regular expression for global '{}': {}", name, e)))
+	}
+
+	/// Makes subsequent calls cancellable through `token`.
+	///
+	/// Once the token is cancelled, the running call (if any) is terminated, and it as well as all later calls fail with
+	/// [`JsError::Cancelled`](enum.JsError.html#variant.Cancelled). The script itself remains usable with another token.
+	/// Passing `None` detaches the current token.
+	pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+		self.cancellation = token;
+	}
+
+	/// Returns a handle which aborts the call currently running in this script, from any thread.
+	///
+	/// See [`TerminationHandle`](struct.TerminationHandle.html). All handles of a script are equivalent.
+	pub fn termination_handle(&self) -> TerminationHandle {
+		self.termination.clone()
+	}
+
+	/// Returns the CPU profile recorded since the script was created, or since the last invocation of this method.
+	///
+	/// Requires the script to be built with [`ScriptBuilder::cpu_profile()`](struct.ScriptBuilder.html#method.cpu_profile);
+	/// fails otherwise. Profiling continues afterwards, starting a new recording.
+	pub fn take_cpu_profile(&mut self) -> Result<CpuProfile, AnyError> {
+		let interval_us = self.profile_interval_us.ok_or_else(|| {
+			generic_error("CPU profiling is not enabled; use ScriptBuilder::cpu_profile()")
+		})?;
+
+		let inspector = self.inspector.as_mut().expect("Inspector exists while profiling");
+		let result = inspector.send("Profiler.stop", json!({}))?;
+		inspector.send("Profiler.start", json!({}))?;
+
+		CpuProfile::from_inspector(result, interval_us)
+	}
+
+	/// Returns whether the global function `name` is declared `async`, and should thus be invoked with
+	/// [`call_async()`](#method.call_async).
+	///
+	/// Only the `async` keyword is detected: a regular function that returns a promise yields `false`, although it needs
+	/// `call_async()` as well. Async generator functions yield `false`, since they return iterators rather than promises.
+	/// Fails if `name` is not a function.
+	pub fn is_function_async(&mut self, name: &str) -> Result<bool, AnyError> {
+		let function = format!(
+			"(() => {{
+				const f = {global};
+				if (typeof f !== 'function') {{
+					throw new TypeError({name} + ' is not a function');
+				}}
+				return Object.prototype.toString.call(f) === '[object AsyncFunction]';
+			}})",
+			global = global_ref(name)?,
+			name = serde_json::to_string(name)?,
+		);
+
+		let result = self.call_internal(&function, &JsValue::Null)?;
+		Ok(serde_json::from_value(result.json_value)?)
+	}
+
+	/// Redirects console output of this script to a channel.
+	///
+	/// Every subsequent `console.*` call in JS sends one [`LogRecord`] to the returned receiver, which can be polled from another
+	/// thread. By default, output is printed to the host's stdout (`warn` and `error` to stderr).
+	///
+	/// The channel is unbounded: logging never blocks the script, but records accumulate in memory until they are received.
+	/// Calling this method again replaces the previous channel; once a receiver is dropped, records sent to it are discarded.
+	pub fn console_channel(&mut self) -> Receiver<LogRecord> {
+		let (sender, receiver) = mpsc::channel();
+		self.runtime.op_state().borrow_mut().put(ConsoleSink::Channel(sender));
+
+		receiver
+	}
+
+	/// Passes console output of this script to `handler`, e.g. to route it into the application's logging.
+	///
+	/// Every subsequent `console.*` call in JS invokes the handler with one [`LogRecord`], synchronously on the script's thread,
+	/// so records arrive in order and can be attributed to this script by state captured in the closure. The script waits while
+	/// the handler runs. Calling this method again, or [`console_channel()`](#method.console_channel), replaces the handler.
+	pub fn console_handler(&mut self, handler: impl FnMut(LogRecord) + 'static) {
+		self.runtime.op_state().borrow_mut().put(ConsoleSink::Handler(Box::new(handler)));
+	}
+
+	/// Sends `message` to the script, whose `onmessage` handler receives it as the `data` of an event, like in a web worker.
+	///
+	/// The message is converted like the arguments of [`call()`](#method.call), and the handler runs before this method returns;
+	/// `timeout_ms` limits it like a call. If the script has not assigned a function to the global `onmessage`, the message is
+	/// dropped. Fails if the script was not built with [`ScriptBuilder::message_channel()`](struct.ScriptBuilder.html#method.message_channel),
+	/// or if the handler throws.
+	pub fn post_message<T>(&mut self, message: &T, timeout_ms: Option<u64>) -> Result<(), AnyError>
+	where
+		T: Serialize,
+	{
+		self.check_message_channel()?;
+		let json_message = args::to_json("onmessage", message)?;
+		self.call_impl("__rust.dispatchMessage", &json_message, timeout_ms).map(|_| ())
+	}
+
+	/// Returns the messages the script has posted with `host.postMessage()` since the last poll, oldest first.
+	///
+	/// Messages are converted like the results of [`call()`](#method.call). They are queued until polled, unless a
+	/// [`message_handler()`](#method.message_handler) receives them instead. Fails if the script was not built with
+	/// [`ScriptBuilder::message_channel()`](struct.ScriptBuilder.html#method.message_channel).
+	pub fn poll_messages(&mut self) -> Result<Vec<JsValue>, AnyError> {
+		self.check_message_channel()?;
+		match self.runtime.op_state().borrow_mut().borrow_mut::<MessageSink>() {
+			MessageSink::Queue(queue) => Ok(queue.drain(..).collect()),
+			MessageSink::Handler(_) => Ok(Vec::new()),
+		}
+	}
+
+	/// Passes every message the script posts with `host.postMessage()` to `handler`, instead of queueing it for
+	/// [`poll_messages()`](#method.poll_messages).
+	///
+	/// The handler runs synchronously on the script's thread, while the script waits in `postMessage()`. Messages still queued
+	/// are discarded. Calling this method again replaces the handler. Fails if the script was not built with
+	/// [`ScriptBuilder::message_channel()`](struct.ScriptBuilder.html#method.message_channel).
+	pub fn message_handler(&mut self, handler: impl FnMut(JsValue) + 'static) -> Result<(), AnyError> {
+		self.check_message_channel()?;
+		self.runtime.op_state().borrow_mut().put(MessageSink::Handler(Box::new(handler)));
+		Ok(())
+	}
+
+	fn check_message_channel(&mut self) -> Result<(), AnyError> {
+		if self.runtime.op_state().borrow().try_borrow::<MessageSink>().is_none() {
+			return Err(generic_error("script has no message channel; see ScriptBuilder::message_channel()"));
+		}
+		Ok(())
+	}
+
+	/// Invokes a JavaScript function, and captures the console output it produces.
+	///
+	/// Behaves like [`call()`](#method.call), but messages logged during the call are collected into an [`Output`] instead of
+	/// reaching the script's usual destination: `log`, `info` and `debug` into its `stdout`, `warn` and `error` into its
+	/// `stderr`. Afterwards, console output goes wherever it went before. If the call fails, the error is returned together with
+	/// the output captured until then, which often explains the failure. A
+	/// [rate limit](struct.ScriptBuilder.html#method.console_rate_limit) still applies to captured messages.
+	pub fn call_with_output<P, R>(
+		&mut self,
+		fn_name: &str,
+		args: &P,
+		timeout_ms: Option<u64>,
+	) -> Result<(R, Output), (Output, AnyError)>
+	where
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let state_rc = self.runtime.op_state();
+		let previous = state_rc.borrow_mut().take::<ConsoleSink>();
+		state_rc.borrow_mut().put(ConsoleSink::Capture(Output::default()));
+
+		let result = self.call(fn_name, args, timeout_ms);
 
-	/// Initialize a script with the given JavaScript source code
+		let captured = state_rc.borrow_mut().take::<ConsoleSink>();
+		state_rc.borrow_mut().put(previous);
+
+		let output = match captured {
+			ConsoleSink::Capture(output) => output,
+			_ => unreachable!("Console sink is not replaced during a call"),
+		};
+		match result {
+			Ok(result) => Ok((result, output)),
+			Err(error) => Err((output, error)),
+		}
+	}
+
+	/// Returns how many `console` messages have been dropped, because they exceeded the
+	/// [rate limit](struct.ScriptBuilder.html#method.console_rate_limit).
 	///
-	/// Returns a new object on success, and an error in case of syntax or initialization error with the code.
-	pub fn from_string(js_code: &str) -> Result<Self, AnyError> {
-		// console.log() is not available by default -- add the most basic version with single argument (and no warn/info/... variants)
-		let all_code = "const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };".to_string() + js_code;
+	/// The count covers the whole lifetime of the script. Without rate limit, this is always 0.
+	pub fn console_dropped(&mut self) -> u64 {
+		let state_rc = self.runtime.op_state();
+		let state = state_rc.borrow();
+		state.try_borrow::<ConsoleLimit>().map_or(0, |limit| limit.dropped)
+	}
 
-		Self::create_script(&all_code, Self::DEFAULT_FILENAME)
+	/// Returns memory statistics of the script's V8 heap.
+	pub fn heap_stats(&mut self) -> HeapStats {
+		HeapStats::from_isolate(self.runtime.v8_isolate())
 	}
 
-	/// Initialize a script by loading it from a .js file
+	/// Returns resource usage of the script: heap statistics, and the number and total duration of calls so far.
 	///
-	/// Returns a new object on success. Fails if the file cannot be opened or in case of syntax or initialization error with the code.
-	pub fn from_file(file: impl AsRef<Path>) -> Result<Self, AnyError> {
-		let filename = file
-			.as_ref()
-			.file_name()
-			.and_then(|s| s.to_str())
-			.unwrap_or(Self::DEFAULT_FILENAME)
-			.to_owned();
+	/// Every method that runs JavaScript counts as a call, successful or not, including ones like [`release()`](#method.release)
+	/// that run internal code only. Some methods enter JavaScript repeatedly and count each time, e.g.
+	/// [`advance_time()`](#method.advance_time) for every timer. Durations are wall-clock time, from the start of a call until
+	/// its result is available; for async calls, this includes the event loop run until the promise has settled.
+	pub fn stats(&mut self) -> ScriptStats {
+		let heap = self.heap_stats();
+		self.usage.stats(heap)
+	}
+
+	/// Registers a handler which receives the duration of each call after it has finished.
+	///
+	/// Calls are counted as in [`stats()`](#method.stats). The handler runs synchronously on the script's thread, once the call's
+	/// JavaScript has finished executing, and before its result is returned. Calling this method again replaces the handler.
+	pub fn call_duration_handler(&mut self, handler: impl FnMut(Duration) + 'static) {
+		self.usage.set_handler(Box::new(handler));
+	}
+
+	/// Returns how often each host function has been invoked over the script's lifetime.
+	///
+	/// Contains every function registered with [`add_fn()`](#method.add_fn) or [`register_ops()`](#method.register_ops), by the
+	/// name also used for [op limits](struct.ScriptBuilder.html#method.op_limit), including those never invoked. Invocations
+	/// rejected by an op limit are counted as well, since the script attempted them. Counts are not cleared by
+	/// [`reset()`](#method.reset). [Fast functions](#method.add_fast_fn) bypass the op dispatch, and are not tracked.
+	pub fn op_stats(&mut self) -> HashMap<String, u64> {
+		self.runtime.op_state().borrow().borrow::<HostFns>().totals.clone()
+	}
+
+	/// Returns the number of bytes currently used by the script's V8 heap.
+	///
+	/// Shorthand for `heap_stats().used_heap_size`, e.g. for per-request metrics. This reflects only the JavaScript heap of
+	/// this script, not the total memory of the process. It includes garbage that has not been collected yet.
+	pub fn memory_usage_bytes(&mut self) -> usize {
+		self.heap_stats().used_heap_size
+	}
+
+	/// Returns line information about the source files loaded into this script.
+	///
+	/// Allows tools to translate character offsets reported by V8 into line/column positions.
+	pub fn source_index(&self) -> &SourceIndex {
+		&self.sources
+	}
+
+	/// Registers a Rust function, which JavaScript can invoke as `host.<name>(arg)`.
+	///
+	/// The global object is named `host`, unless another [namespace](struct.ScriptBuilder.html#method.ops_namespace) was set.
+	/// The single argument and the return value are transported as JSON, like in [`call()`](#method.call). An error returned by `f`
+	/// is thrown as a JS `Error` with the same message. So is a panic inside `f`, which is caught before it can unwind into the JS
+	/// engine; the script remains usable afterwards. Fails if a host function with the same name has already been registered.
+	///
+	/// For feature detection, every global holding host functions has a read-only, non-enumerable `__features` property. Its
+	/// `ops` and `polyfills` list the names of all host functions registered so far -- qualified for other namespaces, like
+	/// in [op limits](struct.ScriptBuilder.html#method.op_limit) -- and of the enabled shims (`"node"`, `"deno"`). The methods
+	/// `hasOp(name)` and `hasPolyfill(name)` check for a single entry, so a plugin can fall back gracefully on hosts lacking
+	/// a function: `host.__features.hasOp("query") ? host.query(q) : null`.
+	pub fn add_fn<F>(&mut self, name: &str, f: F) -> Result<(), AnyError>
+	where
+		F: Fn(JsValue) -> Result<JsValue, AnyError> + 'static,
+	{
+		let namespace = self.host_namespace.clone();
+		self.add_boxed_fn(&namespace, name, Box::new(f))
+	}
+
+	/// Registers all methods of `api` as Rust functions, which JavaScript can invoke as `<namespace>.<method>(arg)`.
+	///
+	/// Each method behaves like a function registered with [`add_fn()`](#method.add_fn); `api` is shared among them. Outside of
+	/// the `host` namespace, [op limits](struct.ScriptBuilder.html#method.op_limit) and error messages refer to the functions
+	/// by their qualified name, e.g. `"inventory.count"`. Fails without registering anything if `namespace` is not a valid
+	/// identifier, or if a method name occurs twice or has already been registered in the namespace.
+	pub fn register_ops<T: HostApi>(&mut self, namespace: &str, api: T) -> Result<(), AnyError> {
+		if !is_identifier(namespace) {
+			return Err(generic_error(format!("'{}' is not a valid namespace", namespace)));
+		}
 
-		match std::fs::read_to_string(file) {
-			Ok(js_code) => Self::create_script(&js_code, &filename),
-			Err(e) => Err(AnyError::from(e)),
+		let methods = T::methods();
+		for (i, (name, _)) in methods.iter().enumerate() {
+			if methods[..i].iter().any(|(other, _)| other == name) {
+				return Err(generic_error(format!("host API defines method '{}' more than once", name)));
+			}
+			let qualified_name = self.qualified_fn_name(namespace, name);
+			if self.has_host_fn(&qualified_name) {
+				return Err(generic_error(format!("host function '{}' is already registered", qualified_name)));
+			}
 		}
+
+		let api = Rc::new(api);
+		for (name, method) in methods {
+			let api = api.clone();
+			self.add_boxed_fn(namespace, name, Box::new(move |arg| method(&api, arg)))?;
+		}
+		Ok(())
 	}
 
-	/// Invokes a JavaScript function.
+	fn has_host_fn(&mut self, qualified_name: &str) -> bool {
+		// Fast functions are no ops, and always live in the host namespace, where names are unqualified
+		if self.fast_fns.iter().any(|entry| entry.name == qualified_name) {
+			return true;
+		}
+
+		let op_name = HostFns::op_name(qualified_name);
+		OpTable::op_entries(self.runtime.op_state()).iter().any(|(op, _)| *op == op_name)
+	}
+
+	pub(crate) fn add_boxed_fn(&mut self, namespace: &str, name: &str, f: Box<HostFn>) -> Result<(), AnyError> {
+		self.check_host_fns_allowed()?;
+		let fn_name = self.qualified_fn_name(namespace, name);
+		if self.has_host_fn(&fn_name) {
+			return Err(generic_error(format!("host function '{}' is already registered", fn_name)));
+		}
+
+		let op_name = HostFns::op_name(&fn_name);
+		let feature_name = serde_json::to_string(&fn_name)?;
+		self.runtime.op_state().borrow_mut().borrow_mut::<HostFns>().totals.insert(fn_name.clone(), 0);
+		self.runtime.register_op(&op_name, deno_core::op_sync(move |state, args: String, _buf| {
+			HostFns::record_call(state, &fn_name)?;
+
+			let result = ops::call_host_fn(&fn_name, &*f, json::from_js(&args)?)?;
+			Ok(serde_json::to_string(&result)?)
+		}));
+
+		let js_code = format!(
+			"__rust.defineHost({namespace}, {name}, __rust.hostFn({op})); __rust.addFeature({namespace}, \"op\", {fn_name});",
+			namespace = serde_json::to_string(namespace)?,
+			name = serde_json::to_string(name)?,
+			op = serde_json::to_string(&op_name)?,
+			fn_name = feature_name,
+		);
+		self.execute_internal(&js_code)
+	}
+
+	/// Name under which the host functions registered with add_fn() are qualified, e.g. in op limits.
+	fn qualified_fn_name(&self, namespace: &str, name: &str) -> String {
+		if namespace == self.host_namespace {
+			name.to_string()
+		} else {
+			format!("{}.{}", namespace, name)
+		}
+	}
+
+	/// Guards the `host` global and the host functions registered so far against being overwritten; see
+	/// [`ScriptBuilder::protect_host_globals()`](struct.ScriptBuilder.html#method.protect_host_globals).
+	pub(crate) fn protect_host_globals(&mut self) -> Result<(), AnyError> {
+		let js_code = format!("__rust.protectHost({});", serde_json::to_string(&self.host_namespace)?);
+		self.execute_internal(&js_code)
+	}
+
+	/// Sets the global object holding host functions; see [`ScriptBuilder::ops_namespace()`](struct.ScriptBuilder.html#method.ops_namespace).
+	pub(crate) fn set_host_namespace(&mut self, namespace: &str) -> Result<(), AnyError> {
+		if !is_identifier(namespace) {
+			return Err(generic_error(format!("'{}' is not a valid namespace", namespace)));
+		}
+		self.host_namespace = namespace.to_string();
+		Ok(())
+	}
+
+	/// Lists an op or polyfill in `host.__features`; `kind` is either `"op"` or `"polyfill"`.
+	pub(crate) fn add_feature(&mut self, kind: &str, name: &str) -> Result<(), AnyError> {
+		let js_code = format!(
+			"__rust.addFeature({}, {}, {});",
+			serde_json::to_string(&self.host_namespace)?,
+			serde_json::to_string(kind)?,
+			serde_json::to_string(name)?
+		);
+		self.execute_internal(&js_code)
+	}
+
+	/// Registers a numeric Rust function, which JavaScript can invoke as `host.<name>(x)` with minimal overhead.
 	///
-	/// Passes a single argument `args` to JS by serializing it to JSON (using serde_json).
-	/// Multiple arguments are currently not supported, but can easily be emulated using a `Vec` to work as a JSON array.
-	/// Optional value for `timeout_ms` forces script to run no more than specified number of milliseconds
-	pub fn call<P, R>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<R, AnyError>
+	/// Like with [`add_fn()`](#method.add_fn), the global object may be named differently. Unlike `add_fn()`, the argument and
+	/// return value are passed directly as JS numbers, without any JSON serialization. This is meant for hot callbacks invoked
+	/// very often, such as lookups or math helpers. The argument is converted like with JS's `Number()`, so a missing argument
+	/// becomes `NaN`. A panic inside `f` is thrown as a JS `Error`. [Op limits](struct.ScriptBuilder.html#method.op_limit) do
+	/// not apply to these functions. Fails if a host function with the same name has already been registered.
+	pub fn add_fast_fn<F>(&mut self, name: &str, f: F) -> Result<(), AnyError>
 	where
-		P: Serialize,
-		R: DeserializeOwned,
+		F: Fn(f64) -> f64 + 'static,
 	{
-		let json_args = serde_json::to_value(args)?;
-		let json_result = self.call_json(fn_name, &json_args, timeout_ms)?;
-		let result: R = serde_json::from_value(json_result)?;
+		self.add_boxed_fast_fn(name, Box::new(f))
+	}
 
-		Ok(result)
+	pub(crate) fn add_boxed_fast_fn(&mut self, name: &str, f: Box<FastFn>) -> Result<(), AnyError> {
+		self.check_host_fns_allowed()?;
+		let entry = ops::install_fast_fn(&mut self.runtime, &self.host_namespace, name, f)?;
+		self.fast_fns.push(entry);
+		self.add_feature("op", name)
 	}
 
-	pub(crate) fn call_json(&mut self, fn_name: &str, args: &JsValue, timeout_ms: Option<u64>) -> Result<JsValue, AnyError> {
-		// Note: ops() is required to initialize internal state
-		// Wrap everything in scoped block
+	/// Rejects host functions registered from now on, see SandboxPolicy.
+	pub(crate) fn deny_host_fns(&mut self) {
+		self.host_fns_denied = true;
+	}
+
+	fn check_host_fns_allowed(&self) -> Result<(), AnyError> {
+		if self.host_fns_denied {
+			return Err(generic_error("host functions are not allowed by the sandbox policy"));
+		}
+		Ok(())
+	}
+
+	/// Translates positions in errors raised by the code of `js_filename` with `map_json`; see ScriptBuilder::source_map().
+	pub(crate) fn add_source_map(&mut self, js_filename: &str, map_json: &str, line_offset: u32) -> Result<(), AnyError> {
+		self.source_maps.borrow_mut().add(js_filename, map_json, line_offset)
+	}
+
+	/// Registers the source map embedded in `js_code`, if any. A map that cannot be decoded is ignored, like in browsers.
+	pub(crate) fn add_inline_source_map(&mut self, js_filename: &str, js_code: &str, line_offset: u32) {
+		if let Some(map_json) = source_map::inline_source_map(js_code) {
+			let _ = self.add_source_map(js_filename, &map_json, line_offset);
+		}
+	}
+
+	pub(crate) fn set_console_limit(&mut self, limit: ConsoleLimit) {
+		self.runtime.op_state().borrow_mut().put(limit);
+	}
+
+	pub(crate) fn set_stack_trace_limit(&mut self, frames: u32) -> Result<(), AnyError> {
+		let frames = frames.min(i32::MAX as u32);
+		self.runtime.v8_isolate().set_capture_stack_trace_for_uncaught_exceptions(true, frames as i32);
+		self.execute_internal(&format!("Error.stackTraceLimit = {};", frames))
+	}
+
+	pub(crate) fn set_allowed_calls(&mut self, allowed_calls: HashSet<String>) {
+		self.allowed_calls = Some(allowed_calls);
+	}
+
+	pub(crate) fn set_op_limits(&mut self, limits: HashMap<String, u64>) {
+		self.runtime.op_state().borrow_mut().borrow_mut::<HostFns>().limits = limits;
+	}
+
+	/// Evaluates an expression in the script's global scope, using the same code path as calls.
+	pub(crate) fn eval_json(&mut self, js_expr: &str) -> Result<JsValue, AnyError> {
+		self.eval_impl(js_expr, None).map(|result| result.json_value)
+	}
+
+	fn eval_impl(&mut self, js_expr: &str, timeout_ms: Option<u64>) -> Result<ReturnValue, AnyError> {
+		// The expression starts on a line of its own, so that error positions can be related to it
+		let function = format!("(() => (\n{}\n))", self.metered(js_expr)?);
+		self.call_impl(&function, &JsValue::Null, timeout_ms)
+			.map_err(|e| relocate_eval_error(e, js_expr))
+	}
+
+	/// Instruments code evaluated at runtime like the script's own code, if the script has a gas limit.
+	fn metered(&self, js_code: &str) -> Result<String, AnyError> {
+		match self.gas {
+			Some(_) => gas::instrument(js_code),
+			None => Ok(js_code.to_string()),
+		}
+	}
+
+	fn call_impl(&mut self, fn_name: &str, args: &JsValue, timeout_ms: Option<u64>) -> Result<ReturnValue, AnyError> {
+		let result = self.call_impl_raw(fn_name, args, timeout_ms)?;
+		Self::parse_result(result)
+	}
+
+	/// Like call_impl() without timeout, for js-sandbox's own helpers rather than the script's entry points: exceptions are not
+	/// offered to the script's error handlers.
+	fn call_internal(&mut self, function: &str, args: &JsValue) -> Result<ReturnValue, AnyError> {
+		self.internal_call = true;
+		let result = self.call_impl(function, args, None);
+		self.internal_call = false;
+		result
+	}
+
+	fn call_impl_raw(&mut self, fn_name: &str, args: &JsValue, timeout_ms: Option<u64>) -> Result<ResultResource, AnyError> {
+		let registration = self.register_cancellation()?;
+		let watchdog = self.start_watchdog(timeout_ms, JsError::Timeout);
+
+		let result = self.execute_call(fn_name, args, false);
+		if let Some(watchdog) = watchdog {
+			watchdog.stop();
+		}
+
+		// Unregister before evaluating termination, so that a late cancellation cannot hit the next call
+		drop(registration);
+		self.finish_call_raw(result)
+	}
+
+	fn register_cancellation(&self) -> Result<Registration, JsError> {
+		Registration::new(&self.terminator, &self.termination, self.cancellation.as_ref())
+	}
+
+	fn start_watchdog(&self, timeout_ms: Option<u64>, reason: JsError) -> Option<Watchdog> {
+		timeout_ms.map(|timeout_ms| Watchdog::start(self.terminator.clone(), Duration::from_millis(timeout_ms), reason))
+	}
+
+	fn execute_call(&mut self, fn_name: &str, args: &JsValue, is_async: bool) -> Result<(), AnyError> {
+		// Wrap everything in scoped block, or async function for awaiting the result
 
-		// undefined will cause JSON serialization error, so it needs to be treated as null
-		let js_code = format!("{{
-			let __rust_result = {f}({a});
-			if (typeof __rust_result === 'undefined')
-				__rust_result = null;
+		// Arguments and result are transported as JSON strings, see prelude.js
+		// undefined has no JSON representation, so it is reported separately
+		// Thrown values which are not Errors are reported separately as well, since V8 only keeps their string form
+		let js_code = format!("{open}
+			try {{ __rust.returnValue({wait}{f}(__rust.fromHost({a}))); }} catch (e) {{ __rust.thrown(e, {dispatch}); }}\
+		{close}",
+			open = if is_async { "(async () => {" } else { "{" },
+			close = if is_async { "})();" } else { "}" },
+			wait = if is_async { "await " } else { "" },
+			f = fn_name,
+			a = serde_json::to_string(&args.to_string())?,
+			dispatch = !self.internal_call,
+		);
+
+		self.begin_call()?;
+		bytes::begin_call(&mut self.runtime.op_state().borrow_mut());
+		self.runtime.execute(Self::DEFAULT_FILENAME, &js_code)
+	}
 
-			Deno.core.ops();
-			Deno.core.opSync(\"__rust_return\", __rust_result);\
-		}}", f = fn_name, a = args);
+	/// Resets the per-call state: invocation counts of host functions, gas, and a value thrown by a previous call.
+	///
+	/// Starts measuring the call's execution time as well, which `usage.end()` accounts once the call has finished.
+	fn begin_call(&mut self) -> Result<(), AnyError> {
+		self.debug_assert_thread();
+		self.usage.begin();
+		self.restore_heap_limit();
 
-		if let Some(timeout_duration) = timeout_ms {
-			let handle = self.runtime.v8_isolate().thread_safe_handle();
+		let state_rc = self.runtime.op_state();
+		let mut state = state_rc.borrow_mut();
+		state.borrow_mut::<HostFns>().calls.clear();
+		state.try_take::<ThrownValue>();
+		drop(state);
 
-			thread::spawn(move || {
-				thread::sleep(Duration::from_millis(timeout_duration));
-				handle.terminate_execution();
-			});
+		match &self.gas {
+			Some(gas) => gas.refill(&mut self.runtime),
+			None => Ok(()),
 		}
+	}
 
-		self.runtime.execute(Self::DEFAULT_FILENAME, &js_code)?;
+	/// Evaluates the outcome of execute_call(), once timers are stopped.
+	fn finish_call(&mut self, result: Result<(), AnyError>) -> Result<ReturnValue, AnyError> {
+		let result = self.finish_call_raw(result)?;
+		Self::parse_result(result)
+	}
+
+	/// Like finish_call(), but leaves the result as a JSON string.
+	fn finish_call_raw(&mut self, result: Result<(), AnyError>) -> Result<ResultResource, AnyError> {
+		self.usage.end();
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		let thrown = self.runtime.op_state().borrow_mut().try_take::<ThrownValue>();
+		if let (Err(_), Some(ThrownValue { message, value })) = (&result, thrown) {
+			return Err(JsError::Thrown { message, value }.into());
+		}
+		result?;
 
 		let state_rc = self.runtime.op_state();
 		let mut state = state_rc.borrow_mut();
+		bytes::finish_call(&mut state);
 		let table = &mut state.resource_table;
 
 		// Get resource, and free slot (no longer needed)
-		let entry: Rc<ResultResource> = table.take(self.last_rid).expect("Resource entry must be present");
-		let extracted = Rc::try_unwrap(entry).expect("Rc must hold single strong ref to resource entry");
+		// A missing entry means the call did not hand back a result, which is reported rather than trusted
+		let entry: Rc<ResultResource> = table
+			.take(self.last_rid)
+			.ok_or_else(|| generic_error("JS call completed without returning a result"))?;
 		self.last_rid += 1;
 
-		Ok(extracted.json_value)
+		Rc::try_unwrap(entry).map_err(|_| generic_error("result of JS call is still referenced"))
+	}
+
+	fn parse_result(result: ResultResource) -> Result<ReturnValue, AnyError> {
+		Ok(ReturnValue {
+			json_value: json::from_js(&result.json)?,
+			is_undefined: result.is_undefined,
+		})
+	}
+
+	fn call_serialized<P>(&mut self, fn_name: &str, args: &P, timeout_ms: Option<u64>) -> Result<ReturnValue, AnyError>
+	where
+		P: Serialize,
+	{
+		self.check_allowed(fn_name)?;
+		let json_args = args::to_json(fn_name, args)?;
+		self.call_impl(fn_name, &json_args, timeout_ms)
+	}
+
+	fn deserialize_result<R>(fn_name: &str, result: ReturnValue) -> Result<R, AnyError>
+	where
+		R: DeserializeOwned,
+	{
+		Self::convert_result(fn_name, result).map_err(AnyError::from)
+	}
+
+	/// Converts the result of a call to `R`; on failure, the JsError::Conversion keeps the value.
+	fn convert_result<R>(fn_name: &str, result: ReturnValue) -> Result<R, JsError>
+	where
+		R: DeserializeOwned,
+	{
+		let ReturnValue { json_value, is_undefined } = result;
+		let error = match R::deserialize(&json_value) {
+			Ok(value) => return Ok(value),
+			Err(e) => e,
+		};
+
+		let expected = std::any::type_name::<R>();
+		let message = if is_undefined || json_value.is_null() {
+			format!(
+				"JS function '{}' returned {}, which cannot be converted to `{}` \
+				(use `Option<T>` or `()` for functions that may return nothing): {}",
+				fn_name,
+				if is_undefined { "undefined" } else { "null" },
+				expected,
+				error
+			)
+		} else {
+			format!("JS function '{}' returned a value which cannot be converted to `{}`: {}", fn_name, expected, error)
+		};
+
+		Err(JsError::Conversion {
+			function: fn_name.to_string(),
+			expected: expected.to_string(),
+			value: json_value,
+			message,
+		})
+	}
+
+	/// Creates a script with fresh JS runtime, in which only the prelude has been executed.
+	pub(crate) fn new_runtime() -> Result<Self, AnyError> {
+		match engine::take_spare_script() {
+			Some(script) => Ok(script),
+			None => Self::create_runtime(),
+		}
+	}
+
+	/// Like new_runtime(), but never uses a runtime prepared by enable_isolate_reuse().
+	pub(crate) fn create_runtime() -> Result<Self, AnyError> {
+		Self::create_runtime_with(None, None)
 	}
 
-	fn create_script(js_code: &str, js_filename: &str) -> Result<Self, AnyError> {
-		let options = RuntimeOptions::default();
+	/// Like create_runtime(), but creates the isolate with the given parameters, e.g. heap limits, and module loader.
+	pub(crate) fn create_runtime_with(
+		create_params: Option<v8::CreateParams>,
+		module_loader: Option<Rc<dyn ModuleLoader>>,
+	) -> Result<Self, AnyError> {
+		let options = RuntimeOptions {
+			create_params,
+			module_loader,
+			..RuntimeOptions::default()
+		};
+		Self::create_runtime_from(options)
+	}
+
+	/// Like create_runtime_with(), but with arbitrary runtime options. A startup snapshot already contains the prelude.
+	fn create_runtime_from(mut options: RuntimeOptions) -> Result<Self, AnyError> {
+		let has_prelude = options.startup_snapshot.is_some();
 
+		// Errors raised by V8 point into the generated code; positions covered by a source map are translated
+		let source_maps = Rc::new(RefCell::new(SourceMaps::default()));
+		let error_maps = source_maps.clone();
+		options.js_error_create_fn = Some(Rc::new(move |error| AnyError::from(error_maps.borrow().apply(error))));
+
+		engine::on_runtime_created();
 		let mut runtime = JsRuntime::new(options);
-		runtime.execute(js_filename, &js_code)?;
 		runtime.register_op("__rust_return", deno_core::op_sync(Self::op_return));
+		runtime.register_op("__rust_console", deno_core::op_sync(console::op_console));
+		runtime.register_op("__rust_thrown", deno_core::op_sync(Self::op_thrown));
+		runtime.register_op("__rust_gas_exhausted", deno_core::op_sync(gas::op_gas_exhausted));
+		runtime.register_op("__rust_iter_next", deno_core::op_sync(Self::op_iter_next));
+		runtime.register_op("__rust_bytes_in", deno_core::op_sync(bytes::op_bytes_in));
+		runtime.register_op("__rust_bytes_out", deno_core::op_sync(bytes::op_bytes_out));
+
+		let terminator = Terminator::new(runtime.v8_isolate().thread_safe_handle());
+		{
+			let state_rc = runtime.op_state();
+			let mut state = state_rc.borrow_mut();
+			state.put(ConsoleSink::Stdout);
+			state.put(HostFns::default());
+			state.put(terminator.clone());
+		}
+
+		if !has_prelude {
+			runtime.execute(Self::PRELUDE_FILENAME, include_str!("prelude.js"))?;
+		}
+
+		Ok(Script {
+			inspector: None,
+			gas: None,
+			contexts: HashMap::new(),
+			bound_fns: HashMap::new(),
+			runtime,
+			last_rid: 0,
+			terminator,
+			sources: SourceIndex::default(),
+			source_maps,
+			top_level: Vec::new(),
+			sticky_globals: Vec::new(),
+			cancellation: None,
+			termination: TerminationHandle::default(),
+			source_file: None,
+			allowed_calls: None,
+			host_fns_denied: false,
+			internal_call: false,
+			host_namespace: Self::HOST_NAMESPACE.to_string(),
+			heap_limit: None,
+			profile_interval_us: None,
+			usage: Usage::default(),
+			fast_fns: Vec::new(),
+			next_context: 1,
+			next_bound_fn: 1,
+			id: NEXT_SCRIPT_ID.fetch_add(1, Ordering::Relaxed),
+			thread_id: thread::current().id(),
+			_live: engine::LiveScript::new(),
+		})
+	}
+
+	/// Runs top-level code in a runtime created for snapshotting, and returns the serialized heap; see Snapshot.
+	pub(crate) fn snapshot_code(js_filename: &str, js_code: &str) -> Result<Box<[u8]>, AnyError> {
+		let options = RuntimeOptions {
+			will_snapshot: true,
+			..RuntimeOptions::default()
+		};
+
+		let mut script = Self::create_runtime_from(options)?;
+		script.execute(js_filename, js_code, 0, None)?;
+		let data = script.runtime.snapshot();
+		Ok(data.to_vec().into_boxed_slice())
+	}
+
+	/// Installs the JS functions evaluated from `reviver` and `replacer` for all values crossing the boundary.
+	pub(crate) fn set_json_hooks(&mut self, reviver: Option<&str>, replacer: Option<&str>) -> Result<(), AnyError> {
+		// Each expression on lines of its own, so that a trailing comment cannot swallow the rest
+		let expression = |hook: Option<&str>| hook.map_or("null".to_string(), |code| format!("(\n{}\n)", code));
+		let js_code = format!("__rust.setJsonHooks({}, {});", expression(reviver), expression(replacer));
+		self.execute_internal(&js_code)
+	}
+
+	/// Meters the script's execution, see ScriptBuilder::gas_limit(). Top-level code executed afterwards is metered as one call.
+	pub(crate) fn enable_gas(&mut self, limit: u64) -> Result<(), AnyError> {
+		self.gas = Some(GasMeter::install(&mut self.runtime, limit)?);
+		Ok(())
+	}
+
+	/// Provides fetch() to the script, see ScriptBuilder::fetch().
+	pub(crate) fn enable_fetch(&mut self, options: FetchOptions) -> Result<(), AnyError> {
+		fetch::install(&mut self.runtime, options)?;
+		self.add_feature("polyfill", "fetch")
+	}
+
+	/// Provides the `fs` global to the script, see ScriptBuilder::filesystem().
+	pub(crate) fn enable_filesystem(&mut self, fs: VirtualFs) -> Result<(), AnyError> {
+		fs::install(&mut self.runtime, fs)?;
+		self.add_feature("polyfill", "fs")
+	}
+
+	/// Provides TextEncoder, URL, structuredClone() etc. to the script, see ScriptBuilder::web_globals().
+	pub(crate) fn enable_web_globals(&mut self) -> Result<(), AnyError> {
+		let js_code = format!("{}(globalThis, Deno.core);", include_str!("web.js").trim_end());
+		self.execute_internal(&js_code)?;
+		self.add_feature("polyfill", "web")
+	}
+
+	/// Sets the defaults of Intl and toLocale*() methods, see ScriptBuilder::locale() and ScriptBuilder::timezone().
+	pub(crate) fn set_locale_defaults(&mut self, locale: Option<&str>, timezone: Option<&str>) -> Result<(), AnyError> {
+		let config = json!({ "locale": locale, "timeZone": timezone });
+		let js_code = format!("{}(globalThis, {});", include_str!("locale.js").trim_end(), config);
+		self.execute_internal(&js_code)
+			.map_err(|e| e.context(format!("unsupported locale {:?} or time zone {:?}", locale, timezone)))
+	}
+
+	/// Checks the script's WebAssembly modules and provides the host's, see ScriptBuilder::wasm() and ScriptBuilder::add_wasm_module().
+	pub(crate) fn enable_wasm(&mut self, options: Option<WasmOptions>, modules: HashMap<String, Vec<u8>>) -> Result<(), AnyError> {
+		if !modules.is_empty() {
+			self.check_host_fns_allowed()?;
+		}
+		wasm::install(&mut self.runtime, options, modules, &self.host_namespace)?;
+		self.add_feature("polyfill", "wasm")
+	}
+
+	pub(crate) fn enable_messages(&mut self) -> Result<(), AnyError> {
+		self.check_host_fns_allowed()?;
+		messages::install(&mut self.runtime);
+
+		let js_code = format!(
+			"__rust.defineHost({}, \"postMessage\", __rust.postMessage);",
+			serde_json::to_string(&self.host_namespace)?
+		);
+		self.execute_internal(&js_code)?;
+		self.add_feature("polyfill", "messages")
+	}
+
+	/// Aborts execution with JsError::HeapLimitExceeded once the heap nears `limit`, see ScriptBuilder::max_heap_size().
+	pub(crate) fn enforce_heap_limit(&mut self, limit: usize) {
+		let terminator = self.terminator.clone();
+		let raised = Rc::new(Cell::new(false));
+		let raised_by_callback = raised.clone();
+		self.runtime.add_near_heap_limit_callback(move |current_limit, _initial_limit| {
+			terminator.terminate(JsError::HeapLimitExceeded { limit });
+			raised_by_callback.set(true);
+
+			// V8 aborts the process if the heap is still full after this callback; the extra room lets the script unwind
+			current_limit * 2
+		});
+		self.heap_limit = Some((limit, raised));
+	}
+
+	/// Returns to the original heap limit after a call was aborted for exceeding it. V8 keeps the limit above the heap's
+	/// current size, so this only takes full effect once the garbage of the aborted call is collected.
+	fn restore_heap_limit(&mut self) {
+		if let Some((limit, raised)) = &self.heap_limit {
+			if raised.get() {
+				let limit = *limit;
+				self.runtime.remove_near_heap_limit_callback(limit);
+				self.enforce_heap_limit(limit);
+			}
+		}
+	}
+
+	/// Executes top-level code like execute(), aborting it with `reason` once `timeout_ms` elapses.
+	pub(crate) fn execute_with_timeout(
+		&mut self,
+		js_filename: &str,
+		js_code: &str,
+		line_offset: i32,
+		code_cache: Option<&[u8]>,
+		timeout_ms: Option<u64>,
+		reason: JsError,
+	) -> Result<(), AnyError> {
+		let watchdog = self.start_watchdog(timeout_ms, reason);
+		let result = self.execute(js_filename, js_code, line_offset, code_cache);
+		drop(watchdog);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Loads and evaluates the module `specifier` with the runtime's module loader, and defines its exports as globals.
+	///
+	/// Evaluation is aborted with JsError::InitTimeout once `timeout_ms` elapses.
+	pub(crate) fn execute_module(&mut self, specifier: &ModuleSpecifier, timeout_ms: Option<u64>) -> Result<(), AnyError> {
+		self.debug_assert_thread();
+
+		let watchdog = self.start_watchdog(timeout_ms, JsError::InitTimeout);
+		let result = futures::executor::block_on(self.evaluate_module(specifier));
+		drop(watchdog);
+
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	async fn evaluate_module(&mut self, specifier: &ModuleSpecifier) -> Result<(), AnyError> {
+		let id = self.runtime.load_module(specifier, None).await?;
+		let mut evaluated = self.runtime.mod_evaluate(id);
+		self.runtime.run_event_loop().await?;
+		evaluated
+			.next()
+			.await
+			.unwrap_or_else(|| Err(generic_error(format!("evaluation of module '{}' did not complete", specifier))))?;
+
+		// The module map is private to the runtime, so its namespace is obtained through a dynamic import of the (already
+		// evaluated) module instead
+		let js_code = format!(
+			"import({}).then(namespace => Object.assign(globalThis, namespace));",
+			serde_json::to_string(specifier.as_str())?
+		);
+		self.runtime.execute(Self::PRELUDE_FILENAME, &js_code)?;
+		self.runtime.run_event_loop().await?;
+		Ok(())
+	}
+
+	/// Executes setup code provided by js-sandbox within the script's global scope.
+	pub(crate) fn execute_internal(&mut self, js_code: &str) -> Result<(), AnyError> {
+		self.runtime.execute(Self::PRELUDE_FILENAME, js_code)
+	}
+
+	pub(crate) fn start_cpu_profile(&mut self, interval_us: u64) -> Result<(), AnyError> {
+		let runtime = &mut self.runtime;
+		let inspector = self.inspector.get_or_insert_with(|| Inspector::new(runtime));
+
+		inspector.send("Profiler.enable", json!({}))?;
+		inspector.send("Profiler.setSamplingInterval", json!({ "interval": interval_us }))?;
+		inspector.send("Profiler.start", json!({}))?;
+
+		self.profile_interval_us = Some(interval_us);
+		Ok(())
+	}
+
+	/// Defines a `globalThis` accessor for the lexical binding `name`.
+	pub(crate) fn expose_lexical_global(&mut self, name: &str) -> Result<(), AnyError> {
+		if !is_identifier(name) {
+			return Err(generic_error(format!("'{}' is not a valid identifier", name)));
+		}
+
+		// Evaluating the binding first fails early for undeclared names
+		let js_code = format!(
+			"void {name};
+			Object.defineProperty(globalThis, {key}, {{ get: () => {name}, set: v => {{ {name} = v; }}, configurable: true }});",
+			name = name,
+			key = serde_json::to_string(name)?,
+		);
+		self.execute_internal(&js_code)
+	}
+
+	/// Compiles top-level code within the script's global scope, without running it.
+	fn compile(&mut self, js_filename: &str, js_code: &str) -> Result<(), AnyError> {
+		self.compile_with_cache(js_filename, js_code, false).map(|_| ())
+	}
+
+	/// Compiles code without running it, and returns V8's code cache for it if `produce_cache` is set.
+	///
+	/// The cache is made after eager compilation, so that it covers all functions and not just the top-level code.
+	pub(crate) fn compile_with_cache(&mut self, js_filename: &str, js_code: &str, produce_cache: bool) -> Result<Vec<u8>, AnyError> {
+		let context = self.runtime.global_context();
+		let scope = &mut v8::HandleScope::with_context(self.runtime.v8_isolate(), context);
+		let source = Self::script_source(scope, js_filename, js_code, 0, None)?;
+
+		let options = if produce_cache {
+			v8::script_compiler::CompileOptions::EagerCompile
+		} else {
+			v8::script_compiler::CompileOptions::NoCompileOptions
+		};
+
+		let tc_scope = &mut v8::TryCatch::new(scope);
+		match v8::script_compiler::compile(tc_scope, source, options, v8::script_compiler::NoCacheReason::NoReason) {
+			Some(script) if produce_cache => {
+				let cache = script.get_unbound_script(tc_scope).create_code_cache();
+				Ok(cache.map(|cache| cache.to_vec()).unwrap_or_default())
+			}
+			Some(_) => Ok(Vec::new()),
+			// Without exception, compilation was aborted, e.g. by termination
+			None => match tc_scope.exception() {
+				Some(exception) => Err(deno_core::error::JsError::from_v8_exception(tc_scope, exception).into()),
+				None => Err(generic_error("compilation was aborted")),
+			},
+		}
+	}
+
+	fn script_source(
+		scope: &mut v8::HandleScope,
+		js_filename: &str,
+		js_code: &str,
+		line_offset: i32,
+		code_cache: Option<&[u8]>,
+	) -> Result<v8::script_compiler::Source, AnyError> {
+		let source = v8::String::new(scope, js_code).ok_or_else(|| generic_error("source code is too long"))?;
+		let name = v8::String::new(scope, js_filename).ok_or_else(|| generic_error("file name is too long"))?;
+		let source_map_url = v8::String::new(scope, "").unwrap();
+		// Same origin as for JsRuntime::execute() apart from the line offset, so errors are reported identically
+		let origin =
+			v8::ScriptOrigin::new(scope, name.into(), line_offset, 0, false, 123, source_map_url.into(), true, false, false);
+
+		Ok(match code_cache {
+			Some(code_cache) => v8::script_compiler::Source::new_with_cached_data(source, Some(&origin), v8::CachedData::new(code_cache)),
+			None => v8::script_compiler::Source::new(source, Some(&origin)),
+		})
+	}
+
+	/// Executes top-level code within the script's global scope, e.g. the user's source.
+	///
+	/// Lines are reported shifted by `line_offset`. With `code_cache`, the code is compiled from it; see CompiledScript.
+	fn execute(&mut self, js_filename: &str, js_code: &str, line_offset: i32, code_cache: Option<&[u8]>) -> Result<(), AnyError> {
+		self.debug_assert_thread();
+		self.sources.add(js_filename, js_code, line_offset);
+		self.top_level.push((js_filename.to_string(), js_code.to_string(), line_offset));
 
-		Ok(Script { runtime, last_rid: 0 })
+		let result = Self::run_top_level(&mut self.runtime, &self.source_maps, js_filename, js_code, line_offset, code_cache);
+		if let Some(reason) = self.terminator.take_reason() {
+			return Err(reason.into());
+		}
+		result
+	}
+
+	/// Compiles and runs top-level code like JsRuntime::execute(), which does not support line offsets and code caches.
+	///
+	/// V8 validates the cache against the source, and silently compiles from scratch if it does not match.
+	fn run_top_level(
+		runtime: &mut JsRuntime,
+		source_maps: &RefCell<SourceMaps>,
+		js_filename: &str,
+		js_code: &str,
+		line_offset: i32,
+		code_cache: Option<&[u8]>,
+	) -> Result<(), AnyError> {
+		let context = runtime.global_context();
+		let scope = &mut v8::HandleScope::with_context(runtime.v8_isolate(), context);
+		let source = Self::script_source(scope, js_filename, js_code, line_offset, code_cache)?;
+
+		let tc_scope = &mut v8::TryCatch::new(scope);
+		let options = match code_cache {
+			Some(_) => v8::script_compiler::CompileOptions::ConsumeCodeCache,
+			None => v8::script_compiler::CompileOptions::NoCompileOptions,
+		};
+		let script = v8::script_compiler::compile(tc_scope, source, options, v8::script_compiler::NoCacheReason::NoReason);
+
+		match script.and_then(|script| script.run(tc_scope)) {
+			Some(_) => Ok(()),
+			None => match tc_scope.exception() {
+				Some(exception) if !tc_scope.has_terminated() => {
+					let error = deno_core::error::JsError::from_v8_exception(tc_scope, exception);
+					Err(source_maps.borrow().apply(error).into())
+				}
+				_ => Err(generic_error("Uncaught Error: execution terminated")),
+			},
+		}
+	}
+
+	// Catches use of the isolate from another thread, which is undefined behavior
+	fn debug_assert_thread(&self) {
+		debug_assert!(
+			thread::current().id() == self.thread_id,
+			"Script created on thread {:?} is used on thread {:?}",
+			self.thread_id,
+			thread::current().id()
+		);
+	}
+
+	fn op_thrown(state: &mut OpState, args: (String, String), _buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+		let (message, json) = args;
+		state.put(ThrownValue { message, value: json::from_js(&json)? });
+
+		Ok(serde_json::Value::Null)
+	}
+
+	fn op_iter_next(state: &mut OpState, _args: (), _buf: Option<ZeroCopyBuf>) -> Result<Option<String>, AnyError> {
+		match state.try_borrow_mut::<InputIter>() {
+			Some(InputIter(items)) => items.next().transpose(),
+			None => Err(generic_error("input iterable can only be used during the call that received it")),
+		}
 	}
 
 	fn op_return(
 		state: &mut OpState,
-		args: JsValue,
+		args: (String, bool),
 		_buf: Option<ZeroCopyBuf>,
 	) -> Result<JsValue, AnyError> {
-		let entry = ResultResource { json_value: args };
+		let (json, is_undefined) = args;
+		let entry = ResultResource { json, is_undefined };
 		let resource_table = &mut state.resource_table;
 		let _rid = resource_table.add(entry);
 		//assert_eq!(rid, self.last_rid);
@@ -128,9 +2816,31 @@ impl Script {
 	}
 }
 
+impl Drop for Script {
+	fn drop(&mut self) {
+		self.debug_assert_thread();
+	}
+}
+
+/// Value returned from a JS function, after conversion to JSON
+struct ReturnValue {
+	json_value: JsValue,
+	is_undefined: bool,
+}
+
+/// Value thrown by the current call which is not an `Error`, stored in the op state by op_thrown().
+struct ThrownValue {
+	message: String,
+	value: JsValue,
+}
+
+/// Items of the iterator passed to call_with_iter(), as JSON strings.
+struct InputIter(Box<dyn Iterator<Item = Result<String, AnyError>>>);
+
 #[derive(Debug)]
 struct ResultResource {
-	json_value: JsValue,
+	json: String,
+	is_undefined: bool,
 }
 
 // Type that is stored inside Deno's resource table
@@ -138,4 +2848,82 @@ impl deno_core::Resource for ResultResource {
 	fn name(&self) -> Cow<str> {
 		"__rust_Result".into()
 	}
-}
\ No newline at end of file
+}
+
+/// File name under which errors in eval_json() expressions are reported.
+const EVAL_FILENAME: &str = "eval_json";
+
+/// Line of the code generated by execute_call() on which an eval_json() expression starts.
+const EVAL_FIRST_LINE: i64 = 3;
+
+/// Makes the positions in an error from eval_json() relative to the expression, and removes frames of the generated code.
+fn relocate_eval_error(err: AnyError, js_expr: &str) -> AnyError {
+	let mut error = match err.downcast::<deno_core::error::JsError>() {
+		Ok(error) => error,
+		Err(err) => return err,
+	};
+
+	let num_lines = js_expr.lines().count().max(1) as i64;
+	let expr_line = |line: i64| Some(line - EVAL_FIRST_LINE + 1).filter(|line| (1..=num_lines).contains(line));
+	let is_generated = |file: &Option<String>| file.as_deref() == Some(Script::DEFAULT_FILENAME);
+
+	if is_generated(&error.script_resource_name) {
+		error.script_resource_name = Some(EVAL_FILENAME.to_string());
+		error.line_number = error.line_number.and_then(expr_line);
+	}
+
+	error.frames = error
+		.frames
+		.into_iter()
+		.filter_map(|mut frame| {
+			if is_generated(&frame.file_name) {
+				frame.line_number = Some(expr_line(frame.line_number?)?);
+				frame.file_name = Some(EVAL_FILENAME.to_string());
+			}
+			Some(frame)
+		})
+		.collect();
+
+	error.stack = error.stack.map(|stack| {
+		let stack_lines: Vec<String> = stack.lines().filter_map(|line| relocate_stack_line(line, expr_line)).collect();
+		stack_lines.join("\n")
+	});
+
+	error.into()
+}
+
+/// Relocates a stack line like "    at f (sandboxed.js:3:7)"; returns `None` for frames of the generated code.
+fn relocate_stack_line(stack_line: &str, expr_line: impl Fn(i64) -> Option<i64>) -> Option<String> {
+	let location = format!("{}:", Script::DEFAULT_FILENAME);
+	let start = match stack_line.find(&location) {
+		Some(start) => start,
+		None => return Some(stack_line.to_string()),
+	};
+
+	let rest = &stack_line[start + location.len()..];
+	let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+	let line = expr_line(rest[..digits].parse().ok()?)?;
+
+	Some(format!("{}{}:{}{}", &stack_line[..start], EVAL_FILENAME, line, &rest[digits..]))
+}
+
+/// JS expression referring to the global `name`: the identifier itself if possible (to find lexical bindings), otherwise a
+/// property of `globalThis`.
+fn global_ref(name: &str) -> Result<String, AnyError> {
+	if is_identifier(name) {
+		Ok(name.to_string())
+	} else {
+		Ok(format!("globalThis[{}]", serde_json::to_string(name)?))
+	}
+}
+
+/// Whether `name` can be used as a JS identifier (reserved words are not detected).
+fn is_identifier(name: &str) -> bool {
+	let mut chars = name.chars();
+	let is_part = |c: char| c == '_' || c == '$' || c.is_alphanumeric();
+
+	match chars.next() {
+		Some(first) => !first.is_numeric() && is_part(first) && chars.all(is_part),
+		None => false,
+	}
+}
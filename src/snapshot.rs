@@ -0,0 +1,73 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::Arc;
+
+use crate::{AnyError, Script};
+
+/// Heap of a script after its top-level code ran, from which scripts can be started without running it again.
+///
+/// Creating a [`Script`](struct.Script.html) sets up a fresh V8 context, runs js-sandbox's setup code and then the script's
+/// top-level code. When hundreds of short-lived scripts are created from the same source, `Snapshot` pays this cost only once:
+/// [`create()`](#method.create) runs the code in a dedicated isolate and serializes its heap, which
+/// [`instantiate()`](#method.instantiate) deserializes into each new isolate. Every instance starts with the globals exactly as
+/// the top-level code left them, but with state of its own. Compared to [`CompiledScript`](struct.CompiledScript.html), which
+/// only saves compiling, this also skips the setup and the top-level code, at the cost of a larger blob.
+///
+/// The top-level code must complete synchronously; pending promises or async operations cannot be captured. Since the heap is
+/// restored as-is, instances use no [`ScriptBuilder`](struct.ScriptBuilder.html) settings. [`Script::reset()`](struct.Script.html#method.reset)
+/// runs the top-level code again on the restored heap. V8 only accepts a snapshot from the same V8 version and flags.
+///
+/// The snapshot holds no isolate, only bytes: it is `Send` and `Sync`, and cloning is cheap, so it can be shared among threads.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+	inner: Arc<SnapshotData>,
+}
+
+#[derive(Debug)]
+struct SnapshotData {
+	js_code: String,
+	js_filename: String,
+	data: Box<[u8]>,
+}
+
+impl Snapshot {
+	/// Runs the given JavaScript source code, and takes a snapshot of the resulting heap.
+	///
+	/// Fails in case of syntax or initialization error with the code.
+	pub fn create(js_code: &str) -> Result<Self, AnyError> {
+		let js_filename = Script::DEFAULT_FILENAME;
+		let data = Script::snapshot_code(js_filename, js_code)?;
+
+		Ok(Snapshot {
+			inner: Arc::new(SnapshotData {
+				js_code: js_code.to_string(),
+				js_filename: js_filename.to_string(),
+				data,
+			}),
+		})
+	}
+
+	/// Creates a script in a fresh isolate, restored from the snapshot.
+	///
+	/// Equivalent to [`Script::from_snapshot()`](struct.Script.html#method.from_snapshot).
+	pub fn instantiate(&self) -> Result<Script, AnyError> {
+		Script::from_snapshot(self)
+	}
+
+	/// Size of the serialized heap in bytes.
+	pub fn size(&self) -> usize {
+		self.inner.data.len()
+	}
+
+	pub(crate) fn js_code(&self) -> &str {
+		&self.inner.js_code
+	}
+
+	pub(crate) fn js_filename(&self) -> &str {
+		&self.inner.js_filename
+	}
+
+	pub(crate) fn data(&self) -> &[u8] {
+		&self.inner.data
+	}
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+/// A position in one of the source files of a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourcePosition {
+	/// Name of the source file, as it appears in stack traces.
+	pub file: String,
+	/// Line number, starting at 1.
+	pub line: u32,
+	/// Column number, starting at 1.
+	pub column: u32,
+}
+
+/// Translates character offsets within the sources of a script into line/column positions.
+///
+/// V8 reports locations -- for example in coverage or profiling data -- as character offsets from the start of a source file,
+/// counted in UTF-16 code units. This index maps them back to positions that can be displayed next to the source.
+#[derive(Clone, Debug, Default)]
+pub struct SourceIndex {
+	files: Vec<SourceFile>,
+}
+
+#[derive(Clone, Debug)]
+struct SourceFile {
+	name: String,
+	/// Offset of the first character in each line, in UTF-16 code units
+	line_starts: Vec<usize>,
+	len: usize,
+	/// Number of lines reported before the first one, see ScriptBuilder::source_name_and_offset()
+	line_offset: i32,
+}
+
+impl SourceIndex {
+	/// Names of all indexed source files, in the order they were loaded.
+	pub fn files(&self) -> impl Iterator<Item = &str> {
+		self.files.iter().map(|f| f.name.as_str())
+	}
+
+	/// Returns the line/column position of `offset` in the source file `file`.
+	///
+	/// Returns `None` if no such file was loaded, or if the offset lies beyond its end.
+	pub fn position(&self, file: &str, offset: usize) -> Option<SourcePosition> {
+		// If a file name was loaded multiple times, the latest source applies
+		let source = self.files.iter().rev().find(|f| f.name == file)?;
+		if offset > source.len {
+			return None;
+		}
+
+		let line_idx = match source.line_starts.binary_search(&offset) {
+			Ok(idx) => idx,
+			Err(idx) => idx - 1,
+		};
+
+		Some(SourcePosition {
+			file: source.name.clone(),
+			line: (line_idx as i64 + 1 + i64::from(source.line_offset)) as u32,
+			column: (offset - source.line_starts[line_idx]) as u32 + 1,
+		})
+	}
+
+	pub(crate) fn add(&mut self, name: &str, code: &str, line_offset: i32) {
+		let mut line_starts = vec![0];
+		let mut offset = 0;
+		for c in code.chars() {
+			offset += c.len_utf16();
+			if c == '\n' {
+				line_starts.push(offset);
+			}
+		}
+
+		self.files.push(SourceFile { name: name.to_string(), line_starts, len: offset, line_offset });
+	}
+}
@@ -0,0 +1,257 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Source maps (version 3) of bundled or transpiled code, used to translate positions in errors back to the original files.
+// Only what is needed for that is decoded: the mappings, the source names and their optional contents. Index maps with
+// sections are not supported. Maps are either passed by the host, or embedded in the code as a base64 data URL, the way
+// bundlers like esbuild and webpack emit inline source maps.
+
+use std::collections::HashMap;
+
+use deno_core::error::{generic_error, JsError};
+use serde::Deserialize;
+
+use crate::AnyError;
+
+/// Source maps of a script, by the name of the generated file they describe.
+#[derive(Default)]
+pub(crate) struct SourceMaps {
+	maps: HashMap<String, SourceMap>,
+}
+
+struct SourceMap {
+	/// Source file names, including the source root
+	sources: Vec<String>,
+	/// Contents of each source file, if embedded in the map
+	contents: Vec<Option<String>>,
+	/// Segments of each generated line, ordered by column
+	lines: Vec<Vec<Segment>>,
+	/// Number of lines preceding the code the map describes
+	line_offset: u32,
+}
+
+struct Segment {
+	/// Generated column, starting at 0
+	column: u32,
+	/// Source index, line and column (starting at 0); `None` for generated code without an original position
+	original: Option<(usize, u32, u32)>,
+}
+
+/// A position in an original source file, with line and column starting at 1.
+struct Original<'a> {
+	file: &'a str,
+	line: u32,
+	column: u32,
+	source_line: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+	version: u32,
+	#[serde(default)]
+	source_root: Option<String>,
+	#[serde(default)]
+	sources: Vec<Option<String>>,
+	#[serde(default)]
+	sources_content: Vec<Option<String>>,
+	mappings: String,
+}
+
+impl SourceMaps {
+	/// Registers `map_json` for the generated file `file`, replacing an earlier map; `line_offset` lines precede the code it
+	/// describes.
+	pub(crate) fn add(&mut self, file: &str, map_json: &str, line_offset: u32) -> Result<(), AnyError> {
+		let map = SourceMap::parse(map_json, line_offset)
+			.map_err(|e| e.context(format!("invalid source map for '{}'", file)))?;
+		self.maps.insert(file.to_string(), map);
+		Ok(())
+	}
+
+	/// Translates the positions of `error` -- its location, stack frames and stack trace -- for all files with a source map.
+	pub(crate) fn apply(&self, mut error: JsError) -> JsError {
+		if self.maps.is_empty() {
+			return error;
+		}
+
+		if let (Some(file), Some(line), Some(start)) = (&error.script_resource_name, error.line_number, error.start_column) {
+			if let Some(original) = self.original(file, line, start + 1) {
+				let width = error.end_column.map(|end| end - start);
+				error.source_line = original.source_line.map(str::to_string);
+				error.line_number = Some(original.line as i64);
+				error.start_column = Some(original.column as i64 - 1);
+				error.end_column = width.map(|width| original.column as i64 - 1 + width);
+				error.script_resource_name = Some(original.file.to_string());
+			}
+		}
+
+		// Stack trace lines correspond to the frames, in the same order
+		let mut replacements = Vec::new();
+		for frame in &mut error.frames {
+			if let (Some(file), Some(line), Some(column)) = (&frame.file_name, frame.line_number, frame.column_number) {
+				if let Some(original) = self.original(file, line, column) {
+					let generated = format!("{}:{}:{}", file, line, column);
+					replacements.push((generated, format!("{}:{}:{}", original.file, original.line, original.column)));
+
+					frame.file_name = Some(original.file.to_string());
+					frame.line_number = Some(original.line as i64);
+					frame.column_number = Some(original.column as i64);
+				}
+			}
+		}
+
+		if let Some(stack) = &error.stack {
+			let mut replacements = replacements.into_iter().peekable();
+			let lines: Vec<String> = stack
+				.lines()
+				.map(|line| match replacements.peek() {
+					Some((generated, original)) if line.trim_start().starts_with("at ") && line.contains(generated.as_str()) => {
+						let line = line.replacen(generated.as_str(), original, 1);
+						replacements.next();
+						line
+					}
+					_ => line.to_string(),
+				})
+				.collect();
+			error.stack = Some(lines.join("\n"));
+		}
+
+		error
+	}
+
+	fn original(&self, file: &str, line: i64, column: i64) -> Option<Original<'_>> {
+		let map = self.maps.get(file)?;
+		if line < 1 || column < 1 {
+			return None;
+		}
+		map.original(line as u32, column as u32)
+	}
+}
+
+impl SourceMap {
+	fn parse(map_json: &str, line_offset: u32) -> Result<Self, AnyError> {
+		let raw: RawSourceMap = serde_json::from_str(map_json)?;
+		if raw.version != 3 {
+			return Err(generic_error(format!("unsupported source map version {}", raw.version)));
+		}
+
+		let root = raw.source_root.unwrap_or_default();
+		let separator = if root.is_empty() || root.ends_with('/') { "" } else { "/" };
+		let sources = raw
+			.sources
+			.into_iter()
+			.map(|source| format!("{}{}{}", root, separator, source.unwrap_or_default()))
+			.collect();
+
+		// All fields but the generated column are relative to the previous segment, across lines
+		let mut lines = Vec::new();
+		let (mut source, mut original_line, mut original_column) = (0i64, 0i64, 0i64);
+		for line in raw.mappings.split(';') {
+			let mut segments = Vec::new();
+			let mut column = 0i64;
+			for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+				let fields = decode_vlq(segment)?;
+				column += fields[0];
+				let original = if fields.len() >= 4 {
+					source += fields[1];
+					original_line += fields[2];
+					original_column += fields[3];
+					Some((source as usize, original_line as u32, original_column as u32))
+				} else {
+					None
+				};
+				segments.push(Segment { column: column as u32, original });
+			}
+			segments.sort_by_key(|segment| segment.column);
+			lines.push(segments);
+		}
+
+		Ok(SourceMap { sources, contents: raw.sources_content, lines, line_offset })
+	}
+
+	fn original(&self, line: u32, column: u32) -> Option<Original<'_>> {
+		let segments = self.lines.get(line.checked_sub(self.line_offset + 1)? as usize)?;
+		let index = match segments.binary_search_by_key(&(column - 1), |segment| segment.column) {
+			Ok(index) => index,
+			Err(0) => return None,
+			Err(index) => index - 1,
+		};
+
+		let (source, line, column) = segments[index].original?;
+		let source_line = self
+			.contents
+			.get(source)
+			.and_then(Option::as_deref)
+			.and_then(|content| content.lines().nth(line as usize));
+
+		Some(Original {
+			file: self.sources.get(source)?,
+			line: line + 1,
+			column: column + 1,
+			source_line,
+		})
+	}
+}
+
+/// Extracts the source map embedded in `js_code` as a base64 data URL, e.g. `//# sourceMappingURL=data:application/json;base64,...`
+pub(crate) fn inline_source_map(js_code: &str) -> Option<String> {
+	let line = js_code.lines().rev().map(str::trim).find(|line| !line.is_empty())?;
+	let url = line
+		.strip_prefix("//# sourceMappingURL=")
+		.or_else(|| line.strip_prefix("//@ sourceMappingURL="))?;
+
+	let data = url.strip_prefix("data:application/json")?;
+	let payload_start = data.find(";base64,")? + ";base64,".len();
+	String::from_utf8(decode_base64(&data[payload_start..])?).ok()
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>, AnyError> {
+	let mut fields = Vec::new();
+	let (mut value, mut shift) = (0i64, 0);
+	for c in segment.bytes() {
+		let digit = base64_digit(c).ok_or_else(|| generic_error(format!("invalid character '{}' in mappings", c as char)))?;
+		if shift > 55 {
+			return Err(generic_error("value in mappings is too large"));
+		}
+		value += i64::from(digit & 0b11111) << shift;
+
+		// The 6th bit marks a continuation, the lowest bit of the value its sign
+		if digit & 0b100000 != 0 {
+			shift += 5;
+		} else {
+			fields.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+			value = 0;
+			shift = 0;
+		}
+	}
+
+	if shift != 0 || !matches!(fields.len(), 1 | 4 | 5) {
+		return Err(generic_error(format!("invalid segment '{}' in mappings", segment)));
+	}
+	Ok(fields)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::new();
+	let (mut buffer, mut bits) = (0u32, 0);
+	for c in text.bytes().take_while(|&c| c != b'=') {
+		buffer = (buffer << 6) | u32::from(base64_digit(c)?);
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			bytes.push((buffer >> bits) as u8);
+			buffer &= (1 << bits) - 1;
+		}
+	}
+	Some(bytes)
+}
+
+fn base64_digit(c: u8) -> Option<u8> {
+	match c {
+		b'A'..=b'Z' => Some(c - b'A'),
+		b'a'..=b'z' => Some(c - b'a' + 26),
+		b'0'..=b'9' => Some(c - b'0' + 52),
+		b'+' => Some(62),
+		b'/' => Some(63),
+		_ => None,
+	}
+}
@@ -0,0 +1,56 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::time::{Duration, Instant};
+
+use crate::HeapStats;
+
+/// Resource usage of a script, as returned by [`Script::stats()`](struct.Script.html#method.stats).
+///
+/// Meant for hosts running many scripts, to monitor them, spot leaks, or decide which ones to evict. Call counts and times are
+/// cumulative since the script was created.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScriptStats {
+	/// Memory statistics of the script's V8 heap, as returned by [`Script::heap_stats()`](struct.Script.html#method.heap_stats).
+	pub heap: HeapStats,
+	/// Number of times the host has run JavaScript in the script, successfully or not.
+	pub calls: u64,
+	/// Total time spent in these calls, including the event loop driven by async calls.
+	pub execution_time: Duration,
+}
+
+/// Execution time accounting of a script; see Script::stats().
+#[derive(Default)]
+pub(crate) struct Usage {
+	calls: u64,
+	execution_time: Duration,
+	started: Option<Instant>,
+	handler: Option<Box<dyn FnMut(Duration)>>,
+}
+
+impl Usage {
+	/// Starts measuring a call; a call which failed before being accounted is discarded.
+	pub(crate) fn begin(&mut self) {
+		self.started = Some(Instant::now());
+	}
+
+	/// Accounts the call measured since begin(), if any, and reports its duration to the handler.
+	pub(crate) fn end(&mut self) {
+		if let Some(started) = self.started.take() {
+			let duration = started.elapsed();
+			self.calls += 1;
+			self.execution_time += duration;
+			if let Some(handler) = &mut self.handler {
+				handler(duration);
+			}
+		}
+	}
+
+	pub(crate) fn set_handler(&mut self, handler: Box<dyn FnMut(Duration)>) {
+		self.handler = Some(handler);
+	}
+
+	pub(crate) fn stats(&self, heap: HeapStats) -> ScriptStats {
+		ScriptStats { heap, calls: self.calls, execution_time: self.execution_time }
+	}
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use crate::{AnyError, Script};
+
+/// Chunks of a JavaScript `ReadableStream`, as returned by [`Script::call_stream()`](struct.Script.html#method.call_stream).
+///
+/// Chunks are read lazily, one per call to [`next()`](#method.next), so only a single chunk is held in memory at a time.
+/// The stream borrows its script mutably; the script cannot be called otherwise until the stream is dropped.
+///
+/// Dropping the stream before it is exhausted cancels its reader, which lets the producer in the script stop early.
+pub struct JsStream<'a, T> {
+	script: &'a mut Script,
+	id: u64,
+	timeout_ms: Option<u64>,
+	done: bool,
+	chunk: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> JsStream<'a, T>
+where
+	T: DeserializeOwned,
+{
+	pub(crate) fn new(script: &'a mut Script, id: u64, timeout_ms: Option<u64>) -> Self {
+		JsStream {
+			script,
+			id,
+			timeout_ms,
+			done: false,
+			chunk: PhantomData,
+		}
+	}
+
+	/// Waits for the next chunk, driving the script's event loop, and returns it; `None` once the stream has ended.
+	///
+	/// Fails if the stream errors, if a chunk cannot be converted to `T`, or if the pull exceeds the stream's timeout. The
+	/// stream stays usable afterwards, so whether to read further is up to the caller.
+	pub async fn next(&mut self) -> Result<Option<T>, AnyError> {
+		if self.done {
+			return Ok(None);
+		}
+
+		match self.script.pull_stream(self.id, self.timeout_ms).await? {
+			Some(chunk) => Ok(Some(serde_json::from_value(chunk)?)),
+			None => {
+				self.done = true;
+				Ok(None)
+			}
+		}
+	}
+}
+
+impl<'a, T> Drop for JsStream<'a, T> {
+	fn drop(&mut self) {
+		// An exhausted stream has been released by the script already
+		if !self.done {
+			let _ = self.script.release_stream(self.id);
+		}
+	}
+}
+
+/// Items of a JavaScript generator or other iterable, as returned by [`Script::call_iter()`](struct.Script.html#method.call_iter).
+///
+/// Items are pulled lazily, one per call to [`next()`](#method.next); each is `Err` if the iterator throws or the item cannot
+/// be converted to `T`. The iterator borrows its script mutably; the script cannot be called otherwise until it is dropped.
+///
+/// Dropping the iterator before it is exhausted calls the generator's `return()`, which runs its `finally` blocks.
+pub struct JsIter<'a, T> {
+	script: &'a mut Script,
+	id: u64,
+	timeout_ms: Option<u64>,
+	done: bool,
+	item: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> JsIter<'a, T>
+where
+	T: DeserializeOwned,
+{
+	pub(crate) fn new(script: &'a mut Script, id: u64, timeout_ms: Option<u64>) -> Self {
+		JsIter {
+			script,
+			id,
+			timeout_ms,
+			done: false,
+			item: PhantomData,
+		}
+	}
+}
+
+impl<'a, T> Iterator for JsIter<'a, T>
+where
+	T: DeserializeOwned,
+{
+	type Item = Result<T, AnyError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+
+		match self.script.pull_iter(self.id, self.timeout_ms) {
+			Ok(Some(item)) => Some(serde_json::from_value(item).map_err(AnyError::from)),
+			Ok(None) => {
+				self.done = true;
+				None
+			}
+			Err(e) => Some(Err(e)),
+		}
+	}
+}
+
+impl<'a, T> Drop for JsIter<'a, T> {
+	fn drop(&mut self) {
+		// An exhausted iterator has been released by the script already
+		if !self.done {
+			let _ = self.script.release_iter(self.id);
+		}
+	}
+}
@@ -0,0 +1,138 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use deno_core::v8;
+
+use crate::JsError;
+
+/// Terminates a running script on behalf of the host, remembering why.
+///
+/// Stored both in the `Script` and in its op state, so that ops can abort execution as well.
+#[derive(Clone)]
+pub(crate) struct Terminator {
+	handle: v8::IsolateHandle,
+	reason: Arc<Mutex<Option<JsError>>>,
+	deadlines: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl Terminator {
+	pub fn new(handle: v8::IsolateHandle) -> Self {
+		Terminator {
+			handle,
+			reason: Arc::new(Mutex::new(None)),
+			deadlines: Arc::new(Mutex::new(Vec::new())),
+		}
+	}
+
+	/// Stops JS execution as soon as possible. If termination was already requested, the first reason is kept.
+	pub fn terminate(&self, reason: JsError) {
+		let mut guard = self.reason.lock().unwrap();
+		if guard.is_none() {
+			*guard = Some(reason);
+		}
+
+		self.handle.terminate_execution();
+	}
+
+	/// Whether termination was requested since the last invocation of `take_reason()`.
+	pub fn is_terminating(&self) -> bool {
+		self.reason.lock().unwrap().is_some()
+	}
+
+	/// Returns the reason of a termination since the last invocation, and allows the isolate to execute again.
+	pub fn take_reason(&self) -> Option<JsError> {
+		let reason = self.reason.lock().unwrap().take();
+		if reason.is_some() {
+			self.handle.cancel_terminate_execution();
+		}
+
+		reason
+	}
+
+	/// Time left until the earliest running watchdog fires, or `None` without watchdog.
+	///
+	/// Lets ops which block the thread, such as `fetch()`, give up no later than the call they run in.
+	#[cfg(feature = "http")]
+	pub fn remaining(&self) -> Option<Duration> {
+		let deadlines = self.deadlines.lock().unwrap();
+		deadlines.iter().min().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+	}
+}
+
+/// Background thread which terminates JS execution once a timeout elapses.
+///
+/// The thread lives only as long as the watchdog: stopping or dropping it wakes the thread up and joins it, so that no timer
+/// outlives the call it guards, and no late termination can hit a subsequent call.
+pub(crate) struct Watchdog {
+	terminator: Terminator,
+	deadline: Instant,
+	stop: Option<Sender<()>>,
+	thread: Option<JoinHandle<bool>>,
+	waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl Watchdog {
+	/// Starts the timer. On timeout, execution is terminated with `reason`, which is then reported by `take_reason()`.
+	pub fn start(terminator: Terminator, timeout: Duration, reason: JsError) -> Self {
+		let (stop, stopped) = mpsc::channel::<()>();
+		let thread_terminator = terminator.clone();
+		let waker = Arc::new(Mutex::new(None::<Waker>));
+		let thread_waker = waker.clone();
+
+		let deadline = Instant::now() + timeout;
+		terminator.deadlines.lock().unwrap().push(deadline);
+
+		let thread = thread::spawn(move || match stopped.recv_timeout(timeout) {
+			Err(RecvTimeoutError::Timeout) => {
+				thread_terminator.terminate(reason);
+				if let Some(waker) = thread_waker.lock().unwrap().take() {
+					waker.wake();
+				}
+				true
+			}
+			// Stop signal, or watchdog dropped
+			_ => false,
+		});
+
+		Watchdog { terminator, deadline, stop: Some(stop), thread: Some(thread), waker }
+	}
+
+	/// Wakes up `waker` once the timer fires, for futures which wait without running JS, such as the event loop.
+	pub fn wake_on_fire(&self, waker: &Waker) {
+		*self.waker.lock().unwrap() = Some(waker.clone());
+	}
+
+	/// Stops the timer and returns whether it had fired. Allowing the isolate to execute again is left to `take_reason()`.
+	pub fn stop(mut self) -> bool {
+		self.join()
+	}
+
+	fn join(&mut self) -> bool {
+		// Dropping the sender disconnects the channel and wakes up the thread
+		self.stop.take();
+
+		match self.thread.take() {
+			Some(thread) => {
+				let mut deadlines = self.terminator.deadlines.lock().unwrap();
+				if let Some(index) = deadlines.iter().position(|deadline| *deadline == self.deadline) {
+					deadlines.swap_remove(index);
+				}
+				drop(deadlines);
+
+				thread.join().unwrap_or(false)
+			}
+			None => false,
+		}
+	}
+}
+
+impl Drop for Watchdog {
+	fn drop(&mut self) {
+		self.join();
+	}
+}
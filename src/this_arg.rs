@@ -0,0 +1,30 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use crate::JsValue;
+
+/// Object bound to `this` by [`Script::call_with_this()`](struct.Script.html#method.call_with_this).
+///
+/// Converts from `&str` for an expression and from `JsValue` for a value, so either can be passed directly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ThisArg<'a> {
+	/// JS expression evaluated right before the call, typically a path to an existing object such as `"plugins.main"`.
+	///
+	/// The method then operates on the live object, so modifications of `this` persist in the script.
+	Expr(&'a str),
+	/// Value passed from Rust, converted like the arguments of [`Script::call()`](struct.Script.html#method.call).
+	///
+	/// Each call receives a fresh object, so modifications of `this` are not visible afterwards.
+	Value(JsValue),
+}
+
+impl<'a> From<&'a str> for ThisArg<'a> {
+	fn from(expr: &'a str) -> Self {
+		ThisArg::Expr(expr)
+	}
+}
+
+impl From<JsValue> for ThisArg<'_> {
+	fn from(value: JsValue) -> Self {
+		ThisArg::Value(value)
+	}
+}
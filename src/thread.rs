@@ -0,0 +1,95 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle, ThreadId};
+
+use crate::{AnyError, JsError, Script};
+
+type Task = Box<dyn FnOnce(&mut Script) + Send>;
+
+/// Dedicated OS thread owning a single [`Script`](struct.Script.html), as returned by [`Script::on_thread()`](struct.Script.html#method.on_thread).
+///
+/// The script is created on that thread and never leaves it. Other threads access it by sending closures with
+/// [`run()`](#method.run), which are executed one after another. This makes the thread an isolate lives on explicit, for
+/// example to give a plugin a thread of its own whose priority or CPU affinity is configured separately.
+///
+/// Dropping the handle waits for the running closure to finish, then disposes the script and ends the thread.
+pub struct ScriptThread {
+	sender: Option<mpsc::Sender<Task>>,
+	thread: Option<JoinHandle<()>>,
+	thread_id: ThreadId,
+}
+
+impl ScriptThread {
+	pub(crate) fn spawn<F>(name: &str, create: F) -> Result<Self, AnyError>
+	where
+		F: FnOnce() -> Result<Script, AnyError> + Send + 'static,
+	{
+		let (sender, receiver) = mpsc::channel::<Task>();
+		let (created_sender, created_receiver) = mpsc::channel();
+
+		let thread = thread::Builder::new().name(name.to_string()).spawn(move || {
+			let mut script = match create() {
+				Ok(script) => script,
+				Err(e) => {
+					let _ = created_sender.send(Err(e));
+					return;
+				}
+			};
+			let _ = created_sender.send(Ok(()));
+
+			for task in receiver {
+				// A panicking closure fails its own run() call, but leaves the script available for subsequent ones
+				let _ = panic::catch_unwind(AssertUnwindSafe(|| task(&mut script)));
+			}
+		})?;
+
+		match created_receiver.recv() {
+			Ok(Ok(())) => {}
+			Ok(Err(e)) => return Err(e),
+			Err(_) => return Err(JsError::WorkerFailed.into()),
+		}
+
+		Ok(ScriptThread {
+			sender: Some(sender),
+			thread_id: thread.thread().id(),
+			thread: Some(thread),
+		})
+	}
+
+	/// Runs `f` with the script on its thread, blocking until it returns, and yields its result.
+	///
+	/// If `f` panics, the panic is not propagated; instead, this returns [`JsError::WorkerFailed`](enum.JsError.html#variant.WorkerFailed).
+	pub fn run<F, T>(&self, f: F) -> Result<T, AnyError>
+	where
+		F: FnOnce(&mut Script) -> T + Send + 'static,
+		T: Send + 'static,
+	{
+		let (result_sender, result_receiver) = mpsc::channel();
+		let task: Task = Box::new(move |script| {
+			let _ = result_sender.send(f(script));
+		});
+
+		let sent = self.sender.as_ref().map(|sender| sender.send(task));
+		match (sent, result_receiver.recv()) {
+			(Some(Ok(())), Ok(result)) => Ok(result),
+			_ => Err(JsError::WorkerFailed.into()),
+		}
+	}
+
+	/// ID of the thread owning the script.
+	pub fn thread_id(&self) -> ThreadId {
+		self.thread_id
+	}
+}
+
+impl Drop for ScriptThread {
+	fn drop(&mut self) {
+		// Closing the channel ends the thread's loop
+		self.sender.take();
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
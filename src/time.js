@@ -0,0 +1,24 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Clock under the host's control, see ScriptBuilder::time_steps() and deterministic().
+// Evaluates to a function installing it; `now` is called on every read of the current time, and returns it in milliseconds
+// since the Unix epoch. Dates constructed from explicit values are unaffected.
+
+(globalThis, now) => {
+	const RealDate = globalThis.Date;
+
+	function Date(...args) {
+		if (new.target === undefined) {
+			return new RealDate(now()).toString();
+		}
+		return Reflect.construct(RealDate, args.length === 0 ? [now()] : args, new.target);
+	}
+
+	// Static methods such as Date.parse() and Date.UTC() are inherited; instanceof keeps working
+	Object.setPrototypeOf(Date, RealDate);
+	Date.prototype = RealDate.prototype;
+	Date.now = now;
+
+	Object.defineProperty(RealDate.prototype, "constructor", { value: Date, writable: true, configurable: true });
+	Object.defineProperty(globalThis, "Date", { value: Date, writable: true, configurable: true });
+}
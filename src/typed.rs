@@ -0,0 +1,112 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+/// Declares a strongly typed wrapper around a [`Script`](struct.Script.html), with one Rust method per JavaScript function.
+///
+/// Each function is declared with its parameters and return type, which must implement `Serialize` and `DeserializeOwned`
+/// respectively; without `->`, the result is discarded. The generated struct offers:
+///
+/// * `bind(script)`, which takes ownership of the script and checks that every declared function is defined by it. This
+///   catches misspelled or missing functions when a plugin is loaded, instead of on the first call.
+/// * One method per function, which invokes it like [`Script::call_args()`](struct.Script.html#method.call_args) with the
+///   arguments in declaration order, and returns its result as `Result<R, AnyError>`.
+/// * `with_timeout(timeout_ms)`, which applies a timeout to all calls made through the wrapper.
+/// * `script()` and `into_script()`, to access the underlying script.
+///
+/// Only the existence of the functions can be checked in advance; arguments and results are converted on every call, as usual.
+///
+/// This is a declarative macro instead of an attribute like `#[js_api] trait Plugin { fn triple(&self, x: i32) -> i32; }`,
+/// since attribute macros must live in a separate proc-macro crate, which js-sandbox and its users would then depend on
+/// along with `syn` and `quote`. The declarations translate one to one: the trait becomes a struct owning the script, as the
+/// wrapper needs somewhere to keep it; the receiver is left out, since every method takes `&mut self` to run the script; and
+/// the declared return type `R` becomes `Result<R, AnyError>`, since any call can fail.
+///
+/// ```rust
+/// use js_sandbox::{AnyError, Script};
+///
+/// js_sandbox::js_api! {
+/// 	/// Functions every plugin must define.
+/// 	pub struct Plugin {
+/// 		fn triple(x: i32) -> i32;
+/// 		fn greet(name: &str, times: usize) -> String;
+/// 		fn reset();
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), AnyError> {
+/// 	let script = Script::from_string("
+/// 		function triple(x) { return 3 * x; }
+/// 		function greet(name, times) { return `Hello ${name}`.repeat(times); }
+/// 		function reset() {}")?;
+///
+/// 	let mut plugin = Plugin::bind(script)?.with_timeout(1000);
+/// 	assert_eq!(plugin.triple(7)?, 21);
+/// 	assert_eq!(plugin.greet("JS", 2)?, "Hello JSHello JS");
+/// 	plugin.reset()?;
+///
+/// 	assert!(Plugin::bind(Script::from_string("function triple(x) { return 3 * x; }")?).is_err());
+/// 	Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! js_api {
+	(@result) => { () };
+	(@result $result:ty) => { $result };
+
+	(
+		$(#[$attr:meta])*
+		$vis:vis struct $name:ident {
+			$(
+				$(#[$fn_attr:meta])*
+				fn $function:ident($($arg:ident: $arg_ty:ty),* $(,)?) $(-> $result:ty)?;
+			)*
+		}
+	) => {
+		$(#[$attr])*
+		$vis struct $name {
+			script: $crate::Script,
+			timeout_ms: Option<u64>,
+		}
+
+		impl $name {
+			/// Wraps `script`, failing if it does not define all the functions of this API.
+			$vis fn bind(mut script: $crate::Script) -> Result<Self, $crate::AnyError> {
+				$(
+					let kind = script.typeof_global(stringify!($function))?;
+					if kind != "function" {
+						return Err($crate::AnyError::msg(format!(
+							"cannot bind {}: JS function '{}' is not defined (found {})",
+							stringify!($name),
+							stringify!($function),
+							kind
+						)));
+					}
+				)*
+
+				Ok($name { script, timeout_ms: None })
+			}
+
+			/// Aborts calls made through this wrapper which do not complete within `timeout_ms` milliseconds.
+			$vis fn with_timeout(mut self, timeout_ms: u64) -> Self {
+				self.timeout_ms = Some(timeout_ms);
+				self
+			}
+
+			/// Returns the wrapped script, e.g. to invoke functions not declared in this API.
+			$vis fn script(&mut self) -> &mut $crate::Script {
+				&mut self.script
+			}
+
+			/// Unwraps the script.
+			$vis fn into_script(self) -> $crate::Script {
+				self.script
+			}
+
+			$(
+				$(#[$fn_attr])*
+				$vis fn $function(&mut self, $($arg: $arg_ty),*) -> Result<$crate::js_api!(@result $($result)?), $crate::AnyError> {
+					self.script.call_args(stringify!($function), &($($arg,)*), self.timeout_ms)
+				}
+			)*
+		}
+	};
+}
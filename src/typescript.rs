@@ -0,0 +1,116 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Transpilation of TypeScript to JavaScript, using swc like Deno does. Enabled with the `typescript` feature.
+// swc's code generator lays out the code anew. To keep line numbers in stack traces meaningful, the generated lines are
+// rearranged with the help of its source map, so that every statement starts on the line it had in the TypeScript source.
+
+use deno_core::error::generic_error;
+use swc_common::sync::Lrc;
+use swc_common::{BytePos, FileName, Globals, LineCol, SourceMap, GLOBALS};
+use swc_ecmascript::ast::{Module, ModuleItem};
+use swc_ecmascript::codegen::text_writer::JsWriter;
+use swc_ecmascript::codegen::{Config, Emitter};
+use swc_ecmascript::parser::error::Error;
+use swc_ecmascript::parser::{lexer::Lexer, JscTarget, Parser, StringInput, Syntax, TsConfig};
+use swc_ecmascript::transforms::{fixer, typescript};
+use swc_ecmascript::visit::FoldWith;
+
+use crate::AnyError;
+
+/// Strips the types from TypeScript code, keeping each statement on its original line.
+///
+/// Scripts are parsed as such, so that code which is only valid outside of modules (sloppy mode) is accepted; `is_module`
+/// selects module syntax, with `import` and `export` declarations.
+pub(crate) fn transpile(file_name: &str, ts_code: &str, is_module: bool) -> Result<String, AnyError> {
+	let source_map: Lrc<SourceMap> = Default::default();
+	let source_file = source_map.new_source_file(FileName::Custom(file_name.to_string()), ts_code.to_string());
+
+	let syntax = Syntax::Typescript(TsConfig { dynamic_import: true, ..TsConfig::default() });
+	let lexer = Lexer::new(syntax, JscTarget::Es2020, StringInput::from(&*source_file), None);
+	let mut parser = Parser::new_from(lexer);
+	let parse_error = |e: Error| {
+		let position = source_map.lookup_char_pos(e.span().lo);
+		generic_error(format!(
+			"cannot transpile TypeScript: {} ({}:{}:{})",
+			e.into_kind().msg(),
+			file_name,
+			position.line,
+			position.col_display + 1
+		))
+	};
+
+	// A script is transformed and emitted as a module with statements only, which yields the same code
+	let module = if is_module {
+		parser.parse_module().map_err(parse_error)?
+	} else {
+		let script = parser.parse_script().map_err(parse_error)?;
+		Module {
+			span: script.span,
+			body: script.body.into_iter().map(ModuleItem::Stmt).collect(),
+			shebang: script.shebang,
+		}
+	};
+
+	let module = GLOBALS.set(&Globals::new(), || module.fold_with(&mut typescript::strip()).fold_with(&mut fixer(None)));
+
+	let mut js_code = Vec::new();
+	let mut mappings: Vec<(BytePos, LineCol)> = Vec::new();
+	{
+		let writer = JsWriter::new(source_map.clone(), "\n", &mut js_code, Some(&mut mappings));
+		let mut emitter = Emitter {
+			cfg: Config { minify: false },
+			cm: source_map.clone(),
+			comments: None,
+			wr: Box::new(writer),
+		};
+		emitter.emit_module(&module)?;
+	}
+	let js_code = String::from_utf8(js_code)?;
+
+	// Original line (1-based) of the first token on each generated line (0-based)
+	let mut original_lines = vec![None; js_code.lines().count()];
+	for (pos, generated) in mappings {
+		if let Some(line @ None) = original_lines.get_mut(generated.line as usize) {
+			if !pos.is_dummy() {
+				*line = Some(source_map.lookup_char_pos(pos).line);
+			}
+		}
+	}
+
+	Ok(restore_lines(&js_code, &original_lines))
+}
+
+/// Moves each generated line to its original line, by inserting line breaks or joining lines.
+///
+/// swc emits semicolons and no comments, so joining lines does not change the meaning. Lines without mapping, such as the
+/// continuation of a multi-line template literal, are never joined to the previous one.
+fn restore_lines(js_code: &str, original_lines: &[Option<usize>]) -> String {
+	let mut restored = String::with_capacity(js_code.len());
+	let mut current_line = 1;
+
+	for (i, line) in js_code.lines().enumerate() {
+		if i > 0 {
+			match original_lines[i] {
+				Some(original) if original <= current_line => {
+					restored.push(' ');
+					restored.push_str(line.trim_start());
+					continue;
+				}
+				Some(original) => {
+					restored.push_str(&"\n".repeat(original - current_line));
+					current_line = original;
+				}
+				None => {
+					restored.push('\n');
+					current_line += 1;
+				}
+			}
+		} else if let Some(original) = original_lines[0] {
+			restored.push_str(&"\n".repeat(original - 1));
+			current_line = original;
+		}
+		restored.push_str(line);
+	}
+
+	restored
+}
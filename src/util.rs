@@ -7,13 +7,11 @@ use crate::{AnyError, JsValue, Script};
 /// If there is an error, Err will be returned.
 /// This function is primarily useful for small standalone experiments. Usually, you would want to use the [`Script`](struct.Script.html) struct
 /// for more sophisticated Rust->JS interaction.
+///
+/// The expression is evaluated inside a fresh, empty script. Globals, `console` output, value conversions and errors are therefore
+/// exactly the same as for a function called with [`Script::call()`](struct.Script.html#method.call). Positions in syntax and
+/// runtime errors refer to the expression itself, which is reported as file "eval_json" starting at line 1.
 pub fn eval_json(js_expr: &str) -> Result<JsValue, AnyError> {
-	let code = format!("
-		function __rust_expr() {{
-			return ({expr});
-		}}
-	", expr = js_expr);
-
-	let mut script = Script::from_string(&code)?;
-	script.call_json("__rust_expr", &JsValue::Null, None)
+	let mut script = Script::new_runtime()?;
+	script.eval_json(js_expr)
 }
@@ -0,0 +1,106 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// WebAssembly limits and pre-registered modules, see ScriptBuilder::wasm() and ScriptBuilder::add_wasm_module().
+// Every way of creating a module from bytes or a memory from a descriptor is wrapped, so the host checks it first. Instances
+// need no check: their module was checked when it was created, and imported memories when they were constructed.
+
+((globalThis, core, config) => {
+	const wasm = globalThis.WebAssembly;
+	const { Module, Memory } = wasm;
+
+	function toBytes(source) {
+		if (source instanceof ArrayBuffer) {
+			return new Uint8Array(source);
+		}
+		if (ArrayBuffer.isView(source)) {
+			return new Uint8Array(source.buffer, source.byteOffset, source.byteLength);
+		}
+		throw new TypeError("WebAssembly expects the module as ArrayBuffer or ArrayBufferView");
+	}
+
+	// Replaces `original` by `wrapper`, such that existing objects and instanceof checks are unaffected
+	function replaceConstructor(name, original, wrapper) {
+		Object.defineProperty(wrapper, "name", { value: name });
+		for (const key of Object.getOwnPropertyNames(original)) {
+			if (!["length", "name", "prototype", "caller", "arguments"].includes(key)) {
+				Object.defineProperty(wrapper, key, Object.getOwnPropertyDescriptor(original, key));
+			}
+		}
+		wrapper.prototype = original.prototype;
+		Object.defineProperty(original.prototype, "constructor", { value: wrapper, writable: true, configurable: true });
+		Object.defineProperty(wasm, name, { value: wrapper, writable: true, configurable: true });
+	}
+
+	if (config.maxMemoryPages !== null) {
+		const { compile, instantiate } = wasm;
+
+		// Returns a copy of the checked bytes, which the script cannot modify before V8 compiles them
+		function checked(source) {
+			const bytes = toBytes(source).slice();
+			core.ops();
+			core.opSync("__rust_wasm_check", null, bytes);
+			return bytes;
+		}
+
+		replaceConstructor("Module", Module, function CheckedModule(source) {
+			if (new.target === undefined) {
+				throw new TypeError("WebAssembly.Module must be invoked with 'new'");
+			}
+			return Reflect.construct(Module, [checked(source)], new.target);
+		});
+
+		replaceConstructor("Memory", Memory, function CheckedMemory(descriptor) {
+			if (new.target === undefined) {
+				throw new TypeError("WebAssembly.Memory must be invoked with 'new'");
+			}
+			const { initial, maximum, shared } = descriptor ?? {};
+			if (maximum === undefined) {
+				throw new RangeError("WebAssembly.Memory must declare a maximum size");
+			}
+			const checkedDescriptor = { initial: Number(initial), maximum: Number(maximum), shared: Boolean(shared) };
+			if (checkedDescriptor.maximum > config.maxMemoryPages) {
+				throw new RangeError(
+					`WebAssembly.Memory of up to ${checkedDescriptor.maximum} pages exceeds the limit of ${config.maxMemoryPages} pages`
+				);
+			}
+			return Reflect.construct(Memory, [checkedDescriptor], new.target);
+		});
+
+		wasm.compile = {
+			async compile(source) {
+				return compile.call(wasm, checked(source));
+			},
+		}.compile;
+
+		wasm.instantiate = {
+			async instantiate(source, imports) {
+				const module = source instanceof Module ? source : checked(source);
+				return instantiate.call(wasm, module, imports);
+			},
+		}.instantiate;
+
+		// Streaming variants take a Response, whose bytes would bypass the check
+		delete wasm.compileStreaming;
+		delete wasm.instantiateStreaming;
+	}
+
+	if (config.namespace !== null) {
+		const compiled = new Map();
+
+		function wasmModule(name) {
+			name = String(name);
+			if (!compiled.has(name)) {
+				core.ops();
+				const length = core.opSync("__rust_wasm_module", name);
+				const bytes = new Uint8Array(length);
+				core.opSync("__rust_wasm_take", null, bytes);
+
+				// The host's modules are trusted, and bypass the limits for the script's own modules
+				compiled.set(name, new Module(bytes));
+			}
+			return compiled.get(name);
+		}
+
+		__rust.defineHost(config.namespace, "wasmModule", wasmModule);
+	}
+})
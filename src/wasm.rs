@@ -0,0 +1,205 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// WebAssembly for scripts, see ScriptBuilder::wasm() and ScriptBuilder::add_wasm_module().
+// V8 compiles and runs the modules itself. wasm.js routes every way in which a script can create a module or a memory through
+// a check of its size and declared memory limits, for which the binary is scanned here. Pre-registered modules are staged in
+// the op state by name, from where a second op copies them into a Uint8Array.
+
+use std::collections::HashMap;
+
+use deno_core::error::generic_error;
+use deno_core::{JsRuntime, OpState, ZeroCopyBuf};
+use serde::Serialize;
+
+use crate::{AnyError, JsValue};
+
+/// Size of a WebAssembly memory page in bytes.
+const PAGE_BYTES: usize = 64 * 1024;
+
+/// Restricts the WebAssembly modules which a script can compile itself; see
+/// [`ScriptBuilder::wasm()`](struct.ScriptBuilder.html#method.wasm).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmOptions {
+	/// Maximum size of a module's binary in bytes; larger modules are rejected before they are compiled. 8 MiB by default.
+	pub max_module_bytes: usize,
+	/// Maximum size of a single linear memory in bytes, rounded down to whole 64 KiB pages. Memories must declare a maximum
+	/// size within this limit, so that they cannot grow beyond it later. 64 MiB by default.
+	pub max_memory_bytes: usize,
+}
+
+impl Default for WasmOptions {
+	fn default() -> Self {
+		WasmOptions {
+			max_module_bytes: 8 << 20,
+			max_memory_bytes: 64 << 20,
+		}
+	}
+}
+
+/// Modules registered by the host, by name.
+struct WasmModules(HashMap<String, Vec<u8>>);
+
+/// Binary of the module requested last, until JS takes it.
+struct ModuleBytes(Vec<u8>);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WasmConfig<'a> {
+	/// Maximum pages per memory, if the script's modules are checked
+	max_memory_pages: Option<usize>,
+	/// Host namespace receiving `wasmModule()`, if modules are registered
+	namespace: Option<&'a str>,
+}
+
+/// Registers the ops behind the checks and `host.wasmModule()`, and installs them.
+pub(crate) fn install(
+	runtime: &mut JsRuntime,
+	options: Option<WasmOptions>,
+	modules: HashMap<String, Vec<u8>>,
+	namespace: &str,
+) -> Result<(), AnyError> {
+	let config = WasmConfig {
+		max_memory_pages: options.as_ref().map(|options| options.max_memory_bytes / PAGE_BYTES),
+		namespace: if modules.is_empty() { None } else { Some(namespace) },
+	};
+	let config = serde_json::to_string(&config)?;
+
+	// Each op is only available together with the state it needs
+	if let Some(options) = options {
+		runtime.register_op("__rust_wasm_check", deno_core::op_sync(op_wasm_check));
+		runtime.op_state().borrow_mut().put(options);
+	}
+	if !modules.is_empty() {
+		runtime.register_op("__rust_wasm_module", deno_core::op_sync(op_wasm_module));
+		runtime.register_op("__rust_wasm_take", deno_core::op_sync(op_wasm_take));
+		runtime.op_state().borrow_mut().put(WasmModules(modules));
+	}
+
+	let js_code = format!("{}(globalThis, Deno.core, {});", include_str!("wasm.js").trim_end(), config);
+	runtime.execute("js-sandbox:wasm.js", &js_code)
+}
+
+/// Checks a module binary passed by the script against the limits, before V8 compiles it.
+fn op_wasm_check(state: &mut OpState, _args: (), buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let options = state
+		.try_borrow::<WasmOptions>()
+		.ok_or_else(|| generic_error("WebAssembly limits are not configured"))?;
+	let bytes = buf.as_deref().unwrap_or_default();
+
+	if bytes.len() > options.max_module_bytes {
+		return Err(generic_error(format!(
+			"WebAssembly module of {} bytes exceeds the limit of {} bytes",
+			bytes.len(),
+			options.max_module_bytes
+		)));
+	}
+
+	// A binary which cannot be scanned is rejected, even though V8 would do so as well
+	let memories = defined_memories(bytes).ok_or_else(|| generic_error("WebAssembly module is malformed"))?;
+	let max_pages = (options.max_memory_bytes / PAGE_BYTES) as u64;
+	for (initial, maximum) in memories {
+		match maximum {
+			None => return Err(generic_error("WebAssembly module defines a memory without a maximum size")),
+			Some(maximum) if maximum.max(initial) > max_pages => {
+				return Err(generic_error(format!(
+					"WebAssembly module defines a memory of up to {} pages, which exceeds the limit of {} pages ({} bytes)",
+					maximum.max(initial),
+					max_pages,
+					options.max_memory_bytes
+				)))
+			}
+			Some(_) => {}
+		}
+	}
+
+	Ok(JsValue::Null)
+}
+
+/// Stages the module registered as `name`, and returns its size.
+fn op_wasm_module(state: &mut OpState, name: String, _buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let bytes = state
+		.borrow::<WasmModules>()
+		.0
+		.get(&name)
+		.cloned()
+		.ok_or_else(|| generic_error(format!("no WebAssembly module is registered as '{}'", name)))?;
+
+	let length = bytes.len();
+	state.put(ModuleBytes(bytes));
+	Ok(length.into())
+}
+
+/// Copies the module staged last into the `Uint8Array` allocated for it by JS.
+fn op_wasm_take(state: &mut OpState, _args: (), buf: Option<ZeroCopyBuf>) -> Result<JsValue, AnyError> {
+	let ModuleBytes(bytes) = state
+		.try_take::<ModuleBytes>()
+		.ok_or_else(|| generic_error("module binary is no longer available"))?;
+	if bytes.is_empty() {
+		return Ok(JsValue::Null);
+	}
+
+	let mut buf = buf.ok_or_else(|| generic_error("no buffer to receive module binary"))?;
+	if buf.len() != bytes.len() {
+		return Err(generic_error("buffer size does not match module binary"));
+	}
+	buf.copy_from_slice(&bytes);
+
+	Ok(JsValue::Null)
+}
+
+/// Returns the initial and maximum page counts of the memories defined (not imported) by a module; `None` if malformed.
+fn defined_memories(bytes: &[u8]) -> Option<Vec<(u64, Option<u64>)>> {
+	let mut reader = Reader { bytes, pos: 0 };
+	if reader.take(8)? != b"\0asm\x01\0\0\0" {
+		return None;
+	}
+
+	let mut memories = Vec::new();
+	while reader.pos < bytes.len() {
+		let id = reader.byte()?;
+		let size = reader.leb()? as usize;
+		let section = reader.take(size)?;
+
+		// Memory section
+		if id == 5 {
+			let mut section = Reader { bytes: section, pos: 0 };
+			for _ in 0..section.leb()? {
+				let flags = section.leb()?;
+				let initial = section.leb()?;
+				let maximum = if flags & 1 != 0 { Some(section.leb()?) } else { None };
+				memories.push((initial, maximum));
+			}
+		}
+	}
+	Some(memories)
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+		let slice = self.bytes.get(self.pos..self.pos.checked_add(count)?)?;
+		self.pos += count;
+		Some(slice)
+	}
+
+	fn byte(&mut self) -> Option<u8> {
+		self.take(1).map(|slice| slice[0])
+	}
+
+	/// Unsigned LEB128, as used for all sizes and limits
+	fn leb(&mut self) -> Option<u64> {
+		let mut value = 0u64;
+		for shift in (0..64).step_by(7) {
+			let byte = self.byte()?;
+			value |= u64::from(byte & 0x7f) << shift;
+			if byte & 0x80 == 0 {
+				return Some(value);
+			}
+		}
+		None
+	}
+}
@@ -0,0 +1,627 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Web platform globals for scripts, see ScriptBuilder::web_globals().
+// All of them are pure computation and never reach the host. URL parsing follows the WHATWG URL standard for the common cases
+// (special schemes, relative references, default ports), but not in every corner, e.g. IDNA host names.
+
+((globalThis, core) => {
+	function namedError(name, message) {
+		const error = new Error(message);
+		error.name = name;
+		return error;
+	}
+
+	function toBytes(input, what) {
+		if (input === undefined) {
+			return new Uint8Array(0);
+		}
+		if (input instanceof ArrayBuffer) {
+			return new Uint8Array(input);
+		}
+		if (ArrayBuffer.isView(input)) {
+			return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+		}
+		throw new TypeError(`${what} expects an ArrayBuffer or ArrayBufferView`);
+	}
+
+	// Length of a UTF-8 sequence starting with `byte`, or 0 if it cannot start one
+	function sequenceLength(byte) {
+		return byte < 0x80 ? 1 : byte >= 0xc2 && byte < 0xe0 ? 2 : byte >= 0xe0 && byte < 0xf0 ? 3 : byte >= 0xf0 && byte < 0xf5 ? 4 : 0;
+	}
+
+	function isValidUtf8(bytes) {
+		for (let i = 0; i < bytes.length; ) {
+			const length = sequenceLength(bytes[i]);
+			if (length === 0 || i + length > bytes.length) {
+				return false;
+			}
+			let codePoint = length === 1 ? bytes[i] : bytes[i] & (0xff >> (length + 1));
+			for (let k = 1; k < length; ++k) {
+				if ((bytes[i + k] & 0xc0) !== 0x80) {
+					return false;
+				}
+				codePoint = (codePoint << 6) | (bytes[i + k] & 0x3f);
+			}
+			const minimum = [0, 0, 0x80, 0x800, 0x10000][length];
+			if (codePoint < minimum || codePoint > 0x10ffff || (codePoint >= 0xd800 && codePoint < 0xe000)) {
+				return false;
+			}
+			i += length;
+		}
+		return true;
+	}
+
+	class TextEncoder {
+		get encoding() {
+			return "utf-8";
+		}
+
+		encode(input = "") {
+			return core.encode(String(input));
+		}
+
+		// Writes as many whole characters as fit into `destination`
+		encodeInto(source, destination) {
+			let read = 0;
+			let written = 0;
+			for (const char of String(source)) {
+				const bytes = core.encode(char);
+				if (written + bytes.length > destination.length) {
+					break;
+				}
+				destination.set(bytes, written);
+				read += char.length;
+				written += bytes.length;
+			}
+			return { read, written };
+		}
+	}
+
+	class TextDecoder {
+		#fatal;
+		#ignoreBOM;
+
+		constructor(label = "utf-8", options = {}) {
+			if (!["utf-8", "utf8", "unicode-1-1-utf-8"].includes(String(label).trim().toLowerCase())) {
+				throw new RangeError(`the encoding '${label}' is not supported, only UTF-8 is`);
+			}
+			this.#fatal = Boolean(options.fatal);
+			this.#ignoreBOM = Boolean(options.ignoreBOM);
+		}
+
+		get encoding() {
+			return "utf-8";
+		}
+		get fatal() {
+			return this.#fatal;
+		}
+		get ignoreBOM() {
+			return this.#ignoreBOM;
+		}
+
+		decode(input, options = {}) {
+			if (options.stream) {
+				throw new TypeError("streaming decode is not supported");
+			}
+			let bytes = toBytes(input, "TextDecoder.decode()");
+			if (!this.#ignoreBOM && bytes[0] === 0xef && bytes[1] === 0xbb && bytes[2] === 0xbf) {
+				bytes = bytes.subarray(3);
+			}
+			if (this.#fatal && !isValidUtf8(bytes)) {
+				throw new TypeError("the encoded data is not valid UTF-8");
+			}
+			return bytes.length === 0 ? "" : core.decode(bytes);
+		}
+	}
+
+	const BASE64 = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+	function btoa(data) {
+		const text = String(data);
+		let result = "";
+		for (let i = 0; i < text.length; i += 3) {
+			const codes = [text.charCodeAt(i), text.charCodeAt(i + 1), text.charCodeAt(i + 2)];
+			if (codes.some(code => code > 0xff)) {
+				throw namedError("InvalidCharacterError", "btoa() only accepts characters in the Latin1 range");
+			}
+			const [a, b = 0, c = 0] = codes.filter(code => !Number.isNaN(code));
+			const bits = (a << 16) | (b << 8) | c;
+			result += BASE64[bits >> 18] + BASE64[(bits >> 12) & 63];
+			result += i + 1 < text.length ? BASE64[(bits >> 6) & 63] : "=";
+			result += i + 2 < text.length ? BASE64[bits & 63] : "=";
+		}
+		return result;
+	}
+
+	function atob(data) {
+		let text = String(data).replace(/[\t\n\f\r ]/g, "");
+		if (text.length % 4 === 0) {
+			text = text.replace(/==?$/, "");
+		}
+		if (text.length % 4 === 1 || /[^A-Za-z0-9+/]/.test(text)) {
+			throw namedError("InvalidCharacterError", "atob() received a string that is not valid base64");
+		}
+
+		let result = "";
+		let bits = 0;
+		let count = 0;
+		for (const char of text) {
+			bits = (bits << 6) | BASE64.indexOf(char);
+			count += 6;
+			if (count >= 8) {
+				count -= 8;
+				result += String.fromCharCode((bits >> count) & 0xff);
+			}
+		}
+		return result;
+	}
+
+	function structuredClone(value) {
+		const clones = new Map();
+
+		function clone(value) {
+			if (typeof value === "symbol" || typeof value === "function") {
+				throw namedError("DataCloneError", `${typeof value} values cannot be cloned`);
+			}
+			if (value === null || typeof value !== "object") {
+				return value;
+			}
+			if (clones.has(value)) {
+				return clones.get(value);
+			}
+
+			const remember = copy => (clones.set(value, copy), copy);
+			const tag = Object.prototype.toString.call(value);
+			switch (tag) {
+				case "[object Boolean]":
+				case "[object Number]":
+				case "[object String]":
+				case "[object BigInt]":
+					return remember(Object(value.valueOf()));
+				case "[object Date]":
+					return remember(new Date(value.getTime()));
+				case "[object RegExp]":
+					return remember(new RegExp(value.source, value.flags));
+				case "[object ArrayBuffer]":
+					return remember(value.slice(0));
+				case "[object DataView]":
+					return remember(new DataView(clone(value.buffer), value.byteOffset, value.byteLength));
+				case "[object Map]": {
+					const copy = remember(new Map());
+					for (const [key, entry] of value) {
+						copy.set(clone(key), clone(entry));
+					}
+					return copy;
+				}
+				case "[object Set]": {
+					const copy = remember(new Set());
+					for (const entry of value) {
+						copy.add(clone(entry));
+					}
+					return copy;
+				}
+				case "[object Error]": {
+					const copy = remember(new Error(value.message));
+					copy.name = value.name;
+					copy.stack = value.stack;
+					return copy;
+				}
+			}
+			if (ArrayBuffer.isView(value)) {
+				const copy = new value.constructor(clone(value.buffer), value.byteOffset, value.length);
+				return remember(copy);
+			}
+			if (tag !== "[object Object]" && tag !== "[object Array]") {
+				throw namedError("DataCloneError", `${tag.slice(8, -1)} objects cannot be cloned`);
+			}
+
+			const copy = remember(Array.isArray(value) ? new Array(value.length) : {});
+			for (const key of Object.keys(value)) {
+				copy[key] = clone(value[key]);
+			}
+			return copy;
+		}
+
+		return clone(value);
+	}
+
+	// application/x-www-form-urlencoded, as used by URLSearchParams
+	function formEncode(text) {
+		return encodeURIComponent(text)
+			.replace(/[!'()~]/g, char => "%" + char.charCodeAt(0).toString(16).toUpperCase())
+			.replace(/%20/g, "+");
+	}
+
+	function percentDecode(text) {
+		try {
+			return decodeURIComponent(text);
+		} catch {
+			return text;
+		}
+	}
+
+	function formDecode(text) {
+		return percentDecode(text.replace(/\+/g, " "));
+	}
+
+	class URLSearchParams {
+		#entries = [];
+		#onChange = null;
+
+		constructor(init = "") {
+			if (init instanceof URLSearchParams) {
+				this.#entries = [...init.#entries];
+			} else if (typeof init === "object" && init !== null && typeof init[Symbol.iterator] === "function") {
+				for (const pair of init) {
+					const [name, value, ...rest] = pair;
+					if (pair.length !== 2 || rest.length !== 0) {
+						throw new TypeError("each URLSearchParams pair must consist of a name and a value");
+					}
+					this.#entries.push([String(name), String(value)]);
+				}
+			} else if (typeof init === "object" && init !== null) {
+				this.#entries = Object.keys(init).map(name => [name, String(init[name])]);
+			} else {
+				this.#parse(String(init));
+			}
+		}
+
+		#parse(query) {
+			this.#entries = [];
+			for (const part of query.replace(/^\?/, "").split("&")) {
+				if (part !== "") {
+					const split = part.indexOf("=");
+					const name = split === -1 ? part : part.slice(0, split);
+					const value = split === -1 ? "" : part.slice(split + 1);
+					this.#entries.push([formDecode(name), formDecode(value)]);
+				}
+			}
+		}
+
+		// Links the parameters to a URL, whose query is updated on every change
+		static link(params, query, onChange) {
+			params.#parse(query);
+			params.#onChange = onChange;
+		}
+
+		#changed() {
+			this.#onChange?.(this.toString());
+		}
+
+		get size() {
+			return this.#entries.length;
+		}
+
+		append(name, value) {
+			this.#entries.push([String(name), String(value)]);
+			this.#changed();
+		}
+		delete(name) {
+			this.#entries = this.#entries.filter(([key]) => key !== String(name));
+			this.#changed();
+		}
+		get(name) {
+			const entry = this.#entries.find(([key]) => key === String(name));
+			return entry === undefined ? null : entry[1];
+		}
+		getAll(name) {
+			return this.#entries.filter(([key]) => key === String(name)).map(([, value]) => value);
+		}
+		has(name) {
+			return this.#entries.some(([key]) => key === String(name));
+		}
+		set(name, value) {
+			const index = this.#entries.findIndex(([key]) => key === String(name));
+			if (index === -1) {
+				this.#entries.push([String(name), String(value)]);
+			} else {
+				this.#entries[index] = [String(name), String(value)];
+				this.#entries = this.#entries.filter(([key], i) => i <= index || key !== String(name));
+			}
+			this.#changed();
+		}
+		sort() {
+			// Array.prototype.sort() is stable, as the standard requires
+			this.#entries.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+			this.#changed();
+		}
+		forEach(callback, thisArg) {
+			for (const [name, value] of this.#entries) {
+				callback.call(thisArg, value, name, this);
+			}
+		}
+		entries() {
+			return this.#entries.map(entry => [...entry])[Symbol.iterator]();
+		}
+		keys() {
+			return this.#entries.map(([name]) => name)[Symbol.iterator]();
+		}
+		values() {
+			return this.#entries.map(([, value]) => value)[Symbol.iterator]();
+		}
+		[Symbol.iterator]() {
+			return this.entries();
+		}
+		toString() {
+			return this.#entries.map(([name, value]) => `${formEncode(name)}=${formEncode(value)}`).join("&");
+		}
+	}
+
+	const DEFAULT_PORTS = { "http:": "80", "https:": "443", "ws:": "80", "wss:": "443", "ftp:": "21", "file:": "" };
+	const isSpecial = protocol => Object.prototype.hasOwnProperty.call(DEFAULT_PORTS, protocol);
+
+	// Percent-encodes characters which may not appear literally in the given URL component
+	function encodeComponent(text, extra) {
+		let result = "";
+		for (const char of text) {
+			const code = char.codePointAt(0);
+			if (code > 0x20 && code < 0x7f && !extra.includes(char)) {
+				result += char;
+			} else {
+				try {
+					result += encodeURIComponent(char);
+				} catch {
+					result += "%EF%BF%BD";
+				}
+			}
+		}
+		return result;
+	}
+
+	function removeDotSegments(path) {
+		const output = [];
+		const segments = path.split("/");
+		for (let i = 0; i < segments.length; ++i) {
+			const segment = segments[i];
+			const isLast = i === segments.length - 1;
+			if (segment === "." || segment.toLowerCase() === "%2e") {
+				if (isLast) {
+					output.push("");
+				}
+			} else if (segment === ".." || /^(\.|%2e){2}$/i.test(segment)) {
+				if (output.length > 1) {
+					output.pop();
+				}
+				if (isLast) {
+					output.push("");
+				}
+			} else {
+				output.push(segment);
+			}
+		}
+		return output.join("/");
+	}
+
+	// Splits `input` into its components; `base` resolves relative references
+	function parseUrl(input, base) {
+		const text = String(input).replace(/^[\u0000- ]+|[\u0000- ]+$/g, "").replace(/[\t\n\r]/g, "");
+		const schemeMatch = /^([a-zA-Z][a-zA-Z0-9+\-.]*):(.*)$/s.exec(text);
+
+		let protocol;
+		let rest;
+		if (schemeMatch) {
+			protocol = schemeMatch[1].toLowerCase() + ":";
+			rest = schemeMatch[2];
+			// A special URL with the base's scheme and no slashes is relative to the base, e.g. "http:path"
+			if (base && base.protocol === protocol && isSpecial(protocol) && !/^[/\\]/.test(rest)) {
+				return resolveRelative(rest, base);
+			}
+		} else if (base) {
+			return resolveRelative(text, base);
+		} else {
+			return null;
+		}
+
+		const special = isSpecial(protocol);
+		if (special) {
+			rest = rest.replace(/\\/g, "/");
+		}
+
+		const url = { protocol, username: "", password: "", hostname: "", port: "", pathname: "", search: "", hash: "", opaque: false };
+		rest = splitQueryAndFragment(rest, url);
+
+		if (rest.startsWith("//") || (special && protocol !== "file:")) {
+			rest = rest.replace(/^\/*/, "");
+			const end = rest.search(/\//);
+			const authority = end === -1 ? rest : rest.slice(0, end);
+			if (!parseAuthority(authority, url, special)) {
+				return null;
+			}
+			rest = end === -1 ? "" : rest.slice(end);
+		}
+
+		if (special) {
+			url.pathname = removeDotSegments(encodeComponent(rest, ' "<>`#?{}') || "/");
+			if (!url.pathname.startsWith("/")) {
+				url.pathname = "/" + url.pathname;
+			}
+		} else if (rest.startsWith("/") || url.hostname !== "") {
+			url.pathname = removeDotSegments(encodeComponent(rest, ' "<>`#?{}'));
+		} else {
+			url.opaque = true;
+			url.pathname = encodeComponent(rest, "");
+		}
+		return url;
+	}
+
+	function splitQueryAndFragment(rest, url) {
+		const hashStart = rest.indexOf("#");
+		if (hashStart !== -1) {
+			url.hash = "#" + encodeComponent(rest.slice(hashStart + 1), ' "<>`');
+			rest = rest.slice(0, hashStart);
+		}
+		const queryStart = rest.indexOf("?");
+		if (queryStart !== -1) {
+			url.search = "?" + encodeComponent(rest.slice(queryStart + 1), ' "<>#');
+			rest = rest.slice(0, queryStart);
+		}
+		if (url.search === "?") {
+			url.search = "";
+		}
+		if (url.hash === "#") {
+			url.hash = "";
+		}
+		return rest;
+	}
+
+	function parseAuthority(authority, url, special) {
+		const at = authority.lastIndexOf("@");
+		if (at !== -1) {
+			const credentials = authority.slice(0, at);
+			const colon = credentials.indexOf(":");
+			url.username = encodeComponent(colon === -1 ? credentials : credentials.slice(0, colon), ' "<>`#?{}/:;=@[\\]^|');
+			url.password = colon === -1 ? "" : encodeComponent(credentials.slice(colon + 1), ' "<>`#?{}/:;=@[\\]^|');
+			authority = authority.slice(at + 1);
+		}
+
+		const hostMatch = /^(\[[0-9a-fA-F:.]+\]|[^:]*)(?::(\d*))?$/.exec(authority);
+		if (!hostMatch) {
+			return false;
+		}
+		const hostname = percentDecode(hostMatch[1]);
+		if (/[\u0000- #%/:<>?@[\\\]^|]/.test(hostname.replace(/^\[.*\]$/, "")) || (special && hostname === "" && url.protocol !== "file:")) {
+			return false;
+		}
+		url.hostname = special ? hostname.toLowerCase() : hostname;
+
+		const port = hostMatch[2] ?? "";
+		if (port !== "") {
+			const number = Number(port);
+			if (number > 65535) {
+				return false;
+			}
+			url.port = String(number) === DEFAULT_PORTS[url.protocol] ? "" : String(number);
+		}
+		return true;
+	}
+
+	function resolveRelative(reference, base) {
+		if (base.opaque) {
+			return reference.startsWith("#") ? { ...base, hash: parseUrl("a:" + reference).hash } : null;
+		}
+
+		const url = { ...base, search: "", hash: "" };
+		const special = isSpecial(base.protocol);
+		if (special) {
+			reference = reference.replace(/\\/g, "/");
+		}
+
+		if (reference.startsWith("//")) {
+			return parseUrl(base.protocol + reference);
+		}
+		const path = splitQueryAndFragment(reference, url);
+		if (path === "") {
+			url.pathname = base.pathname;
+			if (reference.startsWith("#")) {
+				url.search = base.search;
+			}
+		} else if (path.startsWith("/")) {
+			url.pathname = removeDotSegments(encodeComponent(path, ' "<>`#?{}'));
+		} else {
+			const directory = base.pathname.slice(0, base.pathname.lastIndexOf("/") + 1);
+			url.pathname = removeDotSegments(encodeComponent(directory + path, ' "<>`#?{}'));
+		}
+		return url;
+	}
+
+	class URL {
+		#url;
+		#searchParams = new URLSearchParams();
+
+		constructor(url, base = undefined) {
+			let parsedBase;
+			if (base !== undefined) {
+				parsedBase = parseUrl(String(base));
+				if (parsedBase === null) {
+					throw new TypeError(`Invalid base URL: ${base}`);
+				}
+			}
+			const parsed = parseUrl(url, parsedBase);
+			if (parsed === null) {
+				throw new TypeError(`Invalid URL: ${url}`);
+			}
+			this.#url = parsed;
+			this.#linkSearchParams();
+		}
+
+		static canParse(url, base = undefined) {
+			try {
+				new URL(url, base);
+				return true;
+			} catch {
+				return false;
+			}
+		}
+
+		#linkSearchParams() {
+			URLSearchParams.link(this.#searchParams, this.#url.search, query => {
+				this.#url.search = query === "" ? "" : "?" + query;
+			});
+		}
+
+		get href() {
+			const { protocol, username, password, pathname, search, hash, opaque } = this.#url;
+			if (opaque) {
+				return protocol + pathname + search + hash;
+			}
+			const credentials = username || password ? `${username}${password ? ":" + password : ""}@` : "";
+			const hasAuthority = isSpecial(protocol) || this.#url.hostname !== "" || credentials !== "";
+			return protocol + (hasAuthority ? "//" + credentials + this.host : "") + pathname + search + hash;
+		}
+		get origin() {
+			const { protocol } = this.#url;
+			return isSpecial(protocol) && protocol !== "file:" ? `${protocol}//${this.host}` : "null";
+		}
+		get protocol() {
+			return this.#url.protocol;
+		}
+		get username() {
+			return this.#url.username;
+		}
+		get password() {
+			return this.#url.password;
+		}
+		get host() {
+			return this.#url.hostname + (this.#url.port === "" ? "" : ":" + this.#url.port);
+		}
+		get hostname() {
+			return this.#url.hostname;
+		}
+		get port() {
+			return this.#url.port;
+		}
+		get pathname() {
+			return this.#url.pathname;
+		}
+		get search() {
+			return this.#url.search;
+		}
+		set search(value) {
+			const search = String(value).replace(/^\?/, "");
+			this.#url.search = search === "" ? "" : "?" + encodeComponent(search, ' "<>#');
+			this.#linkSearchParams();
+		}
+		get searchParams() {
+			return this.#searchParams;
+		}
+		get hash() {
+			return this.#url.hash;
+		}
+		set hash(value) {
+			const hash = String(value).replace(/^#/, "");
+			this.#url.hash = hash === "" ? "" : "#" + encodeComponent(hash, ' "<>`');
+		}
+
+		toString() {
+			return this.href;
+		}
+		toJSON() {
+			return this.href;
+		}
+	}
+
+	const globals = { TextEncoder, TextDecoder, URL, URLSearchParams, atob, btoa, structuredClone };
+	for (const [name, value] of Object.entries(globals)) {
+		Object.defineProperty(globalThis, name, { value, writable: true, configurable: true });
+	}
+})
@@ -0,0 +1,3 @@
+export function start() {
+	import("./lib/busy.js");
+}
@@ -0,0 +1,2 @@
+// Never finishes evaluating, see drain_async_timeout in test_async.rs
+for (;;) {}
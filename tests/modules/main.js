@@ -0,0 +1,12 @@
+import { add } from "./lib/math.js";
+import greeting from "./lib/text.js";
+
+const offset = await Promise.resolve(100);
+
+export function compute(a) {
+	return add(a, offset);
+}
+
+export function greet(name) {
+	return greeting + " " + name;
+}
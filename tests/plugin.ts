@@ -0,0 +1,12 @@
+interface Point {
+	x: number;
+	y: number;
+}
+
+function length(p: Point): number {
+	return Math.sqrt(p.x * p.x + p.y * p.y);
+}
+
+function fail(message: string): never {
+	throw new Error(message);
+}
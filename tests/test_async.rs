@@ -0,0 +1,278 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::time::Duration;
+
+use deno_core::futures::executor::block_on;
+use js_sandbox::{AnyError, JsError, Script};
+
+#[test]
+fn call_async() -> Result<(), AnyError> {
+	let js_code = "async function double(a) { await null; return 2 * a; }
+		function sync(a) { return a + 1; }
+		async function fail() { await null; throw new Error('rejected'); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: i32 = script.call_async("double", &21, None, None)?;
+	assert_eq!(result, 42);
+
+	let result: i32 = script.call_async("sync", &1, None, None)?;
+	assert_eq!(result, 2);
+
+	let result: Result<(), AnyError> = script.call_async("fail", &(), None, None);
+	assert!(result.is_err(), "Rejected promise fails the call");
+	Ok(())
+}
+
+#[test]
+fn call_future() -> Result<(), AnyError> {
+	let js_code = "async function double(a) { await null; return 2 * a; }
+		async function fail() { await null; throw new Error('rejected'); }
+		function pending() { return new Promise(() => {}); }
+		async function slow() { await null; for(;;){} }";
+	let mut script = Script::from_string(js_code)?;
+
+	let future = script.call_future("double", &21, None);
+	let result: i32 = block_on(future)?;
+	assert_eq!(result, 42);
+
+	let result: Result<(), AnyError> = block_on(script.call_future("fail", &(), None));
+	assert!(result.unwrap_err().to_string().contains("rejected"));
+
+	let result: Result<(), AnyError> = block_on(script.call_future("pending", &(), None));
+	assert!(result.unwrap_err().to_string().contains("never settled"));
+
+	let result: Result<(), AnyError> = block_on(script.call_future("slow", &(), Some(100)));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::TotalTimeout));
+	Ok(())
+}
+
+#[test]
+fn call_async_never_settles() -> Result<(), AnyError> {
+	let js_code = "function pending() { return new Promise(() => {}); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: Result<(), AnyError> = script.call_async("pending", &(), None, None);
+	assert!(result.is_err(), "Call fails if the promise can never settle");
+	Ok(())
+}
+
+#[test]
+fn call_async_budgets() -> Result<(), AnyError> {
+	let js_code = "function slow_dispatch() { for(;;){} }
+		async function slow_settle() { await null; for(;;){} }
+		async function quick(a) { await null; return a; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: Result<(), AnyError> = script.call_async("slow_dispatch", &(), Some(50), Some(2000));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::SyncTimeout));
+
+	let result: Result<(), AnyError> = script.call_async("slow_settle", &(), Some(50), Some(200));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::TotalTimeout));
+
+	let result: i32 = script.call_async("quick", &7, Some(50), Some(200))?;
+	assert_eq!(result, 7);
+	Ok(())
+}
+
+#[test]
+fn is_function_async() -> Result<(), AnyError> {
+	let js_code = "async function fetch_data() {} function compute() {} function promised() { return Promise.resolve(1); }
+		const arrow = async () => 1; let data = 3;";
+	let mut script = Script::from_string(js_code)?;
+
+	assert!(script.is_function_async("fetch_data")?);
+	assert!(script.is_function_async("arrow")?);
+	assert!(!script.is_function_async("compute")?);
+	assert!(!script.is_function_async("promised")?, "Only the async keyword is detected");
+	assert!(script.is_function_async("data").is_err(), "Not a function");
+	Ok(())
+}
+
+#[test]
+fn eval_async() -> Result<(), AnyError> {
+	let mut script = Script::from_string("function triple(a) { return 3 * a; }")?;
+
+	let result = script.eval_async("const x = await Promise.resolve(5); x", None)?;
+	assert_eq!(result, 5);
+
+	let result = script.eval_async("({ value: triple(await Promise.resolve(x)), big: 7n })", None)?;
+	assert_eq!(result, serde_json::json!({ "value": 15, "big": "7" }));
+
+	let result = script.eval_async("await Promise.reject(new Error('rejected'))", None);
+	assert!(result.unwrap_err().to_string().contains("rejected"));
+
+	let result = script.eval_async("await null; [-0, { nested: -0 }]", None)?;
+	let zeros: (f64, serde_json::Value) = serde_json::from_value(result)?;
+	assert!(zeros.0.is_sign_negative(), "Negative zero survives");
+	assert_eq!(zeros.1["nested"].as_f64().map(f64::to_bits), Some((-0.0f64).to_bits()), "Nested negative zero survives");
+	let result = script.eval_async("await null; -0", None)?;
+	assert_eq!(result.as_f64().map(f64::to_bits), Some((-0.0f64).to_bits()), "Negative zero survives as primitive");
+
+	let err = script.eval_async("await null; null.x", None).unwrap_err();
+	match err.downcast_ref::<JsError>() {
+		Some(JsError::Exception { name, .. }) => assert_eq!(name.as_deref(), Some("TypeError")),
+		other => panic!("Errors are reported as exceptions, got {:?}", other),
+	}
+	let err = script.eval_async("await null; throw { code: 7 }", None).unwrap_err();
+	match err.downcast_ref::<JsError>() {
+		Some(JsError::Thrown { value, .. }) => assert_eq!(value, &serde_json::json!({ "code": 7 })),
+		other => panic!("Other values are reported as thrown, got {:?}", other),
+	}
+
+	let result = script.eval_async("await new Promise(() => {})", None);
+	assert!(result.is_err(), "Evaluation fails if the promise can never settle");
+
+	let result = script.eval_async("await null; for(;;){}", Some(100));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::TotalTimeout));
+	Ok(())
+}
+
+#[test]
+fn drain_async() -> Result<(), AnyError> {
+	let js_code = "var state = 'initial';
+		function start() { Promise.resolve().then(() => new Promise(resolve => resolve('done'))).then(v => { state = v; }); }
+		function get() { return state; }";
+	let mut script = Script::from_string(js_code)?;
+
+	script.call_void("start", &(), None)?;
+	script.drain_async(Some(1000))?;
+
+	let result: String = script.call("get", &(), None)?;
+	assert_eq!(result, "done");
+
+	script.drain_async(None)?;
+	Ok(())
+}
+
+#[test]
+fn drain_async_timeout() -> Result<(), AnyError> {
+	let mut script = Script::from_module("tests/modules/drain.js")?;
+
+	// The dynamically imported module never finishes evaluating
+	script.call_void("start", &(), None)?;
+	let err = script.drain_async(Some(100)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::DrainTimeout { pending: 0 }));
+	assert!(err.to_string().contains("0 async ops still pending"), "{}", err);
+
+	let result: i32 = script.eval("1 + 2", None)?;
+	assert_eq!(result, 3, "Script stays usable");
+	Ok(())
+}
+
+#[test]
+fn advance_time() -> Result<(), AnyError> {
+	let js_code = "var log = [];
+		setTimeout(() => log.push('late'), 300);
+		setTimeout(name => { log.push(name); Promise.resolve().then(() => log.push('then')); }, 100, 'early');
+		var interval = setInterval(() => { log.push('tick'); if (log.length > 4) clearInterval(interval); }, 50);
+		setTimeout(() => { throw new Error('failed'); }, 1000);
+		function get() { return log; }";
+	let mut script = Script::builder().timers().build(js_code)?;
+
+	assert_eq!(script.advance_time(Duration::from_millis(0), None)?, 0);
+	assert_eq!(script.pending_timers()?, 4);
+
+	assert_eq!(script.advance_time(Duration::from_millis(120), None)?, 3);
+	let result: Vec<String> = script.call("get", &(), None)?;
+	assert_eq!(result, ["tick", "early", "then", "tick"]);
+
+	script.advance_time(Duration::from_millis(500), None)?;
+	let result: Vec<String> = script.call("get", &(), None)?;
+	assert_eq!(result, ["tick", "early", "then", "tick", "tick", "late"]);
+	assert_eq!(script.pending_timers()?, 1);
+
+	assert!(script.advance_time(Duration::from_millis(500), None).is_err(), "Throwing callback fails the step");
+	assert_eq!(script.pending_timers()?, 0);
+
+	let mut script = Script::builder().timers().build("setInterval(() => { for(;;){} }, 10);")?;
+	let result = script.advance_time(Duration::from_secs(1), Some(100));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Timeout));
+
+	let mut script = Script::from_string("function hasTimers() { return typeof setTimeout !== 'undefined'; }")?;
+	assert!(!script.call::<_, bool>("hasTimers", &(), None)?);
+	assert!(script.advance_time(Duration::from_millis(10), None).is_err(), "Timers are opt-in");
+	Ok(())
+}
+
+#[test]
+fn call_stream() -> Result<(), AnyError> {
+	// Minimal stand-in for ReadableStream, which the bare runtime lacks
+	let js_code = "var cancelled = false;
+		function produce(count) {
+			let i = 0;
+			return { getReader: () => ({
+				read: async () => { await null; return i < count ? { done: false, value: new Uint8Array([i++, 0xff]) } : { done: true }; },
+				cancel: async () => { cancelled = true; },
+			}) };
+		}
+		function wasCancelled() { return cancelled; }";
+	let mut script = Script::from_string(js_code)?;
+
+	block_on(async {
+		let mut stream = script.call_stream::<_, Vec<u8>>("produce", &2, Some(1000))?;
+		assert_eq!(stream.next().await?, Some(vec![0, 0xff]));
+		assert_eq!(stream.next().await?, Some(vec![1, 0xff]));
+		assert_eq!(stream.next().await?, None);
+		assert_eq!(stream.next().await?, None);
+		Ok::<_, AnyError>(())
+	})?;
+
+	let cancelled: bool = script.call("wasCancelled", &(), None)?;
+	assert!(!cancelled, "Exhausted stream is not cancelled");
+
+	block_on(async {
+		let mut stream = script.call_stream::<_, Vec<u8>>("produce", &5, None)?;
+		assert!(stream.next().await?.is_some());
+		Ok::<_, AnyError>(())
+	})?;
+
+	let cancelled: bool = script.call("wasCancelled", &(), None)?;
+	assert!(cancelled, "Dropping the stream early cancels it");
+
+	let result = script.call_stream::<_, Vec<u8>>("wasCancelled", &(), None).map(|_| ());
+	assert!(result.is_err(), "Function must return a stream");
+	Ok(())
+}
+
+#[test]
+fn call_iter() -> Result<(), AnyError> {
+	let js_code = "var closed = false;
+		function* rows(count) {
+			try {
+				for (let i = 0; i < count; ++i) yield { id: i };
+			} finally {
+				closed = true;
+			}
+		}
+		async function* delayed(count) {
+			for (let i = 0; i < count; ++i) { await null; yield i; }
+			throw new Error('no more');
+		}
+		function wasClosed() { return closed; }";
+	let mut script = Script::from_string(js_code)?;
+
+	#[derive(serde::Deserialize, PartialEq, Debug)]
+	struct Row {
+		id: u32,
+	}
+
+	let rows = script.call_iter::<_, Row>("rows", &3, None)?.collect::<Result<Vec<_>, _>>()?;
+	assert_eq!(rows, [Row { id: 0 }, Row { id: 1 }, Row { id: 2 }]);
+
+	script.set_global("closed", &false)?;
+	let first = script.call_iter::<_, Row>("rows", &1000, None)?.next().transpose()?;
+	assert_eq!(first, Some(Row { id: 0 }));
+	let closed: bool = script.call("wasClosed", &(), None)?;
+	assert!(closed, "Dropping the iterator early closes the generator");
+
+	let mut delayed = script.call_iter::<_, i32>("delayed", &2, Some(1000))?;
+	assert_eq!(delayed.next().transpose()?, Some(0));
+	assert_eq!(delayed.next().transpose()?, Some(1));
+	assert!(delayed.next().expect("error item").is_err(), "Thrown error ends up as item");
+	assert!(delayed.next().is_none());
+	drop(delayed);
+
+	let result = script.call_iter::<_, i32>("wasClosed", &(), None).map(|_| ());
+	assert!(result.is_err(), "Function must return an iterable");
+	Ok(())
+}
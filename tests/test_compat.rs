@@ -0,0 +1,43 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, CompatEnv, Script};
+
+fn compat_env() -> CompatEnv {
+	let mut env = CompatEnv::default();
+	env.env.insert("MODE".to_string(), "test".to_string());
+	env.platform = "linux".to_string();
+	env
+}
+
+#[test]
+fn no_compat() -> Result<(), AnyError> {
+	let mut script = Script::from_string("function has_process() { return typeof process !== 'undefined'; }")?;
+
+	let result: bool = script.call("has_process", &(), None)?;
+	assert!(!result, "No shim by default");
+	Ok(())
+}
+
+#[test]
+fn node_compat() -> Result<(), AnyError> {
+	let js_code = "function info() { return [process.env.MODE, process.env.HOME ?? null, process.platform]; }";
+	let mut script = Script::builder()
+		.node_compat(compat_env())
+		.build(js_code)?;
+
+	let result: (String, Option<String>, String) = script.call("info", &(), None)?;
+	assert_eq!(result, ("test".to_string(), None, "linux".to_string()));
+	Ok(())
+}
+
+#[test]
+fn deno_compat() -> Result<(), AnyError> {
+	let js_code = "function info() { return [Deno.env.get('MODE'), Deno.env.toObject(), Deno.build.os]; }";
+	let mut script = Script::builder()
+		.deno_compat(compat_env())
+		.build(js_code)?;
+
+	let result: (String, serde_json::Value, String) = script.call("info", &(), None)?;
+	assert_eq!(result, ("test".to_string(), serde_json::json!({"MODE": "test"}), "linux".to_string()));
+	Ok(())
+}
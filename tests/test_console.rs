@@ -0,0 +1,118 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use js_sandbox::{AnyError, JsValue, LogLevel, Output, Script};
+use serde_json::json;
+
+#[test]
+fn console_channel() -> Result<(), AnyError> {
+	let js_code = "function greet(name) { console.log('Hello', name); console.error('Bye', 7); }";
+	let mut script = Script::from_string(js_code)?;
+	let receiver = script.console_channel();
+
+	let _: () = script.call("greet", &"Rust", None)?;
+
+	let records: Vec<_> = receiver.try_iter().map(|r| (r.level, r.message)).collect();
+	assert_eq!(records, vec![
+		(LogLevel::Log, "Hello Rust".to_string()),
+		(LogLevel::Error, "Bye 7".to_string()),
+	]);
+	Ok(())
+}
+
+#[test]
+fn console_handler() -> Result<(), AnyError> {
+	let js_code = "function greet(name) { console.info('Hello', name); console.warn('Careful'); }";
+	let records = Rc::new(RefCell::new(Vec::new()));
+
+	let mut script = Script::from_string(js_code)?;
+	let sink = records.clone();
+	script.console_handler(move |record| sink.borrow_mut().push(("plugin-a", record.level, record.message)));
+
+	script.call_void("greet", &"Rust", None)?;
+	assert_eq!(*records.borrow(), vec![
+		("plugin-a", LogLevel::Info, "Hello Rust".to_string()),
+		("plugin-a", LogLevel::Warn, "Careful".to_string()),
+	]);
+
+	let (_, output) = script.call_with_output::<_, ()>("greet", &"again", None).map_err(|(_, e)| e)?;
+	assert_eq!(output.stdout, vec!["Hello again"]);
+	assert_eq!(records.borrow().len(), 2, "Captured output bypasses the handler");
+
+	script.call_void("greet", &"Rust", None)?;
+	assert_eq!(records.borrow().len(), 4, "Handler is restored after capturing");
+	Ok(())
+}
+
+#[test]
+fn console_structured() -> Result<(), AnyError> {
+	let js_code = "function report() {
+			console.log('user', { name: 'Ann', age: 30 }, undefined, () => 1);
+			console.table([{ a: 1, b: 'x' }, { a: 22 }]);
+			console.group('section');
+			console.dir({ k: [1] });
+			console.groupEnd();
+			console.log('after');
+		}";
+	let mut script = Script::from_string(js_code)?;
+	let receiver = script.console_channel();
+
+	script.call_void("report", &(), None)?;
+
+	let records: Vec<_> = receiver.try_iter().collect();
+	let messages: Vec<_> = records.iter().map(|r| r.message.as_str()).collect();
+	assert_eq!(messages, vec![
+		"user [object Object] undefined () => 1",
+		"(index) | a  | b\n--------+----+--\n0       | 1  | x\n1       | 22 |",
+		"section",
+		"  {\n    \"k\": [\n      1\n    ]\n  }",
+		"after",
+	]);
+
+	assert_eq!(records[0].args, vec![json!("user"), json!({ "name": "Ann", "age": 30 }), JsValue::Null, JsValue::Null]);
+	assert_eq!(records[1].args, vec![json!([{ "a": 1, "b": "x" }, { "a": 22 }])]);
+	assert!(records.iter().all(|r| r.level == LogLevel::Log));
+	Ok(())
+}
+
+#[test]
+fn console_rate_limit() -> Result<(), AnyError> {
+	let js_code = "function spam(n) { for (let i = 0; i < n; ++i) { console.log('line', i); } }";
+	let mut script = Script::builder()
+		.console_rate_limit(3, Duration::from_secs(60))
+		.build(js_code)?;
+	let receiver = script.console_channel();
+
+	script.call_void("spam", &10, None)?;
+
+	let messages: Vec<_> = receiver.try_iter().map(|r| r.message).collect();
+	assert_eq!(messages, vec!["line 0", "line 1", "line 2"]);
+	assert_eq!(script.console_dropped(), 7);
+	Ok(())
+}
+
+#[test]
+fn call_with_output() -> Result<(), AnyError> {
+	let js_code = "function grade(n) { console.log('start'); console.warn('careful'); console.info('n =', n); console.error('failed'); return n * 2; }
+		function fail() { console.log('before'); throw new Error('broken'); }";
+	let mut script = Script::from_string(js_code)?;
+	let receiver = script.console_channel();
+
+	let (result, output): (i32, Output) = script.call_with_output("grade", &21, None).map_err(|(_, e)| e)?;
+	assert_eq!(result, 42);
+	assert_eq!(output.stdout, vec!["start", "n = 21"]);
+	assert_eq!(output.stderr, vec!["careful", "failed"]);
+	assert_eq!(receiver.try_iter().count(), 0, "Captured output is not sent to the channel");
+
+	script.call_void("grade", &1, None)?;
+	assert_eq!(receiver.try_iter().count(), 4, "Previous destination is restored");
+
+	let (output, err) = script.call_with_output::<_, ()>("fail", &(), None).unwrap_err();
+	assert_eq!(output.stdout, vec!["before"], "Output is kept when the call fails");
+	assert!(err.to_string().contains("broken"), "{}", err);
+	assert_eq!(receiver.try_iter().count(), 0);
+	Ok(())
+}
@@ -0,0 +1,37 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// Counts the threads of the whole process, so this file must contain a single test.
+
+use js_sandbox::{AnyError, Script};
+
+#[cfg(target_os = "linux")]
+fn thread_count() -> usize {
+	let status = std::fs::read_to_string("/proc/self/status").expect("Process status is readable");
+	status
+		.lines()
+		.find_map(|line| line.strip_prefix("Threads:"))
+		.and_then(|count| count.trim().parse().ok())
+		.expect("Process status contains thread count")
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn drop_many_scripts() -> Result<(), AnyError> {
+	let js_code = "function inc(a) { return a + 1; }";
+
+	// First script starts the V8 platform, including its worker threads
+	let mut script = Script::from_string(js_code)?;
+	let _: i32 = script.call("inc", &0, Some(1000))?;
+	drop(script);
+	let threads_before = thread_count();
+
+	for i in 0..500 {
+		let mut script = Script::from_string(js_code)?;
+		let result: i32 = script.call("inc", &i, Some(1000))?;
+		assert_eq!(result, i + 1);
+	}
+
+	let threads_after = thread_count();
+	assert_eq!(threads_before, threads_after, "Dropped scripts must not leave threads behind");
+	Ok(())
+}
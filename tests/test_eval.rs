@@ -1,6 +1,8 @@
 // Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
 
-use js_sandbox::JsValue;
+use deno_core::error::JsError;
+
+use js_sandbox::{AnyError, JsValue, Script};
 use util::expect_error;
 
 mod util;
@@ -29,3 +31,73 @@ fn syntax_error() {
 
 	expect_error(result_opt, "Syntax error");
 }
+
+#[test]
+fn error_position() {
+	let result = js_sandbox::eval_json("[1,\n 2,,\n 3)]");
+	let err = result.expect_err("Syntax error must be reported");
+	let err = err.downcast_ref::<JsError>().expect("Syntax error leads to JsError type");
+
+	assert_eq!(err.script_resource_name.as_deref(), Some("eval_json"));
+	assert_eq!(err.line_number, Some(3), "Line is relative to the expression: {}", err);
+	assert_eq!(err.source_line.as_deref(), Some(" 3)]"));
+
+	let result = js_sandbox::eval_json("1 +\n missing");
+	let err = result.expect_err("Reference error must be reported");
+	assert!(err.to_string().contains("eval_json:2:"), "Position is relative to the expression: {}", err);
+}
+
+#[test]
+fn same_globals_as_script() {
+	let result: JsValue = js_sandbox::eval_json("[typeof console.warn, 2n ** 70n]")
+		.expect("Valid expression can be evaluated");
+
+	let exp_result = serde_json::json!(["function", "1180591620717411303424"]);
+
+	assert_eq!(result, exp_result);
+}
+
+#[test]
+fn eval_typed_at() -> Result<(), AnyError> {
+	let js_code = "const counter = (() => {
+			let count = 0;
+			const history = [];
+			return { increment() { history.push(count); return ++count; } };
+		})();";
+	let mut script = Script::from_string(js_code)?;
+	script.call_void("counter.increment", &(), None)?;
+	script.call_void("counter.increment", &(), None)?;
+
+	let count: i32 = script.eval_typed_at("counter.increment", "count")?;
+	assert_eq!(count, 2);
+
+	let history: Vec<i32> = script.eval_typed_at("counter.increment", "history.concat([count * 10])")?;
+	assert_eq!(history, vec![0, 1, 20]);
+
+	let result: Result<i32, AnyError> = script.eval_typed_at("counter", "count");
+	assert!(result.is_err(), "Target must be a function");
+	Ok(())
+}
+
+#[test]
+fn eval_in_script() -> Result<(), AnyError> {
+	let js_code = "var state = { items: ['a', 'b', 'c', 'd'] };
+		function add(item) { state.items.push(item); return { count: state.items.length, last: item }; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result = script.call_json("add", &"e", None)?;
+	assert_eq!(result, serde_json::json!({ "count": 5, "last": "e" }));
+
+	let result: JsValue = script.eval("state.items.slice(3)", None)?;
+	assert_eq!(result, serde_json::json!(["d", "e"]));
+
+	let count: usize = script.eval("state.items.length", None)?;
+	assert_eq!(count, 5, "Sees the changes of earlier calls");
+
+	let err = script.eval::<JsValue>("state.\n missing.length", None).unwrap_err();
+	assert!(err.to_string().contains("eval_json:2:"), "Position is relative to the expression: {}", err);
+
+	let result: Result<i32, AnyError> = script.eval("(() => { for(;;){} })()", Some(100));
+	assert!(result.is_err(), "Timeout applies");
+	Ok(())
+}
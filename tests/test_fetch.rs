@@ -0,0 +1,114 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, FetchOptions, Script};
+
+#[cfg(feature = "http")]
+fn serve_once(response: &'static str) -> Result<std::net::SocketAddr, AnyError> {
+	serve_after(std::time::Duration::from_secs(0), response)
+}
+
+#[cfg(feature = "http")]
+fn serve_after(delay: std::time::Duration, response: &'static str) -> Result<std::net::SocketAddr, AnyError> {
+	use std::io::{Read, Write};
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+	let address = listener.local_addr()?;
+	std::thread::spawn(move || {
+		if let Ok((mut stream, _)) = listener.accept() {
+			let mut request = [0u8; 4096];
+			let _ = stream.read(&mut request);
+			std::thread::sleep(delay);
+			let _ = stream.write_all(response.as_bytes());
+		}
+	});
+	Ok(address)
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn fetch_allowed() -> Result<(), AnyError> {
+	let address = serve_once("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 13\r\n\r\n{\"answer\":42}")?;
+	let js_code = "async function get(url) {
+			const response = await fetch(url, { headers: { Accept: 'application/json' } });
+			return [response.status, response.headers.get('content-type'), (await response.json()).answer];
+		}";
+
+	let options = FetchOptions {
+		allowed_domains: vec!["127.0.0.1".to_string()],
+		allow_http: true,
+		..FetchOptions::default()
+	};
+	let mut script = Script::builder().fetch(options).build(js_code)?;
+
+	let url = format!("http://{}/answer", address);
+	let result: (u16, String, i32) = script.call_async("get", &url, None, Some(10_000))?;
+	assert_eq!(result, (200, "application/json".to_string(), 42));
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn fetch_within_call_timeout() -> Result<(), AnyError> {
+	use std::time::{Duration, Instant};
+
+	let address = serve_after(Duration::from_secs(10), "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")?;
+	let js_code = "async function get(url) {
+			return (await fetch(url)).text();
+		}";
+
+	let options = FetchOptions {
+		allowed_domains: vec!["127.0.0.1".to_string()],
+		allow_http: true,
+		..FetchOptions::default()
+	};
+	let mut script = Script::builder().fetch(options).build(js_code)?;
+
+	let url = format!("http://{}/slow", address);
+	let start = Instant::now();
+	let result: Result<String, AnyError> = script.call_async("get", &url, None, Some(300));
+	assert!(result.is_err(), "slow request must not complete");
+	assert!(start.elapsed() < Duration::from_secs(5), "request outlasted the call: {:?}", start.elapsed());
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn fetch_denied() -> Result<(), AnyError> {
+	let js_code = "async function attempt([url, body]) {
+			try {
+				await fetch(url, { method: 'POST', body });
+				return 'ok';
+			} catch (e) {
+				return e.message;
+			}
+		}";
+
+	let options = FetchOptions {
+		allowed_domains: vec!["example.com".to_string()],
+		max_request_bytes: 4,
+		..FetchOptions::default()
+	};
+	let mut script = Script::builder().fetch(options).build(js_code)?;
+
+	for (url, body, expected) in [
+		("https://example.com.evil.net/", "", "not on the allow-list"),
+		("https://evil.net/?to=example.com", "", "not on the allow-list"),
+		("http://api.example.com/", "", "'http:' URLs is not allowed"),
+		("file:///etc/passwd", "", "'file:' URLs is not allowed"),
+		("https://api.example.com/", "too large", "exceeds the limit"),
+	]
+	.iter()
+	{
+		let result: String = script.call_async("attempt", &(url, body), None, None)?;
+		assert!(result.contains(expected), "for `{}`: {}", url, result);
+	}
+	Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "http"))]
+fn fetch_requires_feature() {
+	let result: Result<Script, AnyError> = Script::builder().fetch(FetchOptions::default()).build("");
+	let err = result.err().expect("fetch() is not available");
+	assert!(err.to_string().contains("`http` feature"), "{}", err);
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Script, VirtualFs};
+
+const JS_CODE: &str = r#"
+	function attempt(code) {
+		try {
+			return JSON.stringify(eval(code) ?? null);
+		} catch (e) {
+			return "error: " + e.message;
+		}
+	}"#;
+
+#[test]
+fn filesystem_in_memory() -> Result<(), AnyError> {
+	let fs = VirtualFs::in_memory();
+	fs.write("data/levels.json", br#"[1, 2]"#)?;
+	fs.write("data/image.bin", &[0, 255])?;
+
+	let mut script = Script::builder().filesystem(fs.clone()).build(JS_CODE)?;
+	for (code, expected) in [
+		("JSON.parse(fs.readTextFile('data/levels.json'))", "[1,2]"),
+		("Array.from(fs.readFile('/data/image.bin'))", "[0,255]"),
+		("fs.writeTextFile('saves/./one.txt', 'ünï')", "null"),
+		("fs.writeFile('saves/two.bin', new Uint8Array([7]))", "null"),
+		("fs.readDir()", r#"["data","saves"]"#),
+		("fs.readDir('saves')", r#"["one.txt","two.bin"]"#),
+		("[fs.exists('saves'), fs.exists('saves/three.txt')]", "[true,false]"),
+		("fs.remove('saves/two.bin')", "null"),
+		("fs.readTextFile('missing.txt')", "error: 'missing.txt' does not exist"),
+		("fs.readTextFile('../etc/passwd')", "error: path '../etc/passwd' is outside of the filesystem"),
+		("fs.readTextFile('data/../../etc/passwd')", "error: path 'data/../../etc/passwd' is outside of the filesystem"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+
+	assert_eq!(fs.read("saves/one.txt")?, "ünï".as_bytes(), "Host sees the script's writes");
+	assert!(!fs.exists("saves/two.bin"));
+	Ok(())
+}
+
+#[test]
+fn filesystem_read_only() -> Result<(), AnyError> {
+	let fs = VirtualFs::in_memory().read_only();
+	fs.write("config.txt", b"fixed")?;
+
+	let mut script = Script::builder().filesystem(fs.clone()).build(JS_CODE)?;
+	for (code, expected) in [
+		("fs.readTextFile('config.txt')", r#""fixed""#),
+		("fs.writeTextFile('config.txt', 'changed')", "error: the filesystem is read-only"),
+		("fs.remove('config.txt')", "error: the filesystem is read-only"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	assert_eq!(fs.read("config.txt")?, b"fixed");
+	Ok(())
+}
+
+#[test]
+fn filesystem_directory() -> Result<(), AnyError> {
+	let root = std::env::temp_dir().join(format!("js-sandbox-fs-{}", std::process::id()));
+	std::fs::create_dir_all(root.join("mod"))?;
+	std::fs::write(root.join("mod/data.txt"), "shipped")?;
+
+	let fs = VirtualFs::directory(root.join("mod"))?;
+	let mut script = Script::builder().filesystem(fs).build(JS_CODE)?;
+	for (code, expected) in [
+		("fs.readTextFile('data.txt')", r#""shipped""#),
+		("fs.writeTextFile('cache/out.txt', 'written')", "null"),
+		("fs.readDir('/')", r#"["cache","data.txt"]"#),
+		("fs.readTextFile('../mod/data.txt')", "error: path '../mod/data.txt' is outside of the filesystem"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	assert_eq!(std::fs::read_to_string(root.join("mod/cache/out.txt"))?, "written");
+
+	std::fs::remove_dir_all(&root)?;
+	Ok(())
+}
+
+#[test]
+fn filesystem_not_provided() -> Result<(), AnyError> {
+	let mut script = Script::from_string(JS_CODE)?;
+	let result: String = script.call("attempt", &"fs.readTextFile('data.txt')", None)?;
+	assert_eq!(result, "error: fs is not defined");
+	Ok(())
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, JsError, Script};
+
+#[test]
+fn gas_exhausted() -> Result<(), AnyError> {
+	let js_code = r#"
+		function spin() { while (true) {} }
+		function count(n) { let sum = 0; for (let i = 0; i < n; i++) { sum += i; } return sum; }
+		const fib = n => n < 2 ? n : fib(n - 1) + fib(n - 2);"#;
+
+	let mut script = Script::builder().gas_limit(1000).build(js_code)?;
+
+	let err = script.call::<_, ()>("spin", &(), None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	// Every call starts with the full limit again
+	let result: i64 = script.call("count", &100, None)?;
+	assert_eq!(result, 4950);
+
+	let err = script.call::<_, i64>("count", &1000, None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let result: i64 = script.call("fib", &10, None)?;
+	assert_eq!(result, 55);
+
+	let err = script.call::<_, i64>("fib", &20, None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+	Ok(())
+}
+
+#[test]
+fn gas_per_call() -> Result<(), AnyError> {
+	let js_code = "function count(n) { let sum = 0; for (let i = 0; i < n; i++) { sum += i; } return sum; }";
+	let mut script = Script::builder().gas_limit(1000).build(js_code)?;
+
+	let result: i64 = script.call_with_gas("count", &10_000, 20_000, None)?;
+	assert_eq!(result, 49_995_000);
+
+	let err = script.call_with_gas::<_, i64>("count", &100, 50, None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let err = script.call::<_, i64>("count", &10_000, None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted), "Script limit applies again");
+
+	let mut unmetered = Script::from_string(js_code)?;
+	assert!(unmetered.call_with_gas::<_, i64>("count", &10, 100, None).is_err(), "Requires a gas limit");
+	Ok(())
+}
+
+#[test]
+fn gas_top_level() {
+	let err = Script::builder().gas_limit(1000).build("for (;;) {}").err().expect("Top-level code is metered");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let err = Script::builder().gas_limit(1000).build("const x = __rust;").err().expect("Reserved identifier is rejected");
+	assert!(err.to_string().contains("__rust"));
+}
+
+#[test]
+fn gas_blocks_eval() -> Result<(), AnyError> {
+	let js_code = r#"
+		function attempt(code) {
+			try {
+				code();
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}"#;
+
+	let mut script = Script::builder().gas_limit(1000).build(js_code)?;
+
+	for (code, expected) in [
+		("() => eval('1')", "EvalError"),
+		("() => new Function('return 1')", "EvalError"),
+		("() => (function () {}).constructor('return 1')", "EvalError"),
+		("() => (async () => {}).constructor('return 1')", "EvalError"),
+		("() => (() => {}) instanceof Function", "ok"),
+	]
+	.iter()
+	{
+		let result: String = script.call(&format!("(() => attempt({}))", code), &(), None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	Ok(())
+}
+
+#[test]
+fn gas_braceless_loops() -> Result<(), AnyError> {
+	let js_code = r#"
+		function grow() { const items = [1]; for (const item of items) items.push(item); }
+		function keys() { const seen = {}; for (const key in { a: 1, b: 2 }) seen[key] = true; return Object.keys(seen); }
+		function matches(words) { let count = 0; for (const word of words) if (/^a/.test(word)) count++; else count--; return count; }"#;
+
+	let mut script = Script::builder().gas_limit(1000).build(js_code)?;
+
+	let err = script.call::<_, ()>("grow", &(), Some(10_000)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let result: Vec<String> = script.call("keys", &(), None)?;
+	assert_eq!(result, vec!["a", "b"]);
+
+	let result: i32 = script.call("matches", &["apple", "avocado", "banana"], None)?;
+	assert_eq!(result, 1);
+	Ok(())
+}
+
+#[test]
+fn gas_regex_after_paren() -> Result<(), AnyError> {
+	let js_code = r#"
+		function check(s) { if (s) /[)}]/.test(s); while (false) /x/g; return s.length / 2 / 1; }
+		function spin() { for (;;) if (true) /}/.test("}"); }"#;
+
+	let mut script = Script::builder().gas_limit(1000).build(js_code)?;
+	let result: f64 = script.call("check", &"ab", None)?;
+	assert_eq!(result, 1.0);
+
+	let err = script.call::<_, ()>("spin", &(), Some(10_000)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+	Ok(())
+}
+
+#[test]
+fn gas_eval() -> Result<(), AnyError> {
+	let mut script = Script::builder().gas_limit(1000).build("")?;
+
+	let err = script.eval::<i32>("(() => { while (true) {} })()", Some(10_000)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let err = script.eval_async("for (;;) { await null; }", Some(10_000)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::GasExhausted));
+
+	let result = script.eval_async("let sum = 0; for (let i = 0; i < 10; i++) sum += i; sum", None)?;
+	assert_eq!(result, 45);
+
+	assert!(script.eval::<i32>("__rust.gas", None).is_err(), "Reserved identifier is rejected");
+	Ok(())
+}
@@ -0,0 +1,253 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::collections::HashMap;
+
+use js_sandbox::{AnyError, CompatEnv, HostApi, HostMethod, JsError, JsValue, Script};
+
+#[test]
+fn add_fn() -> Result<(), AnyError> {
+	let js_code = "function describe(key) { return key + '=' + host.lookup(key); }";
+	let mut script = Script::from_string(js_code)?;
+
+	script.add_fn("lookup", |key| Ok(JsValue::from(key.as_str().unwrap_or("").len())))?;
+
+	let result: String = script.call("describe", &"width", None)?;
+	assert_eq!(result, "width=5");
+	Ok(())
+}
+
+#[test]
+fn add_fn_panic() -> Result<(), AnyError> {
+	let js_code = "function explode() { return host.explode(); }
+		function sum(a) { return a[0] + a[1]; }";
+	let mut script = Script::from_string(js_code)?;
+
+	script.add_fn("explode", |_| panic!("boom"))?;
+
+	let result: Result<(), AnyError> = script.call("explode", &(), None);
+	let err = result.expect_err("Panic must be reported as error");
+	assert!(err.to_string().contains("panicked: boom"), "Panic message is preserved: {}", err);
+
+	// Script remains usable
+	let sum: i32 = script.call("sum", &[1, 2], None)?;
+	assert_eq!(sum, 3);
+	Ok(())
+}
+
+#[test]
+fn op_limit() -> Result<(), AnyError> {
+	let js_code = "function spam(n) { for (let i = 0; i < n; ++i) { try { host.query(i); } catch (e) {} } return n; }";
+	let mut script = Script::builder()
+		.add_fn("query", |_| Ok(JsValue::Null))
+		.op_limit("query", 3)
+		.build(js_code)?;
+
+	let within: i32 = script.call("spam", &3, None)?;
+	assert_eq!(within, 3);
+
+	let exceeded: Result<i32, AnyError> = script.call("spam", &10, None);
+	let err = exceeded.expect_err("Exceeding the limit must abort the call");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::OpLimitExceeded { op: "query".to_string(), limit: 3 }));
+
+	// Next call starts counting from zero again
+	let again: i32 = script.call("spam", &2, None)?;
+	assert_eq!(again, 2);
+	Ok(())
+}
+
+#[test]
+fn op_stats() -> Result<(), AnyError> {
+	let js_code = "function spam(n) { for (let i = 0; i < n; ++i) { try { host.query(i); } catch (e) {} } return counter.step(); }";
+	let mut script = Script::builder()
+		.add_fn("query", |_| Ok(JsValue::Null))
+		.add_fn("idle", |_| Ok(JsValue::Null))
+		.op_limit("query", 3)
+		.build(js_code)?;
+	script.register_ops("counter", Counter { step: 1 })?;
+
+	let _: i32 = script.call("spam", &3, None)?;
+	let _ = script.call::<_, i32>("spam", &10, None).unwrap_err();
+	script.reset()?;
+	let _: i32 = script.call("spam", &2, None)?;
+
+	let stats = script.op_stats();
+	let expected: HashMap<String, u64> = [("query", 3 + 4 + 2), ("idle", 0), ("counter.next", 0), ("counter.step", 2)]
+		.iter()
+		.map(|&(name, count)| (name.to_string(), count))
+		.collect();
+	assert_eq!(stats, expected, "Rejected invocations count, reset does not clear");
+	Ok(())
+}
+
+#[test]
+fn add_fast_fn() -> Result<(), AnyError> {
+	let js_code = "function total(n) { let sum = 0; for (let i = 0; i < n; ++i) { sum += host.square(i); } return sum; }
+		function fail() { return host.fail(1); }";
+	let mut script = Script::builder()
+		.add_fast_fn("square", |x| x * x)
+		.build(js_code)?;
+
+	script.add_fast_fn("fail", |_| panic!("fast boom"))?;
+	assert!(script.add_fast_fn("square", |x| x).is_err(), "Duplicate names are rejected");
+	let err = script.add_fn("square", |_| Ok(JsValue::Null)).unwrap_err();
+	assert!(err.to_string().contains("'square' is already registered"), "Collision with add_fn() is reported: {}", err);
+	let result = Script::builder()
+		.add_fast_fn("twice", |x| 2.0 * x)
+		.add_fn("twice", |_| Ok(JsValue::Null))
+		.build(js_code);
+	assert!(result.is_err(), "Collision in the builder is reported");
+
+	let result: f64 = script.call("total", &4, None)?;
+	assert_eq!(result, 14.0);
+
+	let result: Result<f64, AnyError> = script.call("fail", &(), None);
+	let err = result.expect_err("Panic must be reported as error");
+	assert!(err.to_string().contains("panicked: fast boom"), "Panic message is preserved: {}", err);
+	Ok(())
+}
+
+#[test]
+fn protect_host_globals() -> Result<(), AnyError> {
+	let builder = || Script::builder().add_fn("double", |arg| Ok((2 * arg.as_i64().unwrap_or(0)).into()));
+
+	// Unprotected, the script silently replaces the host functions
+	let mut script = builder().build("var host = {}; function quadruple(a) { return host.double(host.double(a)); }")?;
+	let result: Result<i32, AnyError> = script.call("quadruple", &5, None);
+	assert!(result.is_err());
+
+	let result = builder().protect_host_globals().build("var host = {};");
+	let err = result.err().expect("Overwriting host must fail");
+	assert!(err.to_string().contains("cannot overwrite host-injected global 'host'"), "Error names the global: {}", err);
+
+	let result = builder().protect_host_globals().build("let host = 3;");
+	assert!(result.is_err(), "Redeclaring host must fail");
+
+	let js_code = "function quadruple(a) { return host.double(host.double(a)); }
+		function tamper() { try { host.double = null; return 'overwritten'; } catch (e) { return e.message; } }";
+	let mut script = builder().protect_host_globals().build(js_code)?;
+
+	let message: String = script.call("tamper", &(), None)?;
+	assert_eq!(message, "cannot overwrite host function 'host.double'");
+
+	let result: i32 = script.call("quadruple", &5, None)?;
+	assert_eq!(result, 20);
+	Ok(())
+}
+
+#[test]
+fn ops_namespace() -> Result<(), AnyError> {
+	let js_code = "function run(a) { return typeof host === 'undefined' ? engine.spawn(a) + engine.half(a) : -1; }
+		function tamper() { try { engine.spawn = null; return 'overwritten'; } catch (e) { return e.message; } }";
+	let mut script = Script::builder()
+		.ops_namespace("engine")
+		.add_fn("spawn", |arg| Ok((10 * arg.as_i64().unwrap_or(0)).into()))
+		.add_fast_fn("half", |x| x / 2.0)
+		.op_limit("spawn", 1)
+		.protect_host_globals()
+		.build(js_code)?;
+
+	let result: f64 = script.call("run", &4, None)?;
+	assert_eq!(result, 42.0);
+
+	let message: String = script.call("tamper", &(), None)?;
+	assert_eq!(message, "cannot overwrite host function 'engine.spawn'");
+
+	let result = Script::builder().ops_namespace("not valid").build("");
+	assert!(result.is_err(), "Namespace must be an identifier");
+	Ok(())
+}
+
+#[test]
+fn host_features() -> Result<(), AnyError> {
+	let js_code = "function features() { return [host.__features.ops, host.__features.polyfills, Object.keys(host)]; }
+		function has(name) { return host.__features.hasOp(name); }";
+	let mut script = Script::builder()
+		.add_fn("query", Ok)
+		.add_fast_fn("half", |x| x / 2.0)
+		.node_compat(CompatEnv::default())
+		.build(js_code)?;
+	script.register_ops("counter", Counter { step: 1 })?;
+
+	let (ops, polyfills, keys): (Vec<String>, Vec<String>, Vec<String>) = script.call("features", &(), None)?;
+	assert_eq!(ops, vec!["counter.next", "counter.step", "half", "query"]);
+	assert_eq!(polyfills, vec!["node"]);
+	assert_eq!(keys, vec!["query", "half"], "Features are not enumerable");
+
+	assert!(script.call::<_, bool>("has", &"query", None)?);
+	assert!(!script.call::<_, bool>("has", &"missing", None)?);
+
+	script.add_fn("late", Ok)?;
+	assert!(script.call::<_, bool>("has", &"late", None)?, "Functions added later are listed");
+	Ok(())
+}
+
+struct Counter {
+	step: i64,
+}
+
+impl Counter {
+	fn next(&self, arg: JsValue) -> Result<JsValue, AnyError> {
+		Ok((arg.as_i64().unwrap_or(0) + self.step).into())
+	}
+
+	fn step(&self, _arg: JsValue) -> Result<JsValue, AnyError> {
+		Ok(self.step.into())
+	}
+}
+
+impl HostApi for Counter {
+	fn methods() -> Vec<(&'static str, HostMethod<Self>)> {
+		vec![("next", Counter::next), ("step", Counter::step)]
+	}
+}
+
+struct Duplicated;
+
+impl HostApi for Duplicated {
+	fn methods() -> Vec<(&'static str, HostMethod<Self>)> {
+		vec![("same", |_, _| Ok(JsValue::Null)), ("same", |_, _| Ok(JsValue::Null))]
+	}
+}
+
+#[test]
+fn register_ops() -> Result<(), AnyError> {
+	let js_code = "function advance(a) { return [counter.next(a), counter.step(), typeof host]; }";
+	let mut script = Script::from_string(js_code)?;
+	script.register_ops("counter", Counter { step: 3 })?;
+
+	let result: (i64, i64, String) = script.call("advance", &4, None)?;
+	assert_eq!(result, (7, 3, "undefined".to_string()));
+
+	let err = script.register_ops("counter", Counter { step: 1 }).unwrap_err();
+	assert!(err.to_string().contains("'counter.next' is already registered"), "Collision is reported: {}", err);
+
+	let err = script.register_ops("other", Duplicated).unwrap_err();
+	assert!(err.to_string().contains("method 'same' more than once"), "Collision is reported: {}", err);
+	Ok(())
+}
+
+#[test]
+fn message_channel() -> Result<(), AnyError> {
+	let js_code = "host.postMessage('ready');
+		var received = [];
+		onmessage = event => {
+			received.push(event.data);
+			host.postMessage({ echo: event.data.value * 2 });
+		};";
+	let mut script = Script::builder().message_channel().build(js_code)?;
+
+	script.post_message(&serde_json::json!({ "value": 21 }), None)?;
+	assert_eq!(script.poll_messages()?, [JsValue::from("ready"), serde_json::json!({ "echo": 42 })]);
+	assert!(script.poll_messages()?.is_empty(), "Polling drains the queue");
+
+	let sink = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+	let handler_sink = sink.clone();
+	script.message_handler(move |message| handler_sink.borrow_mut().push(message))?;
+	script.post_message(&serde_json::json!({ "value": 1 }), None)?;
+	assert_eq!(*sink.borrow(), [serde_json::json!({ "echo": 2 })]);
+
+	let mut plain = Script::from_string("var onmessage = () => {};")?;
+	assert!(plain.post_message(&1, None).is_err(), "Channel must be enabled");
+	assert!(plain.poll_messages().is_err());
+	Ok(())
+}
@@ -0,0 +1,54 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use serde::{Deserialize, Serialize};
+
+use js_sandbox::{AnyError, Script};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Point {
+	x: i32,
+	y: i32,
+}
+
+js_sandbox::js_api! {
+	struct Geometry {
+		fn translate(point: Point, dx: i32, dy: i32) -> Point;
+		fn origin() -> Point;
+		fn log(message: String);
+	}
+}
+
+#[test]
+fn typed_calls() -> Result<(), AnyError> {
+	let js_code = "var logged = [];
+		function translate(p, dx, dy) { return { x: p.x + dx, y: p.y + dy }; }
+		function origin() { return { x: 0, y: 0 }; }
+		function log(message) { logged.push(message); }
+		function logs() { return logged; }";
+	let mut geometry = Geometry::bind(Script::from_string(js_code)?)?;
+
+	assert_eq!(geometry.translate(Point { x: 1, y: 2 }, 10, 20)?, Point { x: 11, y: 22 });
+	assert_eq!(geometry.origin()?, Point { x: 0, y: 0 });
+	geometry.log("moved".to_string())?;
+
+	let logged: Vec<String> = geometry.script().call("logs", &(), None)?;
+	assert_eq!(logged, vec!["moved"]);
+	Ok(())
+}
+
+#[test]
+fn bind_checks_functions() -> Result<(), AnyError> {
+	let script = Script::from_string("function translate() {} var origin = 5;")?;
+	let err = Geometry::bind(script).err().expect("Functions are checked on bind");
+	assert!(err.to_string().contains("'origin' is not defined (found number)"), "{}", err);
+	Ok(())
+}
+
+#[test]
+fn typed_timeout() -> Result<(), AnyError> {
+	let js_code = "function translate(p) { for (;;) {} } function origin() {} function log() {}";
+	let mut geometry = Geometry::bind(Script::from_string(js_code)?)?.with_timeout(50);
+
+	assert!(geometry.translate(Point { x: 0, y: 0 }, 1, 1).is_err(), "Timeout applies");
+	Ok(())
+}
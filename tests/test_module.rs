@@ -0,0 +1,31 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Script};
+
+#[test]
+fn from_module() -> Result<(), AnyError> {
+	let mut script = Script::from_module("tests/modules/main.js")?;
+
+	let result: i32 = script.call("compute", &5, None)?;
+	assert_eq!(result, 105);
+
+	let result: String = script.call("greet", &"modules", None)?;
+	assert_eq!(result, "hello modules");
+	Ok(())
+}
+
+#[test]
+fn module_imports_restricted() {
+	let result = Script::from_module("tests/modules/escape.js");
+	assert!(result.is_err(), "Imports outside of the module root fail");
+
+	let result = Script::builder().allow_imports(&["lib/math.js"]).build_module("tests/modules/main.js");
+	let err = result.err().expect("Imports outside of the allow-list fail");
+	assert!(err.to_string().contains("text.js"), "Error names the module: {}", err);
+
+	let result = Script::builder()
+		.module_root("tests")
+		.allow_imports(&["modules/lib/math.js", "modules/lib/text.js"])
+		.build_module("tests/modules/main.js");
+	assert!(result.is_ok(), "Allowed imports succeed");
+}
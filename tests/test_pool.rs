@@ -0,0 +1,124 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread;
+use std::time::Duration;
+
+use js_sandbox::{AnyError, Bytes, Script, ScriptPool, Snapshot};
+
+#[test]
+fn pool_submit() -> Result<(), AnyError> {
+	let pool = ScriptPool::new(3)?;
+
+	let submissions: Vec<_> = (0..12)
+		.map(|i| pool.submit::<_, i32>("function triple(a) { return 3 * a; }", "triple", &i, None))
+		.collect();
+
+	for (i, submission) in submissions.into_iter().enumerate() {
+		assert_eq!(submission.wait()?, 3 * i as i32);
+	}
+
+	let result = pool.submit::<_, ()>("function fail() { throw new Error('failed'); }", "fail", &(), None).wait();
+	assert!(result.is_err(), "Exceptions are reported to the submission");
+
+	let result = pool.submit::<_, ()>("syntax error", "f", &(), None).wait();
+	assert!(result.is_err(), "Failing script creation is reported to the submission");
+
+	let stats = pool.stats();
+	assert_eq!(stats.workers.len(), 3);
+	assert_eq!(stats.workers.iter().map(|worker| worker.completed).sum::<u64>(), 14);
+	assert!(stats.workers.iter().all(|worker| worker.queue_depth == 0));
+
+	assert!(ScriptPool::new(0).is_err(), "A pool needs at least one worker");
+	Ok(())
+}
+
+#[test]
+fn pool_submit_args() -> Result<(), AnyError> {
+	let pool = ScriptPool::new(1)?;
+	let js_code = "function describe([label, data]) { return label + ': ' + (data instanceof Uint8Array) + ' ' + data.join(','); }";
+
+	let result: String = pool.submit(js_code, "describe", &("data", Bytes(vec![1, 2, 255])), None).wait()?;
+	assert_eq!(result, "data: true 1,2,255");
+
+	let err = pool.submit::<_, String>(js_code, "describe", &("nan", f64::NAN), None).wait().unwrap_err();
+	assert!(err.to_string().contains("'describe'") && err.to_string().contains("NaN"), "Arguments are checked: {}", err);
+	assert_eq!(pool.stats().workers[0].completed, 1, "Rejected arguments never reach a worker");
+	Ok(())
+}
+
+#[test]
+fn pool_shortest_queue() -> Result<(), AnyError> {
+	let pool = ScriptPool::new(2)?;
+	let slow_code = "function slow() { const end = Date.now() + 300; while (Date.now() < end) {} return 'slow'; }";
+
+	let slow = pool.submit::<_, String>(slow_code, "slow", &(), None);
+	thread::sleep(Duration::from_millis(100));
+
+	// The busy worker has a longer queue, so all quick submissions go to the other worker
+	for _ in 0..3 {
+		let result: i32 = pool.submit("function quick() { return 1; }", "quick", &(), None).wait()?;
+		assert_eq!(result, 1);
+	}
+
+	let stats = pool.stats();
+	let busy = stats.workers.iter().find(|worker| worker.queue_depth == 1).expect("Slow submission is running");
+	assert_eq!(busy.completed, 0);
+
+	assert_eq!(slow.wait()?, "slow");
+	let stats = pool.stats();
+	assert!(stats.workers.iter().any(|worker| worker.completed == 1 && worker.busy_time >= Duration::from_millis(300)));
+	assert!(stats.workers.iter().any(|worker| worker.completed == 3));
+	Ok(())
+}
+
+#[test]
+fn pool_submission_future() -> Result<(), AnyError> {
+	let pool = ScriptPool::new(1)?;
+	let mut submission = pool.submit::<_, String>("function greet(name) { return 'hi ' + name; }", "greet", &"pool", None);
+
+	let waker = noop_waker();
+	let mut cx = Context::from_waker(&waker);
+	let result = loop {
+		match Pin::new(&mut submission).poll(&mut cx) {
+			Poll::Ready(result) => break result?,
+			Poll::Pending => thread::sleep(Duration::from_millis(10)),
+		}
+	};
+
+	assert_eq!(result, "hi pool");
+	Ok(())
+}
+
+#[test]
+fn pool_resident_script() -> Result<(), AnyError> {
+	let pool = ScriptPool::with_script(2, || Script::from_string("var calls = 0; function triple(x) { ++calls; return 3 * x; }"))?;
+	let submissions: Vec<_> = (0..10).map(|i| pool.call::<_, i32>("triple", &i, None)).collect();
+	let results = submissions.into_iter().map(|submission| submission.wait()).collect::<Result<Vec<_>, _>>()?;
+	assert_eq!(results, (0..10).map(|i| 3 * i).collect::<Vec<_>>());
+
+	let snapshot = Snapshot::create("const table = [1, 4, 9]; function lookup(i) { return table[i]; }")?;
+	let pool = ScriptPool::from_snapshot(1, &snapshot)?;
+	assert_eq!(pool.call::<_, i32>("lookup", &2, None).wait()?, 9);
+	let err = pool.call::<_, i32>("lookup", &f64::INFINITY, None).wait().unwrap_err();
+	assert!(err.to_string().contains("'lookup'") && err.to_string().contains("Infinity"), "Arguments are checked: {}", err);
+
+	let pool = ScriptPool::with_script(1, || Script::from_string("syntax error ("))?;
+	assert!(pool.call::<_, i32>("lookup", &2, None).wait().is_err(), "Creation error is reported");
+
+	let pool = ScriptPool::new(1)?;
+	assert!(pool.call::<_, i32>("lookup", &2, None).wait().is_err(), "Pool without script cannot call");
+	Ok(())
+}
+
+fn noop_waker() -> Waker {
+	fn clone(_: *const ()) -> RawWaker {
+		RawWaker::new(std::ptr::null(), &VTABLE)
+	}
+	fn noop(_: *const ()) {}
+	static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+	unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
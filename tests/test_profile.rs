@@ -0,0 +1,35 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use std::time::{Duration, Instant};
+
+use js_sandbox::{AnyError, Script};
+
+#[test]
+fn cpu_profile() -> Result<(), AnyError> {
+	let js_code = "function busy(ms) { const end = Date.now() + ms; let x = 0; while (Date.now() < end) { x++; } return x; }
+		function run(ms) { return busy(ms); }";
+	let mut script = Script::builder()
+		.cpu_profile()
+		.build(js_code)?;
+
+	let start = Instant::now();
+	while start.elapsed() < Duration::from_millis(100) {
+		script.call_void("run", &20, None)?;
+	}
+
+	let profile = script.take_cpu_profile()?;
+	let busy = profile.nodes.iter().find(|node| node.function_name == "busy").expect("Profile contains busy()");
+	assert_eq!(busy.line, 1);
+	assert!(busy.total_samples > 0);
+	assert!(profile.nodes[0].total_samples >= busy.total_samples, "Root includes all samples");
+	assert!(profile.folded_stacks().contains("run;busy "));
+	Ok(())
+}
+
+#[test]
+fn cpu_profile_disabled() -> Result<(), AnyError> {
+	let mut script = Script::from_string("function f() {}")?;
+
+	assert!(script.take_cpu_profile().is_err(), "Profiling must be enabled");
+	Ok(())
+}
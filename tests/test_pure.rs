@@ -0,0 +1,166 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Capability, Date, SandboxPolicy, Script};
+
+#[test]
+fn pure_computation() -> Result<(), AnyError> {
+	let js_code = "function formula(x) { return Math.sqrt(x.a * x.a + x.b * x.b) + JSON.parse('[1, 2]').length; }";
+	let mut script = Script::builder().pure_sandbox().build(js_code)?;
+
+	let result: f64 = script.call("formula", &serde_json::json!({"a": 3, "b": 4}), None)?;
+	assert_eq!(result, 7.0);
+
+	let mut script = Script::builder().pure_sandbox().build("function year(date) { return date.getUTCFullYear(); }")?;
+	let year: i32 = script.call("year", &Date("2021-03-04T05:06:07Z".to_string()), None)?;
+	assert_eq!(year, 2021, "Dates passed by the host arrive");
+	Ok(())
+}
+
+#[test]
+fn removed_globals_throw() -> Result<(), AnyError> {
+	let js_code = r#"
+		function attempt(code) {
+			try {
+				eval(code);
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}"#;
+
+	let mut script = Script::builder()
+		.add_fn("square", |arg| Ok((arg.as_i64().unwrap_or(0).pow(2)).into()))
+		.pure_sandbox()
+		.build(js_code)?;
+
+	for (code, expected) in [
+		("Date.now()", "ReferenceError"),
+		("new Date()", "ReferenceError"),
+		("Math.random()", "TypeError"),
+		("console.log('hi')", "ReferenceError"),
+		("Deno.core.ops()", "ReferenceError"),
+		("Intl.DateTimeFormat()", "ReferenceError"),
+		("addEventListener('error', () => {})", "ReferenceError"),
+		("host.square(3)", "ok"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	Ok(())
+}
+
+#[test]
+fn sandbox_policy() -> Result<(), AnyError> {
+	let js_code = r#"
+		function attempt(code) {
+			try {
+				eval(code);
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}"#;
+
+	let mut script = Script::builder().policy(SandboxPolicy::new()).build(js_code)?;
+	for (code, expected) in [
+		("console.log('hi')", "ReferenceError"),
+		("setTimeout(() => {}, 10)", "ReferenceError"),
+		("Math.random()", "TypeError"),
+		("Date.now()", "ReferenceError"),
+		("Deno.core.ops()", "ReferenceError"),
+		("Math.sqrt(4)", "ok"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	assert!(script.add_fn("square", |arg| Ok(arg)).is_err(), "Host functions are denied after building");
+
+	let denied = Script::builder().add_fn("square", |arg| Ok(arg)).policy(SandboxPolicy::new()).build(js_code);
+	assert!(denied.is_err(), "Host functions are denied when building");
+
+	let policy = SandboxPolicy::permissive().deny(Capability::Date);
+	assert!(policy.allows(Capability::HostFns) && !policy.allows(Capability::Date));
+	let mut script = Script::builder().add_fn("square", |arg| Ok(arg)).policy(policy).build(js_code)?;
+	for (code, expected) in [
+		("console.log('hi')", "ok"),
+		("setTimeout(() => {}, 10)", "ok"),
+		("Math.random()", "ok"),
+		("Date.now()", "ReferenceError"),
+		("host.square(3)", "ok"),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+
+	// Dates passed by the host still arrive, even though the script cannot create them
+	let policy = SandboxPolicy::new().allow(Capability::Console);
+	let mut script = Script::builder().policy(policy).build("function year(date) { return date.getUTCFullYear(); }")?;
+	let year: i32 = script.call("year", &Date("2021-03-04T05:06:07Z".to_string()), None)?;
+	assert_eq!(year, 2021);
+	Ok(())
+}
+
+#[test]
+fn freeze_builtins() -> Result<(), AnyError> {
+	let js_code = r#"
+		function pollute() {
+			Object.prototype.isAdmin = true;
+			return ({}).isAdmin === true;
+		}
+		function pollute_strict() {
+			"use strict";
+			try {
+				Array.prototype.push = () => 0;
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}
+		function replace_json() {
+			"use strict";
+			try {
+				JSON.stringify = () => "{}";
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}
+		function replace_to_string() {
+			try {
+				Object.prototype.toString = () => "hacked";
+				return "ok";
+			} catch (e) {
+				return e.constructor.name;
+			}
+		}
+		class ParseError extends Error {
+			constructor(message) {
+				super(message);
+				this.name = "ParseError";
+			}
+		}
+		function custom_error() {
+			const point = { x: 1 };
+			point.toString = () => "(1)";
+			return [new ParseError("bad input").toString(), String(point)];
+		}"#;
+
+	let mut script = Script::from_string(js_code)?;
+	assert!(script.call::<_, bool>("pollute", &(), None)?, "Without the setting, prototypes can be polluted");
+
+	let mut script = Script::builder().freeze_builtins().build(js_code)?;
+	assert!(!script.call::<_, bool>("pollute", &(), None)?, "Sloppy-mode pollution has no effect");
+	assert_eq!(script.call::<_, String>("pollute_strict", &(), None)?, "TypeError");
+	assert_eq!(script.call::<_, String>("replace_json", &(), None)?, "TypeError");
+	assert_eq!(script.call::<_, String>("replace_to_string", &(), None)?, "TypeError", "Overridable properties throw in sloppy mode");
+
+	let result: serde_json::Value = script.call("custom_error", &(), None)?;
+	assert_eq!(result, serde_json::json!(["ParseError: bad input", "(1)"]), "Objects can override inherited properties");
+	Ok(())
+}
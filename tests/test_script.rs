@@ -1,10 +1,14 @@
 // Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
 
-use std::time::Instant;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
-use js_sandbox::{AnyError, Script};
+use js_sandbox::{AnyError, BigInt, Bytes, CallOutcome, CancellationToken, CompiledScript, Date, ImmutableScript, JsError, JsValue, PollDecision, Script, Snapshot, ThisArg};
 use util::expect_error;
 
 mod util;
@@ -27,6 +31,28 @@ struct Person {
 	age: u8,
 }
 
+#[derive(Deserialize, Debug, PartialEq)]
+struct Hook {
+	handled: bool,
+	value: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReturnedError {
+	name: String,
+	message: String,
+	stack: String,
+	code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+enum Command {
+	Move { x: i32, y: i32 },
+	Say { text: String },
+	Stop,
+}
+
 #[test]
 fn call() {
 	let src = r#"
@@ -89,98 +115,1674 @@ fn call_void() -> Result<(), AnyError> {
 }
 
 #[test]
-fn call_from_file() {
-	let mut script = Script::from_file("tests/hello.js")
-		.expect("File can be loaded");
+fn call_void_discards_result() -> Result<(), AnyError> {
+	let js_code = "var total = 0; function add(a) { total += a; return total; }";
+	let mut script = Script::from_string(js_code)?;
 
-	let args = JsArgs { text: "hi".to_string(), num: 4 };
-	let exp_result = JsResult { new_text: "hi.".to_string(), new_num: 12 };
+	script.call_void("add", &4, None)?;
+	script.call_void("add", &5, None)?;
 
-	let result: JsResult = script.call("extract", &args, None).unwrap();
-	assert_eq!(result, exp_result);
+	let total: i32 = script.call("add", &0, None)?;
+	assert_eq!(total, 9);
+	Ok(())
 }
 
 #[test]
-fn call_local_state() {
-	let src = "var i = 0;
-	function inc() { return ++i; }";
-	let mut script = Script::from_string(src)
-		.expect("Initialization succeeds");
+fn reset() -> Result<(), AnyError> {
+	let js_code = "var total = ''; function append(s) { total += s; return total; }";
+	let mut script = Script::from_string(js_code)?;
 
-	let args = ();
+	let _: String = script.call("append", &"ab", None)?;
+	script.reset()?;
 
-	let result: i32 = script.call("inc", &args, None).unwrap();
-	assert_eq!(result, 1);
+	let result: String = script.call("append", &"c", None)?;
+	assert_eq!(result, "c");
 
-	let result: i32 = script.call("inc", &args, None).unwrap();
+	let mut script = Script::from_string("let count = 0; function inc() { return ++count; }")?;
+	let _: i32 = script.call("inc", &(), None)?;
+	assert!(script.reset().is_err(), "Lexical declarations cannot be re-run");
+
+	let result: i32 = script.call("inc", &(), None)?;
+	assert_eq!(result, 2, "State is unchanged after failed reset");
+	Ok(())
+}
+
+#[test]
+fn sticky_globals() -> Result<(), AnyError> {
+	let js_code = "var scaled = config.base * 2; var seen = [];
+		function run(x) { seen.push(x); return [scaled, seen.length, typeof limit === 'undefined' ? null : limit]; }
+		function tamper() { 'use strict'; try { config.base = 0; return 'mutated'; } catch (e) { return e.constructor.name; } }
+		function redefine() {
+			delete globalThis.config;
+			try { Object.defineProperty(globalThis, 'config', { value: { base: 0 } }); return ['redefined', config.base]; }
+			catch (e) { return [e.constructor.name, config.base]; }
+		}";
+	let mut script = Script::builder().sticky_global("config", serde_json::json!({ "base": 21 })).build(js_code)?;
+
+	let result: (i32, usize, Option<i32>) = script.call("run", &1, None)?;
+	assert_eq!(result, (42, 1, None));
+
+	let result: String = script.call("tamper", &(), None)?;
+	assert_eq!(result, "TypeError", "Sticky value is frozen");
+
+	script.set_sticky_global("limit", &7)?;
+	let _: (i32, usize, Option<i32>) = script.call("run", &2, None)?;
+	script.reset()?;
+
+	let result: (i32, usize, Option<i32>) = script.call("run", &3, None)?;
+	assert_eq!(result, (42, 1, Some(7)), "Script state is cleared, sticky globals survive");
+
+	assert!(script.set_sticky_global("seen", &0).is_err(), "var declarations cannot be redefined");
+	let err = script.set_sticky_global("ratio", &f64::NAN).unwrap_err();
+	assert!(err.to_string().contains("NaN"), "Non-finite numbers are rejected: {}", err);
+
+	let result: (String, i32) = script.call("redefine", &(), None)?;
+	assert_eq!(result, ("TypeError".to_string(), 21), "Sticky global cannot be redefined by the script");
+	Ok(())
+}
+
+#[test]
+fn call_args() -> Result<(), AnyError> {
+	let js_code = "function add(a, b) { return a + b; }
+		function sum(...nums) { return [nums.length, nums.reduce((a, b) => a + b, 0)]; }
+		function describe(name, ...rest) { return name + ':' + JSON.stringify(rest); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: i32 = script.call_args("add", &(1, 2), None)?;
+	assert_eq!(result, 3);
+
+	let result: (usize, i32) = script.call_args("sum", &[1, 2, 3], None)?;
+	assert_eq!(result, (3, 6), "Rest parameter collects spread arguments");
+
+	let result: (usize, i32) = script.call_args("sum", &(), None)?;
+	assert_eq!(result, (0, 0));
+
+	let result: String = script.call_args("describe", &("x", [1], true), None)?;
+	assert_eq!(result, "x:[[1],true]");
+
+	let args: Vec<JsValue> = vec!["y".into(), serde_json::json!({ "a": 1 }), JsValue::Null];
+	let result: String = script.call_args("describe", &args, None)?;
+	assert_eq!(result, r#"y:[{"a":1},null]"#, "Arguments can be built at runtime");
+
+	let result: Result<i32, AnyError> = script.call_args("add", &5, None);
+	assert!(result.is_err(), "Arguments must be a sequence");
+	Ok(())
+}
+
+#[test]
+fn call_refs() -> Result<(), AnyError> {
+	let js_code = "var input = [1, 2, 3];
+		const config = { factor: 10 };
+		function scale(values, config) { return values.map(v => v * config.factor); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: Vec<i32> = script.call_refs("scale", &["input", "config"], None)?;
+	assert_eq!(result, vec![10, 20, 30]);
+
+	let err = script.call_refs::<Vec<i32>>("scale", &["input", "settings", "other"], None).unwrap_err();
+	assert!(err.to_string().contains("'settings', 'other' not defined"), "Missing globals are named: {}", err);
+	Ok(())
+}
+
+#[test]
+fn call_option() -> Result<(), AnyError> {
+	let js_code = "
+		function positive(a) { if (a > 0) return a; }
+		function nullable(a) { return a > 0 ? a : null; }
+		function hook(a) { return { handled: a > 0, value: a > 0 ? a : undefined }; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let some: Option<i32> = script.call("positive", &5, None)?;
+	let none: Option<i32> = script.call("positive", &-5, None)?;
+	assert_eq!(some, Some(5));
+	assert_eq!(none, None);
+
+	let some: Option<i32> = script.call("nullable", &5, None)?;
+	let none: Option<i32> = script.call("nullable", &-5, None)?;
+	assert_eq!(some, Some(5));
+	assert_eq!(none, None);
+
+	let result: Hook = script.call("hook", &-5, None)?;
+	assert_eq!(result, Hook { handled: false, value: None });
+	Ok(())
+}
+
+#[test]
+fn call_tagged_enum() -> Result<(), AnyError> {
+	let js_code = r#"
+		function describe(cmd) {
+			switch (cmd.type) {
+				case "Move": return `${Object.keys(cmd).join(",")} -> ${cmd.x},${cmd.y}`;
+				case "Say": return cmd.text;
+				default: return JSON.stringify(cmd);
+			}
+		}
+		function reverse(cmd) { return { type: "Move", x: -cmd.x, y: -cmd.y }; }"#;
+	let mut script = Script::from_string(js_code)?;
+
+	let result: String = script.call("describe", &Command::Move { x: 1, y: 2 }, None)?;
+	assert_eq!(result, "type,x,y -> 1,2");
+
+	let result: String = script.call("describe", &Command::Say { text: "hi".to_string() }, None)?;
+	assert_eq!(result, "hi");
+
+	let result: String = script.call("describe", &Command::Stop, None)?;
+	assert_eq!(result, r#"{"type":"Stop"}"#);
+
+	let result: Command = script.call("reverse", &Command::Move { x: 1, y: 2 }, None)?;
+	assert_eq!(result, Command::Move { x: -1, y: -2 });
+	Ok(())
+}
+
+#[test]
+fn call_returned_error() -> Result<(), AnyError> {
+	let js_code = r#"
+		function check() { return new Error("oops"); }
+		function checkAll() {
+			const e = new RangeError("too large");
+			e.code = "E_RANGE";
+			return [e, { nested: new TypeError("bad type") }];
+		}"#;
+	let mut script = Script::from_string(js_code)?;
+
+	let result: ReturnedError = script.call("check", &(), None)?;
+	assert_eq!(result.name, "Error");
+	assert_eq!(result.message, "oops");
+	assert!(result.stack.contains("at check"), "Stack is transported: {}", result.stack);
+	assert_eq!(result.code, None);
+
+	let result: JsValue = script.call("checkAll", &(), None)?;
+	assert_eq!(result[0]["name"], "RangeError");
+	assert_eq!(result[0]["message"], "too large");
+	assert_eq!(result[0]["code"], "E_RANGE", "Own properties are kept");
+	assert_eq!(result[1]["nested"]["message"], "bad type");
+	Ok(())
+}
+
+#[test]
+fn json_reviver_replacer() -> Result<(), AnyError> {
+	let js_code = "function size(map) { return map.size; } function make() { return { items: new Map([['x', 1]]) }; }";
+	let mut script = Script::builder()
+		.json_reviver("(key, value) => value !== null && value.__map__ ? new Map(value.__map__) : value")
+		.json_replacer("(key, value) => value instanceof Map ? { __map__: [...value] } : value")
+		.build(js_code)?;
+
+	let result: usize = script.call("size", &serde_json::json!({ "__map__": [["a", 1], ["b", 2]] }), None)?;
 	assert_eq!(result, 2);
+
+	let result: JsValue = script.call("make", &(), None)?;
+	assert_eq!(result, serde_json::json!({ "items": { "__map__": [["x", 1]] } }));
+
+	let err = Script::builder().json_reviver("42").build("").err().expect("Reviver must be a function");
+	assert!(err.to_string().contains("expected a function"), "Error states the cause: {}", err);
+	Ok(())
 }
 
 #[test]
-fn call_repeated() {
-	let src = "
-		function triple(a) { return 3 * a; }
-		function square(a) { return a * a; }";
+fn max_source_bytes() -> Result<(), AnyError> {
+	let js_code = "function f() { return 1; }";
+	Script::builder().max_source_bytes(js_code.len() as u64).build(js_code)?;
 
-	let mut script = Script::from_string(src)
+	let err = Script::builder().max_source_bytes(10).build(js_code).err().expect("Oversized source is rejected");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::SourceTooLarge { size: js_code.len() as u64, limit: 10 }));
+
+	let err = Script::builder().max_source_bytes(10).build_file("tests/hello.js").err().expect("Oversized file is rejected");
+	assert!(matches!(err.downcast_ref::<JsError>(), Some(JsError::SourceTooLarge { limit: 10, .. })));
+	Ok(())
+}
+
+#[test]
+fn call_checked() -> Result<(), AnyError> {
+	let js_code = "function items(broken) { return { total: 2, items: [{ name: 'a' }, { name: broken ? 7 : 'b' }] }; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let schema = serde_json::json!({
+		"type": "object",
+		"required": ["total", "items"],
+		"properties": {
+			"total": { "type": "integer", "minimum": 0 },
+			"items": { "type": "array", "items": { "type": "object", "properties": { "name": { "type": "string" } } } },
+		},
+		"additionalProperties": false,
+	});
+
+	let result = script.call_checked("items", &false, None, &schema)?;
+	assert_eq!(result["items"][1]["name"], "b");
+
+	let err = script.call_checked("items", &true, None, &schema).expect_err("Mismatching result is rejected");
+	assert!(err.to_string().contains("expected type \"string\", got number (at `result.items[1].name`)"), "Error names the path: {}", err);
+	Ok(())
+}
+
+#[test]
+fn call_error_undefined() {
+	let js_code = "function nothing() {}";
+	let mut script = Script::from_string(js_code)
 		.expect("Initialization succeeds");
 
-	let args = 7;
+	let result: Result<String, AnyError> = script.call("nothing", &(), None);
+	let err = result.expect_err("Undefined into String must not succeed");
 
-	let result_triple: i32 = script.call("triple", &args, None).unwrap();
-	let result_square: i32 = script.call("square", &args, None).unwrap();
+	assert!(err.to_string().contains("returned undefined"), "Error states the cause: {}", err);
+}
 
-	assert_eq!(result_triple, 21);
-	assert_eq!(result_square, 49);
+#[test]
+fn call_error_conversion() -> Result<(), AnyError> {
+	let js_code = "function pair() { return { x: 1, y: 'two' }; }
+		function fail() { throw new Error('failed'); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let err = script.call::<_, (i32, i32)>("pair", &(), None).unwrap_err();
+	match err.downcast_ref::<JsError>() {
+		Some(JsError::Conversion { function, expected, value, .. }) => {
+			assert_eq!(function, "pair");
+			assert_eq!(expected, "(i32, i32)");
+			assert_eq!(value, &serde_json::json!({ "x": 1, "y": "two" }));
+		}
+		_ => panic!("Expected conversion error, got: {}", err),
+	}
+	assert!(err.to_string().contains("JS function 'pair' returned a value which cannot be converted to `(i32, i32)`"), "{}", err);
+
+	let (value, err) = script.call_lossy::<_, String>("pair", &(), None).unwrap_err();
+	assert_eq!(value["y"], "two");
+	assert!(matches!(err.downcast_ref::<JsError>(), Some(JsError::Conversion { .. })));
+
+	let x: JsValue = script.call_lossy("pair", &(), None).map_err(|(_, e)| e)?;
+	assert_eq!(x["x"], 1);
+
+	let (value, err) = script.call_lossy::<_, String>("fail", &(), None).unwrap_err();
+	assert_eq!(value, JsValue::Null, "No value for a failed call");
+	assert!(err.to_string().contains("Error: failed"));
+	Ok(())
 }
 
 #[test]
-fn ctor_error_syntax() {
-	let src = "function triple(a) { return 3 *. a; }";
-	let script = Script::from_string(src);
+fn call_bigint() -> Result<(), AnyError> {
+	let js_code = "function big() { return 2n ** 100n; }
+		function next(n) { return n + 1n; }";
+	let mut script = Script::from_string(js_code)?;
 
-	expect_error(script, "Syntax error");
+	let as_string: String = script.call("big", &(), None)?;
+	let as_int: BigInt = script.call("big", &(), None)?;
+	assert_eq!(as_string, "1267650600228229401496703205376");
+	assert_eq!(as_int, BigInt(1 << 100));
+
+	let arg = BigInt(u64::MAX as i128 * 1000);
+	let result: BigInt = script.call("next", &arg, None)?;
+	assert_eq!(result, BigInt(u64::MAX as i128 * 1000 + 1));
+	Ok(())
 }
 
 #[test]
-fn call_error_inexistent_function() {
-	// TODO call bad
-	let src = "function triple(a) { return 3 * a; }";
-	let mut script = Script::from_string(src)
-		.expect("Initialization succeeds");
+fn call_float_roundtrip() -> Result<(), AnyError> {
+	let js_code = "function identity(x) { return x; }
+		function isNegativeZero(x) { return Object.is(x, -0); }
+		function sum() { return 0.1 + 0.2; }
+		function nan() { return NaN; }";
+	let mut script = Script::from_string(js_code)?;
 
-	let args = 7;
-	let result: Result<i32, AnyError> = script.call("tripel", &args, None);
+	let values = [0.1, 0.1 + 0.2, f64::MIN_POSITIVE, 5e-324, f64::MAX, f64::MIN, 1.2345678901234567e-200, -0.0];
+	for &value in &values {
+		let result: f64 = script.call("identity", &value, None)?;
+		assert_eq!(result.to_bits(), value.to_bits(), "{:e} survives the round trip, got {:e}", value, result);
+	}
 
-	expect_error(result, "Inexistent function");
+	let result: Vec<f64> = script.call("identity", &values, None)?;
+	assert!(result.iter().zip(&values).all(|(a, b)| a.to_bits() == b.to_bits()), "Nested values survive: {:?}", result);
+
+	assert!(script.call::<_, bool>("isNegativeZero", &-0.0, None)?, "JS receives negative zero");
+	let result: String = script.call("identity", &"__rust_negative_zero", None)?;
+	assert_eq!(result, "__rust_negative_zero", "Strings are not mistaken for negative zero");
+	assert_eq!(script.call::<_, f64>("sum", &(), None)?, 0.1 + 0.2);
+	assert_eq!(script.call::<_, Option<f64>>("nan", &(), None)?, None, "NaN arrives as null");
+	Ok(())
 }
 
 #[test]
-fn call_error_exception() {
-	let src = "function triple(a) { throw \"string_error\"; }";
-	let mut script = Script::from_string(src)
-		.expect("Initialization succeeds");
+fn call_value() -> Result<(), AnyError> {
+	let mut script = Script::from_string("function wrap(body) { return { received: body.items.length }; }")?;
 
-	let args = 7;
-	let result: Result<i32, AnyError> = script.call("triple", &args, None);
+	let body: JsValue = serde_json::from_str(r#"{"items": [1, 2, 3]}"#)?;
+	let result = script.call_value("wrap", &body, None)?;
+	assert_eq!(result, serde_json::json!({"received": 3}));
+	Ok(())
+}
 
-	expect_error(result, "Runtime exception");
+#[test]
+fn call_error_args() -> Result<(), AnyError> {
+	#[derive(Serialize)]
+	struct Sample {
+		name: String,
+		values: Vec<f64>,
+	}
+
+	let mut script = Script::from_string("function id(a) { return a; }")?;
+
+	let args = Sample { name: "s".to_string(), values: vec![1.0, f64::NAN] };
+	let result: Result<(), AnyError> = script.call("id", &args, None);
+	let message = result.unwrap_err().to_string();
+	assert!(message.contains("'id'"), "Names the function: {}", message);
+	assert!(message.contains("NaN"), "Names the invalid value: {}", message);
+	assert!(message.contains("args.values[1]"), "Contains the path: {}", message);
+
+	let mut map = std::collections::HashMap::new();
+	map.insert((1, 2), "tuple key");
+	let result: Result<(), AnyError> = script.call("id", &map, None);
+	let message = result.unwrap_err().to_string();
+	assert!(message.contains("key must be a string"), "Contains the serde error: {}", message);
+	Ok(())
 }
 
 #[test]
-fn call_error_timeout() {
-	let timeout = 200;
-	let expected_stop_time = 50;
+fn call_date() -> Result<(), AnyError> {
+	let js_code = "function epoch() { return new Date(0); }
+		function year(d) { return d.getUTCFullYear(); }
+		function invalid() { return new Date(NaN); }";
+	let mut script = Script::from_string(js_code)?;
 
-	let js_code = "function run_forever() { for(;;){} }";
-	let mut script = Script::from_string(js_code)
+	let result: String = script.call("epoch", &(), None)?;
+	assert_eq!(result, "1970-01-01T00:00:00.000Z");
+
+	let result: i32 = script.call("year", &Date("2021-03-14T15:09:26Z"), None)?;
+	assert_eq!(result, 2021);
+	let result: i32 = script.call("year", &Date(1_000_000_000_000u64), None)?;
+	assert_eq!(result, 2001);
+
+	let result: Option<Date> = script.call("invalid", &(), None)?;
+	assert_eq!(result, None);
+	Ok(())
+}
+
+#[test]
+fn call_bytes() -> Result<(), AnyError> {
+	let js_code = "function invert(bytes) { return bytes instanceof Uint8Array ? bytes.map(b => 255 - b) : null; }
+		function split(bytes) { return { head: bytes.subarray(0, 2), tail: bytes.buffer.slice(2), count: bytes.length }; }
+		function view() { return new DataView(new Uint16Array([0x0102]).buffer); }";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: Bytes = script.call("invert", &Bytes(vec![0, 1, 254]), None)?;
+	assert_eq!(result, Bytes(vec![255, 254, 1]));
+	let result: Bytes = script.call("invert", &Bytes(Vec::new()), None)?;
+	assert_eq!(result, Bytes(Vec::new()), "Empty buffers are supported");
+
+	#[derive(Deserialize)]
+	struct Parts {
+		head: Bytes,
+		tail: Bytes,
+		count: usize,
+	}
+	let parts: Parts = script.call("split", &Bytes(vec![1, 2, 3, 4, 5]), None)?;
+	assert_eq!((parts.head.0, parts.tail.0, parts.count), (vec![1, 2], vec![3, 4, 5], 5));
+
+	let result: Bytes = script.call("view", &(), None)?;
+	assert_eq!(result.0.len(), 2, "DataView is transferred as raw bytes");
+
+	// Outside of calls, Bytes serializes like other byte arrays
+	assert_eq!(serde_json::to_string(&Bytes(vec![1, 2]))?, "[1,2]");
+	Ok(())
+}
+
+#[test]
+fn call_direct() -> Result<(), AnyError> {
+	#[derive(Serialize, Deserialize, PartialEq, Debug)]
+	struct Item {
+		name: String,
+		count: u32,
+		weights: Vec<f64>,
+	}
+
+	let js_code = "function restock(item) { return { ...item, count: item.count + 10 }; }
+		function fail() { throw new RangeError('no stock'); }
+		function run_forever() { for (;;) {} }";
+	let mut script = Script::from_string(js_code)?;
+
+	let item = Item { name: "apple".to_string(), count: 5, weights: vec![0.25, 1.5] };
+	let result: Item = script.call_direct("restock", &item, None)?;
+	assert_eq!((result.name.as_str(), result.count, result.weights), ("apple", 15, vec![0.25, 1.5]));
+
+	expect_error(script.call_direct::<_, ()>("fail", &(), None), "Exceptions are reported");
+	assert!(script.call_direct::<_, ()>("missing", &(), None).is_err(), "Function must exist");
+	let err = script.call_direct::<_, ()>("run_forever", &(), Some(50)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Timeout), "Timeout applies");
+
+	let result: Item = script.call_direct("restock", &item, None)?;
+	assert_eq!(result.count, 15, "Script is usable after a timeout");
+	Ok(())
+}
+
+#[test]
+fn bind() -> Result<(), AnyError> {
+	let js_code = "function add([a, b]) { return a + b; }
+		function fail() { throw new Error('bound failure'); }
+		function spin() { for (;;) {} }";
+	let mut script = Script::from_string(js_code)?;
+
+	let add = script.bind::<(i32, i32), i32>("add")?;
+	let mut sum = 0;
+	for i in 0..1000 {
+		sum = script.call_bound(&add, &(sum, i), None)?;
+	}
+	assert_eq!(sum, 499_500);
+
+	script.add_source("override.js", "add = () => 'replaced';")?;
+	assert_eq!(script.call_bound(&add, &(1, 2), None)?, 3, "Bound function survives reassignment");
+
+	let fail = script.bind::<(), ()>("fail")?;
+	expect_error(script.call_bound(&fail, &(), None), "Exceptions are reported");
+	let spin = script.bind::<(), ()>("spin")?;
+	let err = script.call_bound(&spin, &(), Some(50)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Timeout), "Timeout applies");
+	assert!(script.bind::<(), ()>("missing").is_err(), "Function must exist");
+
+	let mut other = Script::from_string(js_code)?;
+	assert!(other.call_bound(&add, &(1, 2), None).is_err(), "Handle belongs to another script");
+	script.unbind(add)?;
+	Ok(())
+}
+
+#[test]
+fn contexts() -> Result<(), AnyError> {
+	let mut script = Script::from_string("var owner = 'script';")?;
+	let plugin = "var owner = 'plugin'; var calls = 0;
+		function info() { return [owner, ++calls, typeof console]; }
+		function spin() { for (;;) {} }";
+	let first = script.create_context(plugin, None)?;
+	let second = script.create_context(plugin, None)?;
+
+	let info: (String, u32, String) = script.call_in_context(&first, "info", &(), None)?;
+	assert_eq!(info, ("plugin".to_string(), 1, "undefined".to_string()));
+	let info: (String, u32, String) = script.call_in_context(&first, "info", &(), None)?;
+	assert_eq!(info.1, 2);
+	let info: (String, u32, String) = script.call_in_context(&second, "info", &(), None)?;
+	assert_eq!(info.1, 1, "Contexts do not share globals");
+	assert_eq!(script.get_global::<String>("owner")?, "script", "Script's globals are unaffected");
+
+	let err = script.call_in_context::<_, ()>(&first, "spin", &(), Some(50)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Timeout), "Timeout applies");
+	let info: (String, u32, String) = script.call_in_context(&first, "info", &(), None)?;
+	assert_eq!(info.1, 3, "Context is usable after a timeout");
+
+	expect_error(script.create_context("throw new Error('broken')", None), "Errors in top-level code are reported");
+	let err = script.create_context("for (;;) {}", Some(50)).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::InitTimeout));
+
+	let mut other = Script::new_empty()?;
+	assert!(other.call_in_context::<_, JsValue>(&first, "info", &(), None).is_err(), "Handle belongs to another script");
+
+	script.release_context(first)?;
+	assert_eq!(script.call_in_context::<_, (String, u32, String)>(&second, "info", &(), None)?.1, 2);
+	Ok(())
+}
+
+#[test]
+fn time_steps() -> Result<(), AnyError> {
+	let js_code = "function reads() { return [Date.now(), new Date().getTime(), Date.now()]; }
+		function checks() { return new Date() instanceof Date && Date.UTC(2000, 0) === 946684800000 && new Date(5).getTime() === 5; }
+		function year(d) { return d.getUTCFullYear(); }";
+	let mut script = Script::builder().time_steps(1_000, 250).build(js_code)?;
+
+	let result: Vec<i64> = script.call("reads", &(), None)?;
+	assert_eq!(result, vec![1_000, 1_250, 1_500]);
+
+	let result: Vec<i64> = script.call("reads", &(), None)?;
+	assert_eq!(result, vec![1_750, 2_000, 2_250], "Clock advances across calls");
+
+	assert!(script.call::<_, bool>("checks", &(), None)?);
+	let result: i32 = script.call("year", &Date("2021-03-14T15:09:26Z"), None)?;
+	assert_eq!(result, 2021);
+	Ok(())
+}
+
+#[test]
+fn deterministic() -> Result<(), AnyError> {
+	let js_code = "function sample() { return [Math.random(), Math.random(), Date.now(), performance.now()]; }
+		function inRange() { return Array.from({ length: 1000 }, Math.random).every(x => x >= 0 && x < 1); }";
+	let build = |seed| Script::builder().deterministic(seed, 1_000_000).build(js_code);
+
+	let mut first = build(42)?;
+	let mut second = build(42)?;
+	let mut other = build(43)?;
+
+	let result: (f64, f64, i64, f64) = first.call("sample", &(), None)?;
+	assert_eq!(second.call::<_, (f64, f64, i64, f64)>("sample", &(), None)?, result, "Same seed yields same sequence");
+	assert_ne!(other.call::<_, (f64, f64, i64, f64)>("sample", &(), None)?.0, result.0);
+	assert_ne!(result.0, result.1);
+	assert_eq!((result.2, result.3), (1_000_000, 0.0), "Clock stands still");
+
+	first.advance_time(Duration::from_millis(1500), None)?;
+	let result: (f64, f64, i64, f64) = first.call("sample", &(), None)?;
+	assert_eq!((result.2, result.3), (1_001_500, 1500.0), "Clock advances with the host");
+
+	assert!(first.call::<_, bool>("inRange", &(), None)?);
+	Ok(())
+}
+
+#[test]
+fn stack_trace_limit() -> Result<(), AnyError> {
+	let js_code = "function recurse(n) { if (n === 0) throw new Error('deep'); return recurse(n - 1); }
+		function frames() { try { recurse(30); } catch (e) { return e.stack.split('\\n').length - 1; } }";
+
+	let mut script = Script::from_string(js_code)?;
+	assert_eq!(script.call::<_, usize>("frames", &(), None)?, 10, "V8's default applies");
+
+	let mut script = Script::builder().stack_trace_limit(50).build(js_code)?;
+	let frames: usize = script.call("frames", &(), None)?;
+	assert!(frames > 31, "All recursions plus frames() are captured: {}", frames);
+
+	let err = script.call::<_, ()>("recurse", &30, None).unwrap_err();
+	let frames = err.to_string().lines().filter(|line| line.trim_start().starts_with("at ")).count();
+	assert!(frames > 30, "Error surfaced to Rust has all frames: {}", err);
+	Ok(())
+}
+
+#[test]
+fn source_name_and_offset() {
+	let js_code = "function fail() {\n\tthrow new Error('oops');\n}";
+	let mut script = Script::builder()
+		.source_name_and_offset("plugin.js", 42)
+		.build(js_code)
 		.expect("Initialization succeeds");
 
-	let start = Instant::now();
-	let result: Result<String, AnyError> = script.call("run_forever", &(), Some(timeout));
-	let duration = start.elapsed().as_millis() as u64;
+	let result: Result<(), AnyError> = script.call("fail", &(), None);
+	let err = result.expect_err("Call must fail");
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Error is a JsError");
 
-	expect_error(result, "Timed out");
-	assert!(duration >= timeout, "Terminates before the specified timeout (at {}ms)", duration);
-	assert!(duration < timeout + expected_stop_time, "Took longer than {}ms to terminate (stopped at {}ms)", expected_stop_time, duration);
-}
\ No newline at end of file
+	let frame = &err.frames[0];
+	assert_eq!(frame.file_name.as_deref(), Some("plugin.js"));
+	assert_eq!(frame.line_number, Some(44));
+
+	let result = Script::builder().source_name_and_offset("plugin.js", u32::MAX).build(js_code);
+	assert!(result.is_err(), "Line offset beyond i32::MAX is rejected");
+}
+
+#[test]
+fn strict_mode() -> Result<(), AnyError> {
+	let js_code = "function assign() { undeclared = 1; return typeof undeclared; }\nfunction fail() { throw new Error('oops'); }";
+
+	let mut sloppy = Script::from_string(js_code)?;
+	assert_eq!(sloppy.call::<_, String>("assign", &(), None)?, "number");
+
+	let mut strict = Script::builder().strict_mode().build(js_code)?;
+	let err = strict.call::<_, String>("assign", &(), None).unwrap_err();
+	assert!(err.to_string().contains("ReferenceError: undeclared is not defined"), "{}", err);
+
+	// The directive does not shift any position, with or without a line offset
+	let err = strict.call::<_, ()>("fail", &(), None).unwrap_err();
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Error is a JsError");
+	assert_eq!((err.frames[0].line_number, err.frames[0].column_number), (Some(2), Some(25)));
+
+	let mut strict = Script::builder().strict_mode().source_name_and_offset("plugin.js", 5).build(js_code)?;
+	assert!(strict.call::<_, String>("assign", &(), None).is_err());
+	let err = strict.call::<_, ()>("fail", &(), None).unwrap_err();
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Error is a JsError");
+	assert_eq!((err.frames[0].line_number, err.frames[0].column_number), (Some(7), Some(25)));
+	Ok(())
+}
+
+#[test]
+fn locale_and_timezone() -> Result<(), AnyError> {
+	let js_code = "function format() {
+			const date = new Date(Date.UTC(2021, 0, 2, 3, 4, 5));
+			return [
+				date.toLocaleDateString(),
+				date.toLocaleTimeString(undefined, { hour: '2-digit', minute: '2-digit', hour12: false }),
+				date.toLocaleDateString('en-US', { timeZone: 'UTC' }),
+				Intl.DateTimeFormat().resolvedOptions().timeZone,
+				new Intl.NumberFormat().resolvedOptions().locale,
+			];
+		}";
+
+	let mut script = Script::builder().locale("de-CH").timezone("Asia/Tokyo").build(js_code)?;
+	let formatted: Vec<String> = script.call("format", &(), None)?;
+	assert_eq!(formatted, ["2.1.2021", "12:04", "1/2/2021", "Asia/Tokyo", "de-CH"]);
+
+	assert!(Script::builder().timezone("Mars/Base").build(js_code).is_err(), "Unknown time zone is rejected");
+	assert!(Script::builder().locale("not a locale").build(js_code).is_err(), "Invalid locale is rejected");
+	Ok(())
+}
+
+#[test]
+fn source_map() -> Result<(), AnyError> {
+	// Generated lines 2 to 4 stem from lines 10 to 12 of src/plugin.ts
+	let map_json = r#"{"version":3,"sources":["plugin.ts"],"sourceRoot":"src","names":[],"mappings":";AASA;AACA;AACA"}"#;
+	let js_code = "// bundled\nfunction fail() {\n\tthrow new Error('oops');\n}";
+
+	let check = |mut script: Script| {
+		let err = script.call::<_, ()>("fail", &(), None).expect_err("Call must fail");
+		match JsError::from_any(&err) {
+			Some(JsError::Exception { location: Some(location), stack: Some(stack), .. }) => {
+				assert_eq!((location.file.as_str(), location.line, location.column), ("src/plugin.ts", 11, 1));
+				assert!(stack.contains("at fail (src/plugin.ts:11:1)"), "{}", stack);
+			}
+			other => panic!("Unexpected error: {:?}", other),
+		}
+	};
+
+	check(Script::builder().source_map(map_json).build(js_code)?);
+
+	let inline = "\n//# sourceMappingURL=data:application/json;base64,eyJ2ZXJzaW9uIjozLCJzb3VyY2VzIjpbInBsdWdpbi50cyJdLCJzb3VyY2VSb290Ijoic3JjIiwibmFtZXMiOltdLCJtYXBwaW5ncyI6IjtBQVNBO0FBQ0E7QUFDQSJ9";
+	check(Script::from_string(&format!("{}{}", js_code, inline))?);
+
+	assert!(Script::builder().source_map("{}").build(js_code).is_err(), "Invalid map is rejected");
+	Ok(())
+}
+
+#[test]
+fn call_from_file() {
+	let mut script = Script::from_file("tests/hello.js")
+		.expect("File can be loaded");
+
+	let args = JsArgs { text: "hi".to_string(), num: 4 };
+	let exp_result = JsResult { new_text: "hi.".to_string(), new_num: 12 };
+
+	let result: JsResult = script.call("extract", &args, None).unwrap();
+	assert_eq!(result, exp_result);
+}
+
+#[test]
+fn call_from_reader() -> Result<(), AnyError> {
+	struct Broken;
+	impl std::io::Read for Broken {
+		fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+			Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "stream broke"))
+		}
+	}
+
+	let js_code = "function where() { return new Error().stack; }";
+	let mut script = Script::from_reader(js_code.as_bytes(), "remote.js")?;
+	let stack: String = script.call("where", &(), None)?;
+	assert!(stack.contains("remote.js"), "Source name appears in stack: {}", stack);
+
+	let err = Script::from_reader(Broken, "remote.js").err().expect("I/O error fails creation");
+	assert!(err.to_string().contains("remote.js"), "Context names the source: {}", err);
+	assert_eq!(err.root_cause().to_string(), "stream broke");
+
+	let err = Script::builder().max_source_bytes(10).build_reader(js_code.as_bytes(), "remote.js").err().expect("Oversized stream is rejected");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::SourceTooLarge { size: 11, limit: 10 }));
+	Ok(())
+}
+
+#[test]
+fn on_thread() -> Result<(), AnyError> {
+	let plugin = Script::on_thread("plugin", || Script::from_string("var calls = 0; function inc() { return ++calls; }"))?;
+	assert_ne!(plugin.thread_id(), thread::current().id());
+
+	let (first, owner) = plugin.run(|script| (script.call::<_, i32>("inc", &(), None), script.thread_id()))?;
+	assert_eq!(first?, 1);
+	assert_eq!(owner, plugin.thread_id(), "Script lives on the dedicated thread");
+
+	let err = plugin.run(|_| -> i32 { panic!("closure failed") }).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::WorkerFailed));
+
+	let second: i32 = plugin.run(|script| script.call("inc", &(), None))??;
+	assert_eq!(second, 2, "State survives a panicking closure");
+
+	let err = Script::on_thread("broken", || Script::from_string("syntax error")).err().expect("Creation error is returned");
+	assert!(err.to_string().contains("SyntaxError"), "{}", err);
+	Ok(())
+}
+
+#[test]
+fn add_source() -> Result<(), AnyError> {
+	let mut script = Script::new_empty()?;
+	script.add_source("prelude.js", "var calls = 0;\nconst greeting = 'hello';")?;
+	script.add_source("plugin.js", "function greet(name) {\n\tcalls++;\n\tif (!name) throw new Error('no name');\n\treturn greeting + ' ' + name;\n}")?;
+
+	assert_eq!(script.call::<_, String>("greet", &"Rust", None)?, "hello Rust", "Later sources see earlier globals");
+
+	let err = script.call::<_, String>("greet", &"", None).unwrap_err();
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Error is a JsError");
+	assert_eq!(err.frames[0].file_name.as_deref(), Some("plugin.js"));
+	assert_eq!(err.frames[0].line_number, Some(3));
+
+	let err = script.add_source("footer.js", "function broken( {").unwrap_err();
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Syntax error is a JsError");
+	assert_eq!(err.script_resource_name.as_deref(), Some("footer.js"));
+
+	assert_eq!(script.source_index().files().collect::<Vec<_>>(), vec!["prelude.js", "plugin.js", "footer.js"]);
+	Ok(())
+}
+
+#[test]
+fn call_local_state() {
+	let src = "var i = 0;
+	function inc() { return ++i; }";
+	let mut script = Script::from_string(src)
+		.expect("Initialization succeeds");
+
+	let args = ();
+
+	let result: i32 = script.call("inc", &args, None).unwrap();
+	assert_eq!(result, 1);
+
+	let result: i32 = script.call("inc", &args, None).unwrap();
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn call_repeated() {
+	let src = "
+		function triple(a) { return 3 * a; }
+		function square(a) { return a * a; }";
+
+	let mut script = Script::from_string(src)
+		.expect("Initialization succeeds");
+
+	let args = 7;
+
+	let result_triple: i32 = script.call("triple", &args, None).unwrap();
+	let result_square: i32 = script.call("square", &args, None).unwrap();
+
+	assert_eq!(result_triple, 21);
+	assert_eq!(result_square, 49);
+}
+
+#[test]
+fn source_index() {
+	let src = "var a = 1;\nfunction ünïcode() {}\n  var b = 2;";
+	let script = Script::from_string(src)
+		.expect("Initialization succeeds");
+
+	let index = script.source_index();
+	let offset = src.encode_utf16().count() - "b = 2;".len();
+	let position = index.position("sandboxed.js", offset).expect("Offset lies within source");
+
+	assert_eq!(index.files().collect::<Vec<_>>(), vec!["sandboxed.js"]);
+	assert_eq!((position.line, position.column), (3, 7));
+	assert_eq!(index.position("sandboxed.js", offset + 100), None);
+	assert_eq!(index.position("other.js", 0), None);
+}
+
+#[test]
+fn ctor_error_syntax() {
+	let src = "function triple(a) { return 3 *. a; }";
+	let script = Script::from_string(src);
+
+	expect_error(script, "Syntax error");
+}
+
+#[test]
+fn call_error_inexistent_function() {
+	// TODO call bad
+	let src = "function triple(a) { return 3 * a; }";
+	let mut script = Script::from_string(src)
+		.expect("Initialization succeeds");
+
+	let args = 7;
+	let result: Result<i32, AnyError> = script.call("tripel", &args, None);
+
+	expect_error(result, "Inexistent function");
+}
+
+#[test]
+fn call_error_exception() {
+	let src = "function triple(a) { throw \"string_error\"; }";
+	let mut script = Script::from_string(src)
+		.expect("Initialization succeeds");
+
+	let args = 7;
+	let result: Result<i32, AnyError> = script.call("triple", &args, None);
+
+	expect_error(result, "Runtime exception");
+}
+
+#[test]
+fn call_error_thrown_value() -> Result<(), AnyError> {
+	let js_code = "function fail(kind) {
+			switch (kind) {
+				case 'string': throw 'limit reached';
+				case 'number': throw 42;
+				case 'object': throw { code: 'E_LIMIT', max: 3 };
+				default: throw new TypeError('wrong kind');
+			}
+		}";
+	let mut script = Script::from_string(js_code)?;
+
+	let thrown = |script: &mut Script, kind: &str| script.call::<_, ()>("fail", &kind, None).unwrap_err();
+
+	let err = thrown(&mut script, "string");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Thrown {
+		message: "limit reached".to_string(),
+		value: serde_json::json!("limit reached"),
+	}));
+
+	let err = thrown(&mut script, "number");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Thrown { message: "42".to_string(), value: serde_json::json!(42) }));
+
+	let err = thrown(&mut script, "object");
+	match err.downcast_ref::<JsError>() {
+		Some(JsError::Thrown { message, value }) => {
+			assert_eq!(message, r#"{"code":"E_LIMIT","max":3}"#);
+			assert_eq!(value["code"], "E_LIMIT");
+		}
+		_ => panic!("Expected thrown value, got: {}", err),
+	}
+
+	let err = thrown(&mut script, "error");
+	assert!(err.downcast_ref::<JsError>().is_none(), "Errors are reported as before");
+	assert!(err.to_string().contains("TypeError: wrong kind"));
+	Ok(())
+}
+
+#[test]
+fn error_formatting() -> Result<(), AnyError> {
+	let js_code = "function fail() {\n\tthrow new TypeError('wrong kind');\n}\nfunction reject() { throw { code: 'E_LIMIT' }; }";
+	let mut script = Script::builder().source_name_and_offset("plugin.js", 10).build(js_code)?;
+
+	let err = script.call::<_, ()>("fail", &(), None).unwrap_err();
+	let err = JsError::from_any(&err).expect("Exceptions are converted");
+	let summary = err.to_string();
+	assert_eq!(summary.lines().count(), 1, "Display is a single line: {}", summary);
+	assert!(summary.contains("TypeError: wrong kind"), "{}", summary);
+	assert!(summary.contains("plugin.js:12:"), "Source name and line are included: {}", summary);
+	assert!(format!("{:?}", err).starts_with("Exception: Uncaught TypeError"), "Debug names the variant: {:?}", err);
+	assert!(format!("{:?}", err).contains("at fail (plugin.js:12:"), "Debug includes the stack: {:?}", err);
+	assert_eq!(format!("{:?}", JsError::Timeout), "Timeout: function did not complete within its timeout");
+	match &err {
+		JsError::Exception { name, location: Some(location), .. } => {
+			assert_eq!(name.as_deref(), Some("TypeError"));
+			assert_eq!((location.file.as_str(), location.line), ("plugin.js", 12));
+		}
+		other => panic!("Unexpected error: {:?}", other),
+	}
+
+	let err = script.call::<_, ()>("reject", &(), None).unwrap_err();
+	let err = JsError::from_any(&err).expect("Thrown values are converted");
+	assert!(format!("{:?}", err).contains(r#""code": "E_LIMIT""#), "Debug includes the value: {:?}", err);
+
+	let err = Script::builder().source_name_and_offset("broken.js", 0).build("let x = ;").err().expect("Code has a syntax error");
+	match JsError::from_any(&err) {
+		Some(JsError::Exception { name, location: Some(location), .. }) => {
+			assert_eq!(name.as_deref(), Some("SyntaxError"), "Compile errors are converted as well");
+			assert_eq!((location.file.as_str(), location.line), ("broken.js", 1));
+		}
+		other => panic!("Unexpected error: {:?}", other),
+	}
+
+	let err = AnyError::from(std::io::Error::new(std::io::ErrorKind::Other, "disk full"));
+	assert_eq!(JsError::from_any(&err), None);
+	Ok(())
+}
+
+#[test]
+fn call_error_timeout() {
+	let timeout = 200;
+	let expected_stop_time = 50;
+
+	let js_code = "function run_forever() { for(;;){} }";
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let start = Instant::now();
+	let result: Result<String, AnyError> = script.call("run_forever", &(), Some(timeout));
+	let duration = start.elapsed().as_millis() as u64;
+
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Timeout));
+	assert!(duration >= timeout, "Terminates before the specified timeout (at {}ms)", duration);
+	assert!(duration < timeout + expected_stop_time, "Took longer than {}ms to terminate (stopped at {}ms)", expected_stop_time, duration);
+}
+
+#[test]
+fn call_after_timeout() {
+	let js_code = "function run_forever() { for(;;){} } function inc(a) { return a + 1; }";
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: Result<(), AnyError> = script.call("run_forever", &(), Some(50));
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Timeout));
+
+	let result: i32 = script.call("inc", &2, Some(1000))
+		.expect("Script remains usable after timeout");
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn call_outcome() -> Result<(), AnyError> {
+	let js_code = "function run_forever() { for(;;){} } function inc(a) { return a + 1; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let outcome: CallOutcome<i32> = script.call_outcome("inc", &2, Some(1000))?;
+	assert_eq!(outcome, CallOutcome::Completed(3));
+
+	let outcome: CallOutcome<()> = script.call_outcome("run_forever", &(), Some(50))?;
+	assert_eq!(outcome, CallOutcome::Terminated(JsError::Timeout));
+	assert!(!outcome.is_completed());
+
+	let result: Result<CallOutcome<()>, AnyError> = script.call_outcome("(() => { throw new Error('thrown'); })", &(), None);
+	assert!(result.is_err(), "Exceptions are errors, not terminations");
+	Ok(())
+}
+
+#[test]
+fn poll_call() -> Result<(), AnyError> {
+	let js_code = "function run_forever() { for(;;){} } function inc(a) { return a + 1; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let mut polls = 0;
+	let result: Result<(), AnyError> = script.poll_call("run_forever", &(), Duration::from_millis(10), |_elapsed| {
+		polls += 1;
+		if polls < 3 { PollDecision::Continue } else { PollDecision::Abort }
+	});
+
+	let err = result.expect_err("Aborted call must fail");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::Cancelled));
+	assert_eq!(polls, 3);
+
+	let result: i32 = script.poll_call("inc", &2, Duration::from_millis(10), |_| PollDecision::Abort)?;
+	assert_eq!(result, 3);
+	Ok(())
+}
+
+#[test]
+fn typeof_global() -> Result<(), AnyError> {
+	let js_code = "function hook() {} let count = 3; const config = {}; globalThis['odd name'] = 'x';";
+	let mut script = Script::from_string(js_code)?;
+
+	assert_eq!(script.typeof_global("hook")?, "function");
+	assert_eq!(script.typeof_global("count")?, "number");
+	assert_eq!(script.typeof_global("config")?, "object");
+	assert_eq!(script.typeof_global("odd name")?, "string");
+	assert_eq!(script.typeof_global("missing")?, "undefined");
+
+	assert!(script.is_callable("hook")?);
+	assert!(!script.is_callable("config")?);
+	Ok(())
+}
+
+#[test]
+fn has_function() -> Result<(), AnyError> {
+	let js_code = "function hook() {} const plugin = { onLoad() {}, data: 1 };
+		Object.defineProperty(globalThis, 'broken', { get() { throw new Error('getter'); } });";
+	let mut script = Script::from_string(js_code)?;
+
+	assert!(script.has_function("hook"));
+	assert!(script.has_function("plugin.onLoad"));
+	assert!(!script.has_function("plugin.data"));
+	assert!(!script.has_function("plugin.missing.deeper"));
+	assert!(!script.has_function("missing.onLoad"));
+	assert!(!script.has_function("broken"));
+	assert!(!script.has_function("plugin"));
+	Ok(())
+}
+
+#[test]
+fn globals_into() -> Result<(), AnyError> {
+	#[derive(Deserialize, Debug, PartialEq)]
+	struct GameState {
+		score: i32,
+		players: Vec<String>,
+		#[serde(rename = "level name")]
+		level: String,
+		winner: Option<String>,
+		#[serde(default)]
+		round: u32,
+	}
+
+	let js_code = "let score = 0; var players = ['ann', 'bob']; globalThis['level name'] = 'intro'; function play() { score += 10; }";
+	let mut script = Script::from_string(js_code)?;
+	script.call_void("play", &(), None)?;
+
+	let state: GameState = script.globals_into()?;
+	assert_eq!(state, GameState {
+		score: 10,
+		players: vec!["ann".to_string(), "bob".to_string()],
+		level: "intro".to_string(),
+		winner: None,
+		round: 0,
+	});
+
+	let result: Result<i32, AnyError> = script.globals_into();
+	assert!(result.is_err(), "Only structs can be read from globals");
+	Ok(())
+}
+
+#[test]
+fn immutable_script() -> Result<(), AnyError> {
+	let js_code = "function triple(a) { return 3 * a; }";
+	let script = Arc::new(ImmutableScript::from_string(js_code)?);
+
+	let threads: Vec<_> = (0..4)
+		.map(|i| {
+			let script = Arc::clone(&script);
+			thread::spawn(move || (0..10).map(|j| script.call::<_, i32>("triple", &(i * 10 + j), None)).sum::<Result<i32, _>>())
+		})
+		.collect();
+
+	let sums: Vec<i32> = threads.into_iter().map(|t| t.join().expect("Thread does not panic")).collect::<Result<_, _>>()?;
+	assert_eq!(sums, vec![135, 435, 735, 1035]);
+
+	let result: i32 = script.call("triple", &7, None)?;
+	assert_eq!(result, 21);
+	Ok(())
+}
+
+#[test]
+fn immutable_script_mixed() -> Result<(), AnyError> {
+	let shared = ImmutableScript::from_string("function inc(a) { return a + 1; }")?;
+	let shared_clone = shared.clone();
+
+	// Scripts of all kinds are dropped in an order not mirroring their creation, the worker for `shared` only at thread exit
+	let thread = thread::spawn(move || -> Result<(), AnyError> {
+		let mut plain = Script::from_string("function square(a) { return a * a; }")?;
+		let first = ImmutableScript::from_string("function double(a) { return 2 * a; }")?;
+		let second = ImmutableScript::from_string("function negate(a) { return -a; }")?;
+
+		assert_eq!(first.call::<_, i32>("double", &4, None)?, 8);
+		assert_eq!(shared_clone.call::<_, i32>("inc", &4, None)?, 5);
+		assert_eq!(second.call::<_, i32>("negate", &4, None)?, -4);
+		drop(first);
+
+		assert_eq!(plain.call::<_, i32>("square", &4, None)?, 16);
+		drop(plain);
+
+		assert_eq!(second.call::<_, i32>("negate", &5, None)?, -5);
+		Ok(())
+	});
+
+	thread.join().expect("Thread does not panic")?;
+	assert_eq!(shared.call::<_, i32>("inc", &1, None)?, 2);
+	Ok(())
+}
+
+#[test]
+fn set_global_regexp() -> Result<(), AnyError> {
+	let js_code = "function matches(s) { return ID.test(s); } function flags() { return ID.flags; }";
+	let mut script = Script::from_string(js_code)?;
+
+	script.set_global_regexp("ID", "^[a-z]+-\\d+$", "i")?;
+	assert!(script.call::<_, bool>("matches", &"Item-42", None)?);
+	assert!(!script.call::<_, bool>("matches", &"item 42", None)?);
+	assert_eq!(script.call::<_, String>("flags", &(), None)?, "i");
+
+	assert!(script.set_global_regexp("ID", "(unclosed", "").is_err(), "Invalid pattern is rejected");
+	assert!(script.set_global_regexp("ID", "a", "x").is_err(), "Unknown flag is rejected");
+	assert!(script.set_global_regexp("ID", "a", "gg").is_err(), "Duplicate flag is rejected");
+	assert_eq!(script.call::<_, String>("flags", &(), None)?, "i", "Global is unchanged after failure");
+	Ok(())
+}
+
+#[test]
+fn cancellation_token() -> Result<(), AnyError> {
+	let js_code = "function run_forever() { for(;;){} } function inc(a) { return a + 1; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let token = CancellationToken::new();
+	script.set_cancellation_token(Some(token.clone()));
+
+	let canceller = std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(50));
+		token.cancel();
+	});
+
+	let result: Result<(), AnyError> = script.call("run_forever", &(), None);
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Cancelled));
+	canceller.join().unwrap();
+
+	let result: Result<i32, AnyError> = script.call("inc", &1, None);
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Cancelled), "Cancellation is permanent");
+
+	script.set_cancellation_token(Some(CancellationToken::new()));
+	let result: i32 = script.call("inc", &1, None)?;
+	assert_eq!(result, 2);
+	Ok(())
+}
+
+#[test]
+fn termination_handle() -> Result<(), AnyError> {
+	let js_code = "function run_forever() { for(;;){} } function inc(a) { return a + 1; }";
+	let mut script = Script::from_string(js_code)?;
+	let handle = script.termination_handle();
+	assert!(!handle.terminate(), "Nothing to terminate while idle");
+
+	let remote = handle.clone();
+	let terminator = std::thread::spawn(move || {
+		while !remote.terminate() {
+			std::thread::sleep(Duration::from_millis(10));
+		}
+	});
+
+	let result: Result<(), AnyError> = script.call("run_forever", &(), None);
+	assert_eq!(result.unwrap_err().downcast_ref::<JsError>(), Some(&JsError::Cancelled));
+	terminator.join().unwrap();
+
+	let result: i32 = script.call("inc", &1, None)?;
+	assert_eq!(result, 2, "Termination only affects the running call");
+	Ok(())
+}
+
+#[test]
+fn reload_from_file() -> Result<(), AnyError> {
+	let path = std::env::temp_dir().join(format!("js-sandbox-reload-{}.js", std::process::id()));
+	std::fs::write(&path, "var score = 0; function add(n) { score += n; return score; } function exportState() { return { score }; }")?;
+
+	let mut script = Script::from_file(&path)?;
+	assert!(!script.source_modified());
+	assert_eq!(script.call::<_, i32>("add", &5, None)?, 5);
+
+	// Modification times may have a coarse resolution
+	thread::sleep(Duration::from_millis(1100));
+	std::fs::write(&path, "var score = 0; function restore(state) { score = state.score; } function add(n) { score += 2 * n; return score; }")?;
+	assert!(script.source_modified());
+
+	let mut script = script.reload(Script::builder(), |state, new| new.call_void("restore", &state, None))?;
+	assert_eq!(script.call::<_, i32>("add", &1, None)?, 7, "New code runs with the migrated state");
+	assert!(!script.source_modified());
+
+	let result = script.reload(Script::builder(), |_, _| Err(AnyError::msg("incompatible")));
+	assert_eq!(result.err().expect("Failed migration is reported").to_string(), "incompatible");
+
+	let mut script = Script::from_file(&path)?;
+	assert_eq!(script.call::<_, i32>("add", &1, None)?, 2, "Isolates are dropped in order, so new scripts can be built");
+
+	std::fs::remove_file(&path)?;
+	assert!(Script::from_string("")?.reload(Script::builder(), |_, _| Ok(())).is_err(), "Requires a file");
+	Ok(())
+}
+
+#[test]
+fn set_get_global() -> Result<(), AnyError> {
+	#[derive(Serialize)]
+	struct Config {
+		rate: f64,
+		names: Vec<&'static str>,
+	}
+
+	let js_code = "let total = 0; const limit = 10; function add(n) { total += n * config.rate; return total; }";
+	let mut script = Script::from_string(js_code)?;
+
+	script.set_global("config", &Config { rate: 2.0, names: vec!["a"] })?;
+	script.call_void("add", &3, None)?;
+	assert_eq!(script.get_global::<f64>("total")?, 6.0);
+	assert_eq!(script.get_global::<Vec<String>>("config.names").ok(), None, "Names are not expressions");
+
+	script.set_global("total", &100)?;
+	assert_eq!(script.call::<_, f64>("add", &1, None)?, 102.0, "Lexical globals are assigned");
+	assert_eq!(script.get_global::<i32>("limit")?, 10);
+
+	assert!(script.set_global("limit", &5).is_err(), "Constants cannot be assigned");
+	assert_eq!(script.get_global::<Option<i32>>("missing")?, None);
+	assert!(script.get_global::<i32>("missing").is_err());
+
+	script.set_global("odd name", &"works")?;
+	assert_eq!(script.get_global::<String>("odd name")?, "works");
+	Ok(())
+}
+
+#[test]
+fn lexical_globals() -> Result<(), AnyError> {
+	let js_code = "const config = { mode: 'test' }; let count = 1;
+		function globals() { return [globalThis.config ?? null, globalThis.count ?? null]; }
+		function bump() { globalThis.count += 1; return count; }";
+
+	let mut script = Script::from_string(js_code)?;
+	let result: (Option<JsValue>, Option<i32>) = script.call("globals", &(), None)?;
+	assert_eq!(result, (None, None), "Lexical bindings are not properties of globalThis");
+
+	let mut script = Script::builder()
+		.expose_lexical_globals(&["config", "count"])
+		.build(js_code)?;
+	let result: (JsValue, i32) = script.call("globals", &(), None)?;
+	assert_eq!(result, (serde_json::json!({"mode": "test"}), 1));
+	let result: i32 = script.call("bump", &(), None)?;
+	assert_eq!(result, 2);
+
+	let result = Script::builder().expose_lexical_globals(&["missing"]).build(js_code);
+	assert!(result.is_err(), "Undeclared bindings cannot be exposed");
+	Ok(())
+}
+
+#[test]
+fn function_handle() -> Result<(), AnyError> {
+	let js_code = "function make_adder(n) { return a => a + n; } function not_a_factory() { return 5; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let add_2 = script.call_handle("make_adder", &2, None)?;
+	let add_10 = script.call_handle("make_adder", &10, None)?;
+	let result: i32 = script.invoke(&add_2, &1, None)?;
+	assert_eq!(result, 3);
+	let result: i32 = script.invoke(&add_10, &1, None)?;
+	assert_eq!(result, 11);
+
+	assert!(script.call_handle("not_a_factory", &(), None).is_err(), "Result must be a function");
+
+	let mut other = Script::from_string(js_code)?;
+	let result: Result<i32, AnyError> = other.invoke(&add_2, &1, None);
+	assert!(result.is_err(), "Handle belongs to another script");
+
+	script.release(add_2)?;
+	let result: i32 = script.invoke(&add_10, &1, None)?;
+	assert_eq!(result, 11);
+	Ok(())
+}
+
+#[test]
+fn object_handle() -> Result<(), AnyError> {
+	let js_code = "class Counter {
+			#count = 0;
+			constructor(step) { this.step = step; }
+			add(times) { this.#count += this.step * times; return this.#count; }
+			get() { return this.#count; }
+		}
+		function make_counter(step) { return new Counter(step); }
+		function not_an_object() { return 5; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let by_2 = script.call_object("make_counter", &2, None)?;
+	let by_5 = script.call_object("make_counter", &5, None)?;
+	assert_eq!(script.call_method::<_, i32>(&by_2, "add", &(3,), None)?, 6);
+	assert_eq!(script.call_method::<_, i32>(&by_2, "add", &(1,), None)?, 8, "Object keeps its state");
+	assert_eq!(script.call_method::<_, i32>(&by_5, "get", &(), None)?, 0);
+
+	assert!(script.call_object("not_an_object", &(), None).is_err(), "Result must be an object");
+	let result: Result<i32, AnyError> = script.call_method(&by_2, "missing", &(), None);
+	assert!(result.is_err(), "Method must exist");
+
+	let mut other = Script::from_string(js_code)?;
+	let result: Result<i32, AnyError> = other.call_method(&by_2, "get", &(), None);
+	assert!(result.is_err(), "Handle belongs to another script");
+
+	script.release_object(by_2)?;
+	assert_eq!(script.call_method::<_, i32>(&by_5, "add", &(1,), None)?, 5);
+	Ok(())
+}
+
+#[test]
+fn heap_stats() -> Result<(), AnyError> {
+	let js_code = "var data = []; function grow(n) { for (let i = 0; i < n; ++i) { data.push({ i, text: 'x'.repeat(100) }); } }";
+	let mut script = Script::from_string(js_code)?;
+
+	let before = script.memory_usage_bytes();
+	script.call_void("grow", &10_000, None)?;
+	let stats = script.heap_stats();
+
+	assert!(stats.used_heap_size > before, "Heap grows with allocations");
+	assert!(stats.used_heap_size <= stats.total_heap_size);
+	assert!(stats.total_heap_size <= stats.heap_size_limit);
+	Ok(())
+}
+
+#[test]
+fn script_stats() -> Result<(), AnyError> {
+	let js_code = "function busy(ms) { const end = Date.now() + ms; while (Date.now() < end) {} }
+		function fail() { throw new Error('failed'); }";
+	let mut script = Script::from_string(js_code)?;
+	assert_eq!(script.stats().calls, 0, "Top-level code is not a call");
+
+	let durations = Rc::new(Cell::new(Duration::default()));
+	let sink = durations.clone();
+	script.call_duration_handler(move |duration| sink.set(sink.get() + duration));
+
+	script.call_void("busy", &20, None)?;
+	assert!(script.call_void("fail", &(), None).is_err());
+
+	let stats = script.stats();
+	assert_eq!(stats.calls, 2, "Failed calls count as well");
+	assert!(stats.execution_time >= Duration::from_millis(20));
+	assert_eq!(stats.execution_time, durations.get(), "Handler receives every call's duration");
+	assert!(stats.heap.used_heap_size > 0 && stats.heap.used_heap_size <= stats.heap.total_heap_size);
+	Ok(())
+}
+
+#[test]
+fn initial_heap_size() -> Result<(), AnyError> {
+	let js_code = "var data = []; function grow(n) { for (let i = 0; i < n; ++i) { data.push({ i }); } return data.length; }";
+	let default_stats = Script::from_string(js_code)?.heap_stats();
+	let default_limit = default_stats.heap_size_limit;
+
+	let mut script = Script::builder().initial_heap_size(64 << 20).build(js_code)?;
+	let total = script.heap_stats().total_heap_size;
+	assert!(
+		total > default_stats.total_heap_size,
+		"Heap starts out larger than by default ({} vs. {} bytes)",
+		total,
+		default_stats.total_heap_size
+	);
+
+	let result: usize = script.call("grow", &10_000, None)?;
+	assert_eq!(result, 10_000);
+	assert!(script.heap_stats().heap_size_limit >= 64 << 20);
+
+	let result = Script::builder().initial_heap_size(default_limit * 2).build(js_code);
+	assert!(result.is_err(), "Initial size must not exceed the limit");
+	Ok(())
+}
+
+#[test]
+fn max_heap_size() -> Result<(), AnyError> {
+	let js_code = "var data = []; function grow(n) { for (let i = 0; i < n; ++i) { data.push(new Array(1000).fill(i)); } return data.length; } function clear() { data = []; }";
+	let limit = 32 << 20;
+	let mut script = Script::builder().max_heap_size(limit).build(js_code)?;
+
+	let result: usize = script.call("grow", &10, None)?;
+	assert_eq!(result, 10);
+
+	let result: Result<usize, AnyError> = script.call("grow", &1_000_000, None);
+	let err = result.expect_err("Allocating beyond the limit fails");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::HeapLimitExceeded { limit }));
+
+	script.call::<_, ()>("clear", &(), None)?;
+	let result: usize = script.call("grow", &10, None)?;
+	assert_eq!(result, 10, "Script remains usable");
+	Ok(())
+}
+
+#[test]
+fn isolate_reuse() -> Result<(), AnyError> {
+	js_sandbox::enable_isolate_reuse()?;
+
+	for i in 0..3 {
+		let js_code = format!("const id = {}; var leaked; function probe() {{ const old = leaked; leaked = id; return [id, old ?? null]; }}", i);
+		let mut script = Script::from_string(&js_code)?;
+
+		let result: (i32, Option<i32>) = script.call("probe", &(), None)?;
+		assert_eq!(result, (i, None), "No state is shared across scripts");
+	}
+	js_sandbox::enable_isolate_reuse()?;
+
+	// Scripts alive at the same time, the first of which takes over the prepared runtime
+	let mut outer = Script::from_string("function name() { return 'outer'; }")?;
+	let mut inner = Script::from_string("function name() { return 'inner'; }")?;
+	assert_eq!(inner.call::<_, String>("name", &(), None)?, "inner");
+	drop(inner);
+	assert_eq!(outer.call::<_, String>("name", &(), None)?, "outer");
+	drop(outer);
+
+	let thread = thread::spawn(|| -> Result<(), AnyError> {
+		let mut script = Script::from_string("function one() { return 1; }")?;
+		assert!(js_sandbox::enable_isolate_reuse().is_err(), "Cannot be enabled while a script is alive");
+
+		drop(script);
+		js_sandbox::enable_isolate_reuse()?;
+		script = Script::from_string("function two() { return 2; }")?;
+		assert_eq!(script.call::<_, i32>("two", &(), None)?, 2);
+		Ok(())
+	});
+	thread.join().expect("Thread does not panic")
+}
+
+#[test]
+fn allow_calls() -> Result<(), AnyError> {
+	let js_code = "var entered = false; function onTick(a) { return a + 1; } function internal() { entered = true; }";
+	let mut script = Script::builder()
+		.allow_calls(&["onTick"])
+		.build(js_code)?;
+
+	let result: i32 = script.call("onTick", &1, None)?;
+	assert_eq!(result, 2);
+
+	let err = script.call_void("internal", &(), None).unwrap_err();
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::NotAllowed { function: "internal".to_string() }));
+
+	let err = script.call_value("onTick(0) || internal", &JsValue::Null, None).unwrap_err();
+	assert!(matches!(err.downcast_ref::<JsError>(), Some(JsError::NotAllowed { .. })), "Names must match exactly");
+
+	let entered = script.eval_async("entered", None)?;
+	assert_eq!(entered, false, "Disallowed function was not executed");
+	Ok(())
+}
+
+#[test]
+fn compile_only() -> Result<(), AnyError> {
+	Script::compile_only("host.sideEffect(); function f(a) { return a; }")?;
+
+	let result = Script::compile_only("function f(a) { return a;");
+	expect_error(result, "Syntax error");
+	Ok(())
+}
+
+#[test]
+fn engine_version() {
+	let version = js_sandbox::engine_version();
+
+	assert!(version.v8.split('.').all(|part| part.parse::<u32>().is_ok()), "V8 version is numeric: {}", version.v8);
+	assert_eq!(version.deno_core, "0.84");
+
+	// The reported series is maintained by hand, so it must follow the dependency whenever that is updated
+	let manifest = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).expect("Read Cargo.toml");
+	let required = manifest
+		.lines()
+		.find_map(|line| line.strip_prefix("deno_core = \""))
+		.expect("Cargo.toml depends on deno_core");
+	assert!(required.starts_with(&format!("{}.", version.deno_core)), "Cargo.toml requires deno_core {}", required);
+
+	let lock = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.lock")).expect("Read Cargo.lock");
+	let resolved = lock
+		.split("[[package]]")
+		.find(|package| package.contains("\nname = \"deno_core\"\n"))
+		.and_then(|package| package.lines().find_map(|line| line.strip_prefix("version = \"")))
+		.expect("Cargo.lock resolves deno_core");
+	assert!(resolved.starts_with(&format!("{}.", version.deno_core)), "Cargo.lock resolves deno_core {}", resolved);
+}
+
+#[test]
+fn source_transform() -> Result<(), AnyError> {
+	let mut script = Script::builder()
+		.source_transform(|code| Ok(code.replace("__VERSION__", "3")))
+		.source_transform(|code| Ok(format!("{}\nfunction traced() {{ return 'traced'; }}", code)))
+		.build("function version() { return __VERSION__; }")?;
+
+	let result: i32 = script.call("version", &(), None)?;
+	assert_eq!(result, 3);
+	let result: String = script.call("traced", &(), None)?;
+	assert_eq!(result, "traced");
+
+	let result = Script::builder()
+		.source_transform(|_| Err(AnyError::msg("loops are not allowed")))
+		.build("for (;;) {}");
+	let err = result.err().expect("Failing transform must fail the build");
+	assert_eq!(err.to_string(), "loops are not allowed");
+	Ok(())
+}
+
+#[test]
+fn init_timeout() -> Result<(), AnyError> {
+	let start = Instant::now();
+	let err = Script::builder().init_timeout(50).build("for (;;) {}").err().expect("Endless top-level code is aborted");
+	assert_eq!(err.downcast_ref::<JsError>(), Some(&JsError::InitTimeout));
+	assert!(start.elapsed() < Duration::from_secs(5), "Fails fast: {:?}", start.elapsed());
+
+	let mut script = Script::builder().init_timeout(5000).build("var ready = true; function f() { return ready; }")?;
+	assert!(script.call::<_, bool>("f", &(), None)?);
+	Ok(())
+}
+
+#[test]
+fn call_raw_bytes() -> Result<(), AnyError> {
+	let js_code = "function reply(req) { return { id: req.id, items: [1, 'two'], big: 12n }; } function none() {}";
+	let mut script = Script::from_string(js_code)?;
+
+	let bytes = script.call_raw_bytes("reply", &serde_json::json!({ "id": 5 }), None)?;
+	assert_eq!(bytes, br#"{"id":5,"items":[1,"two"],"big":"12"}"#.to_vec());
+
+	let bytes = script.call_raw_bytes("none", &(), None)?;
+	assert_eq!(bytes, b"null".to_vec());
+	Ok(())
+}
+
+#[test]
+fn error_event_listener() -> Result<(), AnyError> {
+	let js_code = r#"
+		var logged = [];
+		addEventListener("error", event => {
+			logged.push(event.message);
+			if (event.error.recoverable) {
+				event.preventDefault();
+			}
+		});
+		function fail(recoverable) {
+			const e = new Error(recoverable ? "minor" : "fatal");
+			e.recoverable = recoverable;
+			throw e;
+		}
+		function log() { return logged; }
+		Object.defineProperty(globalThis, "broken", { get() { throw new Error("getter"); } });"#;
+	let mut script = Script::from_string(js_code)?;
+
+	let result: Option<i32> = script.call("fail", &true, None)?;
+	assert_eq!(result, None, "Prevented errors make the call return undefined");
+
+	let err = script.call::<_, ()>("fail", &false, None).unwrap_err();
+	assert!(err.to_string().contains("fatal"), "Unhandled errors fail the call: {}", err);
+
+	// Failures of js-sandbox's own helpers are not the script's errors
+	assert!(script.get_global::<i32>("broken").is_err());
+	assert!(script.set_global_regexp("pattern", "(", "").is_err());
+
+	let logged: Vec<String> = script.call("log", &(), None)?;
+	assert_eq!(logged, vec!["minor", "fatal"]);
+
+	let js_code = "globalThis.onerror = (message, source, line, column, error) => error === 'ignore'; function fail(v) { throw v; }";
+	let mut script = Script::from_string(js_code)?;
+	script.call_void("fail", &"ignore", None)?;
+	assert!(script.call_void("fail", &"other", None).is_err());
+	Ok(())
+}
+
+#[test]
+fn call_with_this() -> Result<(), AnyError> {
+	let js_code = "class Counter { constructor(n) { this.n = n; } add(k) { this.n += k; return this.n; } }
+		var counters = [new Counter(0), new Counter(100)];";
+	let mut script = Script::from_string(js_code)?;
+
+	let result: i32 = script.call_with_this("counters[1]", "Counter.prototype.add", &5, None)?;
+	assert_eq!(result, 105);
+	let result: i32 = script.call_with_this("counters[1]", "Counter.prototype.add", &5, None)?;
+	assert_eq!(result, 110, "Expression receivers are modified in place");
+
+	let this = serde_json::json!({ "n": 1 });
+	let result: i32 = script.call_with_this(this.clone(), "Counter.prototype.add", &2, None)?;
+	assert_eq!(result, 3);
+	let result: i32 = script.call_with_this(ThisArg::Value(this), "Counter.prototype.add", &2, None)?;
+	assert_eq!(result, 3, "Value receivers are fresh for each call");
+	Ok(())
+}
+
+#[test]
+fn compiled_script() -> Result<(), AnyError> {
+	let js_code = "var calls = 0; function next(step) { calls += step; return calls; }
+		function scaled(x) { return host.scale(x); }";
+	let compiled = CompiledScript::compile(js_code)?;
+	assert!(compiled.cache_size() > 0, "V8 produces a code cache");
+
+	let mut first = compiled.instantiate()?;
+	let mut second = compiled.instantiate()?;
+	assert_eq!(first.call::<_, i32>("next", &2, None)?, 2);
+	assert_eq!(first.call::<_, i32>("next", &2, None)?, 4);
+	assert_eq!(second.call::<_, i32>("next", &1, None)?, 1, "Instances have separate state");
+
+	let shared = compiled.clone();
+	let result = thread::spawn(move || shared.instantiate()?.call::<_, i32>("next", &7, None)).join().unwrap()?;
+	assert_eq!(result, 7);
+
+	let mut configured = Script::builder().add_fn("scale", |x| Ok((x.as_i64().unwrap_or(0) * 10).into())).build_compiled(&compiled)?;
+	assert_eq!(configured.call::<_, i32>("scaled", &4, None)?, 40);
+
+	assert!(CompiledScript::compile("function (").is_err(), "Syntax errors are reported");
+	Ok(())
+}
+
+#[test]
+fn compiled_script_from_code_cache() -> Result<(), AnyError> {
+	let js_code = "function next(step) { return step + 1; }";
+	let code_cache = CompiledScript::compile(js_code)?.code_cache().to_vec();
+	assert_eq!(code_cache.len(), CompiledScript::compile(js_code)?.cache_size());
+
+	let restored = CompiledScript::from_code_cache(js_code, code_cache.clone());
+	assert_eq!(restored.instantiate()?.call::<_, i32>("next", &1, None)?, 2);
+
+	let other_source = CompiledScript::from_code_cache("function next(step) { return step + 2; }", code_cache);
+	assert_eq!(other_source.instantiate()?.call::<_, i32>("next", &1, None)?, 3, "Mismatching cache is rejected");
+
+	let corrupted = CompiledScript::from_code_cache(js_code, vec![0xde, 0xad, 0xbe, 0xef]);
+	assert_eq!(corrupted.instantiate()?.call::<_, i32>("next", &1, None)?, 2, "Corrupted cache is rejected");
+	Ok(())
+}
+
+#[test]
+fn snapshot() -> Result<(), AnyError> {
+	let js_code = "var calls = 0; var table = []; for (let i = 0; i < 100; ++i) { table.push(i * i); }
+		function next(step) { calls += step; return calls + table[10]; }";
+	let snapshot = Snapshot::create(js_code)?;
+	assert!(snapshot.size() > 0);
+
+	let mut first = snapshot.instantiate()?;
+	let mut second = Script::from_snapshot(&snapshot)?;
+	assert_eq!(first.call::<_, i32>("next", &2, None)?, 102, "Top-level state is restored");
+	assert_eq!(first.call::<_, i32>("next", &2, None)?, 104);
+	assert_eq!(second.call::<_, i32>("next", &1, None)?, 101, "Instances have separate state");
+
+	let shared = snapshot.clone();
+	let result = thread::spawn(move || shared.instantiate()?.call::<_, i32>("next", &7, None)).join().unwrap()?;
+	assert_eq!(result, 107);
+
+	first.reset()?;
+	assert_eq!(first.call::<_, i32>("next", &3, None)?, 103, "Reset runs the top-level code again");
+
+	assert!(Snapshot::create("function (").is_err(), "Syntax errors are reported");
+	Ok(())
+}
+
+#[test]
+fn garbage_input_never_panics() {
+	const FRAGMENTS: &[&str] = &[
+		"function", "f", "(", ")", "{", "}", "[", "]", "=>", ";", "\n", "/*", "*/", "//", "\"", "'", "`", "${", "for", "while",
+		"return", "throw", "new", "class", "0x", "1e999", "\\u{", "ä", "€", "🦀", "\u{0}", "\u{feff}", "__rust", "host", ".", ",",
+	];
+
+	// Deterministic xorshift, so that a failure can be reproduced
+	let mut state = 0x2545_f491_4f6c_dd1d_u64;
+	let mut next = move || {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		state as usize
+	};
+
+	for round in 0..200 {
+		let source: String = (0..next() % 24).map(|_| FRAGMENTS[next() % FRAGMENTS.len()]).collect();
+		let fn_name: String = (0..next() % 3).map(|_| FRAGMENTS[next() % FRAGMENTS.len()]).collect();
+		let arg = JsValue::String(source.chars().rev().collect());
+
+		let outcome = std::panic::catch_unwind(|| {
+			// Gas instrumentation parses the source itself, so it is exercised as well
+			let builder = if round % 2 == 0 { Script::builder().gas_limit(1000) } else { Script::builder() };
+			if let Ok(mut script) = builder.build(&source) {
+				let _ = script.call::<_, JsValue>(&fn_name, &arg, Some(100));
+				let _ = script.call::<_, JsValue>("f", &(), Some(100));
+			}
+			let _ = Script::compile_only(&source);
+		});
+		assert!(outcome.is_ok(), "Panicked for source {:?} and function {:?}", source, fn_name);
+	}
+
+	let invalid_utf8: &[u8] = &[b'f', 0xff, 0xfe, b'(', 0xc3];
+	assert!(Script::from_reader(invalid_utf8, "invalid.js").is_err(), "Invalid UTF-8 is an error");
+}
+
+#[test]
+fn call_with_iter() -> Result<(), AnyError> {
+	let js_code = "var kept;
+		function keep(input) { kept = input; }
+		function firstLarge(input) { for (const x of input) { if (x > 10) { return x; } } return null; }
+		function sum(input) { let total = 0; for (const x of input) { total += x; } for (const x of input) {} return total; }
+		function pullLater() { return [...kept]; }";
+	let mut script = Script::from_string(js_code)?;
+
+	let pulled = Rc::new(Cell::new(0));
+	let counter = pulled.clone();
+	let input = (1..1_000_000).map(move |i| {
+		counter.set(counter.get() + 1);
+		i * 4
+	});
+	let result: Option<i32> = script.call_with_iter("firstLarge", input, None)?;
+	assert_eq!(result, Some(12));
+	assert_eq!(pulled.get(), 3, "Only consumed items are pulled");
+
+	let result: Result<i32, AnyError> = script.call_with_iter("sum", vec![1, 2, 3], None);
+	assert!(result.unwrap_err().to_string().contains("only be iterated once"));
+
+	script.call_with_iter::<_, ()>("keep", vec![1], None)?;
+	let result: Result<Vec<i32>, AnyError> = script.call("pullLater", &(), None);
+	assert!(result.is_err(), "Iterable is bound to its call");
+	Ok(())
+}
@@ -0,0 +1,40 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Script};
+
+#[test]
+#[cfg(feature = "typescript")]
+fn typescript_file() -> Result<(), AnyError> {
+	let mut script = Script::from_file("tests/plugin.ts")?;
+
+	let result: f64 = script.call("length", &serde_json::json!({ "x": 3, "y": 4 }), None)?;
+	assert_eq!(result, 5.0);
+
+	let err = script.call::<_, ()>("fail", &"oops", None).unwrap_err();
+	let err = err.downcast_ref::<deno_core::error::JsError>().expect("Error is a JsError");
+	assert_eq!(err.frames[0].file_name.as_deref(), Some("plugin.ts"));
+	assert_eq!(err.frames[0].line_number, Some(11), "Line matches the TypeScript source");
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "typescript")]
+fn typescript_string() -> Result<(), AnyError> {
+	let mut script = Script::builder().typescript().build("const twice = (a: number): number => 2 * a;")?;
+
+	let result: i32 = script.call("twice", &21, None)?;
+	assert_eq!(result, 42);
+
+	// Scripts are not modules, so sloppy-mode code is accepted
+	let mut script = Script::builder().typescript().build("var package: number = 20; function answer(): number { return package + 22; }")?;
+	let result: i32 = script.call("answer", &(), None)?;
+	assert_eq!(result, 42);
+	Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "typescript"))]
+fn typescript_requires_feature() {
+	let err = Script::from_file("tests/plugin.ts").err().expect("TypeScript is not available");
+	assert!(err.to_string().contains("`typescript` feature"), "{}", err);
+}
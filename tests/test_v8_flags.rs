@@ -0,0 +1,18 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// V8 flags are process-global, so this file must contain a single test which runs before any script is created.
+
+use js_sandbox::{AnyError, Script};
+
+#[test]
+fn set_v8_flags() -> Result<(), AnyError> {
+	js_sandbox::set_v8_flags(&["--stack-size=500", "--max-old-space-size=128"])?;
+	assert!(js_sandbox::set_v8_flags(&["--jitless"]).is_err(), "Flags can only be set once");
+
+	let mut script = Script::from_string("function id(a) { return a; }")?;
+	let result: i32 = script.call("id", &3, None)?;
+	assert_eq!(result, 3);
+
+	assert!(js_sandbox::set_v8_flags(&[]).is_err(), "Flags cannot be set after script creation");
+	Ok(())
+}
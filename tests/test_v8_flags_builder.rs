@@ -0,0 +1,23 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+// V8 flags are process-global, so this file must contain a single test which runs before any script is created.
+
+use js_sandbox::{AnyError, Script};
+
+#[test]
+fn builder_v8_flags() -> Result<(), AnyError> {
+	let js_code = "function id(a) { return a; }";
+
+	let mut script = Script::builder().v8_flags(&["--jitless"]).build(js_code)?;
+	let result: i32 = script.call("id", &3, None)?;
+	assert_eq!(result, 3);
+
+	let mut script = Script::builder().v8_flags(&["--jitless"]).build(js_code)?;
+	let result: i32 = script.call("id", &4, None)?;
+	assert_eq!(result, 4, "Builders may repeat the flags already set");
+
+	let err = Script::builder().v8_flags(&["--stack-size=500"]).build(js_code).err().expect("Flags differ from the ones set");
+	assert!(err.to_string().contains("already set to `--jitless`"), "{}", err);
+	assert!(js_sandbox::set_v8_flags(&["--jitless"]).is_err(), "Flags can only be set once");
+	Ok(())
+}
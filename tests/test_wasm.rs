@@ -0,0 +1,145 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Capability, SandboxPolicy, Script, WasmOptions};
+
+// Exports add(a: i32, b: i32) -> i32
+const ADD_WASM: &[u8] = &[
+	0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+	0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // type section: (i32, i32) -> i32
+	0x03, 0x02, 0x01, 0x00, // function section
+	0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00, // export section: "add"
+	0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, // code section: local.get 0, local.get 1, i32.add
+];
+
+const JS_CODE: &str = r#"
+	const header = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+	const modules = {
+		add: new Uint8Array(ADD_WASM),
+		// Memory section with one memory: 1 page initially, at most 2 pages
+		smallMemory: new Uint8Array([...header, 0x05, 0x04, 0x01, 0x01, 0x01, 0x02]),
+		// ...without a maximum
+		unboundedMemory: new Uint8Array([...header, 0x05, 0x03, 0x01, 0x00, 0x01]),
+		// ...with a maximum of 255 pages
+		largeMemory: new Uint8Array([...header, 0x05, 0x05, 0x01, 0x01, 0x01, 0xff, 0x01]),
+	};
+
+	function attempt(code) {
+		try {
+			return JSON.stringify(eval(code) ?? null);
+		} catch (e) {
+			return "error: " + e.message;
+		}
+	}
+
+	async function attemptAsync(code) {
+		try {
+			return JSON.stringify((await eval(code)) ?? null);
+		} catch (e) {
+			return "error: " + e.message;
+		}
+	}"#;
+
+fn js_code() -> String {
+	let bytes: Vec<String> = ADD_WASM.iter().map(u8::to_string).collect();
+	JS_CODE.replace("ADD_WASM", &format!("[{}]", bytes.join(", ")))
+}
+
+fn limited() -> WasmOptions {
+	WasmOptions {
+		max_module_bytes: 64,
+		max_memory_bytes: 4 * 64 * 1024,
+	}
+}
+
+#[test]
+fn wasm_unrestricted() -> Result<(), AnyError> {
+	let mut script = Script::from_string(&js_code())?;
+	let result: String = script.call("attempt", &"new WebAssembly.Module(modules.unboundedMemory) instanceof WebAssembly.Module", None)?;
+	assert_eq!(result, "true");
+	Ok(())
+}
+
+#[test]
+fn wasm_limits() -> Result<(), AnyError> {
+	let mut script = Script::builder().wasm(limited()).build(&js_code())?;
+	for (code, expected) in [
+		("new WebAssembly.Instance(new WebAssembly.Module(modules.add)).exports.add(2, 3)", "5"),
+		("new WebAssembly.Module(modules.add) instanceof WebAssembly.Module", "true"),
+		("WebAssembly.Module.exports(new WebAssembly.Module(modules.add))[0].name", r#""add""#),
+		("new WebAssembly.Module(modules.smallMemory).constructor === WebAssembly.Module", "true"),
+		(
+			"new WebAssembly.Module(modules.unboundedMemory)",
+			"error: WebAssembly module defines a memory without a maximum size",
+		),
+		(
+			"new WebAssembly.Module(modules.largeMemory)",
+			"error: WebAssembly module defines a memory of up to 255 pages, which exceeds the limit of 4 pages (262144 bytes)",
+		),
+		(
+			"new WebAssembly.Module(new Uint8Array(65))",
+			"error: WebAssembly module of 65 bytes exceeds the limit of 64 bytes",
+		),
+		("new WebAssembly.Memory({ initial: 1, maximum: 4 }).buffer.byteLength", "65536"),
+		("new WebAssembly.Memory({ initial: 1 })", "error: WebAssembly.Memory must declare a maximum size"),
+		(
+			"new WebAssembly.Memory({ initial: 1, maximum: 5 })",
+			"error: WebAssembly.Memory of up to 5 pages exceeds the limit of 4 pages",
+		),
+		("typeof WebAssembly.instantiateStreaming", r#""undefined""#),
+	]
+	.iter()
+	{
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+
+	for (code, expected) in [
+		("WebAssembly.instantiate(modules.add).then(({ instance }) => instance.exports.add(4, 5))", "9"),
+		(
+			"WebAssembly.compile(modules.largeMemory)",
+			"error: WebAssembly module defines a memory of up to 255 pages, which exceeds the limit of 4 pages (262144 bytes)",
+		),
+		("WebAssembly.instantiate(modules.unboundedMemory)", "error: WebAssembly module defines a memory without a maximum size"),
+	]
+	.iter()
+	{
+		let result: String = script.call_async("attemptAsync", code, None, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	Ok(())
+}
+
+#[test]
+fn wasm_registered_module() -> Result<(), AnyError> {
+	let js_code = "function add([a, b]) {
+			const instance = new WebAssembly.Instance(host.wasmModule('math'));
+			return instance.exports.add(a, b);
+		}
+		function same() { return host.wasmModule('math') === host.wasmModule('math'); }
+		function missing() { return host.wasmModule('physics'); }
+		function check() { Deno.core.ops(); return Deno.core.opSync('__rust_wasm_check', null, new Uint8Array(8)); }";
+
+	let mut script = Script::builder().add_wasm_module("math", ADD_WASM).build(js_code)?;
+	let sum: i32 = script.call("add", &(20, 22), None)?;
+	assert_eq!(sum, 42);
+	assert!(script.call::<_, bool>("same", &(), None)?);
+
+	let err = script.call::<_, ()>("missing", &(), None).unwrap_err();
+	assert!(err.to_string().contains("no WebAssembly module is registered as 'physics'"), "{}", err);
+
+	// Without limits, the check is not available to the script (instead of failing the host)
+	assert!(script.call::<_, ()>("check", &(), None).is_err());
+
+	// Registered modules bypass the limits for the script's own modules
+	let mut script = Script::builder()
+		.wasm(WasmOptions { max_module_bytes: 8, ..limited() })
+		.add_wasm_module("math", ADD_WASM)
+		.build(js_code)?;
+	let sum: i32 = script.call("add", &(1, 2), None)?;
+	assert_eq!(sum, 3);
+
+	let policy = SandboxPolicy::new().allow(Capability::Console);
+	let result = Script::builder().policy(policy).add_wasm_module("math", ADD_WASM).build(js_code);
+	assert!(result.is_err(), "Registered modules count as host functions");
+	Ok(())
+}
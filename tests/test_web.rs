@@ -0,0 +1,127 @@
+// Copyright (c) 2020-2021 Jan Haller. zlib/libpng license.
+
+use js_sandbox::{AnyError, Script};
+
+const JS_CODE: &str = r#"
+	function attempt(code) {
+		try {
+			return JSON.stringify(eval(code) ?? null);
+		} catch (e) {
+			return "error: " + e.name + ": " + e.message;
+		}
+	}"#;
+
+fn check(script: &mut Script, cases: &[(&str, &str)]) -> Result<(), AnyError> {
+	for (code, expected) in cases {
+		let result: String = script.call("attempt", code, None)?;
+		assert_eq!(&result, expected, "for `{}`", code);
+	}
+	Ok(())
+}
+
+#[test]
+fn web_text_encoding() -> Result<(), AnyError> {
+	let mut script = Script::builder().web_globals().build(JS_CODE)?;
+	check(
+		&mut script,
+		&[
+			("Array.from(new TextEncoder().encode('aé€'))", "[97,195,169,226,130,172]"),
+			("new TextDecoder().decode(new Uint8Array([97, 195, 169]))", r#""aé""#),
+			("new TextDecoder().decode(new Uint8Array([0xef, 0xbb, 0xbf, 120]).buffer)", r#""x""#),
+			("new TextDecoder().decode()", r#""""#),
+			("new TextEncoder().encodeInto('aé€', new Uint8Array(4))", r#"{"read":2,"written":3}"#),
+			(
+				"new TextDecoder('utf-8', { fatal: true }).decode(new Uint8Array([0xc3]))",
+				"error: TypeError: the encoded data is not valid UTF-8",
+			),
+			("new TextDecoder('latin1')", "error: RangeError: the encoding 'latin1' is not supported, only UTF-8 is"),
+		],
+	)
+}
+
+#[test]
+fn web_base64() -> Result<(), AnyError> {
+	let mut script = Script::builder().web_globals().build(JS_CODE)?;
+	check(
+		&mut script,
+		&[
+			("btoa('hello')", r#""aGVsbG8=""#),
+			("btoa('\\xff\\x00')", r#""/wA=""#),
+			("atob('aGVsbG8=')", r#""hello""#),
+			("atob(' aGVs bG8 ')", r#""hello""#),
+			("btoa('€')", "error: InvalidCharacterError: btoa() only accepts characters in the Latin1 range"),
+			("atob('a')", "error: InvalidCharacterError: atob() received a string that is not valid base64"),
+		],
+	)
+}
+
+#[test]
+fn web_url() -> Result<(), AnyError> {
+	let mut script = Script::builder().web_globals().build(JS_CODE)?;
+	check(
+		&mut script,
+		&[
+			(
+				"new URL('HTTPS://user:pw@Example.com:443/a/./b/../c?x=1#top').href",
+				r#""https://user:pw@example.com/a/c?x=1#top""#,
+			),
+			("new URL('../img/logo.png?v=2', 'https://example.com/docs/page.html').href", r#""https://example.com/img/logo.png?v=2""#),
+			("new URL('//cdn.example.com/x', 'https://example.com/').href", r#""https://cdn.example.com/x""#),
+			(
+				"(u => [u.protocol, u.host, u.hostname, u.port, u.pathname, u.origin])(new URL('http://localhost:8080/api'))",
+				r#"["http:","localhost:8080","localhost","8080","/api","http://localhost:8080"]"#,
+			),
+			("new URL('mailto:someone@example.com').pathname", r#""someone@example.com""#),
+			("new URL('https://example.com/a b').pathname", r#""/a%20b""#),
+			("URL.canParse('relative/path')", "false"),
+			("new URL('relative/path')", "error: TypeError: Invalid URL: relative/path"),
+			(
+				"(u => { u.searchParams.append('q', 'a b&c'); u.searchParams.set('page', 2); return u.href; })(new URL('https://example.com/search?page=1'))",
+				r#""https://example.com/search?page=2&q=a+b%26c""#,
+			),
+			("new URLSearchParams('a=1&b=x+y&a=%C3%A9').getAll('a')", r#"["1","é"]"#),
+			("[...new URLSearchParams({ z: 1, a: 2 })].length", "2"),
+			("(p => { p.sort(); return p.toString(); })(new URLSearchParams([['z', '1'], ['a', '2']]))", r#""a=2&z=1""#),
+		],
+	)
+}
+
+#[test]
+fn web_structured_clone() -> Result<(), AnyError> {
+	let mut script = Script::builder().web_globals().build(JS_CODE)?;
+	check(
+		&mut script,
+		&[
+			(
+				"(o => { const c = structuredClone(o); return [c !== o, c.list !== o.list, c.list, c.nested.n]; })({ list: [1, 2], nested: { n: 'x' } })",
+				r#"[true,true,[1,2],"x"]"#,
+			),
+			("(o => { o.self = o; const c = structuredClone(o); return c.self === c && c !== o; })({})", "true"),
+			(
+				"(c => [c.get('k') instanceof Set, [...c.get('k')], c.get('d').getTime()])(structuredClone(new Map([['k', new Set([1])], ['d', new Date(5)]])))",
+				r#"[true,[1],5]"#,
+			),
+			("(a => { const c = structuredClone(a); c[0] = 9; return [a[0], c[0], c instanceof Uint8Array]; })(new Uint8Array([1, 2]))", "[1,9,true]"),
+			("structuredClone(new WeakMap())", "error: DataCloneError: WeakMap objects cannot be cloned"),
+			("structuredClone({ f() {} })", "error: DataCloneError: function values cannot be cloned"),
+		],
+	)
+}
+
+#[test]
+fn web_globals_not_provided() -> Result<(), AnyError> {
+	let mut script = Script::from_string(JS_CODE)?;
+	check(
+		&mut script,
+		&[("typeof TextEncoder", r#""undefined""#), ("typeof structuredClone", r#""undefined""#)],
+	)
+}
+
+#[test]
+fn web_globals_in_pure_sandbox() -> Result<(), AnyError> {
+	let mut script = Script::builder().web_globals().pure_sandbox().build(JS_CODE)?;
+	check(
+		&mut script,
+		&[("new TextDecoder().decode(new TextEncoder().encode('ok'))", r#""ok""#), ("typeof Deno", r#""undefined""#)],
+	)
+}